@@ -0,0 +1,24 @@
+use std::{path::Path, process::Command};
+
+/// Records the git commit being built, if building from a checkout, for the
+/// `version` command.
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=GLOWMARKT_GIT_HASH={}", hash.trim());
+    }
+
+    // Published crates have no checkout to watch.
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,55 @@
+//! Totals a year of half-hourly electricity consumption, processing each
+//! chunk of readings as it arrives rather than holding the whole year.
+//!
+//! ```shell
+//! GLOWMARKT_USERNAME=me@somewhere.com GLOWMARKT_PASSWORD=wibble \
+//!     cargo run --example chunked_total
+//! ```
+
+use std::{env, error::Error};
+
+use glowmarkt::{GlowmarktApi, ReadingPeriod};
+use time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let api = GlowmarktApi::authenticate(
+        &env::var("GLOWMARKT_USERNAME")?,
+        &env::var("GLOWMARKT_PASSWORD")?,
+    )
+    .await?;
+
+    let resource = api
+        .resources()
+        .await?
+        .into_values()
+        .find(|resource| {
+            resource
+                .classifier
+                .as_ref()
+                .is_some_and(|classifier| classifier.as_str() == "electricity.consumption")
+        })
+        .ok_or("No electricity consumption resource")?;
+
+    let end = api.clock().now();
+    let start = end - Duration::days(365);
+
+    let mut total = 0.0;
+    let mut readings = 0;
+    api.readings_for_each_chunk(
+        &resource.id,
+        &start,
+        &end,
+        ReadingPeriod::HalfHour,
+        |chunk| {
+            readings += chunk.len();
+            total += chunk.iter().map(|r| r.value as f64).sum::<f64>();
+            eprintln!("Received {} readings", readings);
+            async { Ok::<_, Box<dyn Error>>(()) }
+        },
+    )
+    .await?;
+
+    println!("{:.3} kWh over {} readings", total, readings);
+    Ok(())
+}
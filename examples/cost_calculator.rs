@@ -0,0 +1,55 @@
+//! Prints what the last week of energy cost using each resource's tariff.
+//!
+//! ```shell
+//! GLOWMARKT_USERNAME=me@somewhere.com GLOWMARKT_PASSWORD=wibble \
+//!     cargo run --example cost_calculator
+//! ```
+
+use std::{env, error::Error};
+
+use glowmarkt::{
+    cost::{cost, is_costable, Rates},
+    GlowmarktApi, ReadingPeriod,
+};
+use time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let api = GlowmarktApi::authenticate(
+        &env::var("GLOWMARKT_USERNAME")?,
+        &env::var("GLOWMARKT_PASSWORD")?,
+    )
+    .await?;
+
+    let end = api.clock().now();
+    let start = end - Duration::weeks(1);
+
+    for resource in api.resources().await?.into_values() {
+        if !is_costable(&resource) {
+            continue;
+        }
+
+        let rates = match api.latest_tariff(&resource.id).await? {
+            Some(tariff) => match Rates::from_tariff(&tariff) {
+                Some(rates) => rates,
+                None => continue,
+            },
+            None => continue,
+        };
+
+        let readings = api
+            .readings_range(&resource.id, &start, &end, ReadingPeriod::HalfHour)
+            .await?;
+        let costs = cost(&readings, &rates, start, end);
+
+        println!(
+            "{}: £{:.2} over {} days (£{:.2} standing charge)",
+            resource.name,
+            costs.total / 100.0,
+            costs.days,
+            costs.standing_charge / 100.0
+        );
+    }
+
+    Ok(())
+}
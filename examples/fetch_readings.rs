@@ -0,0 +1,46 @@
+//! Prints the last day of half-hourly readings for every resource.
+//!
+//! ```shell
+//! GLOWMARKT_USERNAME=me@somewhere.com GLOWMARKT_PASSWORD=wibble \
+//!     cargo run --example fetch_readings
+//! ```
+
+use std::{env, error::Error};
+
+use glowmarkt::{GlowmarktApi, ReadingPeriod};
+use time::{format_description::well_known::Rfc3339, Duration};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let api = GlowmarktApi::authenticate(
+        &env::var("GLOWMARKT_USERNAME")?,
+        &env::var("GLOWMARKT_PASSWORD")?,
+    )
+    .await?;
+
+    let end = api.clock().now();
+    let start = end - Duration::days(1);
+
+    for resource in api.resources().await?.into_values() {
+        println!("{} ({})", resource.name, resource.id);
+
+        let unit = resource.unit();
+        let readings = api
+            .readings_range(&resource.id, &start, &end, ReadingPeriod::HalfHour)
+            .await?;
+        for reading in readings.into_iter().map(|r| r.with_unit(unit.clone())) {
+            println!(
+                "  {} {:.3} {}",
+                reading.start.format(&Rfc3339)?,
+                reading.value,
+                reading
+                    .unit
+                    .as_ref()
+                    .map(|u| u.as_str())
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Writes the last day of readings to InfluxDB.
+//!
+//! `INFLUX_URL` is the full URL of the write API including the bucket, for
+//! example `http://localhost:8086/api/v2/write?org=home&bucket=energy`, and
+//! `INFLUX_TOKEN` the API token if one is needed.
+//!
+//! ```shell
+//! GLOWMARKT_USERNAME=me@somewhere.com GLOWMARKT_PASSWORD=wibble \
+//!     INFLUX_URL=http://localhost:8086/api/v2/write?org=home&bucket=energy \
+//!     INFLUX_TOKEN=secret cargo run --example influx_forwarder
+//! ```
+
+use std::{env, error::Error};
+
+use glowmarkt::{
+    reqwest::Client,
+    sink::{ExportSink, LineProtocolSink},
+    GlowmarktApi, ReadingPeriod,
+};
+use time::Duration;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let api = GlowmarktApi::authenticate(
+        &env::var("GLOWMARKT_USERNAME")?,
+        &env::var("GLOWMARKT_PASSWORD")?,
+    )
+    .await?;
+    let url = env::var("INFLUX_URL")?;
+
+    let end = api.clock().now();
+    let start = end - Duration::days(1);
+
+    // Each resource is tagged with its device and virtual entity.
+    let mut body = Vec::new();
+    let mut sink = LineProtocolSink::new(&mut body);
+    for context in api.resource_contexts(&[]).await? {
+        let readings = api
+            .readings_range(&context.resource.id, &start, &end, ReadingPeriod::HalfHour)
+            .await?;
+        sink.write_readings(&context, &readings)?;
+    }
+    sink.flush()?;
+    drop(sink);
+
+    let mut request = Client::new().post(&url).body(body);
+    if let Ok(token) = env::var("INFLUX_TOKEN") {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+    request.send().await?.error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Publishes the state of every resource to an MQTT broker along with Home
+//! Assistant discovery messages.
+//!
+//! ```shell
+//! GLOWMARKT_USERNAME=me@somewhere.com GLOWMARKT_PASSWORD=wibble \
+//!     MQTT_HOST=localhost cargo run --example mqtt_bridge
+//! ```
+
+use std::{env, error::Error};
+
+use glowmarkt::{
+    homeassistant::ResourceState,
+    sink::{ExportSink, MqttSink},
+    GlowmarktApi,
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let api = GlowmarktApi::authenticate(
+        &env::var("GLOWMARKT_USERNAME")?,
+        &env::var("GLOWMARKT_PASSWORD")?,
+    )
+    .await?;
+    let host = env::var("MQTT_HOST").unwrap_or_else(|_| "localhost".to_string());
+    let port: u16 = match env::var("MQTT_PORT") {
+        Ok(port) => port.parse()?,
+        Err(_) => 1883,
+    };
+
+    let mut states = Vec::new();
+    for resource in api.resources().await?.into_values() {
+        let state = ResourceState::fetch(&api, &resource).await?;
+        states.push((resource, state));
+    }
+
+    // Everything is fetched so it's fine for the sink to block from here.
+    let mut sink = MqttSink::new(&format!("{}:{}", host, port)).client_id("glowmarkt-example");
+    for (resource, state) in &states {
+        sink.publish_discovery(resource, state.meter_read.is_some())?;
+        sink.publish_state(resource, state)?;
+    }
+    sink.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,192 @@
+//! Costs half-hourly consumption at Octopus Agile prices.
+
+use std::io::{stdout, Write};
+
+use glowmarkt::{
+    cost::{cost, CostedReading},
+    format::round,
+    increase_by_period,
+    octopus::{agile_rates, OctopusClient, DEFAULT_AGILE_PRODUCT},
+    settlement::uk_offset,
+    GlowmarktApi, ReadingPeriod,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{macros::format_description, Duration};
+
+use crate::{
+    hint::CliError,
+    lookup::resolve_resource,
+    output::{OutputOptions, ReportFormat},
+    parse_date, parse_end_date, ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct AgileCostArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// The region's letter, e.g. `C` for London, as found at the end of the
+    /// Agile tariff code on a bill.
+    #[clap(long, env = "GLOWMARKT_AGILE_REGION")]
+    region: char,
+    /// The Agile product code.
+    #[clap(long, env = "GLOWMARKT_AGILE_PRODUCT", default_value = DEFAULT_AGILE_PRODUCT)]
+    product: String,
+    /// The daily standing charge in pence.
+    #[clap(long, default_value = "0")]
+    standing_charge: f64,
+    /// The consumption resource, either its ID, its classifier or part of
+    /// its name.
+    #[clap(long, default_value = "electricity.consumption")]
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    resource_id: String,
+    product: String,
+    region: char,
+    days: u32,
+    consumption: f64,
+    standing_charge: f64,
+    consumption_cost: f64,
+    total: f64,
+    /// The average price paid in pence per kWh, weighted by consumption.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    average_rate: Option<f64>,
+    slots: Vec<CostedReading>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+fn print_text(report: &Report) -> Result<(), CliError> {
+    let slot = format_description!("[year]-[month]-[day] [hour]:[minute]");
+
+    let mut out = stdout().lock();
+    writeln!(
+        out,
+        "{:<16}  {:>10}  {:>10}  {:>10}",
+        "Slot", "kWh", "p/kWh", "Cost"
+    )
+    .str_err()?;
+    for reading in &report.slots {
+        let start = reading.start.to_offset(uk_offset(reading.start));
+        writeln!(
+            out,
+            "{:<16}  {:>10}  {:>10}  {:>10}",
+            start.format(&slot).str_err()?,
+            reading.consumption,
+            reading.rate,
+            reading.cost
+        )
+        .str_err()?;
+    }
+
+    writeln!(out).str_err()?;
+    writeln!(
+        out,
+        "{} {} over {} days",
+        report.product, report.region, report.days
+    )
+    .str_err()?;
+    writeln!(out, "Consumption: {} kWh", report.consumption).str_err()?;
+    if let Some(rate) = report.average_rate {
+        writeln!(out, "Average rate: {}p/kWh", rate).str_err()?;
+    }
+    writeln!(out, "Standing charge: {}p", report.standing_charge).str_err()?;
+    writeln!(out, "Unit cost: {}p", report.consumption_cost).str_err()?;
+    writeln!(out, "Total: {}p", report.total).str_err()?;
+
+    for note in &report.notes {
+        writeln!(out).str_err()?;
+        writeln!(out, "{}", note).str_err()?;
+    }
+
+    Ok(())
+}
+
+pub async fn agile_cost(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: AgileCostArgs,
+) -> Result<(), CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let start = parse_date(args.from, period, &api)?;
+    let end = parse_end_date(args.to, period, &api)?;
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+
+    // The reading starting at the end of the range needs a price too.
+    let prices = OctopusClient::default()
+        .agile_prices(
+            &args.product,
+            args.region,
+            start,
+            end + Duration::minutes(30),
+        )
+        .await?;
+    let rates = agile_rates(&prices, args.standing_charge);
+
+    let readings = api
+        .readings_range(&resource_id, &start, &end, period)
+        .await?;
+    let range_end = increase_by_period(end, period).min(api.clock().now());
+    let costs = cost(&readings, &rates, start, range_end);
+
+    let mut notes = Vec::new();
+    let unpriced = readings
+        .iter()
+        .filter(|reading| rates.rate_at(reading.start).is_none())
+        .count();
+    if unpriced > 0 {
+        notes.push(format!(
+            "{} half hours had no Agile price and were costed at zero. Prices are only \
+            published a day ahead and the product must have been available then.",
+            unpriced
+        ));
+    }
+
+    let precision = match args.format {
+        ReportFormat::Text => options.precision.or(Some(3)),
+        ReportFormat::Json => options.precision,
+    };
+    let round = |value: f64| round(value, precision);
+    let consumption: f64 = costs.readings.iter().map(|r| r.consumption).sum();
+    let report = Report {
+        resource_id,
+        product: args.product,
+        region: args.region.to_ascii_uppercase(),
+        days: costs.days,
+        consumption: round(consumption),
+        standing_charge: round(costs.standing_charge),
+        consumption_cost: round(costs.consumption_cost),
+        total: round(costs.total),
+        average_rate: (consumption > 0.0).then(|| round(costs.consumption_cost / consumption)),
+        slots: costs
+            .readings
+            .into_iter()
+            .map(|reading| CostedReading {
+                consumption: round(reading.consumption),
+                rate: round(reading.rate),
+                cost: round(reading.cost),
+                ..reading
+            })
+            .collect(),
+        notes,
+    };
+
+    match args.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&report).str_err()?);
+            Ok(())
+        }
+    }
+}
@@ -4,12 +4,16 @@
 use std::{collections::HashMap, fmt};
 
 use serde::{
-    de::{self, MapAccess, Visitor},
+    de::{self, MapAccess, SeqAccess, Visitor},
     Deserialize, Deserializer, Serialize,
 };
-use time::OffsetDateTime;
+use serde_json::Value;
+use time::{Duration, OffsetDateTime};
 
-use crate::{Error, ErrorKind};
+use crate::{
+    classifier::Classifier, parse_iso_duration, tariff::PlanDetail, unit::Unit, Error, ErrorKind,
+    ReadingPeriod,
+};
 
 #[derive(Serialize, Debug)]
 pub(super) struct AuthRequest {
@@ -52,16 +56,16 @@ impl AuthResponse {
                 if response.valid {
                     Ok(response)
                 } else {
-                    Err(Error {
-                        kind: ErrorKind::NotAuthenticated,
-                        message: "Authentication error".to_string(),
-                    })
+                    Err(Error::new(
+                        ErrorKind::NotAuthenticated,
+                        "Authentication error",
+                    ))
                 }
             }
-            AuthResponse::Invalid(response) => Err(Error {
-                kind: ErrorKind::NotAuthenticated,
-                message: response.error.message,
-            }),
+            AuthResponse::Invalid(response) => Err(Error::new(
+                ErrorKind::NotAuthenticated,
+                response.error.message,
+            )),
         }
     }
 }
@@ -94,28 +98,28 @@ impl ValidateResponse {
                 if response.valid {
                     Ok(response)
                 } else {
-                    Err(Error {
-                        kind: ErrorKind::NotAuthenticated,
-                        message: "Authentication error".to_string(),
-                    })
+                    Err(Error::new(
+                        ErrorKind::NotAuthenticated,
+                        "Authentication error",
+                    ))
                 }
             }
-            ValidateResponse::Invalid(response) => Err(Error {
-                kind: ErrorKind::NotAuthenticated,
-                message: response.error.message,
-            }),
+            ValidateResponse::Invalid(response) => Err(Error::new(
+                ErrorKind::NotAuthenticated,
+                response.error.message,
+            )),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceInfo {
     pub resource_id: String,
     pub resource_type_id: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct VirtualEntity {
     #[serde(rename(deserialize = "veId"))]
@@ -128,21 +132,99 @@ pub struct VirtualEntity {
     pub resources: Vec<ResourceInfo>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A virtual entity along with the full details of its resources.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualEntityResources {
+    #[serde(rename(deserialize = "veId"))]
+    pub id: String,
+    pub name: String,
+    #[serde(rename(deserialize = "veTypeId"))]
+    pub type_id: String,
+    pub owner_id: String,
+    pub resources: Vec<Resource>,
+}
+
+/// The protocol a device or device type communicates with.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ProtocolKind {
+    /// `mqtt`, used by Glow displays and CADs.
+    Mqtt,
+    /// `zigbee`, a smart meter home area network.
+    Zigbee,
+    /// Any other protocol.
+    Other(String),
+}
+
+impl ProtocolKind {
+    /// The protocol as used by the API.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ProtocolKind::Mqtt => "mqtt",
+            ProtocolKind::Zigbee => "zigbee",
+            ProtocolKind::Other(protocol) => protocol,
+        }
+    }
+}
+
+impl From<String> for ProtocolKind {
+    fn from(protocol: String) -> Self {
+        match protocol.to_ascii_lowercase().as_str() {
+            "mqtt" => ProtocolKind::Mqtt,
+            "zigbee" => ProtocolKind::Zigbee,
+            _ => ProtocolKind::Other(protocol),
+        }
+    }
+}
+
+impl From<ProtocolKind> for String {
+    fn from(protocol: ProtocolKind) -> Self {
+        match protocol {
+            ProtocolKind::Other(protocol) => protocol,
+            protocol => protocol.as_str().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A sensor on a device or device type.
+///
+/// Sensors on a [`DeviceType`] describe what devices of that type measure and
+/// have no resource. Sensors on a [`Device`] are bound to the resource holding
+/// their readings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Sensor {
     pub protocol_id: String,
     pub resource_type_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// How a device or device type communicates and the sensors it has.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Protocol {
-    pub protocol: String,
+    pub protocol: ProtocolKind,
     pub sensors: Vec<Sensor>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl Protocol {
+    /// The IDs of the resources the sensors are bound to.
+    pub fn resource_ids(&self) -> impl Iterator<Item = &str> {
+        self.sensors
+            .iter()
+            .filter_map(|sensor| sensor.resource_id.as_deref())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DeviceType {
     #[serde(rename(deserialize = "deviceTypeId"))]
@@ -158,22 +240,15 @@ pub struct DeviceType {
     pub created_at: OffsetDateTime,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct DeviceSensor {
-    pub protocol_id: String,
-    pub resource_id: String,
-    pub resource_type_id: String,
-}
+/// A sensor on a device, now the same as [`Sensor`].
+#[deprecated(note = "use Sensor")]
+pub type DeviceSensor = Sensor;
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct DeviceProtocol {
-    pub protocol: String,
-    pub sensors: Vec<DeviceSensor>,
-}
+/// A device's protocol, now the same as [`Protocol`].
+#[deprecated(note = "use Protocol")]
+pub type DeviceProtocol = Protocol;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Device {
     #[serde(rename(deserialize = "deviceId"))]
@@ -187,14 +262,48 @@ pub struct Device {
     pub hardware_ids: HashMap<String, String>,
     pub parent_hardware_id: Vec<String>,
     pub tags: Vec<String>,
-    pub protocol: DeviceProtocol,
+    pub protocol: Protocol,
     #[serde(with = "time::serde::rfc3339")]
     pub updated_at: OffsetDateTime,
     #[serde(with = "time::serde::rfc3339")]
     pub created_at: OffsetDateTime,
+    /// When the device last communicated with the platform, if the API
+    /// reports it.
+    #[serde(
+        default,
+        alias = "lastTelemetry",
+        alias = "lastCommunication",
+        with = "time::serde::rfc3339::option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub last_seen: Option<OffsetDateTime>,
+    /// The pairing or connection status of the device, if the API reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Normalises a hardware ID for comparison, meter serials and MPANs are often
+/// written with spaces or dashes.
+fn normalise_hardware_id(id: &str) -> String {
+    id.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+impl Device {
+    /// Whether the device is identified by the given hardware ID, such as a
+    /// meter serial number or MPAN.
+    pub fn has_hardware_id(&self, hardware_id: &str) -> bool {
+        let wanted = normalise_hardware_id(hardware_id);
+        !wanted.is_empty()
+            && std::iter::once(&self.hardware_id)
+                .chain(self.hardware_ids.values())
+                .any(|id| normalise_hardware_id(id) == wanted)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DataSourceResourceTypeInfo {
     #[serde(rename = "type")]
@@ -217,7 +326,7 @@ impl From<String> for DataSourceResourceTypeInfo {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Field {
     pub field_name: String,
@@ -225,7 +334,7 @@ pub struct Field {
     pub negative: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Storage {
     #[serde(rename = "type")]
@@ -236,7 +345,7 @@ pub struct Storage {
     pub fields: Vec<Field>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourceType {
     #[serde(rename(deserialize = "resourceTypeId"))]
@@ -245,7 +354,7 @@ pub struct ResourceType {
     pub description: Option<String>,
     pub label: Option<String>,
     pub active: bool,
-    pub classifier: Option<String>,
+    pub classifier: Option<Classifier>,
     pub base_unit: Option<String>,
     pub data_source_type: String,
     #[serde(default, deserialize_with = "ds_type_info_deserializer")]
@@ -255,7 +364,29 @@ pub struct ResourceType {
     pub storage: Vec<Storage>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl ResourceType {
+    /// The finest interval at which this resource type stores data, if known.
+    pub fn sampling(&self) -> Option<Duration> {
+        self.storage
+            .iter()
+            .filter_map(|storage| parse_iso_duration(&storage.sampling).ok())
+            .min()
+    }
+
+    /// Whether readings for this resource type can be requested at the given
+    /// period.
+    ///
+    /// Resource types with unknown sampling are assumed to support every
+    /// period other than [`ReadingPeriod::Minute`].
+    pub fn supports_period(&self, period: ReadingPeriod) -> bool {
+        match self.sampling() {
+            Some(sampling) => sampling <= period.min_duration(),
+            None => !matches!(period, ReadingPeriod::Minute),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Resource {
     #[serde(rename(deserialize = "resourceId"))]
@@ -267,7 +398,7 @@ pub struct Resource {
     #[serde(rename(deserialize = "resourceTypeId"))]
     pub type_id: String,
     pub owner_id: String,
-    pub classifier: Option<String>,
+    pub classifier: Option<Classifier>,
     pub base_unit: Option<String>,
     pub data_source_type: String,
     #[serde(default, deserialize_with = "ds_type_info_deserializer")]
@@ -279,7 +410,161 @@ pub struct Resource {
     pub created_at: OffsetDateTime,
 }
 
-type ReadingTuple = (i64, f32);
+impl Resource {
+    /// The unit of the resource's readings, if known.
+    pub fn unit(&self) -> Option<Unit> {
+        self.base_unit.as_deref().map(Unit::from)
+    }
+
+    /// Checks that readings for the resource can be requested at the given
+    /// period, using the resource types from
+    /// [`GlowmarktApi::resource_types`](crate::GlowmarktApi::resource_types).
+    ///
+    /// See [`GlowmarktApi::check_period`](crate::GlowmarktApi::check_period).
+    pub fn check_period(
+        &self,
+        resource_types: &HashMap<String, ResourceType>,
+        period: ReadingPeriod,
+    ) -> Result<(), Error> {
+        if !matches!(period, ReadingPeriod::Minute) {
+            return Ok(());
+        }
+
+        let supported = resource_types
+            .get(&self.type_id)
+            .map(|resource_type| resource_type.supports_period(period))
+            .unwrap_or(false);
+
+        if supported {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::UnsupportedPeriod,
+                format!(
+                    "Resource {} ({}) does not record readings every {}",
+                    self.name, self.id, period
+                ),
+            ))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Plan {
+    #[serde(default)]
+    pub plan_detail: Vec<PlanDetail>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TariffData {
+    pub tariff_id: Option<String>,
+    pub from: Option<String>,
+    pub structure: Option<String>,
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub plan: Vec<Plan>,
+    #[serde(default)]
+    pub current_rates: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Tariff {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub data: Vec<TariffData>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct InvalidTariffResponse {
+    pub error: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(super) enum TariffResponse {
+    Invalid(InvalidTariffResponse),
+    Valid(Tariff),
+}
+
+impl TariffResponse {
+    pub fn validate(self) -> Result<Tariff, Error> {
+        match self {
+            TariffResponse::Valid(response) => Ok(response),
+            TariffResponse::Invalid(response) => Err(Error::new(
+                ErrorKind::NoTariff,
+                match response.error {
+                    Value::String(message) => message,
+                    Value::Object(map) => match map.get("message") {
+                        Some(Value::String(message)) => message.clone(),
+                        _ => "No tariff available".to_string(),
+                    },
+                    _ => "No tariff available".to_string(),
+                },
+            )),
+        }
+    }
+}
+
+/// A reading as returned by the API, an array of the timestamp and value
+/// followed by any status or quality markers.
+#[derive(Debug)]
+pub struct ReadingTuple {
+    pub timestamp: i64,
+    pub value: f32,
+    /// A single marker as is, or an array if there were several.
+    pub quality: Option<Value>,
+}
+
+impl<'de> Deserialize<'de> for ReadingTuple {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TupleVisitor;
+
+        impl<'de> Visitor<'de> for TupleVisitor {
+            type Value = ReadingTuple;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array of a timestamp and a value")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<ReadingTuple, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let timestamp = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let value = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+                let mut markers = Vec::new();
+                while let Some(marker) = seq.next_element::<Value>()? {
+                    markers.push(marker);
+                }
+                let quality = match markers.len() {
+                    0 => None,
+                    1 => markers.pop(),
+                    _ => Some(Value::Array(markers)),
+                };
+
+                Ok(ReadingTuple {
+                    timestamp,
+                    value,
+                    quality,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(TupleVisitor)
+    }
+}
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -287,6 +572,32 @@ pub struct ReadingsResponse {
     pub data: Vec<ReadingTuple>,
 }
 
+#[derive(Deserialize, Debug)]
+pub(super) struct CurrentResponse {
+    pub data: Vec<ReadingTuple>,
+    pub units: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct ResourceTimeResponse {
+    pub data: ResourceTime,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct ResourceTime {
+    // `first-time` and `last-time` return the same shape with differently
+    // named fields.
+    #[serde(default, alias = "firstTs", alias = "lastTs")]
+    pub timestamp: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct MeterReadResponse {
+    // Registers hold large totals so need more precision than readings.
+    pub data: Vec<(i64, f64)>,
+    pub units: Option<String>,
+}
+
 fn ds_type_info_deserializer<'de, D>(
     deserializer: D,
 ) -> Result<Option<DataSourceResourceTypeInfo>, D::Error>
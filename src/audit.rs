@@ -0,0 +1,380 @@
+//! Checks everything on an account and reports what needs attention.
+//!
+//! Aimed at installers and people supporting others, each device and resource
+//! gets a pass, warn or fail for each check along with a short explanation.
+
+use std::{collections::HashMap, fmt};
+
+use clap::ValueEnum;
+use glowmarkt::{
+    classifier::Classifier, event::is_event_resource, unit::Unit, Device, GlowmarktApi, Resource,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{Duration, OffsetDateTime};
+
+use crate::{devicestatus::latest_reading, hint::CliError, ErrorStr};
+
+/// Devices that haven't reported for this long get a warning.
+const DEVICE_WARN_AFTER: Duration = Duration::hours(2);
+/// Devices that haven't reported for this long fail.
+const DEVICE_FAIL_AFTER: Duration = Duration::hours(24);
+/// Readings are normally delivered within a day, this allows some slack.
+const READINGS_WARN_AFTER: Duration = Duration::hours(36);
+/// Readings this old suggest the meter has lost contact.
+const READINGS_FAIL_AFTER: Duration = Duration::hours(72);
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AuditFormat {
+    /// A JSON report.
+    Json,
+    /// A Markdown table, for pasting into tickets.
+    Markdown,
+}
+
+#[derive(clap::Args)]
+pub struct AuditArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: AuditFormat,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Status::Pass => "pass",
+            Status::Warn => "warn",
+            Status::Fail => "fail",
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Check {
+    name: &'static str,
+    status: Status,
+    message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, message: String) -> Self {
+        Self {
+            name,
+            status,
+            message,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    kind: &'static str,
+    id: String,
+    name: String,
+    status: Status,
+    checks: Vec<Check>,
+}
+
+impl Item {
+    fn new(kind: &'static str, id: String, name: String, checks: Vec<Check>) -> Self {
+        Self {
+            kind,
+            id,
+            name,
+            status: worst(checks.iter().map(|check| check.status)),
+            checks,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    #[serde(with = "time::serde::rfc3339")]
+    generated: OffsetDateTime,
+    status: Status,
+    items: Vec<Item>,
+}
+
+fn worst(statuses: impl Iterator<Item = Status>) -> Status {
+    statuses.max().unwrap_or(Status::Pass)
+}
+
+fn hours(duration: Duration) -> String {
+    format!("{:.1} hours", duration.as_seconds_f64() / 3600.0)
+}
+
+async fn audit_device(
+    api: &GlowmarktApi,
+    device: &Device,
+    resources: &HashMap<String, Resource>,
+    now: OffsetDateTime,
+) -> Item {
+    let mut checks = Vec::new();
+
+    checks.push(if device.active {
+        Check::new("active", Status::Pass, "The device is active.".to_string())
+    } else {
+        Check::new(
+            "active",
+            Status::Warn,
+            "The device is inactive.".to_string(),
+        )
+    });
+
+    let last_seen = match device.last_seen {
+        Some(last_seen) => Some(last_seen),
+        None => latest_reading(api, device).await,
+    };
+    checks.push(match last_seen.map(|last_seen| now - last_seen) {
+        Some(since) if since > DEVICE_FAIL_AFTER => Check::new(
+            "lastSeen",
+            Status::Fail,
+            format!(
+                "Last reported {} ago, check it is powered and online.",
+                hours(since)
+            ),
+        ),
+        Some(since) if since > DEVICE_WARN_AFTER => Check::new(
+            "lastSeen",
+            Status::Warn,
+            format!("Last reported {} ago.", hours(since)),
+        ),
+        Some(since) => Check::new(
+            "lastSeen",
+            Status::Pass,
+            format!("Last reported {} ago.", hours(since)),
+        ),
+        None => Check::new(
+            "lastSeen",
+            Status::Warn,
+            "The device has never reported.".to_string(),
+        ),
+    });
+
+    let missing: Vec<&str> = device
+        .protocol
+        .resource_ids()
+        .filter(|id| !resources.contains_key(*id))
+        .collect();
+    checks.push(if missing.is_empty() {
+        Check::new(
+            "resources",
+            Status::Pass,
+            format!("{} resources.", device.protocol.resource_ids().count()),
+        )
+    } else {
+        Check::new(
+            "resources",
+            Status::Warn,
+            format!(
+                "Resources missing from the account: {}.",
+                missing.join(", ")
+            ),
+        )
+    });
+
+    Item::new(
+        "device",
+        device.id.clone(),
+        device
+            .description
+            .clone()
+            .unwrap_or_else(|| device.hardware_id.clone()),
+        checks,
+    )
+}
+
+fn check_classifier(resource: &Resource) -> Check {
+    let Some(ref classifier) = resource.classifier else {
+        return Check::new(
+            "classifier",
+            Status::Fail,
+            "The resource has no classifier so cannot be identified.".to_string(),
+        );
+    };
+
+    let unit = resource.unit();
+    let unit_ok = match unit {
+        Some(ref unit) if classifier.is_cost() => unit.is_currency(),
+        Some(ref unit) => unit.is_energy() || *unit == Unit::CubicMetres,
+        None => false,
+    };
+
+    if matches!(classifier, Classifier::Other(_)) {
+        Check::new(
+            "classifier",
+            Status::Warn,
+            format!("Unrecognised classifier {}.", classifier),
+        )
+    } else if !unit_ok {
+        Check::new(
+            "classifier",
+            Status::Warn,
+            format!(
+                "A {} resource is measured in {}.",
+                classifier,
+                unit.map(|unit| unit.to_string())
+                    .unwrap_or_else(|| "no unit".to_string())
+            ),
+        )
+    } else {
+        Check::new(
+            "classifier",
+            Status::Pass,
+            format!("{} measured in {}.", classifier, unit.unwrap()),
+        )
+    }
+}
+
+async fn audit_resource(api: &GlowmarktApi, resource: &Resource) -> Result<Item, CliError> {
+    let mut checks = vec![check_classifier(resource)];
+
+    match api.health(resource).await {
+        Ok(health) => {
+            checks.push(match health.staleness.map(Duration::seconds_f64) {
+                Some(since) if since > READINGS_FAIL_AFTER => Check::new(
+                    "freshness",
+                    Status::Fail,
+                    format!("The latest data is {} old.", hours(since)),
+                ),
+                Some(since) if since > READINGS_WARN_AFTER => Check::new(
+                    "freshness",
+                    Status::Warn,
+                    format!("The latest data is {} old.", hours(since)),
+                ),
+                Some(since) => Check::new(
+                    "freshness",
+                    Status::Pass,
+                    format!("The latest data is {} old.", hours(since)),
+                ),
+                None => Check::new(
+                    "freshness",
+                    Status::Fail,
+                    "No non-zero readings in the last 30 days.".to_string(),
+                ),
+            });
+
+            let status = match health.gap_percentage {
+                gaps if gaps < 5.0 => Status::Pass,
+                gaps if gaps < 20.0 => Status::Warn,
+                _ => Status::Fail,
+            };
+            checks.push(Check::new(
+                "gaps",
+                status,
+                format!(
+                    "{:.1}% of half hours missing over 30 days, the longest zero run was {} \
+                    half hours.",
+                    health.gap_percentage, health.longest_zero_run
+                ),
+            ));
+        }
+        Err(e) if e.is_unavailable() => checks.push(Check::new(
+            "freshness",
+            Status::Fail,
+            format!("Readings are unavailable: {}", e),
+        )),
+        Err(e) => return Err(e.into()),
+    }
+
+    let costable = resource
+        .classifier
+        .as_ref()
+        .map(|classifier| classifier.is_consumption() || classifier.is_export())
+        .unwrap_or(false);
+    if costable {
+        let tariff = match api.latest_tariff(&resource.id).await {
+            Ok(tariff) => tariff,
+            Err(e) if e.is_unavailable() => None,
+            Err(e) => return Err(e.into()),
+        };
+        checks.push(match tariff {
+            Some(_) => Check::new("tariff", Status::Pass, "A tariff is set.".to_string()),
+            None => Check::new(
+                "tariff",
+                Status::Warn,
+                "No tariff is set so costs can't be calculated.".to_string(),
+            ),
+        });
+    }
+
+    Ok(Item::new(
+        "resource",
+        resource.id.clone(),
+        resource.name.clone(),
+        checks,
+    ))
+}
+
+fn escape_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+fn markdown(report: &Report) -> String {
+    let mut out = format!(
+        "# Glowmarkt account audit\n\nOverall: **{}**, generated {}.\n\n\
+        | Item | Check | Status | Details |\n| --- | --- | --- | --- |\n",
+        report.status, report.generated
+    );
+
+    for item in &report.items {
+        for check in &item.checks {
+            out.push_str(&format!(
+                "| {} {} ({}) | {} | {} | {} |\n",
+                item.kind,
+                escape_cell(&item.name),
+                item.id,
+                check.name,
+                check.status,
+                escape_cell(&check.message)
+            ));
+        }
+    }
+
+    out
+}
+
+pub async fn audit(api: GlowmarktApi, args: AuditArgs) -> Result<(), CliError> {
+    let now = api.clock().now();
+    let resources = api.resources().await?;
+    let mut devices: Vec<Device> = api.devices().await?.into_values().collect();
+    devices.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut items = Vec::new();
+    for device in &devices {
+        items.push(audit_device(&api, device, &resources, now).await);
+    }
+
+    let mut audited: Vec<&Resource> = resources
+        .values()
+        .filter(|resource| !is_event_resource(resource))
+        .collect();
+    audited.sort_by(|a, b| a.classifier.cmp(&b.classifier).then(a.id.cmp(&b.id)));
+    for resource in audited {
+        items.push(audit_resource(&api, resource).await?);
+    }
+
+    let report = Report {
+        generated: now,
+        status: worst(items.iter().map(|item| item.status)),
+        items,
+    };
+
+    match args.format {
+        AuditFormat::Json => println!("{}", to_string_pretty(&report).str_err()?),
+        AuditFormat::Markdown => print!("{}", markdown(&report)),
+    }
+
+    Ok(())
+}
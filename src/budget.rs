@@ -0,0 +1,213 @@
+use glowmarkt::{
+    cost::{cost, is_costable, Rates},
+    format::round,
+    settlement::uk_offset,
+    GlowmarktApi, ReadingPeriod, Resource,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{util::days_in_year_month, Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::{
+    config::BudgetConfig,
+    dashboard::fuel,
+    forecast::{record_forecast, ForecastOptions},
+    hint::CliError,
+    output::OutputOptions,
+    ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct BudgetArgs {
+    /// Record the projection so its accuracy can be checked later with
+    /// forecast-accuracy.
+    #[clap(long)]
+    record: bool,
+    #[clap(flatten)]
+    forecasts: ForecastOptions,
+}
+
+/// Month-to-date spending against a budget. All amounts are in pence.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub month: String,
+    pub budget: f64,
+    pub spent: f64,
+    pub remaining: f64,
+    pub projected: Option<f64>,
+    pub over_budget: bool,
+    /// Resources left out of the budget and why.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// Returns the start of the given day in UK local time.
+fn uk_midnight(date: Date) -> OffsetDateTime {
+    let midnight = PrimitiveDateTime::new(date, Time::MIDNIGHT);
+    midnight.assume_offset(uk_offset(midnight.assume_utc() - Duration::hours(1)))
+}
+
+async fn budget_resources(
+    api: &GlowmarktApi,
+    config: &BudgetConfig,
+) -> Result<Vec<Resource>, CliError> {
+    let resources = api.resources().await?;
+
+    if config.resources.is_empty() {
+        return Ok(resources
+            .into_values()
+            .filter(|resource| fuel(resource).is_some())
+            .collect());
+    }
+
+    config
+        .resources
+        .iter()
+        .map(|id| match resources.get(id) {
+            Some(resource) if !is_costable(resource) => {
+                Err(format!("Resource {} in budget does not measure energy use", id).into())
+            }
+            Some(resource) => Ok(resource.clone()),
+            None => Err(format!("Unknown resource {} in budget", id).into()),
+        })
+        .collect()
+}
+
+/// Returns the start and end of the UK month starting on the given day.
+fn month_range(first: Date) -> (OffsetDateTime, OffsetDateTime) {
+    let days = days_in_year_month(first.year(), first.month());
+    (
+        uk_midnight(first),
+        uk_midnight(first + Duration::days(days as i64)),
+    )
+}
+
+/// Formats a month as used in budget statuses, e.g. `2024-03`.
+pub fn month_name(first: Date) -> String {
+    format!("{}-{:02}", first.year(), first.month() as u8)
+}
+
+/// Totals the spending on the budget's resources from the start of a month
+/// until the given time, returning the spend, the end of the latest delivered
+/// reading and notes on any resources left out.
+async fn spending(
+    api: &GlowmarktApi,
+    config: &BudgetConfig,
+    month_start: OffsetDateTime,
+    until: OffsetDateTime,
+) -> Result<(f64, Option<OffsetDateTime>, Vec<String>), CliError> {
+    let mut spent = 0.0;
+    let mut latest: Option<OffsetDateTime> = None;
+    let mut notes = Vec::new();
+
+    for resource in budget_resources(api, config).await? {
+        let tariff = match api.latest_tariff(&resource.id).await {
+            Ok(tariff) => tariff,
+            Err(e) if e.is_unavailable() => None,
+            Err(e) => return Err(e.into()),
+        };
+        let rates = match tariff.as_ref().and_then(Rates::from_tariff) {
+            Some(rates) => rates,
+            None => {
+                let note = format!(
+                    "Resource {} ({}) has no usable tariff and is excluded from the budget.",
+                    resource.name, resource.id
+                );
+                log::warn!("{}", note);
+                notes.push(note);
+                continue;
+            }
+        };
+
+        let readings = api
+            .readings_range(&resource.id, &month_start, &until, ReadingPeriod::HalfHour)
+            .await?
+            .into_iter()
+            .filter(|reading| reading.start < until)
+            .collect::<Vec<_>>();
+
+        // Recent readings are zero until the DCC delivers them.
+        if let Some(reading) = readings.iter().rev().find(|r| r.value != 0.0) {
+            let end = reading.start + Duration::minutes(30);
+            latest = Some(latest.map_or(end, |latest| latest.max(end)));
+        }
+
+        spent += cost(&readings, &rates, month_start, until).total;
+    }
+
+    Ok((spent, latest, notes))
+}
+
+/// Calculates what was spent over the whole of a completed UK month.
+pub async fn month_spent(
+    api: &GlowmarktApi,
+    config: &BudgetConfig,
+    first: Date,
+) -> Result<f64, CliError> {
+    let (month_start, month_end) = month_range(first);
+    let (spent, _, _) = spending(api, config, month_start, month_end).await?;
+    Ok(spent)
+}
+
+/// Calculates spending so far this month and projects it to the end of the
+/// month.
+pub async fn budget_status(
+    api: &GlowmarktApi,
+    config: &BudgetConfig,
+) -> Result<BudgetStatus, CliError> {
+    let now = api.clock().now();
+    let today = now.to_offset(uk_offset(now)).date();
+    let first = today.replace_day(1).unwrap();
+    let (month_start, month_end) = month_range(first);
+
+    let (spent, latest, notes) = spending(api, config, month_start, now).await?;
+
+    let projected = latest.map(|latest| {
+        let covered = (latest - month_start).as_seconds_f64();
+        let month = (month_end - month_start).as_seconds_f64();
+        spent * month / covered
+    });
+
+    Ok(BudgetStatus {
+        month: month_name(first),
+        budget: config.monthly,
+        spent,
+        remaining: config.monthly - spent,
+        over_budget: projected.unwrap_or(spent) > config.monthly,
+        projected,
+        notes,
+    })
+}
+
+pub async fn budget(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: BudgetArgs,
+    config: Option<BudgetConfig>,
+) -> Result<(), CliError> {
+    let config = config.ok_or_else(|| {
+        "No budget is configured, add a [budget] section to the config file".to_string()
+    })?;
+
+    let mut status = budget_status(&api, &config).await?;
+    if args.record {
+        record_forecast(&args.forecasts.path()?, &status, api.clock().now())?;
+    }
+
+    if status.over_budget {
+        log::warn!(
+            "Spending is projected to exceed the monthly budget of {}.",
+            status.budget
+        );
+    }
+
+    status.spent = round(status.spent, options.precision);
+    status.remaining = round(status.remaining, options.precision);
+    status.projected = status
+        .projected
+        .map(|projected| round(projected, options.precision));
+
+    println!("{}", to_string_pretty(&status).str_err()?);
+    Ok(())
+}
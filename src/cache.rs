@@ -0,0 +1,327 @@
+//! Caching of readings.
+//!
+//! [`CachedGlowmarktApi`] wraps a [`GlowmarktApi`] and keeps the readings it
+//! retrieves in a [`ReadingsStore`], only going to the API when the store has
+//! no readings for a request or the stored readings may since have changed.
+//!
+//! With the `sqlite` feature enabled [`SqliteStore`] keeps readings on disk
+//! between runs, tracking which ranges of time it holds so that only the parts
+//! of a request it doesn't cover are fetched.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    ops::Deref,
+    sync::Arc,
+    sync::Mutex,
+};
+
+use time::{Duration, OffsetDateTime};
+
+use crate::{Error, GlowmarktApi, GlowmarktClient, Reading, ReadingPeriod};
+
+/// Identifies a single request for readings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// The resource the readings are for.
+    pub resource_id: String,
+    /// The ISO-8601 duration of the reading period.
+    pub period: String,
+    /// The start of the requested range.
+    pub start: OffsetDateTime,
+    /// The end of the requested range.
+    pub end: OffsetDateTime,
+}
+
+/// Readings retrieved for a request.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    /// When the readings were retrieved from the API.
+    pub fetched: OffsetDateTime,
+    /// The readings.
+    pub readings: Vec<Reading>,
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// Readings held for part of a range of time.
+#[derive(Debug, Clone, Default)]
+pub struct StoredRange {
+    /// The stored readings within the range.
+    pub readings: Vec<Reading>,
+    /// The start times of the first and last readings of each part of the range
+    /// known to be complete, in order.
+    pub covered: Vec<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl StoredRange {
+    /// The parts of a range not covered, as the start times of their first and
+    /// last readings. Boundary readings are included in both neighbouring
+    /// parts so the gaps are always safe to request.
+    pub fn gaps(
+        &self,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Vec<(OffsetDateTime, OffsetDateTime)> {
+        let mut gaps = Vec::new();
+        let mut cursor = start;
+
+        for (covered_start, covered_end) in &self.covered {
+            if *covered_end < cursor {
+                continue;
+            }
+            if *covered_start > end {
+                break;
+            }
+            if *covered_start > cursor {
+                gaps.push((cursor, *covered_start));
+            }
+            cursor = cursor.max(*covered_end);
+        }
+
+        if cursor < end {
+            gaps.push((cursor, end));
+        }
+
+        gaps
+    }
+}
+
+/// Somewhere to keep readings between requests.
+pub trait ReadingsStore: Debug + Send + Sync {
+    /// Loads the readings stored for a request.
+    fn load(&self, key: &CacheKey) -> Option<CacheEntry>;
+
+    /// Stores the readings for a request, replacing any previously stored.
+    fn save(&self, key: CacheKey, entry: CacheEntry);
+
+    /// Loads the readings stored for a resource within a range, along with the
+    /// parts of the range they completely cover.
+    ///
+    /// Stores that only keep whole requests return `None`, the default.
+    fn load_range(
+        &self,
+        _resource_id: &str,
+        _period: &str,
+        _start: OffsetDateTime,
+        _end: OffsetDateTime,
+    ) -> Option<StoredRange> {
+        None
+    }
+
+    /// Stores the readings for a resource over a range that is complete and
+    /// won't change, merging them with any already stored.
+    fn save_range(
+        &self,
+        _resource_id: &str,
+        _period: &str,
+        _start: OffsetDateTime,
+        _end: OffsetDateTime,
+        _readings: &[Reading],
+    ) {
+    }
+}
+
+/// A store that keeps readings in memory for the life of the process.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+}
+
+impl ReadingsStore for MemoryStore {
+    fn load(&self, key: &CacheKey) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn save(&self, key: CacheKey, entry: CacheEntry) {
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+}
+
+/// A [`GlowmarktApi`] that caches the readings it retrieves.
+///
+/// Everything other than readings is passed straight through to the wrapped
+/// API.
+#[derive(Debug, Clone)]
+pub struct CachedGlowmarktApi {
+    api: GlowmarktApi,
+    store: Arc<dyn ReadingsStore>,
+    max_age: Duration,
+    settle_time: Duration,
+}
+
+impl CachedGlowmarktApi {
+    /// Wraps an API with a store for readings.
+    pub fn new<S: ReadingsStore + 'static>(api: GlowmarktApi, store: S) -> Self {
+        Self {
+            api,
+            store: Arc::new(store),
+            max_age: Duration::hours(1),
+            settle_time: Duration::days(2),
+        }
+    }
+
+    /// Sets how long readings that may still change are cached for. Defaults
+    /// to an hour.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sets how long after the end of a range its readings are assumed to be
+    /// final. Readings fetched after this are cached indefinitely. Defaults to
+    /// two days, meters that are slow to deliver data may need longer.
+    pub fn settle_time(mut self, settle_time: Duration) -> Self {
+        self.settle_time = settle_time;
+        self
+    }
+
+    /// The wrapped API.
+    pub fn api(&self) -> &GlowmarktApi {
+        &self.api
+    }
+
+    /// Completes the readings held by a range-aware store, fetching the span
+    /// from the first gap to the last and storing whatever has settled.
+    async fn readings_from_range(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+        stored: StoredRange,
+    ) -> Result<Vec<Reading>, Error> {
+        let gaps = stored.gaps(*start, *end);
+        let (Some((fetch_start, _)), Some((_, fetch_end))) = (gaps.first(), gaps.last()) else {
+            log::trace!("Using stored readings for {}", resource_id);
+            return Ok(stored.readings);
+        };
+
+        let fetched = self.api.clock().now();
+        let readings = self
+            .api
+            .readings(resource_id, fetch_start, fetch_end, period)
+            .await?;
+
+        let settled = (*fetch_end).min(fetched - self.settle_time);
+        if settled >= *fetch_start {
+            let complete: Vec<Reading> = readings
+                .iter()
+                .filter(|reading| reading.start <= settled)
+                .cloned()
+                .collect();
+            self.store.save_range(
+                resource_id,
+                period.iso_duration(),
+                *fetch_start,
+                settled,
+                &complete,
+            );
+        }
+
+        let mut merged: BTreeMap<OffsetDateTime, Reading> = stored
+            .readings
+            .into_iter()
+            .map(|reading| (reading.start, reading))
+            .collect();
+        for reading in readings {
+            merged.insert(reading.start, reading);
+        }
+
+        Ok(merged.into_values().collect())
+    }
+
+    fn is_fresh(&self, key: &CacheKey, entry: &CacheEntry) -> bool {
+        entry.fetched >= key.end + self.settle_time
+            || self.api.clock().now() < entry.fetched + self.max_age
+    }
+
+    /// Retrieves the readings for a single resource, from the store if
+    /// possible.
+    ///
+    /// See [`GlowmarktApi::readings`].
+    pub async fn readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<Reading>, Error> {
+        GlowmarktClient::readings(self, resource_id, start, end, period).await
+    }
+
+    /// Retrieves the readings for a single resource over any length of time,
+    /// from the store where possible.
+    ///
+    /// See [`GlowmarktApi::readings_range`].
+    pub async fn readings_range(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<Reading>, Error> {
+        GlowmarktClient::readings_range(self, resource_id, start, end, period).await
+    }
+}
+
+impl Deref for CachedGlowmarktApi {
+    type Target = GlowmarktApi;
+
+    fn deref(&self) -> &GlowmarktApi {
+        &self.api
+    }
+}
+
+impl GlowmarktClient for CachedGlowmarktApi {
+    fn concurrency(&self) -> usize {
+        self.api.concurrency()
+    }
+
+    async fn readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<Reading>, Error> {
+        if let Some(stored) =
+            self.store
+                .load_range(resource_id, period.iso_duration(), *start, *end)
+        {
+            return self
+                .readings_from_range(resource_id, start, end, period, stored)
+                .await;
+        }
+
+        let key = CacheKey {
+            resource_id: resource_id.to_owned(),
+            period: period.iso_duration().to_owned(),
+            start: *start,
+            end: *end,
+        };
+
+        if let Some(entry) = self.store.load(&key) {
+            if self.is_fresh(&key, &entry) {
+                log::trace!("Using cached readings for {}", resource_id);
+                return Ok(entry.readings);
+            }
+        }
+
+        let fetched = self.api.clock().now();
+        let readings = self.api.readings(resource_id, start, end, period).await?;
+        self.store.save(
+            key,
+            CacheEntry {
+                fetched,
+                readings: readings.clone(),
+            },
+        );
+
+        Ok(readings)
+    }
+}
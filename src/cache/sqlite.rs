@@ -0,0 +1,263 @@
+use std::{path::Path, sync::Mutex};
+
+use rusqlite::{params, Connection};
+use time::OffsetDateTime;
+
+use super::{CacheEntry, CacheKey, ReadingsStore, StoredRange};
+use crate::{Error, ErrorKind, Reading, ReadingPeriod};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS readings (
+    resource_id TEXT NOT NULL,
+    period TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    value REAL NOT NULL,
+    quality TEXT,
+    PRIMARY KEY (resource_id, period, start)
+);
+CREATE TABLE IF NOT EXISTS coverage (
+    resource_id TEXT NOT NULL,
+    period TEXT NOT NULL,
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS coverage_resource ON coverage (resource_id, period);
+";
+
+fn storage_error(e: rusqlite::Error) -> Error {
+    Error::new(ErrorKind::Storage, e.to_string())
+}
+
+fn timestamp(seconds: i64) -> Option<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp(seconds).ok()
+}
+
+/// A store that keeps readings in a SQLite database so they survive between
+/// runs.
+///
+/// Readings are kept by the range of time they cover rather than by request,
+/// so a request overlapping readings fetched by an earlier one only fetches
+/// the parts not already held. Only readings old enough to be final are
+/// stored.
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    /// Opens the database at the given path, creating it if necessary.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::from_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// Opens a database held in memory, mostly useful for testing.
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn from_connection(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(SCHEMA).map_err(storage_error)?;
+
+        // Databases created before quality markers were kept lack the column.
+        let has_quality = connection
+            .prepare("SELECT 1 FROM pragma_table_info('readings') WHERE name = 'quality'")
+            .and_then(|mut statement| statement.exists([]))
+            .map_err(storage_error)?;
+        if !has_quality {
+            connection
+                .execute("ALTER TABLE readings ADD COLUMN quality TEXT", [])
+                .map_err(storage_error)?;
+        }
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Loads the readings stored for a resource within a range, along with
+    /// the parts of the range they completely cover.
+    ///
+    /// Unlike [`ReadingsStore::load_range`] this fails if the database can't
+    /// be read rather than treating it as empty.
+    pub fn read_range(
+        &self,
+        resource_id: &str,
+        period: ReadingPeriod,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<StoredRange, Error> {
+        self.try_load_range(resource_id, period.iso_duration(), period, start, end)
+            .map_err(storage_error)
+    }
+
+    fn try_load_range(
+        &self,
+        resource_id: &str,
+        period: &str,
+        reading_period: ReadingPeriod,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> rusqlite::Result<StoredRange> {
+        let connection = self.connection.lock().unwrap();
+
+        let mut statement = connection.prepare_cached(
+            "SELECT start, end FROM coverage
+            WHERE resource_id = ?1 AND period = ?2 AND end >= ?3 AND start <= ?4
+            ORDER BY start",
+        )?;
+        let covered = statement
+            .query_map(
+                params![
+                    resource_id,
+                    period,
+                    start.unix_timestamp(),
+                    end.unix_timestamp()
+                ],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )?
+            .filter_map(|row| match row {
+                Ok((start, end)) => Some(Ok((timestamp(start)?, timestamp(end)?))),
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut statement = connection.prepare_cached(
+            "SELECT start, value, quality FROM readings
+            WHERE resource_id = ?1 AND period = ?2 AND start >= ?3 AND start <= ?4
+            ORDER BY start",
+        )?;
+        let readings = statement
+            .query_map(
+                params![
+                    resource_id,
+                    period,
+                    start.unix_timestamp(),
+                    end.unix_timestamp()
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, f64>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                    ))
+                },
+            )?
+            .filter_map(|row| match row {
+                Ok((start, value, quality)) => Some(Ok(Reading {
+                    start: timestamp(start)?,
+                    period: reading_period,
+                    value: value as f32,
+                    quality: quality.and_then(|quality| serde_json::from_str(&quality).ok()),
+                })),
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(StoredRange { readings, covered })
+    }
+
+    fn try_save_range(
+        &self,
+        resource_id: &str,
+        period: &str,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        readings: &[Reading],
+        period_seconds: i64,
+    ) -> rusqlite::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+
+        {
+            let mut statement = transaction.prepare_cached(
+                "INSERT OR REPLACE INTO readings (resource_id, period, start, value, quality)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for reading in readings {
+                statement.execute(params![
+                    resource_id,
+                    period,
+                    reading.start.unix_timestamp(),
+                    reading.value as f64,
+                    reading.quality.as_ref().map(|quality| quality.to_string())
+                ])?;
+            }
+        }
+
+        // Merge the new range with any it overlaps or touches. Ranges hold the
+        // starts of their first and last readings so touching ranges are a
+        // period apart, at most the longest the period can be.
+        let (start, end) = transaction.query_row(
+            "SELECT MIN(start), MAX(end) FROM (
+                SELECT start, end FROM coverage
+                WHERE resource_id = ?1 AND period = ?2 AND end >= ?3 - ?5 AND start <= ?4 + ?5
+                UNION ALL SELECT ?3, ?4
+            )",
+            params![
+                resource_id,
+                period,
+                start.unix_timestamp(),
+                end.unix_timestamp(),
+                period_seconds
+            ],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+        )?;
+        transaction.execute(
+            "DELETE FROM coverage
+            WHERE resource_id = ?1 AND period = ?2 AND end >= ?3 AND start <= ?4",
+            params![resource_id, period, start, end],
+        )?;
+        transaction.execute(
+            "INSERT INTO coverage (resource_id, period, start, end) VALUES (?1, ?2, ?3, ?4)",
+            params![resource_id, period, start, end],
+        )?;
+
+        transaction.commit()
+    }
+}
+
+impl ReadingsStore for SqliteStore {
+    /// Whole requests aren't kept, see [`ReadingsStore::load_range`].
+    fn load(&self, _key: &CacheKey) -> Option<CacheEntry> {
+        None
+    }
+
+    /// Whole requests aren't kept, see [`ReadingsStore::save_range`].
+    fn save(&self, _key: CacheKey, _entry: CacheEntry) {}
+
+    fn load_range(
+        &self,
+        resource_id: &str,
+        period: &str,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Option<StoredRange> {
+        let reading_period = period.parse::<ReadingPeriod>().ok()?;
+        match self.try_load_range(resource_id, period, reading_period, start, end) {
+            Ok(stored) => Some(stored),
+            Err(e) => {
+                log::warn!("Failed to read cached readings: {}", e);
+                Some(StoredRange::default())
+            }
+        }
+    }
+
+    fn save_range(
+        &self,
+        resource_id: &str,
+        period: &str,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        readings: &[Reading],
+    ) {
+        let period_seconds = period
+            .parse::<ReadingPeriod>()
+            .map(|period| period.max_duration().whole_seconds())
+            .unwrap_or_default();
+        if let Err(e) =
+            self.try_save_range(resource_id, period, start, end, readings, period_seconds)
+        {
+            log::warn!("Failed to cache readings: {}", e);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Where days and weeks begin when aligning dates to reading periods.
+//!
+//! The API buckets daily and longer readings from midnight UTC with weeks
+//! starting on Monday, which is what [`Calendar::default`] matches. Reporting
+//! weeks that start on a Sunday, or days that start at 6am to match a tariff,
+//! can be used instead but readings fetched at daily or longer periods will
+//! then be aggregated over the shifted ranges requested rather than the API's
+//! usual buckets.
+
+use time::{Duration, Month, OffsetDateTime, Time, UtcOffset, Weekday};
+
+use crate::{clear_seconds, ReadingPeriod};
+
+/// The start of the week and of each day used when aligning dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calendar {
+    /// The first day of the week.
+    pub week_start: Weekday,
+    /// The hour, in UTC, at which each day starts.
+    pub day_start_hour: u8,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self {
+            week_start: Weekday::Monday,
+            day_start_hour: 0,
+        }
+    }
+}
+
+impl Calendar {
+    /// Aligns the given date to the start of a reading period.
+    ///
+    /// Days, weeks, months and years are aligned in UTC, as that is how the
+    /// API buckets readings, so for those periods the result is always
+    /// returned in UTC. Minute, half-hour and hour alignment preserve the
+    /// offset of the given date.
+    pub fn align(&self, date: OffsetDateTime, period: ReadingPeriod) -> OffsetDateTime {
+        let day_start = Duration::hours(self.day_start_hour as i64);
+        let utc_day = || (date.to_offset(UtcOffset::UTC) - day_start).replace_time(Time::MIDNIGHT);
+
+        let day = match period {
+            ReadingPeriod::Minute => return clear_seconds(date),
+            ReadingPeriod::HalfHour => {
+                let date = clear_seconds(date);
+                return date.replace_minute(date.minute() / 30 * 30).unwrap();
+            }
+            ReadingPeriod::Hour => return clear_seconds(date).replace_minute(0).unwrap(),
+            ReadingPeriod::Day => utc_day(),
+            ReadingPeriod::Week => {
+                let day = utc_day();
+                let days = (day.weekday().number_days_from_monday() + 7
+                    - self.week_start.number_days_from_monday())
+                    % 7;
+                day - Duration::days(days as i64)
+            }
+            ReadingPeriod::Month => utc_day().replace_day(1).unwrap(),
+            ReadingPeriod::Year => utc_day()
+                .replace_day(1)
+                .unwrap()
+                .replace_month(Month::January)
+                .unwrap(),
+        };
+
+        day + day_start
+    }
+}
+
+/// Parses the name of a day of the week, e.g. `monday` or `sun`.
+pub fn parse_weekday(value: &str) -> Result<Weekday, String> {
+    let day = match value.trim().to_lowercase().as_str() {
+        "monday" | "mon" => Weekday::Monday,
+        "tuesday" | "tue" => Weekday::Tuesday,
+        "wednesday" | "wed" => Weekday::Wednesday,
+        "thursday" | "thu" => Weekday::Thursday,
+        "friday" | "fri" => Weekday::Friday,
+        "saturday" | "sat" => Weekday::Saturday,
+        "sunday" | "sun" => Weekday::Sunday,
+        _ => return Err(format!("Unknown day of the week '{}'", value)),
+    };
+
+    Ok(day)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{
+        macros::{datetime, offset},
+        Weekday,
+    };
+
+    use super::Calendar;
+    use crate::{align_to_period, ReadingPeriod};
+
+    fn sunday_weeks() -> Calendar {
+        Calendar {
+            week_start: Weekday::Sunday,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn bst_aligns_in_utc() {
+        // Still the 25th in UTC.
+        let date = datetime!(2023-03-26 00:30 +01:00);
+
+        let day = align_to_period(date, ReadingPeriod::Day);
+        assert_eq!(day, datetime!(2023-03-25 00:00 UTC));
+        assert_eq!(day.offset(), offset!(UTC));
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Week),
+            datetime!(2023-03-20 00:00 UTC)
+        );
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Month),
+            datetime!(2023-03-01 00:00 UTC)
+        );
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Year),
+            datetime!(2023-01-01 00:00 UTC)
+        );
+    }
+
+    #[test]
+    fn short_periods_keep_offset() {
+        let date = datetime!(2023-03-26 02:45:10 +01:00);
+
+        let half_hour = align_to_period(date, ReadingPeriod::HalfHour);
+        assert_eq!(half_hour, datetime!(2023-03-26 02:30 +01:00));
+        assert_eq!(half_hour.offset(), offset!(+01:00));
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Hour),
+            datetime!(2023-03-26 02:00 +01:00)
+        );
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Minute),
+            datetime!(2023-03-26 02:45 +01:00)
+        );
+    }
+
+    #[test]
+    fn end_of_long_month() {
+        let date = datetime!(2023-01-31 23:59:59 UTC);
+
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Day),
+            datetime!(2023-01-31 00:00 UTC)
+        );
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Month),
+            datetime!(2023-01-01 00:00 UTC)
+        );
+    }
+
+    #[test]
+    fn new_year_in_utc() {
+        // New Year's Day in the given offset but still December in UTC.
+        let date = datetime!(2024-01-01 00:30 +01:00);
+
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Month),
+            datetime!(2023-12-01 00:00 UTC)
+        );
+        assert_eq!(
+            align_to_period(date, ReadingPeriod::Year),
+            datetime!(2023-01-01 00:00 UTC)
+        );
+        assert_eq!(
+            align_to_period(datetime!(2024-01-01 00:00 UTC), ReadingPeriod::Year),
+            datetime!(2024-01-01 00:00 UTC)
+        );
+    }
+
+    #[test]
+    fn week_start() {
+        // A Saturday.
+        let saturday = datetime!(2023-03-25 12:00 UTC);
+        assert_eq!(
+            Calendar::default().align(saturday, ReadingPeriod::Week),
+            datetime!(2023-03-20 00:00 UTC)
+        );
+        assert_eq!(
+            sunday_weeks().align(saturday, ReadingPeriod::Week),
+            datetime!(2023-03-19 00:00 UTC)
+        );
+
+        let sunday = datetime!(2023-03-26 12:00 UTC);
+        assert_eq!(
+            Calendar::default().align(sunday, ReadingPeriod::Week),
+            datetime!(2023-03-20 00:00 UTC)
+        );
+        assert_eq!(
+            sunday_weeks().align(sunday, ReadingPeriod::Week),
+            datetime!(2023-03-26 00:00 UTC)
+        );
+
+        let monday = datetime!(2023-03-27 00:00 UTC);
+        assert_eq!(
+            Calendar::default().align(monday, ReadingPeriod::Week),
+            monday
+        );
+        assert_eq!(
+            sunday_weeks().align(monday, ReadingPeriod::Week),
+            datetime!(2023-03-26 00:00 UTC)
+        );
+    }
+
+    #[test]
+    fn day_start_hour() {
+        let calendar = Calendar {
+            day_start_hour: 6,
+            ..Default::default()
+        };
+        // Before 6am on the first of the month so still in February's last day.
+        let date = datetime!(2023-03-01 05:00 UTC);
+
+        assert_eq!(
+            calendar.align(date, ReadingPeriod::Day),
+            datetime!(2023-02-28 06:00 UTC)
+        );
+        assert_eq!(
+            calendar.align(date, ReadingPeriod::Month),
+            datetime!(2023-02-01 06:00 UTC)
+        );
+    }
+}
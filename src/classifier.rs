@@ -0,0 +1,137 @@
+//! Classifiers describe what a resource measures.
+//!
+//! The API uses dotted strings such as `electricity.consumption` or
+//! `gas.consumption.cost`. The common ones have their own variant and anything
+//! else is kept as [`Classifier::Other`].
+
+use std::{cmp::Ordering, convert::Infallible, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// What a resource measures.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Classifier {
+    /// `electricity.consumption`, electricity use in kWh.
+    ElectricityConsumption,
+    /// `electricity.consumption.cost`, the cost of electricity use in pence.
+    ElectricityConsumptionCost,
+    /// `electricity.export`, electricity exported to the grid in kWh.
+    ElectricityExport,
+    /// `electricity.export.cost`, payment for exported electricity in pence.
+    ElectricityExportCost,
+    /// `gas.consumption`, gas use in kWh.
+    GasConsumption,
+    /// `gas.consumption.cost`, the cost of gas use in pence.
+    GasConsumptionCost,
+    /// Any other classifier.
+    Other(String),
+}
+
+impl Classifier {
+    /// The classifier as used by the API.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Classifier::ElectricityConsumption => "electricity.consumption",
+            Classifier::ElectricityConsumptionCost => "electricity.consumption.cost",
+            Classifier::ElectricityExport => "electricity.export",
+            Classifier::ElectricityExportCost => "electricity.export.cost",
+            Classifier::GasConsumption => "gas.consumption",
+            Classifier::GasConsumptionCost => "gas.consumption.cost",
+            Classifier::Other(classifier) => classifier,
+        }
+    }
+
+    /// The dot separated parts of the classifier.
+    pub fn parts(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.as_str().split('.')
+    }
+
+    /// The first part of the classifier, normally the fuel such as
+    /// `electricity`.
+    pub fn fuel(&self) -> &str {
+        self.parts().next().unwrap_or_default()
+    }
+
+    /// Whether this measures the use of a fuel, e.g. `gas.consumption`.
+    pub fn is_consumption(&self) -> bool {
+        self.parts().skip(1).eq(["consumption"])
+    }
+
+    /// Whether this measures energy exported to the grid, e.g.
+    /// `electricity.export`.
+    pub fn is_export(&self) -> bool {
+        self.parts().skip(1).eq(["export"])
+    }
+
+    /// Whether this measures a cost in pence rather than energy.
+    pub fn is_cost(&self) -> bool {
+        self.parts().count() > 1 && self.parts().next_back() == Some("cost")
+    }
+
+    /// The classifier of the resource measuring the cost of this one, e.g.
+    /// `electricity.consumption.cost` for `electricity.consumption`. Only
+    /// consumption and export have costs.
+    pub fn cost(&self) -> Option<Classifier> {
+        (self.is_consumption() || self.is_export())
+            .then(|| Classifier::from(format!("{}.cost", self.as_str())))
+    }
+}
+
+impl From<&str> for Classifier {
+    fn from(classifier: &str) -> Self {
+        match classifier {
+            "electricity.consumption" => Classifier::ElectricityConsumption,
+            "electricity.consumption.cost" => Classifier::ElectricityConsumptionCost,
+            "electricity.export" => Classifier::ElectricityExport,
+            "electricity.export.cost" => Classifier::ElectricityExportCost,
+            "gas.consumption" => Classifier::GasConsumption,
+            "gas.consumption.cost" => Classifier::GasConsumptionCost,
+            _ => Classifier::Other(classifier.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Classifier {
+    fn from(classifier: String) -> Self {
+        match Classifier::from(classifier.as_str()) {
+            Classifier::Other(_) => Classifier::Other(classifier),
+            known => known,
+        }
+    }
+}
+
+impl From<Classifier> for String {
+    fn from(classifier: Classifier) -> Self {
+        match classifier {
+            Classifier::Other(classifier) => classifier,
+            known => known.as_str().to_owned(),
+        }
+    }
+}
+
+impl FromStr for Classifier {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl fmt::Display for Classifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl PartialOrd for Classifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Classifier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
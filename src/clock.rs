@@ -0,0 +1,45 @@
+//! Sources of the current time.
+//!
+//! Anything that depends on the current time takes a [`Clock`] so that it can
+//! be tested deterministically or run as if at a different time.
+
+use std::fmt;
+
+use time::{Duration, OffsetDateTime};
+
+/// A source of the current time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// The system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// A clock that always returns the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub OffsetDateTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> OffsetDateTime {
+        self.0
+    }
+}
+
+/// The system clock adjusted by a fixed amount, for example to correct for a
+/// known skew.
+#[derive(Debug, Clone, Copy)]
+pub struct SkewedClock(pub Duration);
+
+impl Clock for SkewedClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc() + self.0
+    }
+}
@@ -0,0 +1,85 @@
+//! Sharing the result of identical requests made at the same time.
+
+use std::{collections::HashMap, hash::Hash, sync::Arc, sync::Mutex};
+
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+
+use crate::Error;
+
+type Inflight<V> = Shared<BoxFuture<'static, Result<V, Arc<Error>>>>;
+
+/// Tracks requests that are in progress so that an identical request made
+/// before the first completes waits for its result rather than going to the
+/// API again. Shared by every clone of an API.
+#[derive(Debug)]
+pub(crate) struct Coalescer<K, V: Clone> {
+    inflight: Mutex<HashMap<K, Inflight<V>>>,
+}
+
+impl<K, V: Clone> Default for Coalescer<K, V> {
+    fn default() -> Self {
+        Self {
+            inflight: Default::default(),
+        }
+    }
+}
+
+/// Forgets a request once whoever started it is done with it, even if they
+/// gave up waiting.
+struct Forget<'a, K: Eq + Hash, V: Clone> {
+    coalescer: &'a Coalescer<K, V>,
+    key: &'a K,
+    future: &'a Inflight<V>,
+}
+
+impl<K: Eq + Hash, V: Clone> Drop for Forget<'_, K, V> {
+    fn drop(&mut self) {
+        let mut inflight = self.coalescer.inflight.lock().unwrap();
+        if inflight
+            .get(self.key)
+            .is_some_and(|future| future.ptr_eq(self.future))
+        {
+            inflight.remove(self.key);
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone + Send + Sync + 'static> Coalescer<K, V> {
+    /// Runs the request for a key unless one is already in progress, in which
+    /// case that request's result is returned instead.
+    pub(crate) async fn run<F>(&self, key: K, request: F) -> Result<V, Error>
+    where
+        F: FnOnce() -> BoxFuture<'static, Result<V, Error>>,
+    {
+        let (future, started) = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(&key) {
+                Some(future) => (future.clone(), false),
+                None => {
+                    let future = request()
+                        .map(|result| result.map_err(Arc::new))
+                        .boxed()
+                        .shared();
+                    inflight.insert(key.clone(), future.clone());
+                    (future, true)
+                }
+            }
+        };
+
+        if !started {
+            log::trace!("Waiting for an identical request already in progress");
+            return future.await.map_err(|e| e.duplicate());
+        }
+
+        let _forget = Forget {
+            coalescer: self,
+            key: &key,
+            future: &future,
+        };
+
+        future.clone().await.map_err(|e| e.duplicate())
+    }
+}
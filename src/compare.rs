@@ -0,0 +1,435 @@
+//! Compares a resource's consumption and cost over two windows of time.
+
+use std::io::{stdout, Write};
+
+use clap::ValueEnum;
+use glowmarkt::{cost::Rates, format::round, settlement::uk_offset, GlowmarktApi, ReadingPeriod};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{macros::format_description, Duration, OffsetDateTime};
+
+use crate::{
+    hint::CliError,
+    lookup::resolve_resource,
+    output::{OutputOptions, ReportFormat},
+    parse_date, parse_end_date, ErrorStr,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ComparePeriod {
+    /// Yesterday against the day before, hour by hour.
+    Day,
+    /// The last complete week against the week before, day by day.
+    Week,
+    /// The last complete month against the month before, day by day.
+    Month,
+}
+
+#[derive(clap::Args)]
+pub struct CompareArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// Compare the most recent complete period with the one before it.
+    #[clap(long, value_enum, required_unless_present = "from")]
+    period: Option<ComparePeriod>,
+    /// The start of the window to compare, instead of --period.
+    #[clap(long, allow_hyphen_values = true, conflicts_with = "period")]
+    from: Option<String>,
+    /// The end of the window to compare (defaults to now).
+    #[clap(long, allow_hyphen_values = true, requires = "from")]
+    to: Option<String>,
+    /// The start of the window to compare against. It has the same length as
+    /// the first window.
+    #[clap(long, allow_hyphen_values = true, requires = "from")]
+    baseline: Option<String>,
+    /// The length of each bucket the windows are compared in (30m, 1h, 1d or
+    /// 1w). Defaults to hours for --period day and days otherwise.
+    #[clap(long)]
+    bucket: Option<ReadingPeriod>,
+    /// The unit rate in pence per kWh to use if the resource has no tariff.
+    #[clap(long)]
+    unit_rate: Option<f64>,
+    /// The resource to compare, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+}
+
+/// The totals of one bucket or a whole window.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Usage {
+    consumption: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+/// The change between the baseline and the current window.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Change {
+    consumption: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consumption_percent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost_percent: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bucket {
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    baseline_start: OffsetDateTime,
+    current: Usage,
+    baseline: Usage,
+    change: Change,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Comparison {
+    resource_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    from: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    to: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    baseline_from: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    baseline_to: OffsetDateTime,
+    current: Usage,
+    baseline: Usage,
+    change: Change,
+    buckets: Vec<Bucket>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+fn percent(current: f64, baseline: f64) -> Option<f64> {
+    (baseline != 0.0).then(|| (current - baseline) / baseline * 100.0)
+}
+
+fn change(current: &Usage, baseline: &Usage, precision: Option<u32>) -> Change {
+    let cost = current.cost.zip(baseline.cost);
+    Change {
+        consumption: round(current.consumption - baseline.consumption, precision),
+        consumption_percent: percent(current.consumption, baseline.consumption)
+            .map(|p| round(p, Some(1))),
+        cost: cost.map(|(current, baseline)| round(current - baseline, precision)),
+        cost_percent: cost
+            .and_then(|(current, baseline)| percent(current, baseline))
+            .map(|p| round(p, Some(1))),
+    }
+}
+
+fn bucket_width(bucket: ReadingPeriod) -> Result<Duration, CliError> {
+    match bucket {
+        ReadingPeriod::HalfHour => Ok(Duration::minutes(30)),
+        ReadingPeriod::Hour => Ok(Duration::hours(1)),
+        ReadingPeriod::Day => Ok(Duration::days(1)),
+        ReadingPeriod::Week => Ok(Duration::weeks(1)),
+        period => Err(format!("Windows can't be compared in buckets of {}", period).into()),
+    }
+}
+
+fn previous_month(date: OffsetDateTime) -> OffsetDateTime {
+    let previous = date.month().previous();
+    let year = if previous == time::Month::December {
+        date.year() - 1
+    } else {
+        date.year()
+    };
+
+    date.replace_day(1)
+        .and_then(|date| date.replace_year(year))
+        .and_then(|date| date.replace_month(previous))
+        .unwrap()
+}
+
+/// The start and end of the current and baseline windows.
+type Windows = (
+    (OffsetDateTime, OffsetDateTime),
+    (OffsetDateTime, OffsetDateTime),
+);
+
+fn windows(api: &GlowmarktApi, args: &CompareArgs) -> Result<Windows, CliError> {
+    let calendar = api.calendar();
+    let now = api.clock().now();
+
+    let windows = match (args.period, &args.from) {
+        (_, Some(from)) => {
+            let start = parse_date(from.clone(), ReadingPeriod::HalfHour, api)?;
+            let end = parse_end_date(args.to.clone(), ReadingPeriod::HalfHour, api)?;
+            let baseline = match args.baseline {
+                Some(ref baseline) => parse_date(baseline.clone(), ReadingPeriod::HalfHour, api)?,
+                None => start - (end - start),
+            };
+            ((start, end), (baseline, baseline + (end - start)))
+        }
+        (Some(ComparePeriod::Day), None) => {
+            let end = calendar.align(now, ReadingPeriod::Day);
+            let start = end - Duration::days(1);
+            ((start, end), (start - Duration::days(1), start))
+        }
+        (Some(ComparePeriod::Week), None) => {
+            let end = calendar.align(now, ReadingPeriod::Week);
+            let start = end - Duration::weeks(1);
+            ((start, end), (start - Duration::weeks(1), start))
+        }
+        (Some(ComparePeriod::Month), None) => {
+            let end = calendar.align(now, ReadingPeriod::Month);
+            let start = previous_month(end);
+            ((start, end), (previous_month(start), start))
+        }
+        (None, None) => return Err("Pass either --period or --from".to_string().into()),
+    };
+
+    if windows.0 .0 >= windows.0 .1 {
+        return Err("The window to compare is empty".to_string().into());
+    }
+    Ok(windows)
+}
+
+/// Fetches a window's half-hourly readings and totals them into buckets.
+async fn buckets(
+    api: &GlowmarktApi,
+    resource_id: &str,
+    (start, end): (OffsetDateTime, OffsetDateTime),
+    width: Duration,
+    rates: Option<&Rates>,
+) -> Result<Vec<Usage>, CliError> {
+    // The window excludes the reading starting at its end.
+    let last = end - Duration::minutes(30);
+    let readings = api
+        .readings_range(resource_id, &start, &last, ReadingPeriod::HalfHour)
+        .await?;
+
+    let count = ((end - start).whole_seconds() as f64 / width.whole_seconds() as f64).ceil();
+    let mut buckets: Vec<Usage> = (0..count as usize)
+        .map(|_| Usage {
+            consumption: 0.0,
+            cost: rates.map(|_| 0.0),
+        })
+        .collect();
+
+    for reading in readings
+        .iter()
+        .filter(|r| r.start >= start && r.start < end)
+    {
+        let index = ((reading.start - start).whole_seconds() / width.whole_seconds()) as usize;
+        let Some(bucket) = buckets.get_mut(index) else {
+            continue;
+        };
+
+        let value = reading.value as f64;
+        bucket.consumption += value;
+        if let (Some(cost), Some(rates)) = (bucket.cost.as_mut(), rates) {
+            *cost += value * rates.rate_at(reading.start).unwrap_or_default();
+        }
+    }
+
+    Ok(buckets)
+}
+
+fn total(buckets: &[Usage]) -> Usage {
+    Usage {
+        consumption: buckets.iter().map(|bucket| bucket.consumption).sum(),
+        cost: buckets.iter().map(|bucket| bucket.cost).sum(),
+    }
+}
+
+fn rounded(usage: &Usage, precision: Option<u32>) -> Usage {
+    Usage {
+        consumption: round(usage.consumption, precision),
+        cost: usage.cost.map(|cost| round(cost, precision)),
+    }
+}
+
+fn print_text(comparison: &Comparison) -> Result<(), CliError> {
+    let time = format_description!("[year]-[month]-[day] [hour]:[minute]");
+    let local = |date: OffsetDateTime| date.to_offset(uk_offset(date)).format(&time).str_err();
+    let percent = |percent: Option<f64>| {
+        percent
+            .map(|p| format!("{:+}%", p))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let costed = comparison.current.cost.is_some();
+
+    let mut out = stdout().lock();
+    writeln!(
+        out,
+        "{} to {} against {} to {}",
+        local(comparison.from)?,
+        local(comparison.to)?,
+        local(comparison.baseline_from)?,
+        local(comparison.baseline_to)?
+    )
+    .str_err()?;
+    writeln!(
+        out,
+        "Consumption: {} against {}, {:+} ({})",
+        comparison.current.consumption,
+        comparison.baseline.consumption,
+        comparison.change.consumption,
+        percent(comparison.change.consumption_percent)
+    )
+    .str_err()?;
+    if let (Some(current), Some(baseline), Some(change)) = (
+        comparison.current.cost,
+        comparison.baseline.cost,
+        comparison.change.cost,
+    ) {
+        writeln!(
+            out,
+            "Cost: {}p against {}p, {:+}p ({})",
+            current,
+            baseline,
+            change,
+            percent(comparison.change.cost_percent)
+        )
+        .str_err()?;
+    }
+    for note in &comparison.notes {
+        writeln!(out, "Note: {}", note).str_err()?;
+    }
+
+    writeln!(out).str_err()?;
+    write!(
+        out,
+        "{:<16}  {:>10}  {:>10}  {:>10}  {:>8}",
+        "Start", "Current", "Baseline", "Change", "%"
+    )
+    .str_err()?;
+    if costed {
+        write!(out, "  {:>10}", "Cost change").str_err()?;
+    }
+    writeln!(out).str_err()?;
+
+    for bucket in &comparison.buckets {
+        write!(
+            out,
+            "{:<16}  {:>10}  {:>10}  {:>+10}  {:>8}",
+            local(bucket.start)?,
+            bucket.current.consumption,
+            bucket.baseline.consumption,
+            bucket.change.consumption,
+            percent(bucket.change.consumption_percent)
+        )
+        .str_err()?;
+        if let Some(cost) = bucket.change.cost {
+            write!(out, "  {:>+10}", cost).str_err()?;
+        }
+        writeln!(out).str_err()?;
+    }
+
+    Ok(())
+}
+
+pub async fn compare(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: CompareArgs,
+) -> Result<(), CliError> {
+    let ((start, end), (baseline_start, baseline_end)) = windows(&api, &args)?;
+    let width = match (args.bucket, args.period) {
+        (Some(bucket), _) => bucket_width(bucket)?,
+        (None, Some(ComparePeriod::Day)) => Duration::hours(1),
+        (None, _) => Duration::days(1),
+    };
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+
+    let mut notes = Vec::new();
+    let tariff = match api.latest_tariff(&resource_id).await {
+        Ok(tariff) => tariff,
+        Err(e) if e.is_unavailable() => None,
+        Err(e) => return Err(e.into()),
+    };
+    let rates = match (tariff.as_ref().and_then(Rates::from_tariff), args.unit_rate) {
+        (Some(rates), _) => Some(rates),
+        (None, Some(unit_rate)) => Some(Rates::flat(unit_rate, 0.0)),
+        (None, None) => {
+            notes.push(format!(
+                "Resource {} has no usable tariff so only consumption is compared, pass \
+                --unit-rate to cost it.",
+                resource_id
+            ));
+            None
+        }
+    };
+    if rates.is_some() {
+        notes.push(
+            "Both windows are costed at the current tariff, standing charges are excluded."
+                .to_string(),
+        );
+    }
+
+    let current = buckets(&api, &resource_id, (start, end), width, rates.as_ref()).await?;
+    let baseline = buckets(
+        &api,
+        &resource_id,
+        (baseline_start, baseline_end),
+        width,
+        rates.as_ref(),
+    )
+    .await?;
+
+    // Text output is hard to read with long fractions.
+    let precision = match args.format {
+        ReportFormat::Text => options.precision.or(Some(3)),
+        ReportFormat::Json => options.precision,
+    };
+    let current_total = total(&current);
+    let baseline_total = total(&baseline);
+
+    // Windows such as months can differ in length, buckets missing from the
+    // shorter window count as zero.
+    let count = current.len().max(baseline.len());
+    let buckets = (0..count)
+        .map(|index| {
+            let current = current.get(index).map(|u| rounded(u, precision));
+            let baseline = baseline.get(index).map(|u| rounded(u, precision));
+            let current = current.unwrap_or_else(|| Usage {
+                consumption: 0.0,
+                cost: rates.as_ref().map(|_| 0.0),
+            });
+            let baseline = baseline.unwrap_or_else(|| Usage {
+                consumption: 0.0,
+                cost: rates.as_ref().map(|_| 0.0),
+            });
+            let offset = width * index as u32;
+
+            Bucket {
+                start: start + offset,
+                baseline_start: baseline_start + offset,
+                change: change(&current, &baseline, precision),
+                current,
+                baseline,
+            }
+        })
+        .collect();
+
+    let comparison = Comparison {
+        resource_id,
+        from: start,
+        to: end,
+        baseline_from: baseline_start,
+        baseline_to: baseline_end,
+        change: change(&current_total, &baseline_total, precision),
+        current: rounded(&current_total, precision),
+        baseline: rounded(&baseline_total, precision),
+        buckets,
+        notes,
+    };
+
+    match args.format {
+        ReportFormat::Text => print_text(&comparison),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&comparison).str_err()?);
+            Ok(())
+        }
+    }
+}
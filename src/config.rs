@@ -0,0 +1,75 @@
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// A monthly spending budget.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct BudgetConfig {
+    /// The monthly budget in pence.
+    pub monthly: f64,
+    /// The resources that count towards the budget. If empty all consumption
+    /// resources are included.
+    #[serde(default)]
+    pub resources: Vec<String>,
+}
+
+/// Where weeks and days start when aligning dates to reading periods.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CalendarConfig {
+    /// The first day of the week, e.g. `sunday`.
+    pub week_start: Option<String>,
+    /// The hour, in UTC, at which each day starts.
+    pub day_start_hour: Option<u8>,
+}
+
+/// Settings read from the configuration file.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub budget: Option<BudgetConfig>,
+    pub calendar: CalendarConfig,
+    /// Transforms applied to readings before they are written out, see
+    /// `--transform`.
+    pub transforms: Vec<String>,
+}
+
+impl Config {
+    /// The default location of the configuration file,
+    /// `$XDG_CONFIG_HOME/glowmarkt/config.toml`.
+    pub fn default_path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+
+        Some(base.join("glowmarkt").join("config.toml"))
+    }
+
+    /// Loads the configuration file.
+    ///
+    /// A missing file at the default location gives the default configuration
+    /// but an explicitly requested file must exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match Config::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(Config::default()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        toml::from_str(&data).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+}
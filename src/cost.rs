@@ -0,0 +1,424 @@
+//! Calculating the cost of consumption from a tariff.
+//!
+//! All costs are in pence, matching the units the API uses for tariffs.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Serialize, Serializer};
+use time::{Date, Duration, OffsetDateTime, Time};
+
+use crate::{
+    settlement::uk_offset,
+    tariff::{TierRate, TimeOfUseRate},
+    Reading, Resource, TariffData,
+};
+
+/// Whether a resource's readings are energy that can be costed from a tariff,
+/// rather than costs the API has already calculated.
+///
+/// Resources without a classifier are assumed to measure energy.
+pub fn is_costable(resource: &Resource) -> bool {
+    match resource.classifier {
+        Some(ref classifier) => {
+            !classifier.is_cost() && (classifier.is_consumption() || classifier.is_export())
+        }
+        None => true,
+    }
+}
+
+/// The rates used to cost consumption.
+#[derive(Debug, Clone, Default)]
+pub struct Rates {
+    /// The daily standing charge in pence.
+    pub standing_charge: f64,
+    /// The flat unit rate in pence per kWh, used outside of any time of use
+    /// bands.
+    pub unit_rate: Option<f64>,
+    /// Unit rates that apply during part of the day.
+    pub time_of_use: Vec<TimeOfUseRate>,
+    /// Unit rates for individual half hours, keyed by the start of the half
+    /// hour, as published for Agile tariffs. These take precedence over the
+    /// other rates.
+    pub half_hourly: BTreeMap<OffsetDateTime, f64>,
+}
+
+impl Rates {
+    /// Creates rates with a flat unit rate.
+    pub fn flat(unit_rate: f64, standing_charge: f64) -> Rates {
+        Rates {
+            standing_charge,
+            unit_rate: Some(unit_rate),
+            ..Default::default()
+        }
+    }
+
+    /// Creates rates that change every half hour, with no standing charge.
+    pub fn half_hourly<I>(rates: I) -> Rates
+    where
+        I: IntoIterator<Item = (OffsetDateTime, f64)>,
+    {
+        Rates {
+            half_hourly: rates.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Extracts the rates from a tariff.
+    ///
+    /// Tiered plans are costed at the rate of their first tier. Returns `None`
+    /// if the tariff includes no unit rates at all.
+    pub fn from_tariff(tariff: &TariffData) -> Option<Rates> {
+        let tier_rate = tariff
+            .tier_rates()
+            .into_iter()
+            .min_by(|a: &TierRate, b: &TierRate| {
+                a.threshold
+                    .unwrap_or_default()
+                    .total_cmp(&b.threshold.unwrap_or_default())
+            })
+            .map(|tier| tier.rate);
+
+        let rates = Rates {
+            standing_charge: tariff.standing_charge().unwrap_or_default(),
+            unit_rate: tariff.unit_rate().or(tier_rate),
+            time_of_use: tariff.time_of_use_rates(),
+            half_hourly: BTreeMap::new(),
+        };
+
+        if rates.unit_rate.is_none() && rates.time_of_use.is_empty() {
+            None
+        } else {
+            Some(rates)
+        }
+    }
+
+    /// Returns the unit rate that applies at the given instant.
+    ///
+    /// Time of use bands are matched against UK local time.
+    pub fn rate_at(&self, date: OffsetDateTime) -> Option<f64> {
+        self.lookup(date).1
+    }
+
+    /// Returns the band whose rate applies at the given instant.
+    pub fn band_at(&self, date: OffsetDateTime) -> RateBand {
+        self.lookup(date).0
+    }
+
+    fn lookup(&self, date: OffsetDateTime) -> (RateBand, Option<f64>) {
+        if let Some((_, rate)) = self
+            .half_hourly
+            .range(..=date)
+            .next_back()
+            .filter(|(start, _)| date < **start + Duration::minutes(30))
+        {
+            return (RateBand::HalfHourly, Some(*rate));
+        }
+
+        let time = date.to_offset(uk_offset(date)).time();
+        match self.time_of_use.iter().find(|band| band.applies_at(time)) {
+            Some(band) => (
+                RateBand::TimeOfUse {
+                    start: band.start,
+                    end: band.end,
+                },
+                Some(band.rate),
+            ),
+            None => match self.unit_rate {
+                Some(rate) => (RateBand::Standard, Some(rate)),
+                None => (RateBand::Unrated, None),
+            },
+        }
+    }
+}
+
+/// The part of a tariff that sets the rate for a half hour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RateBand {
+    /// A time of use band, such as the night rate of an Economy 7 tariff,
+    /// running between two UK local times.
+    TimeOfUse {
+        /// When the band starts.
+        start: Time,
+        /// When the band ends.
+        end: Time,
+    },
+    /// A rate set for the individual half hour, as on Agile tariffs.
+    HalfHourly,
+    /// The flat unit rate, used outside any time of use band.
+    Standard,
+    /// No rate applies.
+    Unrated,
+}
+
+impl fmt::Display for RateBand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateBand::TimeOfUse { start, end } => write!(
+                f,
+                "{:02}:{:02}-{:02}:{:02}",
+                start.hour(),
+                start.minute(),
+                end.hour(),
+                end.minute()
+            ),
+            RateBand::HalfHourly => f.write_str("half-hourly"),
+            RateBand::Standard => f.write_str("standard"),
+            RateBand::Unrated => f.write_str("unrated"),
+        }
+    }
+}
+
+impl Serialize for RateBand {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A reading along with its cost.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostedReading {
+    /// The start time of the period.
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The consumption in kWh.
+    pub consumption: f64,
+    /// The unit rate applied in pence per kWh.
+    pub rate: f64,
+    /// The cost of the consumption in pence.
+    pub cost: f64,
+}
+
+/// The cost of a set of readings.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Costs {
+    /// The cost of each reading.
+    pub readings: Vec<CostedReading>,
+    /// The number of UK local days the range covers.
+    pub days: u32,
+    /// The total standing charge in pence.
+    pub standing_charge: f64,
+    /// The total cost of consumption in pence.
+    pub consumption_cost: f64,
+    /// The total cost in pence.
+    pub total: f64,
+}
+
+/// The number of UK local days that the range from `start` up to, but not
+/// including, `end` falls on, even if only in part.
+pub fn uk_days(start: OffsetDateTime, end: OffsetDateTime) -> u32 {
+    if end <= start {
+        return 0;
+    }
+
+    let uk_date = |instant: OffsetDateTime| instant.to_offset(uk_offset(instant)).date();
+    let last = end - Duration::NANOSECOND;
+    ((uk_date(last) - uk_date(start)).whole_days() + 1) as u32
+}
+
+/// Costs readings from the range starting at `start` and ending before `end`
+/// using the given rates.
+///
+/// The standing charge is charged for every UK local day the range falls on,
+/// whether or not there are readings for it, so `end` should be the end of
+/// the last reading's period rather than its start.
+///
+/// Readings longer than half an hour are costed at the rate in force at their
+/// start. Readings that fall outside every time of use band with no flat rate
+/// to fall back on are costed at zero.
+pub fn cost(
+    readings: &[Reading],
+    rates: &Rates,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Costs {
+    let readings: Vec<CostedReading> = readings
+        .iter()
+        .map(|reading| {
+            let consumption = reading.value as f64;
+            let rate = rates.rate_at(reading.start).unwrap_or_default();
+            CostedReading {
+                start: reading.start,
+                consumption,
+                rate,
+                cost: consumption * rate,
+            }
+        })
+        .collect();
+
+    let days = uk_days(start, end);
+    let standing_charge = rates.standing_charge * days as f64;
+    let consumption_cost = readings.iter().map(|r| r.cost).sum::<f64>();
+
+    Costs {
+        readings,
+        days,
+        standing_charge,
+        consumption_cost,
+        total: standing_charge + consumption_cost,
+    }
+}
+
+/// The consumption and cost within one rate band.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BandUsage {
+    /// The band.
+    pub band: RateBand,
+    /// The consumption in kWh.
+    pub consumption: f64,
+    /// The cost of the consumption in pence.
+    pub cost: f64,
+}
+
+/// A UK local day's consumption and cost split between rate bands.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayBreakdown {
+    /// The day.
+    #[serde(serialize_with = "serialize_date")]
+    pub date: Date,
+    /// Each band with consumption that day, in the order of
+    /// [`RateBand`]'s variants and then by start time.
+    pub bands: Vec<BandUsage>,
+}
+
+fn serialize_date<S: Serializer>(date: &Date, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(date)
+}
+
+/// Splits the consumption and cost of readings between the rate bands they
+/// fall in for each UK local day, such as the day and night rates of an
+/// Economy 7 tariff.
+///
+/// Readings are placed in the band in force at their start so half-hourly
+/// readings give the most accurate split.
+pub fn breakdown(readings: &[Reading], rates: &Rates) -> Vec<DayBreakdown> {
+    let mut days: BTreeMap<Date, BTreeMap<RateBand, BandUsage>> = BTreeMap::new();
+
+    for reading in readings {
+        let date = reading.start.to_offset(uk_offset(reading.start)).date();
+        let (band, rate) = rates.lookup(reading.start);
+        let consumption = reading.value as f64;
+
+        let usage = days
+            .entry(date)
+            .or_default()
+            .entry(band)
+            .or_insert(BandUsage {
+                band,
+                consumption: 0.0,
+                cost: 0.0,
+            });
+        usage.consumption += consumption;
+        usage.cost += consumption * rate.unwrap_or_default();
+    }
+
+    days.into_iter()
+        .map(|(date, bands)| DayBreakdown {
+            date,
+            bands: bands.into_values().collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::{cost, uk_days, Rates};
+    use crate::{increase_by_period, Reading, ReadingPeriod};
+
+    fn readings(start: time::OffsetDateTime, count: usize, period: ReadingPeriod) -> Vec<Reading> {
+        let mut readings = Vec::new();
+        let mut current = start;
+        for _ in 0..count {
+            readings.push(Reading {
+                start: current,
+                period,
+                value: 1.0,
+                quality: None,
+            });
+            current = increase_by_period(current, period);
+        }
+        readings
+    }
+
+    #[test]
+    fn coarse_periods_charge_every_day() {
+        let rates = Rates::flat(10.0, 50.0);
+        let start = datetime!(2023-01-01 00:00 UTC);
+        let end = datetime!(2024-01-01 00:00 UTC);
+
+        for (period, count) in [
+            (ReadingPeriod::Year, 1),
+            (ReadingPeriod::Month, 12),
+            (ReadingPeriod::Day, 365),
+        ] {
+            let costs = cost(&readings(start, count, period), &rates, start, end);
+            assert_eq!(costs.days, 365, "{:?}", period);
+            assert_eq!(costs.standing_charge, 365.0 * 50.0);
+            assert_eq!(costs.consumption_cost, count as f64 * 10.0);
+        }
+    }
+
+    #[test]
+    fn days_without_readings_are_charged() {
+        let rates = Rates::flat(10.0, 50.0);
+        let start = datetime!(2023-06-01 00:00 +01:00);
+        let end = datetime!(2023-06-08 00:00 +01:00);
+
+        let costs = cost(
+            &readings(start, 1, ReadingPeriod::HalfHour),
+            &rates,
+            start,
+            end,
+        );
+        assert_eq!(costs.days, 7);
+        assert_eq!(costs.total, 7.0 * 50.0 + 10.0);
+    }
+
+    #[test]
+    fn days_are_uk_local() {
+        // The short and long days when the clocks change.
+        assert_eq!(
+            uk_days(
+                datetime!(2023-03-26 00:00 UTC),
+                datetime!(2023-03-27 00:00 +01:00)
+            ),
+            1
+        );
+        assert_eq!(
+            uk_days(
+                datetime!(2023-10-29 00:00 +01:00),
+                datetime!(2023-10-30 00:00 UTC)
+            ),
+            1
+        );
+
+        // Midnight in BST is the evening before in UTC.
+        assert_eq!(
+            uk_days(
+                datetime!(2023-07-01 23:30 UTC),
+                datetime!(2023-07-02 00:00 UTC)
+            ),
+            1
+        );
+        assert_eq!(
+            uk_days(
+                datetime!(2023-07-01 22:30 UTC),
+                datetime!(2023-07-01 23:30 UTC)
+            ),
+            2
+        );
+
+        assert_eq!(
+            uk_days(
+                datetime!(2023-07-01 00:00 UTC),
+                datetime!(2023-07-01 00:00 UTC)
+            ),
+            0
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Runs continuously, exporting new readings on a schedule.
+//!
+//! The start of the last reading exported for each resource is kept in a
+//! state file so each run only asks the API for readings it hasn't seen, and
+//! picks up where it left off after a restart. New readings are written to a
+//! [write-ahead log](crate::wal) next to the state file before being sent so
+//! any that weren't delivered are sent again after a crash.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use clap::ValueEnum;
+use glowmarkt::{
+    align_to_period,
+    format::round,
+    sink::{field_for_classifier, ExportSink, Measurement, ResourceContext},
+    split_periods, GlowmarktApi, Reading, ReadingPeriod, Resource,
+};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    budget::budget_status,
+    config::BudgetConfig,
+    forecast::{record_forecast, ForecastOptions},
+    hint::CliError,
+    influxdb::{InfluxDbOptions, InfluxDbWriter},
+    lookup::select_resources,
+    mqtt::MqttOptions,
+    notify::{notify, NotifyOptions, Run},
+    output::OutputOptions,
+    schedule::Schedule,
+    state,
+    wal::Wal,
+    ErrorStr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DaemonSink {
+    /// InfluxDB line protocol on stdout.
+    Stdout,
+    /// An InfluxDB server, configured with --url.
+    Influx,
+    /// An MQTT broker, configured with --host. Each reading is published to
+    /// `<topic-prefix>/<resource>/reading`.
+    Mqtt,
+}
+
+#[derive(clap::Args)]
+pub struct DaemonArgs {
+    /// When to poll for new readings, as a five field cron expression in UTC.
+    /// Defaults to a few minutes after each half hour.
+    #[clap(long, env = "GLOWMARKT_SCHEDULE", default_value = "5,35 * * * *")]
+    schedule: Schedule,
+    /// Where to send new readings.
+    #[clap(long, env = "GLOWMARKT_SINK", value_enum, default_value = "stdout")]
+    sink: DaemonSink,
+    /// The resources to poll. If absent all consumption resources are polled.
+    #[clap(long, env = "GLOWMARKT_RESOURCES", use_value_delimiter = true)]
+    resources: Vec<String>,
+    /// How many days back to start from for resources that haven't been
+    /// exported before.
+    #[clap(long, env = "GLOWMARKT_BACKFILL_DAYS", default_value = "1")]
+    backfill_days: u32,
+    /// The file recording the last reading exported for each resource,
+    /// defaults to `$XDG_STATE_HOME/glowmarkt/daemon.json`.
+    #[clap(long, env = "GLOWMARKT_STATE")]
+    state: Option<PathBuf>,
+    /// Poll once and exit rather than running continuously.
+    #[clap(long)]
+    once: bool,
+    #[clap(flatten)]
+    influxdb: InfluxDbOptions,
+    #[clap(flatten)]
+    mqtt: MqttOptions,
+    #[clap(flatten)]
+    notify: NotifyOptions,
+    #[clap(flatten)]
+    forecasts: ForecastOptions,
+}
+
+/// What has been exported so far.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct DaemonState {
+    /// The start of the last reading exported for each resource.
+    #[serde(default)]
+    exported: HashMap<String, i64>,
+    /// The last month an over budget alert was sent for.
+    #[serde(default)]
+    budget_alerted: Option<String>,
+}
+
+/// The default location of the state file.
+fn state_path() -> Option<PathBuf> {
+    state::xdg_path("XDG_STATE_HOME", &[".local", "state"], "daemon.json")
+}
+
+/// Where progress is recorded, the state file and the write-ahead log next to
+/// it.
+struct Progress {
+    path: PathBuf,
+    wal: Wal,
+}
+
+impl Progress {
+    fn new(path: PathBuf) -> Self {
+        Self {
+            wal: Wal::new(path.with_extension("wal")),
+            path,
+        }
+    }
+
+    /// Saves the state and clears the log once everything logged has been
+    /// delivered.
+    fn commit(&self, state: &DaemonState) -> Result<(), CliError> {
+        state::save(&self.path, state)?;
+        self.wal.clear()?;
+        Ok(())
+    }
+}
+
+impl DaemonState {
+    fn exported(&self, resource: &Resource) -> Option<OffsetDateTime> {
+        self.exported
+            .get(&resource.id)
+            .and_then(|start| OffsetDateTime::from_unix_timestamp(*start).ok())
+    }
+}
+
+/// Fetches readings newer than the last exported, leaving out trailing zero
+/// readings that the DCC hasn't delivered yet so they are fetched again next
+/// time.
+async fn new_readings(
+    api: &GlowmarktApi,
+    resource: &Resource,
+    after: Option<OffsetDateTime>,
+    backfill: Duration,
+) -> Result<Vec<Reading>, CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let now = api.clock().now();
+    let start = match after {
+        Some(after) => after + Duration::minutes(30),
+        None => align_to_period(now - backfill, period),
+    };
+    let end = align_to_period(now, period);
+    if start > end {
+        return Ok(Vec::new());
+    }
+
+    let mut readings = Vec::new();
+    for (start, end) in split_periods(start, end, period) {
+        readings.extend(api.readings(&resource.id, &start, &end, period).await?);
+    }
+
+    readings.retain(|reading| after.map(|after| reading.start > after).unwrap_or(true));
+    while readings.last().map(|r| r.value == 0.0).unwrap_or(false) {
+        readings.pop();
+    }
+
+    Ok(readings)
+}
+
+/// The points for readings, tagged and rounded as `export` does.
+fn measurements(
+    context: &ResourceContext,
+    readings: &[Reading],
+    options: OutputOptions,
+) -> Vec<Measurement> {
+    let tags = context.tags();
+
+    // Line protocol has no way to write a missing value.
+    readings
+        .iter()
+        .filter(|reading| !reading.value.is_nan())
+        .map(|reading| {
+            let mut measurement = Measurement::new("glowmarkt", reading.start, tags.clone());
+            measurement.add_field(
+                field_for_classifier(&context.resource.classifier),
+                round(reading.value as f64, options.precision),
+            );
+            measurement
+        })
+        .collect()
+}
+
+/// Sends new readings to the sink.
+async fn forward(
+    args: &DaemonArgs,
+    options: OutputOptions,
+    new: &[(&ResourceContext, Vec<Reading>)],
+) -> Result<(), CliError> {
+    match args.sink {
+        DaemonSink::Stdout => {
+            for (context, readings) in new {
+                for measurement in measurements(context, readings, options) {
+                    println!("{}", measurement);
+                }
+            }
+        }
+        DaemonSink::Influx => {
+            let mut writer = InfluxDbWriter::new(args.influxdb.clone())?
+                .ok_or_else(|| "--url is needed to write to InfluxDB".to_string())?;
+            for (context, readings) in new {
+                for measurement in measurements(context, readings, options) {
+                    writer.write(&measurement).await?;
+                }
+            }
+            writer.finish().await?;
+        }
+        DaemonSink::Mqtt => {
+            // The log is cleared once this returns so the broker must have
+            // every reading. A broker that stops answering times out and fails
+            // the poll, leaving the readings in the log to be replayed.
+            let mut sink = args.mqtt.sink(options.precision).acknowledged(true);
+            for (context, readings) in new {
+                sink.write_readings(context, readings).str_err()?;
+            }
+            sink.flush().str_err()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the month end projection and warns once a month when spending is
+/// projected to go over budget.
+async fn check_budget(
+    api: &GlowmarktApi,
+    args: &DaemonArgs,
+    budget: &BudgetConfig,
+    state: &mut DaemonState,
+) -> Result<(), CliError> {
+    let status = budget_status(api, budget).await?;
+    record_forecast(&args.forecasts.path()?, &status, api.clock().now())?;
+    if !status.over_budget || state.budget_alerted.as_deref() == Some(status.month.as_str()) {
+        return Ok(());
+    }
+
+    log::warn!(
+        "Spending is projected to exceed the monthly budget of {}.",
+        status.budget
+    );
+    notify(&args.notify, &status).await;
+    state.budget_alerted = Some(status.month.clone());
+    Ok(())
+}
+
+/// Records the newest reading delivered for each resource.
+fn record_exported(state: &mut DaemonState, new: &[(&ResourceContext, Vec<Reading>)]) {
+    for (context, readings) in new {
+        if let Some(last) = readings.last() {
+            let start = last.start.unix_timestamp();
+            let exported = state
+                .exported
+                .entry(context.resource.id.clone())
+                .or_default();
+            *exported = (*exported).max(start);
+        }
+    }
+}
+
+/// Delivers readings left in the log by a run that failed or crashed before
+/// delivering them.
+async fn replay(
+    args: &DaemonArgs,
+    options: OutputOptions,
+    resources: &[ResourceContext],
+    state: &mut DaemonState,
+    progress: &Progress,
+) -> Result<(), CliError> {
+    let pending = progress.wal.pending()?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let mut new = Vec::new();
+    for entry in pending {
+        match resources
+            .iter()
+            .find(|context| context.resource.id == entry.resource_id)
+        {
+            Some(resource) => new.push((resource, entry.readings)),
+            None => log::warn!(
+                "Dropping logged readings for {} which is no longer polled",
+                entry.resource_id
+            ),
+        }
+    }
+
+    let points: usize = new.iter().map(|(_, readings)| readings.len()).sum();
+    log::info!(
+        "Delivering {} readings from {}",
+        points,
+        progress.wal.path().display()
+    );
+    forward(args, options, &new).await?;
+
+    record_exported(state, &new);
+    progress.commit(state)?;
+
+    Ok(())
+}
+
+/// Polls every resource once, returning the number of readings exported.
+async fn poll(
+    api: &GlowmarktApi,
+    args: &DaemonArgs,
+    options: OutputOptions,
+    budget: Option<&BudgetConfig>,
+    resources: &[ResourceContext],
+    state: &mut DaemonState,
+    progress: &Progress,
+) -> Result<usize, CliError> {
+    replay(args, options, resources, state, progress).await?;
+
+    let backfill = Duration::days(args.backfill_days as i64);
+
+    let mut new = Vec::new();
+    for context in resources {
+        let resource = &context.resource;
+        let readings = new_readings(api, resource, state.exported(resource), backfill).await?;
+        if !readings.is_empty() {
+            new.push((context, readings));
+        }
+    }
+
+    let points = new.iter().map(|(_, readings)| readings.len()).sum();
+    if !new.is_empty() {
+        for (context, readings) in &new {
+            progress.wal.append(&context.resource.id, readings)?;
+        }
+
+        forward(args, options, &new).await?;
+        record_exported(state, &new);
+    }
+
+    if let Some(budget) = budget {
+        check_budget(api, args, budget, state).await?;
+    }
+
+    progress.commit(state)?;
+    log::info!("Exported {} new readings", points);
+
+    Ok(points)
+}
+
+/// Resolves when the process is asked to stop, by SIGTERM as container
+/// runtimes send or by Ctrl+C.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => log::warn!("Unable to listen for SIGTERM: {}", e),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        log::warn!("Unable to listen for Ctrl+C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+pub async fn daemon(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: DaemonArgs,
+    budget: Option<BudgetConfig>,
+) -> Result<(), CliError> {
+    let path = match args.state.clone().or_else(state_path) {
+        Some(path) => path,
+        None => return Err("No state file location, pass --state".to_string().into()),
+    };
+    let mut state: DaemonState = state::load(&path)?;
+    let progress = Progress::new(path);
+    // Contexts carry the device and entity tags that export adds.
+    let ids: Vec<String> = select_resources(&api, &args.resources)
+        .await?
+        .into_iter()
+        .map(|resource| resource.id)
+        .collect();
+    let resources = api.resource_contexts(&ids).await?;
+    log::info!(
+        "Polling {} resources, recording progress in {}",
+        resources.len(),
+        progress.path.display()
+    );
+
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let run = Run::start("daemon", api.clock());
+        let poll = poll(
+            &api,
+            &args,
+            options,
+            budget.as_ref(),
+            &resources,
+            &mut state,
+            &progress,
+        );
+        tokio::pin!(poll);
+
+        // A poll in progress is allowed to finish so its readings are
+        // delivered and recorded before exiting.
+        let (result, stopping) = tokio::select! {
+            result = &mut poll => (result, false),
+            _ = &mut shutdown => {
+                log::info!("Finishing the current poll before shutting down");
+                (poll.await, true)
+            }
+        };
+
+        if args.once || stopping {
+            return result.map(|_| ());
+        }
+
+        // Only failures are worth a notification when running continuously.
+        if let Err(ref e) = result {
+            log::warn!("Failed to export new readings: {}", e);
+            notify(&args.notify, &run.finish(0, 0, Some(e))).await;
+        }
+
+        let now = api.clock().now();
+        let next = args
+            .schedule
+            .next_after(now)
+            .ok_or_else(|| "The schedule never runs".to_string())?;
+        log::debug!("Next poll at {}", next);
+        tokio::select! {
+            _ = tokio::time::sleep((next - now).unsigned_abs()) => {}
+            _ = &mut shutdown => {
+                log::info!("Shutting down");
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use glowmarkt::{
+    cost::{cost, Rates},
+    format::round,
+    settlement::uk_offset,
+    unit::Unit,
+    GlowmarktApi, Reading, ReadingPeriod, Resource, TariffData,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::{hint::CliError, output::OutputOptions, ErrorStr};
+
+#[derive(clap::Args)]
+pub struct DashboardArgs {
+    /// The number of days of history to include.
+    #[clap(long, default_value = "30")]
+    days: u32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LatestReading {
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    value: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DailyUsage {
+    date: String,
+    usage: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Totals {
+    usage: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Fuel {
+    fuel: String,
+    resource_id: String,
+    name: String,
+    unit: Option<Unit>,
+    tariff: Option<TariffData>,
+    latest_reading: Option<LatestReading>,
+    daily: Vec<DailyUsage>,
+    totals: Totals,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Dashboard {
+    #[serde(with = "time::serde::rfc3339")]
+    generated: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    from: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    to: OffsetDateTime,
+    days: u32,
+    fuels: Vec<Fuel>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+fn uk_date(date: OffsetDateTime) -> Date {
+    date.to_offset(uk_offset(date)).date()
+}
+
+/// Returns the fuel a consumption resource measures, e.g. `electricity` for a
+/// resource classified as `electricity.consumption`.
+pub fn fuel(resource: &Resource) -> Option<&str> {
+    let classifier = resource.classifier.as_ref()?;
+    classifier.is_consumption().then(|| classifier.fuel())
+}
+
+/// Gathers the data for a fuel, noting anything the account doesn't have.
+/// Returns `None` if the resource has no readings to show.
+async fn fuel_data(
+    api: &GlowmarktApi,
+    options: OutputOptions,
+    resource: Resource,
+    (start, end): (OffsetDateTime, OffsetDateTime),
+    notes: &mut Vec<String>,
+) -> Result<Option<Fuel>, CliError> {
+    let readings = match api
+        .readings_range(&resource.id, &start, &end, ReadingPeriod::HalfHour)
+        .await
+    {
+        Ok(readings) => readings,
+        Err(e) if e.is_unavailable() => {
+            notes.push(format!(
+                "Skipped {} ({}) as its readings are unavailable: {}",
+                resource.name, resource.id, e
+            ));
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let tariff = match api.latest_tariff(&resource.id).await {
+        Ok(tariff) => tariff,
+        Err(e) if e.is_unavailable() => None,
+        Err(e) => return Err(e.into()),
+    };
+    if tariff.is_none() {
+        notes.push(format!(
+            "{} ({}) has no tariff so costs are not included.",
+            resource.name, resource.id
+        ));
+    }
+    let rates = tariff.as_ref().and_then(Rates::from_tariff);
+
+    let mut days: BTreeMap<Date, Vec<Reading>> = BTreeMap::new();
+    for reading in &readings {
+        days.entry(uk_date(reading.start))
+            .or_default()
+            .push(reading.clone());
+    }
+
+    let daily: Vec<DailyUsage> = days
+        .into_iter()
+        .map(|(date, readings)| {
+            // Each day has at least one reading and is charged a single day.
+            let day = (readings[0].start, readings[readings.len() - 1].start);
+            DailyUsage {
+                date: date.to_string(),
+                usage: round(
+                    readings.iter().map(|r| r.value as f64).sum(),
+                    options.precision,
+                ),
+                cost: rates.as_ref().map(|rates| {
+                    let costs = cost(&readings, rates, day.0, day.1 + Duration::minutes(30));
+                    round(costs.total, options.precision)
+                }),
+            }
+        })
+        .collect();
+
+    let totals = Totals {
+        usage: round(
+            readings.iter().map(|r| r.value as f64).sum(),
+            options.precision,
+        ),
+        cost: rates
+            .as_ref()
+            .map(|rates| round(cost(&readings, rates, start, end).total, options.precision)),
+    };
+
+    // Recent readings are zero until the DCC delivers them.
+    let latest_reading = readings
+        .iter()
+        .rev()
+        .find(|reading| reading.value != 0.0)
+        .map(|reading| LatestReading {
+            start: reading.start,
+            value: options.value(reading.value),
+        });
+
+    Ok(Some(Fuel {
+        fuel: fuel(&resource).unwrap_or_default().to_string(),
+        unit: resource.unit(),
+        resource_id: resource.id,
+        name: resource.name,
+        tariff,
+        latest_reading,
+        daily,
+        totals,
+    }))
+}
+
+pub async fn dashboard(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: DashboardArgs,
+) -> Result<(), CliError> {
+    let now = api.clock().now();
+    let start = api.calendar().align(
+        now - Duration::days(args.days.saturating_sub(1) as i64),
+        ReadingPeriod::Day,
+    );
+
+    let mut resources: Vec<Resource> = api
+        .resources()
+        .await?
+        .into_values()
+        .filter(|resource| fuel(resource).is_some())
+        .collect();
+    resources.sort_by(|a, b| a.classifier.cmp(&b.classifier).then(a.id.cmp(&b.id)));
+
+    let mut fuels = Vec::new();
+    let mut notes = Vec::new();
+    for resource in resources {
+        if let Some(fuel) = fuel_data(&api, options, resource, (start, now), &mut notes).await? {
+            fuels.push(fuel);
+        }
+    }
+    if fuels.is_empty() {
+        notes.push("The account has no consumption resources with readings.".to_string());
+    }
+
+    let dashboard = Dashboard {
+        generated: now,
+        from: start,
+        to: now,
+        days: args.days,
+        fuels,
+        notes,
+    };
+
+    println!("{}", to_string_pretty(&dashboard).str_err()?);
+    Ok(())
+}
@@ -0,0 +1,148 @@
+//! Reports when each device was last heard from.
+//!
+//! A display or hub that has silently gone offline is the most common reason
+//! for missing data so devices that haven't reported recently are listed
+//! first.
+
+use glowmarkt::{Device, GlowmarktApi};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::{Duration, OffsetDateTime};
+
+use crate::{has_tags, hint::CliError, ErrorStr};
+
+#[derive(clap::Args)]
+pub struct DeviceStatusArgs {
+    /// The number of minutes without a report before a device is considered
+    /// offline.
+    #[clap(long, default_value = "120")]
+    stale_after: u32,
+    /// Only list devices that are offline or have never reported.
+    #[clap(long)]
+    stale_only: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+enum LastSeenSource {
+    /// Reported by the device endpoint.
+    Device,
+    /// The most recent value recorded by one of the device's resources.
+    Reading,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceStatus {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    hardware_id: String,
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_seen: Option<OffsetDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_seen_source: Option<LastSeenSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minutes_since_seen: Option<i64>,
+    stale: bool,
+}
+
+/// Finds the most recent value recorded by any of a device's resources.
+pub async fn latest_reading(api: &GlowmarktApi, device: &Device) -> Option<OffsetDateTime> {
+    let mut latest = None;
+
+    for resource_id in device.protocol.resource_ids() {
+        match api.current(resource_id).await {
+            Ok(Some(current)) => latest = latest.max(Some(current.timestamp)),
+            Ok(None) => {}
+            Err(e) => log::debug!("Unable to read the current value of {}: {}", resource_id, e),
+        }
+    }
+
+    latest
+}
+
+async fn device_status(
+    api: &GlowmarktApi,
+    device: Device,
+    now: OffsetDateTime,
+    stale_after: Duration,
+) -> DeviceStatus {
+    let (last_seen, last_seen_source) = match device.last_seen {
+        Some(last_seen) => (Some(last_seen), Some(LastSeenSource::Device)),
+        None => match latest_reading(api, &device).await {
+            Some(last_seen) => (Some(last_seen), Some(LastSeenSource::Reading)),
+            None => (None, None),
+        },
+    };
+
+    let since = last_seen.map(|last_seen| now - last_seen);
+
+    DeviceStatus {
+        id: device.id,
+        description: device.description,
+        hardware_id: device.hardware_id,
+        active: device.active,
+        status: device.status,
+        last_seen,
+        last_seen_source,
+        minutes_since_seen: since.map(|since| since.whole_minutes()),
+        stale: since.map(|since| since > stale_after).unwrap_or(true),
+    }
+}
+
+pub async fn device_statuses(
+    api: GlowmarktApi,
+    device_tags: Vec<String>,
+    id: Option<String>,
+    args: DeviceStatusArgs,
+) -> Result<(), CliError> {
+    let now = api.clock().now();
+    let stale_after = Duration::minutes(args.stale_after as i64);
+
+    let devices: Vec<Device> = match id {
+        Some(id) => match api.device(&id).await? {
+            Some(device) => vec![device],
+            None => return Err(format!("Unknown device {}", id).into()),
+        },
+        None => api
+            .devices()
+            .await?
+            .into_values()
+            .filter(|device| has_tags(device, &device_tags))
+            .collect(),
+    };
+
+    let mut statuses = Vec::new();
+    for device in devices {
+        let status = device_status(&api, device, now, stale_after).await;
+        if status.stale {
+            log::warn!(
+                "Device {} ({}) has not reported since {}",
+                status.description.as_deref().unwrap_or("unnamed"),
+                status.id,
+                status
+                    .last_seen
+                    .map(|last_seen| last_seen.to_string())
+                    .unwrap_or_else(|| "it was added".to_string())
+            );
+        }
+        if status.stale || !args.stale_only {
+            statuses.push(status);
+        }
+    }
+
+    // Offline devices first, longest silent first.
+    statuses.sort_by(|a, b| {
+        b.stale
+            .cmp(&a.stale)
+            .then(a.last_seen.cmp(&b.last_seen))
+            .then(a.id.cmp(&b.id))
+    });
+
+    println!("{}", to_string_pretty(&statuses).str_err()?);
+    Ok(())
+}
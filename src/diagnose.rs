@@ -0,0 +1,157 @@
+//! A report of how well the API is working, to attach to support requests.
+
+use std::{future::Future, time::Instant};
+
+use glowmarkt::{align_to_period, GlowmarktApi, ReadingPeriod};
+use serde::Serialize;
+use serde_json::{json, to_string_pretty, Value};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+use crate::{dashboard::fuel, hint::CliError, ErrorStr, MAX_CLOCK_SKEW};
+
+/// The length of the range of readings fetched.
+const READINGS_RANGE: Duration = Duration::hours(6);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Check {
+    name: &'static str,
+    ok: bool,
+    /// How long the check took in milliseconds.
+    duration: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    version: &'static str,
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+    ok: bool,
+    checks: Vec<Check>,
+}
+
+/// Times a check. The check returns its detail and whether it passed.
+async fn check<F>(name: &'static str, future: F) -> Check
+where
+    F: Future<Output = Result<(Value, bool), CliError>>,
+{
+    let timer = Instant::now();
+    let result = future.await;
+    let duration = timer.elapsed().as_millis();
+
+    match result {
+        Ok((detail, ok)) => Check {
+            name,
+            ok,
+            duration,
+            detail: Some(detail),
+            error: None,
+            hint: None,
+        },
+        Err(error) => Check {
+            name,
+            ok: false,
+            duration,
+            detail: None,
+            hint: error.hint(),
+            error: Some(error.to_string()),
+        },
+    }
+}
+
+async fn readings(api: &GlowmarktApi) -> Result<(Value, bool), CliError> {
+    let mut resources: Vec<_> = api
+        .resources()
+        .await?
+        .into_values()
+        .filter(|resource| fuel(resource).is_some())
+        .collect();
+    resources.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let resource = resources
+        .first()
+        .ok_or_else(|| "The account has no consumption resources".to_string())?;
+
+    let end = align_to_period(api.clock().now(), ReadingPeriod::HalfHour);
+    let start = end - READINGS_RANGE;
+    let readings = api
+        .readings(&resource.id, &start, &end, ReadingPeriod::HalfHour)
+        .await?;
+
+    Ok((
+        json!({
+            "resourceId": resource.id,
+            "readings": readings.len(),
+            "nonZero": readings.iter().filter(|r| r.value != 0.0).count(),
+        }),
+        true,
+    ))
+}
+
+/// Runs each check in turn and prints the report as JSON. Failed checks are
+/// included in the report rather than stopping it.
+pub async fn diagnose(api: GlowmarktApi) -> Result<(), CliError> {
+    let mut checks = Vec::new();
+
+    checks.push(
+        check("authentication", async {
+            api.validate().await?;
+            let expiry = api
+                .token_expiry()
+                .and_then(|expiry| expiry.format(&Rfc3339).ok());
+            Ok((json!({ "tokenExpiry": expiry }), true))
+        })
+        .await,
+    );
+
+    checks.push(
+        check("metadata", async {
+            let devices = api.devices().await?.len();
+            let resources = api.resources().await?.len();
+            Ok((json!({ "devices": devices, "resources": resources }), true))
+        })
+        .await,
+    );
+
+    checks.push(check("readings", readings(&api)).await);
+
+    checks.push(
+        check("rateLimit", async {
+            let headers = api.rate_limit_headers().await?;
+            let throttled = headers.contains_key("retry-after")
+                || headers
+                    .get("x-ratelimit-remaining")
+                    .is_some_and(|remaining| remaining.trim() == "0");
+            Ok((json!({ "headers": headers }), !throttled))
+        })
+        .await,
+    );
+
+    checks.push(
+        check("clockSkew", async {
+            let skew = api.clock_skew().await?;
+            Ok((
+                json!({ "seconds": skew.whole_seconds() }),
+                skew.abs() <= MAX_CLOCK_SKEW,
+            ))
+        })
+        .await,
+    );
+
+    let report = Report {
+        version: env!("CARGO_PKG_VERSION"),
+        time: api.clock().now(),
+        ok: checks.iter().all(|check| check.ok),
+        checks,
+    };
+
+    println!("{}", to_string_pretty(&report).str_err()?);
+    Ok(())
+}
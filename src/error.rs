@@ -1,8 +1,12 @@
-//! A basic error container.
+//! Errors returned by the API.
 
-use std::fmt::{self, Display};
+use std::{
+    error,
+    fmt::{self, Display},
+};
 
 use reqwest::StatusCode;
+use serde_json::Value;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// The type of an error.
@@ -11,6 +15,8 @@ pub enum ErrorKind {
     NotFound,
     /// Authentication failed.
     NotAuthenticated,
+    /// Too many requests have been made recently.
+    RateLimited,
     /// A network error.
     Network,
     /// An error likely caused by this crate.
@@ -19,21 +25,239 @@ pub enum ErrorKind {
     Server,
     /// An error decoding the API response.
     Response,
+    /// The resource has no tariff configured.
+    NoTariff,
+    /// The resource does not record data at the requested period.
+    UnsupportedPeriod,
+    /// Reading or writing local storage failed.
+    Storage,
 }
 
-/// A fairly generic error container.
-pub struct Error {
+impl ErrorKind {
+    fn for_status(status: StatusCode) -> ErrorKind {
+        if status == StatusCode::NOT_FOUND {
+            ErrorKind::NotFound
+        } else if status == StatusCode::UNAUTHORIZED {
+            ErrorKind::NotAuthenticated
+        } else if status == StatusCode::TOO_MANY_REQUESTS {
+            ErrorKind::RateLimited
+        } else if status.is_server_error() {
+            ErrorKind::Server
+        } else {
+            ErrorKind::Client
+        }
+    }
+}
+
+/// An error from the API or from communicating with it.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The API responded with an error status.
+    Status {
+        /// The status of the response.
+        status: StatusCode,
+        /// The URL that was requested.
+        url: String,
+        /// The error message the server included in the response, if any.
+        message: Option<String>,
+    },
+    /// The request could not be made or the response could not be read.
+    Http {
+        /// The URL that was requested, if known.
+        url: Option<String>,
+        /// The underlying error.
+        source: reqwest::Error,
+    },
+    /// The response could not be decoded.
+    Decode {
+        /// The URL that was requested, if known.
+        url: Option<String>,
+        /// The underlying error.
+        source: serde_json::Error,
+    },
+    /// Any other failure, including failures the API reports in otherwise
+    /// successful responses.
+    Other {
+        /// The type of this error.
+        kind: ErrorKind,
+        /// A description of this error.
+        message: String,
+    },
+}
+
+impl Error {
+    /// Creates an error of the given kind.
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Error::Other {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Creates an error for a response with an error status, extracting the
+    /// server's message from the body if possible.
+    pub(crate) fn from_response(status: StatusCode, url: String, body: &str) -> Self {
+        Error::Status {
+            status,
+            url,
+            message: server_message(body),
+        }
+    }
+
+    /// A copy of this error for when it must be returned to more than one
+    /// caller. The underlying HTTP and decoding errors cannot be copied so
+    /// only their description is kept.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Error::Status {
+                status,
+                url,
+                message,
+            } => Error::Status {
+                status: *status,
+                url: url.clone(),
+                message: message.clone(),
+            },
+            _ => Error::new(self.kind(), self.to_string()),
+        }
+    }
+
     /// The type of this error.
-    pub kind: ErrorKind,
-    /// A description of this error.
-    pub message: String,
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Status { status, .. } => ErrorKind::for_status(*status),
+            Error::Http { source, .. } => {
+                if let Some(status) = source.status() {
+                    ErrorKind::for_status(status)
+                } else if source.is_builder() {
+                    ErrorKind::Client
+                } else if source.is_decode() {
+                    ErrorKind::Response
+                } else {
+                    ErrorKind::Network
+                }
+            }
+            Error::Decode { .. } => ErrorKind::Response,
+            Error::Other { kind, .. } => *kind,
+        }
+    }
+
+    /// The HTTP status of the response, if the API responded with an error
+    /// status.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::Http { source, .. } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// The URL that was being requested, if known.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Error::Status { url, .. } => Some(url),
+            Error::Http { url, .. } | Error::Decode { url, .. } => url.as_deref(),
+            Error::Other { .. } => None,
+        }
+    }
+
+    /// The error message the server returned, if any.
+    pub fn server_message(&self) -> Option<&str> {
+        match self {
+            Error::Status { message, .. } => message.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure may be transient and so worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Network | ErrorKind::Server | ErrorKind::RateLimited
+        )
+    }
+
+    /// Whether the failure means the data doesn't exist for this account or
+    /// resource, such as a missing tariff, rather than that it couldn't be
+    /// fetched. Commands combining several sources can skip these.
+    pub fn is_unavailable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::NotFound | ErrorKind::NoTariff | ErrorKind::UnsupportedPeriod
+        )
+    }
+
+    pub(crate) fn with_url(self, request_url: &str) -> Self {
+        match self {
+            Error::Http { url: None, source } => Error::Http {
+                url: Some(request_url.to_owned()),
+                source,
+            },
+            Error::Decode { url: None, source } => Error::Decode {
+                url: Some(request_url.to_owned()),
+                source,
+            },
+            error => error,
+        }
+    }
+}
+
+/// The longest raw response body included in an error.
+const MAX_BODY_LENGTH: usize = 200;
+
+/// Finds the error message in an error response. The API normally responds
+/// with `{"error": {"message": ...}}` but other shapes are accepted and
+/// anything that isn't JSON is used as is.
+fn server_message(body: &str) -> Option<String> {
+    let body = body.trim();
+    if body.is_empty() {
+        return None;
+    }
+
+    let value = match serde_json::from_str::<Value>(body) {
+        Ok(value) => value,
+        Err(_) => {
+            let mut message: String = body.chars().take(MAX_BODY_LENGTH).collect();
+            if message.len() < body.len() {
+                message.push('…');
+            }
+            return Some(message);
+        }
+    };
+
+    let message = |value: &Value| match value {
+        Value::String(message) => Some(message.clone()),
+        Value::Object(map) => match map.get("message") {
+            Some(Value::String(message)) => Some(message.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    ["error", "message"]
+        .iter()
+        .find_map(|field| value.get(field).and_then(message))
 }
 
 pub(crate) fn maybe<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
     match result {
         Ok(val) => Ok(Some(val)),
         Err(e) => {
-            if e.kind == ErrorKind::NotFound {
+            if e.kind() == ErrorKind::NotFound {
+                Ok(None)
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+pub(crate) fn maybe_tariff<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(val) => Ok(Some(val)),
+        Err(e) => {
+            if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::NoTariff {
                 Ok(None)
             } else {
                 Err(e)
@@ -44,7 +268,33 @@ pub(crate) fn maybe<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.pad(&format!("{:?}: {}", self.kind, self.message))
+        let message = match self {
+            Error::Status {
+                status,
+                url,
+                message: Some(message),
+            } => format!("{} from {}: {}", status, url, message),
+            Error::Status { status, url, .. } => format!("{} from {}", status, url),
+            Error::Http { source, .. } => source.to_string(),
+            Error::Decode {
+                url: Some(url),
+                source,
+            } => format!("Invalid response from {}: {}", url, source),
+            Error::Decode { source, .. } => source.to_string(),
+            Error::Other { message, .. } => message.clone(),
+        };
+
+        f.pad(&format!("{:?}: {}", self.kind(), message))
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Http { source, .. } => Some(source),
+            Error::Decode { source, .. } => Some(source),
+            _ => None,
+        }
     }
 }
 
@@ -56,32 +306,18 @@ impl From<Error> for String {
 
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Self {
-        let kind = if let Some(status) = error.status() {
-            if status == StatusCode::NOT_FOUND {
-                ErrorKind::NotFound
-            } else if status == StatusCode::UNAUTHORIZED {
-                ErrorKind::NotAuthenticated
-            } else if status.is_server_error() {
-                ErrorKind::Server
-            } else {
-                ErrorKind::Client
-            }
-        } else {
-            ErrorKind::Network
-        };
-
-        Self {
-            kind,
-            message: error.to_string(),
+        Error::Http {
+            url: error.url().map(|url| url.to_string()),
+            source: error,
         }
     }
 }
 
 impl From<serde_json::Error> for Error {
     fn from(error: serde_json::Error) -> Self {
-        Self {
-            kind: ErrorKind::Response,
-            message: error.to_string(),
+        Error::Decode {
+            url: None,
+            source: error,
         }
     }
 }
@@ -0,0 +1,66 @@
+//! Meter events such as power outages and tamper alerts.
+//!
+//! Accounts with event resources record each event as a non-zero reading in
+//! the period it happened, these are converted into [`MeterEvent`]s.
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{Error, GlowmarktApi, ReadingPeriod, Resource};
+
+/// Whether a resource records meter events rather than consumption.
+pub fn is_event_resource(resource: &Resource) -> bool {
+    resource
+        .classifier
+        .as_ref()
+        .map(|classifier| {
+            classifier
+                .parts()
+                .any(|part| matches!(part, "event" | "events" | "alert" | "alerts"))
+        })
+        .unwrap_or(false)
+}
+
+/// An event reported by a meter.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeterEvent {
+    /// The resource that recorded the event.
+    pub resource_id: String,
+    /// The kind of event, taken from the resource's classifier.
+    pub kind: String,
+    /// The start of the period the event happened in.
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The number of times the event happened in the period.
+    pub count: f32,
+}
+
+impl GlowmarktApi {
+    /// Retrieves the events recorded by an event resource.
+    pub async fn events(
+        &self,
+        resource: &Resource,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<Vec<MeterEvent>, Error> {
+        let kind = resource
+            .classifier
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| resource.name.clone());
+
+        Ok(self
+            .readings_range(&resource.id, start, end, ReadingPeriod::HalfHour)
+            .await?
+            .into_iter()
+            .filter(|reading| reading.value != 0.0)
+            .map(|reading| MeterEvent {
+                resource_id: resource.id.clone(),
+                kind: kind.clone(),
+                start: reading.start,
+                count: reading.value,
+            })
+            .collect())
+    }
+}
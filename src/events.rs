@@ -0,0 +1,103 @@
+use std::io::{stdout, BufWriter, Write};
+
+use clap::ValueEnum;
+use glowmarkt::{
+    event::{is_event_resource, MeterEvent},
+    format::{csv_field, TimestampFormat},
+    GlowmarktApi, ReadingPeriod,
+};
+use serde_json::{to_writer, to_writer_pretty};
+
+use crate::{hint::CliError, parse_date, parse_end_date, ErrorStr};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum EventFormat {
+    /// A JSON array of events.
+    Json,
+    /// Newline delimited JSON, one event per line.
+    Ndjson,
+    /// Comma separated values.
+    Csv,
+}
+
+#[derive(clap::Args)]
+pub struct EventsArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: EventFormat,
+    /// The event resources to read. If absent all event resources are read.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+    /// Start time of first event.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// End time of last event (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+fn write_events(format: EventFormat, events: &[MeterEvent]) -> Result<(), CliError> {
+    let mut out = BufWriter::new(stdout().lock());
+
+    match format {
+        EventFormat::Json => {
+            to_writer_pretty(&mut out, events).str_err()?;
+            writeln!(out).str_err()?;
+        }
+        EventFormat::Ndjson => {
+            for event in events {
+                to_writer(&mut out, event).str_err()?;
+                writeln!(out).str_err()?;
+            }
+        }
+        EventFormat::Csv => {
+            writeln!(out, "timestamp,resource_id,kind,count").str_err()?;
+            for event in events {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    TimestampFormat::Rfc3339.format(event.start),
+                    csv_field(&event.resource_id),
+                    csv_field(&event.kind),
+                    event.count
+                )
+                .str_err()?;
+            }
+        }
+    }
+
+    out.flush().str_err()?;
+    Ok(())
+}
+
+pub async fn events(api: GlowmarktApi, args: EventsArgs) -> Result<(), CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let start = parse_date(args.from, period, &api)?;
+    let end = parse_end_date(args.to, period, &api)?;
+
+    let mut resources = api.resources().await?;
+    let resources: Vec<_> = if args.resources.is_empty() {
+        resources.into_values().filter(is_event_resource).collect()
+    } else {
+        args.resources
+            .iter()
+            .map(|id| {
+                resources
+                    .remove(id)
+                    .ok_or_else(|| format!("Unknown resource {}", id))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    if resources.is_empty() {
+        log::warn!("This account has no event resources.");
+    }
+
+    let mut events = Vec::new();
+    for resource in &resources {
+        events.extend(api.events(resource, &start, &end).await?);
+    }
+    events.sort_by_key(|event| event.start);
+
+    write_events(args.format, &events)
+}
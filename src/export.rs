@@ -0,0 +1,551 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{stdout, BufWriter},
+    net::TcpStream,
+    path::PathBuf,
+    pin::pin,
+};
+
+use clap::ValueEnum;
+use futures::{stream, StreamExt};
+use glowmarkt::{
+    gaps::{missing_starts, FillStrategy, Filler},
+    sink::{
+        CsvSink, ExportSink, GraphiteSink, JsonSink, LineProtocolSink, QuestDbSink, ResourceContext,
+    },
+    split_periods,
+    transform::{Pipeline, Transform},
+    Error, GlowmarktApi, Reading, ReadingPeriod, Resource, Warning,
+};
+use time::OffsetDateTime;
+
+#[cfg(feature = "sqlite")]
+use glowmarkt::cache::SqliteStore;
+#[cfg(feature = "postgres")]
+use glowmarkt::sink::PostgresSink;
+#[cfg(feature = "parquet")]
+use glowmarkt::sink::{ParquetSink, ParquetSplit};
+
+use crate::{
+    hint::CliError,
+    mqtt::MqttOptions,
+    notify::{notify, NotifyOptions, Run},
+    output::{transform_resource, CsvOptions, OutputOptions, TransformOptions},
+    parse_date, parse_end_date, ErrorStr,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SourceKind {
+    /// The Glowmarkt API.
+    Cloud,
+    /// Readings previously stored in the SQLite database at --database.
+    Local,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SinkKind {
+    /// InfluxDB line protocol.
+    Influx,
+    /// Comma separated values.
+    Csv,
+    /// Newline delimited JSON.
+    Ndjson,
+    /// Graphite's plaintext protocol, sent to --carbon if given.
+    Graphite,
+    /// QuestDB's line protocol endpoint at --questdb.
+    Questdb,
+    /// A PostgreSQL or TimescaleDB table, requires --dsn.
+    Postgres,
+    /// Parquet files written to --output-dir.
+    Parquet,
+    /// An MQTT broker at --host, each reading published to
+    /// `<topic-prefix>/<resource>/reading`.
+    Mqtt,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SplitKind {
+    /// One file for each resource.
+    Resource,
+    /// One file for each resource and month.
+    Month,
+}
+
+#[derive(clap::Args)]
+#[cfg_attr(not(all(feature = "postgres", feature = "parquet")), allow(dead_code))]
+pub struct SinkOptions {
+    /// The PostgreSQL connection string for the postgres sink.
+    #[clap(long, env = "GLOWMARKT_POSTGRES_DSN")]
+    dsn: Option<String>,
+    /// The table the postgres sink writes to, created if necessary.
+    #[clap(long, default_value = "glowmarkt_readings")]
+    table: String,
+    /// The carbon-cache host and port the graphite sink sends readings to,
+    /// e.g. `graphite:2003`. Readings are printed if absent.
+    #[clap(long, env = "GLOWMARKT_CARBON")]
+    carbon: Option<String>,
+    /// The prefix of the graphite sink's paths.
+    #[clap(long, default_value = "glowmarkt")]
+    graphite_prefix: String,
+    /// The host and line protocol port the questdb sink sends readings to,
+    /// e.g. `questdb:9009`.
+    #[clap(long, env = "GLOWMARKT_QUESTDB")]
+    questdb: Option<String>,
+    /// The directory the parquet sink writes files to.
+    #[clap(long, default_value = ".")]
+    output_dir: PathBuf,
+    /// How the parquet sink divides readings between files.
+    #[clap(long, value_enum, default_value = "resource")]
+    split: SplitKind,
+    #[clap(flatten)]
+    mqtt: MqttOptions,
+}
+
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    /// Where to read readings from.
+    #[clap(long, value_enum, default_value = "cloud")]
+    source: SourceKind,
+    /// The SQLite database the local source reads from, as kept by the
+    /// library's SQLite readings cache.
+    #[clap(long, env = "GLOWMARKT_DATABASE")]
+    database: Option<PathBuf>,
+    /// Where to write readings to.
+    #[clap(long, alias = "format", value_enum, default_value = "influx")]
+    sink: SinkKind,
+    /// The resources to export. If absent all resources are exported.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+    /// The length of each reading (1m, 30m, 1h, 1d, 1w, 1mon or 1y).
+    #[clap(long, default_value = "30m")]
+    period: ReadingPeriod,
+    /// Start time of first reading.
+    #[clap(long, allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(long, allow_hyphen_values = true)]
+    to: Option<String>,
+    /// Export the resource measuring the cost of each consumption or export
+    /// resource alongside it. Both are fetched for each chunk before moving
+    /// to the next so an interrupted export leaves matching readings.
+    #[clap(long)]
+    with_cost: bool,
+    /// Fail once the export is complete if any period in the range had no
+    /// reading, so automation notices missing data.
+    #[clap(long)]
+    fail_on_gaps: bool,
+    /// How to fill periods with no reading: none, zero, null (an empty value
+    /// where the sink supports one) or interpolate.
+    #[clap(long, default_value = "none")]
+    fill: FillStrategy,
+    #[clap(flatten)]
+    csv: CsvOptions,
+    #[clap(flatten)]
+    sinks: SinkOptions,
+    #[clap(flatten)]
+    transform: TransformOptions,
+    #[clap(flatten)]
+    notify: NotifyOptions,
+}
+
+/// Where exported readings are written.
+pub enum Sink {
+    /// A sink writing synchronously, to stdout or files.
+    Stream(Box<dyn ExportSink>),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresSink),
+}
+
+impl Sink {
+    pub async fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> Result<(), CliError> {
+        match self {
+            Sink::Stream(sink) => sink.write_readings(context, readings).str_err()?,
+            #[cfg(feature = "postgres")]
+            Sink::Postgres(sink) => sink.write_readings(context, readings).await?,
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), CliError> {
+        match self {
+            Sink::Stream(sink) => sink.flush().str_err()?,
+            #[cfg(feature = "postgres")]
+            Sink::Postgres(_) => (),
+        }
+
+        Ok(())
+    }
+}
+
+pub async fn sink(
+    kind: SinkKind,
+    options: OutputOptions,
+    csv: CsvOptions,
+    sinks: &SinkOptions,
+) -> Result<Sink, CliError> {
+    let out = BufWriter::new(stdout());
+    let sink: Box<dyn ExportSink> = match kind {
+        SinkKind::Influx => Box::new(LineProtocolSink::new(out).precision(options.precision)),
+        SinkKind::Csv => Box::new(
+            csv.apply_sink(CsvSink::new(out))
+                .precision(options.precision),
+        ),
+        SinkKind::Ndjson => Box::new(JsonSink::new(out).precision(options.precision)),
+        SinkKind::Graphite => match sinks.carbon {
+            Some(ref carbon) => {
+                let stream = TcpStream::connect(carbon)
+                    .map_err(|e| format!("Failed to connect to {}: {}", carbon, e))?;
+                Box::new(
+                    GraphiteSink::new(BufWriter::new(stream))
+                        .prefix(&sinks.graphite_prefix)
+                        .precision(options.precision),
+                )
+            }
+            None => Box::new(
+                GraphiteSink::new(out)
+                    .prefix(&sinks.graphite_prefix)
+                    .precision(options.precision),
+            ),
+        },
+        SinkKind::Mqtt => Box::new(sinks.mqtt.sink(options.precision)),
+        SinkKind::Questdb => match sinks.questdb {
+            Some(ref address) => Box::new(QuestDbSink::new(address).precision(options.precision)),
+            None => return Err("The questdb sink requires --questdb".to_string().into()),
+        },
+        #[cfg(feature = "postgres")]
+        SinkKind::Postgres => {
+            let dsn = match &sinks.dsn {
+                Some(dsn) => dsn,
+                None => return Err("The postgres sink requires --dsn".to_string().into()),
+            };
+            return Ok(Sink::Postgres(
+                PostgresSink::connect(dsn, &sinks.table)
+                    .await?
+                    .precision(options.precision),
+            ));
+        }
+        #[cfg(not(feature = "postgres"))]
+        SinkKind::Postgres => {
+            return Err("glowmarkt was built without the postgres feature"
+                .to_string()
+                .into());
+        }
+        #[cfg(feature = "parquet")]
+        SinkKind::Parquet => {
+            let split = match sinks.split {
+                SplitKind::Resource => ParquetSplit::Resource,
+                SplitKind::Month => ParquetSplit::Month,
+            };
+            Box::new(
+                ParquetSink::new(&sinks.output_dir)
+                    .split(split)
+                    .precision(options.precision),
+            )
+        }
+        #[cfg(not(feature = "parquet"))]
+        SinkKind::Parquet => {
+            return Err("glowmarkt was built without the parquet feature"
+                .to_string()
+                .into());
+        }
+    };
+
+    Ok(Sink::Stream(sink))
+}
+
+/// Where exported readings are read from.
+enum Source<'a> {
+    Cloud(&'a GlowmarktApi),
+    #[cfg(feature = "sqlite")]
+    Local(SqliteStore),
+}
+
+impl<'a> Source<'a> {
+    fn open(api: &'a GlowmarktApi, args: &ExportArgs) -> Result<Self, CliError> {
+        match args.source {
+            SourceKind::Cloud => Ok(Source::Cloud(api)),
+            #[cfg(feature = "sqlite")]
+            SourceKind::Local => match args.database {
+                // Opening creates a database, which would have nothing to
+                // export.
+                Some(ref database) if !database.exists() => {
+                    Err(format!("{} does not exist", database.display()).into())
+                }
+                Some(ref database) => Ok(Source::Local(SqliteStore::open(database)?)),
+                None => Err("The local source requires --database".to_string().into()),
+            },
+            #[cfg(not(feature = "sqlite"))]
+            SourceKind::Local => Err("glowmarkt was built without the sqlite feature"
+                .to_string()
+                .into()),
+        }
+    }
+
+    async fn fetch(
+        &self,
+        resource: &Resource,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
+        match self {
+            Source::Cloud(api) => {
+                api.readings_range_with_warnings(&resource.id, &start, &end, period)
+                    .await
+            }
+            #[cfg(feature = "sqlite")]
+            Source::Local(store) => {
+                let stored = store.read_range(&resource.id, period, start, end)?;
+                let warnings = stored
+                    .gaps(start, end)
+                    .into_iter()
+                    .map(|(start, end)| Warning::NotStored {
+                        resource_id: resource.id.clone(),
+                        start,
+                        end,
+                    })
+                    .collect();
+                Ok((stored.readings, warnings))
+            }
+        }
+    }
+}
+
+/// Prints warnings to stderr, apart from any error.
+fn print_warnings(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Finds the cost resource of each resource being exported, keyed by the
+/// exported resource's ID. Cost resources are removed from the list so they
+/// are only exported alongside their energy resource.
+async fn cost_contexts(
+    api: &GlowmarktApi,
+    pipeline: &Pipeline,
+    contexts: &mut Vec<ResourceContext>,
+) -> Result<HashMap<String, ResourceContext>, CliError> {
+    let mut pairs = Vec::new();
+    for context in contexts.iter() {
+        match api.cost_resource(&context.resource).await? {
+            Some(cost) => pairs.push((context.resource.id.clone(), cost.id)),
+            None => log::debug!("Resource {} has no cost resource", context.resource.id),
+        }
+    }
+
+    let cost_ids: Vec<String> = pairs.iter().map(|(_, cost)| cost.clone()).collect();
+    contexts.retain(|context| !cost_ids.contains(&context.resource.id));
+
+    let mut costs = HashMap::new();
+    for ((id, _), mut context) in pairs
+        .into_iter()
+        .zip(api.resource_contexts(&cost_ids).await?)
+    {
+        transform_resource(pipeline, &mut context.resource);
+        costs.insert(id, context);
+    }
+
+    Ok(costs)
+}
+
+pub async fn export(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: ExportArgs,
+    transforms: &[String],
+) -> Result<(), CliError> {
+    let run = Run::start("export", api.clock());
+    let mut points = 0;
+    let mut warnings = Vec::new();
+    let result =
+        export_readings(&api, options, &args, transforms, &mut points, &mut warnings).await;
+
+    print_warnings(&warnings);
+    let summary = run
+        .finish(points, 0, result.as_ref().err())
+        .warnings(warnings);
+    notify(&args.notify, &summary).await;
+
+    result
+}
+
+async fn export_readings(
+    api: &GlowmarktApi,
+    options: OutputOptions,
+    args: &ExportArgs,
+    transforms: &[String],
+    points: &mut usize,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), CliError> {
+    if args.with_cost && args.source != SourceKind::Cloud {
+        return Err("Only the cloud source can export costs with --with-cost"
+            .to_string()
+            .into());
+    }
+
+    let source = Source::open(api, args)?;
+    let pipeline = args.transform.pipeline(transforms)?;
+    let start = parse_date(args.from.clone(), args.period, api)?;
+    let end = parse_end_date(args.to.clone(), args.period, api)?;
+    let ranges = split_periods(start, end, args.period);
+
+    let mut contexts = api.resource_contexts(&args.resources).await?;
+    for context in contexts.iter_mut() {
+        transform_resource(&pipeline, &mut context.resource);
+    }
+    if matches!(args.period, ReadingPeriod::Minute) {
+        let resource_types = api.resource_types().await?;
+        for context in &contexts {
+            context
+                .resource
+                .check_period(&resource_types, args.period)?;
+        }
+    }
+    let costs = if args.with_cost {
+        cost_contexts(api, &pipeline, &mut contexts).await?
+    } else {
+        HashMap::new()
+    };
+    let mut sink = sink(args.sink, options, args.csv, &args.sinks).await?;
+    // The start of every reading received for each resource, before any
+    // transforms that might fill gaps.
+    let mut received: BTreeMap<String, BTreeSet<OffsetDateTime>> = BTreeMap::new();
+    let filler = || Filler::new(args.period, *api.calendar(), args.fill);
+    let fill_range =
+        |filler: &mut Filler, readings, (start, end): (OffsetDateTime, OffsetDateTime)| {
+            // Readings can't be missing from the future.
+            let (end, _) = api.clamp_to_now(&end);
+            filler.fill(readings, start, end)
+        };
+
+    'resources: for context in &contexts {
+        if args.fail_on_gaps {
+            received.insert(context.resource.id.clone(), BTreeSet::new());
+        }
+
+        if let Some(cost) = costs.get(&context.resource.id) {
+            if args.fail_on_gaps {
+                received.insert(cost.resource.id.clone(), BTreeSet::new());
+            }
+            let mut energy_filler = filler();
+            let mut cost_filler = filler();
+            // The stream yields a chunk for each range in order.
+            let mut chunks = pin!(api
+                .paired_readings_stream(
+                    &context.resource.id,
+                    &cost.resource.id,
+                    &start,
+                    &end,
+                    args.period,
+                )
+                .zip(stream::iter(ranges.iter().copied())));
+            while let Some((chunk, range)) = chunks.next().await {
+                let (energy, cost_readings): (Vec<_>, Vec<_>) = chunk?
+                    .into_iter()
+                    .map(|pair| (pair.energy, pair.cost))
+                    .unzip();
+                record_starts(&mut received, &context.resource.id, energy.iter().flatten());
+                record_starts(
+                    &mut received,
+                    &cost.resource.id,
+                    cost_readings.iter().flatten(),
+                );
+                let energy = pipeline.apply(fill_range(
+                    &mut energy_filler,
+                    energy.into_iter().flatten().collect(),
+                    range,
+                ));
+                let cost_readings = pipeline.apply(fill_range(
+                    &mut cost_filler,
+                    cost_readings.into_iter().flatten().collect(),
+                    range,
+                ));
+
+                sink.write_readings(context, &energy).await?;
+                sink.write_readings(cost, &cost_readings).await?;
+                *points += energy.len() + cost_readings.len();
+            }
+
+            let energy = pipeline.apply(energy_filler.finish());
+            let cost_readings = pipeline.apply(cost_filler.finish());
+            sink.write_readings(context, &energy).await?;
+            sink.write_readings(cost, &cost_readings).await?;
+            *points += energy.len() + cost_readings.len();
+            continue;
+        }
+
+        let mut filler = filler();
+        for (start, end) in &ranges {
+            let fetched = source
+                .fetch(&context.resource, *start, *end, args.period)
+                .await;
+            let readings = match fetched {
+                Ok((readings, fetch_warnings)) => {
+                    warnings.extend(fetch_warnings);
+                    record_starts(&mut received, &context.resource.id, &readings);
+                    pipeline.apply(fill_range(&mut filler, readings, (*start, *end)))
+                }
+                // Other resources may still have readings to export.
+                Err(e) if e.is_unavailable() && contexts.len() > 1 => {
+                    received.remove(&context.resource.id);
+                    warnings.push(Warning::SkippedResource {
+                        resource_id: context.resource.id.clone(),
+                        reason: e.to_string(),
+                    });
+                    continue 'resources;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            sink.write_readings(context, &readings).await?;
+            *points += readings.len();
+        }
+
+        let readings = pipeline.apply(filler.finish());
+        sink.write_readings(context, &readings).await?;
+        *points += readings.len();
+    }
+
+    sink.flush()?;
+
+    if args.fail_on_gaps {
+        // Readings can't be missing from the future.
+        let (end, _) = api.clamp_to_now(&end);
+        let mut total = 0;
+        for (resource_id, starts) in received {
+            let missing = missing_starts(starts, start, end, args.period, api.calendar());
+            if let Some(first) = missing.first() {
+                total += missing.len();
+                warnings.push(Warning::MissingReadings {
+                    resource_id,
+                    missing: missing.len(),
+                    first: *first,
+                });
+            }
+        }
+
+        if total > 0 {
+            return Err(format!("{} readings were missing from the export", total).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Notes the readings received for a resource when checking for gaps.
+fn record_starts<'a, I>(
+    received: &mut BTreeMap<String, BTreeSet<OffsetDateTime>>,
+    resource_id: &str,
+    readings: I,
+) where
+    I: IntoIterator<Item = &'a Reading>,
+{
+    if let Some(starts) = received.get_mut(resource_id) {
+        starts.extend(readings.into_iter().map(|reading| reading.start));
+    }
+}
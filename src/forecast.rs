@@ -0,0 +1,213 @@
+//! Keeps the budget's month end projections so they can be checked against
+//! what was actually spent once each month is over, showing how far the
+//! projections can be trusted.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::{format::round, settlement::uk_offset, GlowmarktApi};
+use serde::{Deserialize, Serialize};
+use serde_json::to_string_pretty;
+use time::{Date, Month, OffsetDateTime};
+
+use crate::{
+    budget::{month_name, month_spent, BudgetStatus},
+    config::BudgetConfig,
+    hint::CliError,
+    output::OutputOptions,
+    state, ErrorStr,
+};
+
+#[derive(clap::Args, Clone)]
+pub struct ForecastOptions {
+    /// The file recording past budget projections, defaults to
+    /// `$XDG_DATA_HOME/glowmarkt/forecasts.json`.
+    #[clap(long, env = "GLOWMARKT_FORECASTS")]
+    forecasts: Option<PathBuf>,
+}
+
+impl ForecastOptions {
+    pub fn path(&self) -> Result<PathBuf, CliError> {
+        self.forecasts
+            .clone()
+            .or_else(|| state::xdg_path("XDG_DATA_HOME", &[".local", "share"], "forecasts.json"))
+            .ok_or_else(|| {
+                "No forecast file location, pass --forecasts"
+                    .to_string()
+                    .into()
+            })
+    }
+}
+
+/// A projection of the month's total spending.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Forecast {
+    /// The month projected, e.g. `2024-03`.
+    month: String,
+    #[serde(with = "time::serde::rfc3339")]
+    made: OffsetDateTime,
+    /// What had been spent when the projection was made.
+    spent: f64,
+    projected: f64,
+}
+
+/// Projections made and what was eventually spent.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct ForecastLog {
+    /// The last projection made on each UK day, keyed by the date.
+    #[serde(default)]
+    forecasts: BTreeMap<String, Forecast>,
+    /// What was spent in each completed month, keyed by the month.
+    #[serde(default)]
+    actuals: BTreeMap<String, f64>,
+}
+
+fn uk_date(date: OffsetDateTime) -> Date {
+    date.to_offset(uk_offset(date)).date()
+}
+
+/// Records a budget status's projection as the forecast for the current day,
+/// replacing any made earlier in the day.
+pub fn record_forecast(
+    path: &Path,
+    status: &BudgetStatus,
+    now: OffsetDateTime,
+) -> Result<(), CliError> {
+    let Some(projected) = status.projected else {
+        return Ok(());
+    };
+
+    let mut log: ForecastLog = state::load(path)?;
+    log.forecasts.insert(
+        uk_date(now).to_string(),
+        Forecast {
+            month: status.month.clone(),
+            made: now,
+            spent: status.spent,
+            projected,
+        },
+    );
+    state::save(path, &log)?;
+
+    Ok(())
+}
+
+/// Parses a month as used in budget statuses into its first day.
+fn parse_month(month: &str) -> Option<Date> {
+    let (year, month) = month.split_once('-')?;
+    let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+    Date::from_calendar_date(year.parse().ok()?, month, 1).ok()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DayAccuracy {
+    date: String,
+    month: String,
+    spent: f64,
+    projected: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    actual: Option<f64>,
+    /// The projection less the actual spend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_percent: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Accuracy {
+    forecasts: usize,
+    /// The forecasts for months that have finished.
+    evaluated: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_absolute_error: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mean_absolute_percent_error: Option<f64>,
+    /// The mean error, positive when projections run high.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bias: Option<f64>,
+    days: Vec<DayAccuracy>,
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+pub async fn forecast_accuracy(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    forecasts: ForecastOptions,
+    config: Option<BudgetConfig>,
+) -> Result<(), CliError> {
+    let config = config.ok_or_else(|| {
+        "No budget is configured, add a [budget] section to the config file".to_string()
+    })?;
+    let path = forecasts.path()?;
+    let mut log: ForecastLog = state::load(&path)?;
+
+    // Actual spending is fetched once for each month that has finished.
+    let current = month_name(uk_date(api.clock().now()).replace_day(1).unwrap());
+    let months: Vec<String> = log
+        .forecasts
+        .values()
+        .map(|forecast| forecast.month.clone())
+        .filter(|month| *month < current && !log.actuals.contains_key(month))
+        .collect();
+    for month in months {
+        if log.actuals.contains_key(&month) {
+            continue;
+        }
+        let first = parse_month(&month)
+            .ok_or_else(|| format!("Invalid month '{}' in {}", month, path.display()))?;
+        let actual = month_spent(&api, &config, first).await?;
+        log.actuals.insert(month, actual);
+    }
+    state::save(&path, &log)?;
+
+    let round = |value: f64| round(value, options.precision);
+    let days: Vec<DayAccuracy> = log
+        .forecasts
+        .iter()
+        .map(|(date, forecast)| {
+            let actual = log.actuals.get(&forecast.month).copied();
+            let error = actual.map(|actual| forecast.projected - actual);
+            DayAccuracy {
+                date: date.clone(),
+                month: forecast.month.clone(),
+                spent: round(forecast.spent),
+                projected: round(forecast.projected),
+                actual: actual.map(round),
+                error: error.map(round),
+                error_percent: actual
+                    .zip(error)
+                    .filter(|(actual, _)| *actual != 0.0)
+                    .map(|(actual, error)| round(error / actual * 100.0)),
+            }
+        })
+        .collect();
+
+    let errors = || days.iter().filter_map(|day| day.error);
+    let accuracy = Accuracy {
+        forecasts: days.len(),
+        evaluated: errors().count(),
+        mean_absolute_error: mean(errors().map(f64::abs)).map(round),
+        mean_absolute_percent_error: mean(
+            days.iter()
+                .filter_map(|day| day.error_percent)
+                .map(f64::abs),
+        )
+        .map(round),
+        bias: mean(errors()).map(round),
+        days,
+    };
+
+    println!("{}", to_string_pretty(&accuracy).str_err()?);
+    Ok(())
+}
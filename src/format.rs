@@ -0,0 +1,309 @@
+//! Formatting readings for export.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{settlement::settlement_period, Reading};
+
+/// How timestamps are written in exported data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    /// RFC 3339, e.g. `2023-01-01T00:30:00Z`.
+    #[default]
+    Rfc3339,
+    /// Seconds since the unix epoch.
+    Unix,
+    /// Milliseconds since the unix epoch.
+    UnixMillis,
+}
+
+impl TimestampFormat {
+    /// Formats a timestamp.
+    pub fn format(&self, date: OffsetDateTime) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => date.format(&Rfc3339).unwrap(),
+            TimestampFormat::Unix => date.unix_timestamp().to_string(),
+            TimestampFormat::UnixMillis => (date.unix_timestamp_nanos() / 1_000_000).to_string(),
+        }
+    }
+
+    /// Serializes a timestamp, as a string for RFC 3339 and as a number
+    /// otherwise.
+    pub fn serialize<S: Serializer>(
+        &self,
+        date: OffsetDateTime,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match self {
+            TimestampFormat::Rfc3339 => time::serde::rfc3339::serialize(&date, serializer),
+            TimestampFormat::Unix => serializer.serialize_i64(date.unix_timestamp()),
+            TimestampFormat::UnixMillis => {
+                serializer.serialize_i64((date.unix_timestamp_nanos() / 1_000_000) as i64)
+            }
+        }
+    }
+
+    /// Wraps readings so they serialize with timestamps in this format.
+    ///
+    /// ```
+    /// # use glowmarkt::{format::TimestampFormat, Reading};
+    /// # fn encode(readings: &[Reading]) -> serde_json::Result<String> {
+    /// serde_json::to_string(&TimestampFormat::UnixMillis.wrap(readings))
+    /// # }
+    /// ```
+    pub fn wrap<T: ?Sized>(self, value: &T) -> WithTimestamps<'_, T> {
+        WithTimestamps {
+            value,
+            format: self,
+        }
+    }
+}
+
+/// Readings that serialize with timestamps in a chosen [`TimestampFormat`].
+///
+/// Implemented for a single [`Reading`], slices and `Vec`s of readings.
+#[derive(Debug, Clone, Copy)]
+pub struct WithTimestamps<'a, T: ?Sized> {
+    value: &'a T,
+    format: TimestampFormat,
+}
+
+struct Timestamp(OffsetDateTime, TimestampFormat);
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.1.serialize(self.0, serializer)
+    }
+}
+
+impl Serialize for WithTimestamps<'_, Reading> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Reading", 3)?;
+        state.serialize_field("start", &Timestamp(self.value.start, self.format))?;
+        state.serialize_field("value", &self.value.value)?;
+        match self.value.quality {
+            Some(ref quality) => state.serialize_field("quality", quality)?,
+            None => state.skip_field("quality")?,
+        }
+        state.end()
+    }
+}
+
+impl Serialize for WithTimestamps<'_, [Reading]> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.value.iter().map(|reading| self.format.wrap(reading)))
+    }
+}
+
+impl Serialize for WithTimestamps<'_, Vec<Reading>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.format
+            .wrap(self.value.as_slice())
+            .serialize(serializer)
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "unix" => Ok(TimestampFormat::Unix),
+            "unix-ms" => Ok(TimestampFormat::UnixMillis),
+            _ => Err(format!(
+                "Unknown timestamp format '{}', expected one of rfc3339, unix or unix-ms",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            TimestampFormat::Rfc3339 => "rfc3339",
+            TimestampFormat::Unix => "unix",
+            TimestampFormat::UnixMillis => "unix-ms",
+        })
+    }
+}
+
+/// Rounds a value to the given number of decimal places.
+///
+/// Readings are single precision floats so converting them to other types
+/// often shows artifacts like `0.12300000339`, rounding removes these.
+///
+/// Values are returned unchanged if there are too many places to represent.
+pub fn round(value: f64, precision: Option<u32>) -> f64 {
+    match precision {
+        Some(places) => {
+            let factor = 10_f64.powi(places.min(i32::MAX as u32) as i32);
+            let rounded = (value * factor).round() / factor;
+            if rounded.is_finite() {
+                rounded
+            } else {
+                value
+            }
+        }
+        None => value,
+    }
+}
+
+/// Quotes a CSV field if it contains characters that need escaping.
+pub fn csv_field(field: &str) -> String {
+    delimited_field(field, ',')
+}
+
+/// Quotes a field for CSV using the given delimiter if it contains characters
+/// that need escaping.
+pub fn delimited_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn write_row<W: Write>(out: &mut W, delimiter: char, fields: &[&str]) -> io::Result<()> {
+    let row: Vec<String> = fields
+        .iter()
+        .map(|field| delimited_field(field, delimiter))
+        .collect();
+    writeln!(out, "{}", row.join(&delimiter.to_string()))
+}
+
+/// Writes readings as CSV rows of `timestamp,value,unit,classifier`.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    resource: Option<String>,
+    unit: String,
+    classifier: String,
+    timestamp_format: TimestampFormat,
+    settlement_period: bool,
+    precision: Option<u32>,
+    delimiter: char,
+    decimal_comma: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Creates a writer for readings from a resource with the given unit and
+    /// classifier.
+    pub fn new(out: W, unit: Option<&str>, classifier: Option<&str>) -> Self {
+        Self {
+            out,
+            resource: None,
+            unit: unit.unwrap_or_default().to_owned(),
+            classifier: classifier.unwrap_or_default().to_owned(),
+            timestamp_format: Default::default(),
+            settlement_period: false,
+            precision: None,
+            delimiter: ',',
+            decimal_comma: false,
+        }
+    }
+
+    /// Sets the character separating fields. Defaults to a comma.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Writes values with a comma as the decimal separator, as expected in
+    /// many European locales. This is normally combined with a different
+    /// delimiter.
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> Self {
+        self.decimal_comma = decimal_comma;
+        self
+    }
+
+    /// Sets the format used for timestamps.
+    pub fn timestamp_format(mut self, format: TimestampFormat) -> Self {
+        self.timestamp_format = format;
+        self
+    }
+
+    /// Adds a first column holding the given resource ID, so rows from
+    /// several resources can be told apart.
+    pub fn resource(mut self, resource: Option<&str>) -> Self {
+        self.resource = resource.map(str::to_owned);
+        self
+    }
+
+    /// Adds a column for the UK settlement period of each reading.
+    pub fn settlement_period(mut self, include: bool) -> Self {
+        self.settlement_period = include;
+        self
+    }
+
+    /// Sets the number of decimal places written for values.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Writes the header row.
+    pub fn write_header(&mut self) -> io::Result<()> {
+        let mut columns = Vec::new();
+        if self.resource.is_some() {
+            columns.push("resource");
+        }
+        columns.extend(["timestamp", "value", "unit", "classifier"]);
+        if self.settlement_period {
+            columns.push("settlement_period");
+        }
+        write_row(&mut self.out, self.delimiter, &columns)
+    }
+
+    /// Writes a single reading.
+    pub fn write(&mut self, reading: &Reading) -> io::Result<()> {
+        let timestamp = self.timestamp_format.format(reading.start);
+        let value = self.value(reading.value);
+        let period = settlement_period(reading.start).to_string();
+
+        let mut fields = Vec::new();
+        if let Some(ref resource) = self.resource {
+            fields.push(resource.as_str());
+        }
+        fields.extend([
+            timestamp.as_str(),
+            value.as_str(),
+            self.unit.as_str(),
+            self.classifier.as_str(),
+        ]);
+        if self.settlement_period {
+            fields.push(&period);
+        }
+
+        write_row(&mut self.out, self.delimiter, &fields)
+    }
+
+    fn value(&self, value: f32) -> String {
+        // Missing values are left empty.
+        if value.is_nan() {
+            return String::new();
+        }
+
+        let value = match self.precision {
+            Some(places) => format!("{:.*}", places as usize, value),
+            None => value.to_string(),
+        };
+
+        if self.decimal_comma {
+            value.replace('.', ",")
+        } else {
+            value
+        }
+    }
+
+    /// Flushes any buffered output.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
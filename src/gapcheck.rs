@@ -0,0 +1,100 @@
+//! Lists the periods in a range that a resource has no reading for.
+
+use std::io::{stdout, Write};
+
+use glowmarkt::{
+    gaps::{expected_starts, gaps, Gap},
+    GlowmarktApi, ReadingPeriod,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::format_description::well_known::Rfc3339;
+
+use crate::{
+    hint::CliError, lookup::resolve_resource, output::ReportFormat, parse_date, parse_end_date,
+    ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct GapsArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// The length of each reading (1m, 30m, 1h, 1d, 1w, 1mon or 1y).
+    #[clap(long, default_value = "30m")]
+    period: ReadingPeriod,
+    /// The resource to check, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    resource_id: String,
+    expected: usize,
+    missing: usize,
+    gaps: Vec<Gap>,
+}
+
+fn print_text(report: &Report) -> Result<(), CliError> {
+    let mut out = stdout().lock();
+    writeln!(
+        out,
+        "{} of {} readings missing from {}",
+        report.missing, report.expected, report.resource_id
+    )
+    .str_err()?;
+
+    for gap in &report.gaps {
+        let start = gap.start.format(&Rfc3339).str_err()?;
+        if gap.missing == 1 {
+            writeln!(out, "  {}", start).str_err()?;
+        } else {
+            writeln!(
+                out,
+                "  {} to {} ({} readings)",
+                start,
+                gap.last.format(&Rfc3339).str_err()?,
+                gap.missing
+            )
+            .str_err()?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn gap_report(api: GlowmarktApi, args: GapsArgs) -> Result<(), CliError> {
+    let start = parse_date(args.from, args.period, &api)?;
+    let end = parse_end_date(args.to, args.period, &api)?;
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+
+    // Readings can't be missing from the future.
+    let (end, _) = api.clamp_to_now(&end);
+    let readings = api
+        .readings_range(&resource_id, &start, &end, args.period)
+        .await?;
+
+    let gaps = gaps(&readings, start, end, args.period, api.calendar());
+    let report = Report {
+        resource_id,
+        expected: expected_starts(start, end, args.period, api.calendar()).len(),
+        missing: gaps.iter().map(|gap| gap.missing).sum(),
+        gaps,
+    };
+
+    match args.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&report).str_err()?);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Finding readings missing from a range.
+//!
+//! Smart meter data often has holes where the meter or the DCC failed to
+//! deliver a reading. Comparing the readings received against the start of
+//! every period in the range shows where they are, and [`fill`] can add
+//! explicit readings for them so integrals downstream stay correct.
+
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::{increase_by_period, Calendar, Reading, ReadingPeriod};
+
+/// A run of consecutive periods with no reading.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Gap {
+    /// The start of the first missing period.
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The start of the last missing period.
+    #[serde(with = "time::serde::rfc3339")]
+    pub last: OffsetDateTime,
+    /// The number of periods missing.
+    pub missing: usize,
+}
+
+/// The start of every period in a range, including a period starting at
+/// `end`.
+///
+/// Periods are aligned by the calendar, which should be the one the readings
+/// were requested with, so a `start` part way through a period begins with
+/// the next one.
+pub fn expected_starts(
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    period: ReadingPeriod,
+    calendar: &Calendar,
+) -> Vec<OffsetDateTime> {
+    let mut current = calendar.align(start, period);
+    if current < start {
+        current = increase_by_period(current, period);
+    }
+
+    let mut starts = Vec::new();
+    while current <= end {
+        starts.push(current);
+        current = increase_by_period(current, period);
+    }
+
+    starts
+}
+
+/// The start of every period in a range with no reading, given the start
+/// times of the readings received.
+pub fn missing_starts<I>(
+    received: I,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    period: ReadingPeriod,
+    calendar: &Calendar,
+) -> Vec<OffsetDateTime>
+where
+    I: IntoIterator<Item = OffsetDateTime>,
+{
+    let received: BTreeSet<OffsetDateTime> = received.into_iter().collect();
+
+    expected_starts(start, end, period, calendar)
+        .into_iter()
+        .filter(|expected| !received.contains(expected))
+        .collect()
+}
+
+/// Groups the periods in a range with no reading into runs of consecutive
+/// periods.
+pub fn gaps(
+    readings: &[Reading],
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    period: ReadingPeriod,
+    calendar: &Calendar,
+) -> Vec<Gap> {
+    let received = readings.iter().map(|reading| reading.start);
+    let mut gaps: Vec<Gap> = Vec::new();
+
+    for missing in missing_starts(received, start, end, period, calendar) {
+        match gaps.last_mut() {
+            Some(gap) if increase_by_period(gap.last, period) == missing => {
+                gap.last = missing;
+                gap.missing += 1;
+            }
+            _ => gaps.push(Gap {
+                start: missing,
+                last: missing,
+                missing: 1,
+            }),
+        }
+    }
+
+    gaps
+}
+
+/// How [`fill`] creates readings for periods with no reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FillStrategy {
+    /// Leave the periods out.
+    #[default]
+    None,
+    /// Add readings with a value of zero.
+    Zero,
+    /// Add readings with no value, represented by NaN. Sinks write these as
+    /// empty or null values where they can, Parquet and PostgreSQL store NaN
+    /// and sinks for line protocols that can't represent a missing value
+    /// skip them.
+    Null,
+    /// Add readings with values interpolated linearly between the readings
+    /// either side. Periods before the first or after the last reading have
+    /// nothing to interpolate from and are filled as for `Null`.
+    Interpolate,
+}
+
+impl FillStrategy {
+    /// The name used when parsing.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FillStrategy::None => "none",
+            FillStrategy::Zero => "zero",
+            FillStrategy::Null => "null",
+            FillStrategy::Interpolate => "interpolate",
+        }
+    }
+}
+
+impl FromStr for FillStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FillStrategy::None),
+            "zero" => Ok(FillStrategy::Zero),
+            "null" => Ok(FillStrategy::Null),
+            "interpolate" => Ok(FillStrategy::Interpolate),
+            _ => Err(format!(
+                "Unknown fill strategy '{}', expected one of none, zero, null or interpolate",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FillStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Adds a reading for every period in a range with no reading, as chosen by
+/// the strategy.
+///
+/// Readings must be ordered by start time, as they are returned from the API.
+/// Readings outside the range are kept as they are.
+pub fn fill(
+    readings: Vec<Reading>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    period: ReadingPeriod,
+    calendar: &Calendar,
+    strategy: FillStrategy,
+) -> Vec<Reading> {
+    let value = match strategy {
+        FillStrategy::None => return readings,
+        FillStrategy::Zero => 0.0,
+        FillStrategy::Null | FillStrategy::Interpolate => f32::NAN,
+    };
+
+    let received = readings.iter().map(|reading| reading.start);
+    let missing = missing_starts(received, start, end, period, calendar);
+    if missing.is_empty() {
+        return readings;
+    }
+
+    let mut filled: Vec<Reading> = readings
+        .into_iter()
+        .chain(missing.into_iter().map(|start| Reading {
+            start,
+            period,
+            value,
+            quality: None,
+        }))
+        .collect();
+    filled.sort_by_key(|reading| reading.start);
+
+    if strategy == FillStrategy::Interpolate {
+        interpolate(&mut filled);
+    }
+
+    filled
+}
+
+/// Fills a range one chunk at a time, as [`fill`] would fill the whole range.
+///
+/// When interpolating, missing readings at the end of a chunk are held back
+/// until a later chunk has a reading to interpolate to, and missing readings
+/// at the start of a chunk are interpolated from the last reading of the
+/// chunk before.
+#[derive(Debug, Clone)]
+pub struct Filler {
+    period: ReadingPeriod,
+    calendar: Calendar,
+    strategy: FillStrategy,
+    /// The last reading with a value returned, to interpolate from.
+    previous: Option<Reading>,
+    /// Missing readings waiting for a reading to interpolate to.
+    held: Vec<Reading>,
+}
+
+impl Filler {
+    /// Creates a filler for readings of the given period.
+    pub fn new(period: ReadingPeriod, calendar: Calendar, strategy: FillStrategy) -> Self {
+        Self {
+            period,
+            calendar,
+            strategy,
+            previous: None,
+            held: Vec::new(),
+        }
+    }
+
+    /// Fills the next chunk of the range. Chunks must be given in order.
+    pub fn fill(
+        &mut self,
+        readings: Vec<Reading>,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Vec<Reading> {
+        let filled = fill(
+            readings,
+            start,
+            end,
+            self.period,
+            &self.calendar,
+            self.strategy,
+        );
+        if self.strategy != FillStrategy::Interpolate {
+            return filled;
+        }
+
+        let had_previous = self.previous.is_some();
+        let mut readings: Vec<Reading> = self
+            .previous
+            .take()
+            .into_iter()
+            .chain(self.held.drain(..))
+            .chain(filled)
+            .collect();
+        interpolate(&mut readings);
+
+        let held_from = readings
+            .iter()
+            .rposition(|reading| !reading.value.is_nan())
+            .map(|index| index + 1)
+            .unwrap_or_default();
+        self.held = readings.split_off(held_from);
+        self.previous = readings.last().cloned();
+
+        // The previous reading was returned with the last chunk.
+        if had_previous {
+            readings.remove(0);
+        }
+        readings
+    }
+
+    /// Returns the readings still held back, which had nothing after them to
+    /// interpolate to and so have no value.
+    pub fn finish(&mut self) -> Vec<Reading> {
+        self.previous = None;
+        std::mem::take(&mut self.held)
+    }
+}
+
+/// Replaces runs of NaN values with values on a straight line between the
+/// readings either side.
+fn interpolate(readings: &mut [Reading]) {
+    let mut previous: Option<usize> = None;
+
+    for index in 0..readings.len() {
+        if readings[index].value.is_nan() {
+            continue;
+        }
+
+        if let Some(before) = previous.filter(|before| index - before > 1) {
+            let from = readings[before].value;
+            let to = readings[index].value;
+            let steps = (index - before) as f32;
+            for (step, reading) in readings[before + 1..index].iter_mut().enumerate() {
+                reading.value = from + (to - from) * (step + 1) as f32 / steps;
+            }
+        }
+        previous = Some(index);
+    }
+}
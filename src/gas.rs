@@ -0,0 +1,60 @@
+//! Converting gas volumes to energy.
+//!
+//! Gas meters measure volume, normally in cubic metres, but gas is charged
+//! for by the kWh. Suppliers convert using the calorific value of the gas,
+//! which varies a little from day to day, and a correction for temperature and
+//! pressure. The defaults here match the typical values on UK bills.
+
+use crate::{unit::Unit, Reading, TypedReading};
+
+/// A typical calorific value of UK gas in megajoules per cubic metre.
+pub const DEFAULT_CALORIFIC_VALUE: f64 = 39.5;
+
+/// The standard UK volume correction factor for temperature and pressure.
+pub const DEFAULT_VOLUME_CORRECTION: f64 = 1.02264;
+
+/// The number of megajoules in a kWh.
+const MEGAJOULES_PER_KWH: f64 = 3.6;
+
+/// How to convert gas volumes in cubic metres to energy in kWh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GasConversion {
+    /// The calorific value in megajoules per cubic metre.
+    pub calorific_value: f64,
+    /// The volume correction factor.
+    pub volume_correction: f64,
+}
+
+impl Default for GasConversion {
+    fn default() -> Self {
+        Self {
+            calorific_value: DEFAULT_CALORIFIC_VALUE,
+            volume_correction: DEFAULT_VOLUME_CORRECTION,
+        }
+    }
+}
+
+impl GasConversion {
+    /// Converts a volume in cubic metres to kWh.
+    pub fn kwh(&self, cubic_metres: f64) -> f64 {
+        cubic_metres * self.volume_correction * self.calorific_value / MEGAJOULES_PER_KWH
+    }
+
+    /// Converts readings in cubic metres to kWh in place.
+    pub fn convert(&self, readings: &mut [Reading]) {
+        for reading in readings {
+            reading.value = self.kwh(reading.value as f64) as f32;
+        }
+    }
+
+    /// Converts any readings in cubic metres to kWh in place, leaving readings
+    /// in other units alone.
+    pub fn convert_typed(&self, readings: &mut [TypedReading]) {
+        for reading in readings {
+            if reading.unit == Some(Unit::CubicMetres) {
+                reading.value = self.kwh(reading.value as f64) as f32;
+                reading.unit = Some(Unit::KilowattHours);
+            }
+        }
+    }
+}
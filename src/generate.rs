@@ -0,0 +1,223 @@
+//! Synthetic readings for building and testing dashboards before real data
+//! is available.
+
+use std::{
+    f64::consts::PI,
+    io::{stdout, BufWriter, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use glowmarkt::{
+    align_to_period, classifier::Classifier, clock::Clock, format::CsvWriter,
+    settlement::uk_offset, unit::Unit, AggregationFunction, Reading, ReadingPeriod, Resource,
+};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    hint::CliError,
+    legacy::LegacyReadings,
+    output::{CsvOptions, JsonWriter, LegacyWriter, NdjsonWriter, OutputOptions, ReadingsWriter},
+    ErrorStr, Format,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Profile {
+    /// Household electricity use with morning and evening peaks.
+    Household,
+    /// Gas central heating and hot water, much higher in winter.
+    Gas,
+    /// Constant use with a little noise.
+    Flat,
+}
+
+impl Profile {
+    fn classifier(&self) -> Classifier {
+        match self {
+            Profile::Household | Profile::Flat => Classifier::ElectricityConsumption,
+            Profile::Gas => Classifier::GasConsumption,
+        }
+    }
+
+    /// The typical use in kWh for the half hour starting at the given local
+    /// hour of a day in the given season, where 1 is midwinter and 0 is
+    /// midsummer.
+    fn expected(&self, hour: f64, season: f64, weekend: bool) -> f64 {
+        let peak = |centre: f64, width: f64| (-((hour - centre) / width).powi(2)).exp();
+
+        match self {
+            Profile::Household => {
+                let daytime = if weekend && (9.0..17.0).contains(&hour) {
+                    0.12
+                } else {
+                    0.04
+                };
+                let use_ = 0.08 + daytime + 0.25 * peak(7.5, 1.0) + 0.45 * peak(19.0, 2.0);
+                use_ * (0.85 + 0.3 * season)
+            }
+            Profile::Gas => {
+                let heating = 4.0 * peak(7.0, 1.0) + 3.5 * peak(19.5, 2.0) + 0.3;
+                let hot_water = 0.4 * peak(7.5, 0.5) + 0.2 * peak(21.0, 0.5);
+                heating * (0.05 + 0.95 * season) + hot_water
+            }
+            Profile::Flat => 0.25,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct GenerateArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: Format,
+    /// The kind of use to simulate.
+    #[clap(long, value_enum, default_value = "household")]
+    profile: Profile,
+    /// The number of days of readings to generate, ending now.
+    #[clap(long, default_value = "30")]
+    days: u32,
+    /// A seed for the random noise, for repeatable output.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// Don't leave gaps of zero readings as happens when meter data is
+    /// delayed.
+    #[clap(long)]
+    no_gaps: bool,
+    #[clap(flatten)]
+    csv: CsvOptions,
+}
+
+/// A small xorshift generator, statistical quality doesn't matter here.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    /// Returns a number in `[0, 1)`.
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// How much of the time a gap of missing readings starts.
+const GAP_CHANCE: f64 = 0.002;
+/// The longest gap in half hours.
+const MAX_GAP: u32 = 12;
+
+fn generate_readings(args: &GenerateArgs, end: OffsetDateTime) -> Vec<Reading> {
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let mut random = Random::new(seed);
+
+    let start = end - Duration::days(args.days as i64);
+    let mut readings = Vec::new();
+    let mut gap = 0;
+    let mut time = start;
+
+    while time < end {
+        let local = time.to_offset(uk_offset(time));
+        let hour = local.hour() as f64 + local.minute() as f64 / 60.0;
+        // Peaks in mid January.
+        let season = (1.0 + (2.0 * PI * (local.ordinal() as f64 - 15.0) / 365.0).cos()) / 2.0;
+        let weekend = local.weekday().number_days_from_monday() >= 5;
+
+        if !args.no_gaps && gap == 0 && random.next() < GAP_CHANCE {
+            gap = 1 + (random.next() * MAX_GAP as f64) as u32;
+        }
+
+        let value = if gap > 0 {
+            gap -= 1;
+            0.0
+        } else {
+            let mut value = args.profile.expected(hour, season, weekend);
+            value *= 0.8 + 0.4 * random.next();
+            if matches!(args.profile, Profile::Household) && random.next() < 0.03 {
+                // Occasional short loads such as a kettle or oven.
+                value += 0.3 + 0.5 * random.next();
+            }
+            value
+        };
+
+        readings.push(Reading {
+            start: time,
+            period: ReadingPeriod::HalfHour,
+            value: value as f32,
+            quality: None,
+        });
+        time += Duration::minutes(30);
+    }
+
+    readings
+}
+
+fn synthetic_resource(profile: Profile, now: OffsetDateTime) -> Resource {
+    Resource {
+        id: "synthetic".to_string(),
+        name: "Synthetic data".to_string(),
+        description: None,
+        label: None,
+        active: true,
+        type_id: "synthetic".to_string(),
+        owner_id: "synthetic".to_string(),
+        classifier: Some(profile.classifier()),
+        base_unit: Some("kWh".to_string()),
+        data_source_type: "synthetic".to_string(),
+        data_source_resource_type_info: None,
+        data_source_unit_info: None,
+        updated_at: now,
+        created_at: now,
+    }
+}
+
+/// Writes synthetic half-hourly readings in any of the readings formats.
+pub fn generate(
+    options: OutputOptions,
+    args: &GenerateArgs,
+    clock: &dyn Clock,
+) -> Result<(), CliError> {
+    let now = clock.now();
+    let end = align_to_period(now, ReadingPeriod::HalfHour);
+    let resource = synthetic_resource(args.profile, now);
+    let readings = generate_readings(args, end);
+
+    let out: Box<dyn Write> = Box::new(BufWriter::new(stdout().lock()));
+    let mut writer: Box<dyn ReadingsWriter> = match args.format {
+        Format::Json => Box::new(JsonWriter::new(out, options).unit(resource.unit())),
+        Format::Ndjson => Box::new(NdjsonWriter::new(out, options).unit(resource.unit())),
+        Format::Legacy => Box::new(LegacyWriter::new(
+            out,
+            LegacyReadings::new(
+                &resource,
+                end - Duration::days(args.days as i64),
+                end,
+                ReadingPeriod::HalfHour,
+                AggregationFunction::Sum,
+            ),
+            options,
+        )),
+        Format::Csv => {
+            let csv = CsvWriter::new(
+                out,
+                resource.unit().as_ref().map(Unit::as_str),
+                resource.classifier.as_ref().map(Classifier::as_str),
+            );
+            let mut csv = args.csv.apply(csv).precision(options.precision);
+            csv.write_header().str_err()?;
+            Box::new(csv)
+        }
+    };
+
+    writer.write_chunk(&readings).str_err()?;
+    writer.finish().str_err()?;
+
+    Ok(())
+}
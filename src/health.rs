@@ -0,0 +1,106 @@
+//! Assessing whether a resource's data is arriving as expected.
+//!
+//! Meters that lose their connection to the DCC stop delivering readings, or
+//! deliver runs of zeros, long before anyone notices. [`ResourceHealth`]
+//! summarises how fresh and complete a resource's recent data is.
+
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+
+use crate::{align_to_period, Error, GlowmarktApi, Reading, ReadingPeriod, Resource};
+
+/// How far back health is assessed.
+pub const HEALTH_WINDOW: Duration = Duration::days(30);
+
+/// Data this old is expected given normal DCC delays.
+const EXPECTED_DELAY: Duration = Duration::hours(24);
+
+/// A summary of the recent data for a resource.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceHealth {
+    /// The resource.
+    pub resource_id: String,
+    /// The resource's name.
+    pub name: String,
+    /// The end of the latest non-zero reading.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub latest: Option<OffsetDateTime>,
+    /// How long ago the latest non-zero reading ended, in seconds.
+    pub staleness: Option<f64>,
+    /// The percentage of half-hourly readings missing over the window.
+    pub gap_percentage: f64,
+    /// The longest run of zero readings in half hours.
+    pub longest_zero_run: usize,
+    /// An overall score from 0, no useful data, to 100.
+    pub score: f64,
+}
+
+impl ResourceHealth {
+    /// Assesses the half-hourly readings for a resource covering the
+    /// [`HEALTH_WINDOW`] before `now`.
+    ///
+    /// The score starts at 100 and loses the gap percentage, a point for each
+    /// hour of data delayed beyond a day up to 50 and a point for each hour of
+    /// the longest zero run beyond 12 hours up to 25.
+    pub fn assess(resource: &Resource, readings: &[Reading], now: OffsetDateTime) -> Self {
+        let start = align_to_period(now - HEALTH_WINDOW, ReadingPeriod::HalfHour);
+        let expected = ((now - start).whole_minutes() / 30).max(1) as f64;
+
+        let received = readings
+            .iter()
+            .filter(|reading| reading.start >= start && reading.start < now)
+            .count() as f64;
+        let gap_percentage = (100.0 * (expected - received) / expected).clamp(0.0, 100.0);
+
+        let latest = readings
+            .iter()
+            .rev()
+            .find(|reading| reading.value != 0.0)
+            .map(|reading| reading.start + Duration::minutes(30));
+        let staleness = latest.map(|latest| (now - latest).as_seconds_f64().max(0.0));
+
+        let mut longest_zero_run = 0;
+        let mut run = 0;
+        for reading in readings {
+            if reading.value == 0.0 {
+                run += 1;
+                longest_zero_run = longest_zero_run.max(run);
+            } else {
+                run = 0;
+            }
+        }
+
+        let delay_penalty = match staleness {
+            Some(staleness) => {
+                ((staleness - EXPECTED_DELAY.as_seconds_f64()) / 3600.0).clamp(0.0, 50.0)
+            }
+            None => 50.0,
+        };
+        let zero_penalty = ((longest_zero_run as f64 - 24.0) / 2.0).clamp(0.0, 25.0);
+        let score = (100.0 - gap_percentage - delay_penalty - zero_penalty).max(0.0);
+
+        Self {
+            resource_id: resource.id.clone(),
+            name: resource.name.clone(),
+            latest,
+            staleness,
+            gap_percentage,
+            longest_zero_run,
+            score,
+        }
+    }
+}
+
+impl GlowmarktApi {
+    /// Assesses the health of a resource's data over the last 30 days.
+    pub async fn health(&self, resource: &Resource) -> Result<ResourceHealth, Error> {
+        let now = self.clock().now();
+        let start = align_to_period(now - HEALTH_WINDOW, ReadingPeriod::HalfHour);
+        let readings = self
+            .readings_range(&resource.id, &start, &now, ReadingPeriod::HalfHour)
+            .await?;
+
+        Ok(ResourceHealth::assess(resource, &readings, now))
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use glowmarkt::{
+    event::is_event_resource, health::ResourceHealth, sink::Measurement, GlowmarktApi,
+};
+use serde_json::to_string_pretty;
+
+use crate::{dashboard::fuel, hint::CliError, ErrorStr};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HealthFormat {
+    /// A JSON array of health summaries.
+    Json,
+    /// InfluxDB line protocol.
+    Influx,
+    /// Prometheus text exposition format.
+    Prometheus,
+}
+
+#[derive(clap::Args)]
+pub struct HealthArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: HealthFormat,
+    /// The resources to check. If absent all consumption resources are
+    /// checked.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+}
+
+/// Escapes a Prometheus label value.
+pub fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A Prometheus metric name, its help text and how to get its value.
+type Metric = (
+    &'static str,
+    &'static str,
+    fn(&ResourceHealth) -> Option<f64>,
+);
+
+fn prometheus(healths: &[ResourceHealth]) -> String {
+    let metrics: [Metric; 4] = [
+        (
+            "glowmarkt_resource_staleness_seconds",
+            "Time since the latest non-zero reading ended.",
+            |health| health.staleness,
+        ),
+        (
+            "glowmarkt_resource_gap_percentage",
+            "Percentage of half-hourly readings missing over 30 days.",
+            |health| Some(health.gap_percentage),
+        ),
+        (
+            "glowmarkt_resource_longest_zero_run",
+            "Longest run of zero readings over 30 days in half hours.",
+            |health| Some(health.longest_zero_run as f64),
+        ),
+        (
+            "glowmarkt_resource_health_score",
+            "Overall data health from 0 to 100.",
+            |health| Some(health.score),
+        ),
+    ];
+
+    let mut out = String::new();
+    for (name, help, value) in metrics {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+        for health in healths {
+            if let Some(value) = value(health) {
+                out.push_str(&format!(
+                    "{}{{resource=\"{}\",name=\"{}\"}} {}\n",
+                    name,
+                    escape_label(&health.resource_id),
+                    escape_label(&health.name),
+                    value
+                ));
+            }
+        }
+    }
+
+    out
+}
+
+pub async fn health(api: GlowmarktApi, args: HealthArgs) -> Result<(), CliError> {
+    let resources = api.resources().await?;
+    let selected: Vec<_> = if args.resources.is_empty() {
+        resources
+            .into_values()
+            .filter(|resource| fuel(resource).is_some() && !is_event_resource(resource))
+            .collect()
+    } else {
+        args.resources
+            .iter()
+            .map(|id| {
+                resources
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| format!("Unknown resource {}", id))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut healths = Vec::new();
+    for resource in &selected {
+        healths.push(api.health(resource).await?);
+    }
+    healths.sort_by(|a, b| a.resource_id.cmp(&b.resource_id));
+
+    match args.format {
+        HealthFormat::Json => println!("{}", to_string_pretty(&healths).str_err()?),
+        HealthFormat::Prometheus => print!("{}", prometheus(&healths)),
+        HealthFormat::Influx => {
+            let now = api.clock().now();
+            for health in &healths {
+                let mut tags = BTreeMap::new();
+                tags.insert("resource-id".to_string(), health.resource_id.clone());
+                tags.insert("resource".to_string(), health.name.clone());
+
+                let mut measurement = Measurement::new("glowmarkt_health", now, tags);
+                if let Some(staleness) = health.staleness {
+                    measurement.add_field("staleness", staleness);
+                }
+                measurement.add_field("gap-percentage", health.gap_percentage);
+                measurement.add_field("longest-zero-run", health.longest_zero_run as f64);
+                measurement.add_field("score", health.score);
+                println!("{}", measurement);
+            }
+        }
+    }
+
+    Ok(())
+}
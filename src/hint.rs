@@ -0,0 +1,78 @@
+use std::fmt;
+
+use glowmarkt::{Error, ErrorKind};
+
+/// An error from a CLI command.
+pub enum CliError {
+    /// An error returned by the API.
+    Api(Error),
+    /// Any other failure.
+    Message(String),
+}
+
+impl CliError {
+    /// A suggestion for how the user might resolve this error.
+    pub fn hint(&self) -> Option<&'static str> {
+        let error = match self {
+            CliError::Api(error) => error,
+            CliError::Message(_) => return None,
+        };
+
+        match error.kind() {
+            ErrorKind::NotAuthenticated => Some(
+                "The token may have expired or the credentials are wrong. Pass --username and \
+                --password to generate a new token.",
+            ),
+            ErrorKind::RateLimited => Some(
+                "The API is limiting the rate of requests. Wait a few minutes before trying \
+                again.",
+            ),
+            ErrorKind::NotFound => Some(
+                "Check that the ID is correct, `glowmarkt device` and `glowmarkt resource` list \
+                the IDs available to your account.",
+            ),
+            ErrorKind::Client => Some(
+                "The API rejected the request. Check that the application ID is correct and try \
+                requesting a shorter date range.",
+            ),
+            ErrorKind::Server => {
+                Some("The Glowmarkt API is having problems, try again in a little while.")
+            }
+            ErrorKind::Network => {
+                Some("The Glowmarkt API could not be reached, check your network connection.")
+            }
+            ErrorKind::NoTariff => {
+                Some("No tariff has been configured for this resource in the Bright app.")
+            }
+            ErrorKind::UnsupportedPeriod => Some(
+                "Only real-time resources such as instantaneous power support per-minute \
+                readings, try a period of 30m or longer.",
+            ),
+            ErrorKind::Storage => {
+                Some("Check that the cache file can be written to, or delete it to start again.")
+            }
+            ErrorKind::Response => None,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Api(error) => error.fmt(f),
+            CliError::Message(message) => f.pad(message),
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(error: Error) -> Self {
+        CliError::Api(error)
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError::Message(message)
+    }
+}
@@ -0,0 +1,266 @@
+//! Publishing readings to Home Assistant over MQTT.
+//!
+//! Each resource is published as a retained JSON state message along with
+//! discovery messages so Home Assistant creates sensors for it. The messages
+//! are sent with [`MqttSink`](crate::sink::MqttSink), using the MQTT 3.1.1
+//! packets built here.
+
+use std::io;
+
+use serde::Serialize;
+use serde_json::json;
+use time::{OffsetDateTime, Time};
+
+use crate::{
+    settlement::{uk_local, uk_offset},
+    unit::Unit,
+    Error, GlowmarktApi, ReadingPeriod, Resource,
+};
+
+/// The latest figures for a resource, published as its state message.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceState {
+    /// The meter's cumulative register value, if it reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub meter_read: Option<f64>,
+    /// The total so far today, UK time.
+    pub today: f64,
+    /// The most recent delivered half-hourly reading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest: Option<f64>,
+    /// The start of the most recent delivered half-hourly reading.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub latest_start: Option<OffsetDateTime>,
+}
+
+impl ResourceState {
+    /// Retrieves the current state of a resource.
+    pub async fn fetch(api: &GlowmarktApi, resource: &Resource) -> Result<Self, Error> {
+        let now = api.clock().now();
+        let local = now.to_offset(uk_offset(now));
+        let start = uk_local(local.date(), Time::MIDNIGHT);
+
+        let readings = api
+            .readings_range(&resource.id, &start, &now, ReadingPeriod::HalfHour)
+            .await?;
+        let meter_read = match api.meter_read(&resource.id).await {
+            Ok(read) => read.map(|read| read.value),
+            Err(e) => {
+                log::debug!("No meter reading for {}: {}", resource.id, e);
+                None
+            }
+        };
+
+        // Recent readings are zero until the DCC delivers them.
+        let latest = readings.iter().rev().find(|reading| reading.value != 0.0);
+
+        Ok(Self {
+            meter_read,
+            today: readings.iter().map(|reading| reading.value as f64).sum(),
+            latest: latest.map(|reading| reading.value as f64),
+            latest_start: latest.map(|reading| reading.start),
+        })
+    }
+}
+
+/// The topics resources are published to.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    /// The prefix of the topics states are published to.
+    pub prefix: String,
+    /// The prefix Home Assistant listens for discovery messages on.
+    pub discovery_prefix: String,
+}
+
+impl Default for Topics {
+    fn default() -> Self {
+        Self {
+            prefix: "glowmarkt".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+/// The unit Home Assistant expects for a Glowmarkt unit.
+fn ha_unit(unit: &Unit) -> &str {
+    match unit {
+        Unit::CubicMetres => "m³",
+        unit => unit.as_str(),
+    }
+}
+
+impl Topics {
+    /// The topic a resource's state is published to.
+    pub fn state(&self, resource: &Resource) -> String {
+        format!("{}/{}/state", self.prefix, resource.id)
+    }
+
+    /// The topic each of a resource's readings is published to by
+    /// [`MqttSink`](crate::sink::MqttSink).
+    pub fn reading(&self, resource: &Resource) -> String {
+        format!("{}/{}/reading", self.prefix, resource.id)
+    }
+
+    /// The topics and payloads of the discovery messages for a resource, a
+    /// sensor for today's total and, if the meter reports one, a sensor for
+    /// the meter reading.
+    pub fn discovery(&self, resource: &Resource, meter_read: bool) -> Vec<(String, String)> {
+        let is_gas = resource
+            .classifier
+            .as_ref()
+            .map(|classifier| classifier.is_consumption() && classifier.fuel() == "gas")
+            .unwrap_or(false);
+        let unit = resource.unit();
+        let device_class = if is_gas && unit == Some(Unit::CubicMetres) {
+            "gas"
+        } else {
+            "energy"
+        };
+        let device = json!({
+            "identifiers": [format!("glowmarkt_{}", resource.id)],
+            "name": resource.name,
+            "manufacturer": "Glowmarkt",
+        });
+        let state_topic = self.state(resource);
+
+        let mut sensors = vec![("today", "today", "Today")];
+        if meter_read {
+            sensors.push(("total", "meterRead", "Meter reading"));
+        }
+
+        sensors
+            .into_iter()
+            .map(|(suffix, field, name)| {
+                let id = format!("glowmarkt_{}_{}", resource.id, suffix);
+                let config = json!({
+                    "name": name,
+                    "unique_id": id,
+                    "object_id": id,
+                    "state_topic": state_topic,
+                    "value_template": format!("{{{{ value_json.{} }}}}", field),
+                    "unit_of_measurement": unit.as_ref().map(ha_unit),
+                    "device_class": device_class,
+                    "state_class": "total_increasing",
+                    "device": device,
+                });
+                (
+                    format!("{}/sensor/{}/config", self.discovery_prefix, id),
+                    config.to_string(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn encode_length(out: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    encode_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// Builds an MQTT CONNECT packet for a clean session. The broker replies
+/// with a 4 byte CONNACK packet to pass to [`check_connack`].
+pub(crate) fn connect_packet(
+    client_id: &str,
+    username: Option<&str>,
+    password: Option<&str>,
+) -> Vec<u8> {
+    let mut flags = 0x02; // Clean session.
+    if username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+
+    let mut body = Vec::new();
+    encode_string(&mut body, b"MQTT");
+    body.push(4); // Protocol level 3.1.1.
+    body.push(flags);
+    body.extend_from_slice(&60u16.to_be_bytes()); // Keep alive.
+    encode_string(&mut body, client_id.as_bytes());
+    if let Some(username) = username {
+        encode_string(&mut body, username.as_bytes());
+    }
+    if let Some(password) = password {
+        encode_string(&mut body, password.as_bytes());
+    }
+    packet(0x10, &body)
+}
+
+/// Checks the broker's reply to a CONNECT packet accepted the connection.
+pub(crate) fn check_connack(connack: &[u8; 4]) -> io::Result<()> {
+    match connack {
+        [0x20, 0x02, _, 0] => Ok(()),
+        [0x20, 0x02, _, 4 | 5] => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "The MQTT broker rejected the username or password",
+        )),
+        [0x20, 0x02, _, code] => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("The MQTT broker refused the connection with code {}", code),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unexpected response from the MQTT broker",
+        )),
+    }
+}
+
+/// Builds an MQTT PUBLISH packet for a retained message at QoS 0.
+pub(crate) fn publish_packet(topic: &str, payload: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(payload.as_bytes());
+    packet(0x31, &body)
+}
+
+/// Builds an MQTT PUBLISH packet for a retained message at QoS 1. The broker
+/// replies with a 4 byte PUBACK packet to pass to [`check_puback`].
+///
+/// The packet ID must not be zero.
+pub(crate) fn acknowledged_publish_packet(topic: &str, payload: &str, packet_id: u16) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(&mut body, topic.as_bytes());
+    body.extend_from_slice(&packet_id.to_be_bytes());
+    body.extend_from_slice(payload.as_bytes());
+    packet(0x33, &body)
+}
+
+/// Checks the broker's reply acknowledges the QoS 1 PUBLISH packet with the
+/// given ID.
+pub(crate) fn check_puback(puback: &[u8; 4], packet_id: u16) -> io::Result<()> {
+    match puback {
+        [0x40, 0x02, high, low] if u16::from_be_bytes([*high, *low]) == packet_id => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unexpected acknowledgement from the MQTT broker",
+        )),
+    }
+}
+
+/// Builds an MQTT DISCONNECT packet.
+pub(crate) fn disconnect_packet() -> Vec<u8> {
+    packet(0xE0, &[])
+}
@@ -0,0 +1,175 @@
+//! Writing measurements directly to an InfluxDB server.
+
+use glowmarkt::{
+    reqwest::{Client, RequestBuilder, StatusCode, Url},
+    sink::Measurement,
+    RetryPolicy,
+};
+
+/// Options for writing measurements to InfluxDB rather than printing them.
+#[derive(clap::Args, Clone)]
+pub struct InfluxDbOptions {
+    /// The URL of an InfluxDB server to write measurements to instead of
+    /// printing them, e.g. `http://influx:8086`.
+    #[clap(long, env = "INFLUX_URL")]
+    pub url: Option<String>,
+    /// The organization to write to. Selects the InfluxDB v2 write API.
+    #[clap(long, env = "INFLUX_ORG", requires = "url")]
+    pub org: Option<String>,
+    /// The bucket to write to, or the database for InfluxDB v1.
+    #[clap(long, env = "INFLUX_BUCKET", requires = "url")]
+    pub bucket: Option<String>,
+    /// The API token used with InfluxDB v2.
+    #[clap(long, env = "INFLUX_TOKEN", requires = "org")]
+    pub token: Option<String>,
+    /// The username used with InfluxDB v1.
+    #[clap(
+        long,
+        env = "INFLUX_USERNAME",
+        requires = "url",
+        conflicts_with = "org"
+    )]
+    pub influx_username: Option<String>,
+    /// The password used with InfluxDB v1.
+    #[clap(long, env = "INFLUX_PASSWORD", requires = "influx-username")]
+    pub influx_password: Option<String>,
+    /// The number of measurements to send in each request.
+    #[clap(long, default_value = "5000")]
+    pub batch_size: usize,
+    /// The maximum number of times to attempt each write.
+    #[clap(long, default_value = "3")]
+    pub write_attempts: u32,
+}
+
+/// Writes batches of measurements to the InfluxDB write API.
+pub struct InfluxDbWriter {
+    client: Client,
+    url: Url,
+    options: InfluxDbOptions,
+    retry: RetryPolicy,
+    batch: Vec<String>,
+    written: usize,
+}
+
+impl InfluxDbWriter {
+    /// Creates a writer if a server URL was given.
+    pub fn new(options: InfluxDbOptions) -> Result<Option<Self>, String> {
+        let mut url = match options.url {
+            Some(ref url) => {
+                Url::parse(url).map_err(|e| format!("Invalid InfluxDB URL {}: {}", url, e))?
+            }
+            None => return Ok(None),
+        };
+
+        let bucket = options
+            .bucket
+            .as_deref()
+            .ok_or_else(|| "A bucket or database is needed to write to InfluxDB".to_string())?;
+
+        let base = url.path().trim_end_matches('/').to_owned();
+        match options.org {
+            Some(ref org) => {
+                url.set_path(&format!("{}/api/v2/write", base));
+                url.query_pairs_mut()
+                    .append_pair("org", org)
+                    .append_pair("bucket", bucket)
+                    .append_pair("precision", "ns");
+            }
+            None => {
+                url.set_path(&format!("{}/write", base));
+                url.query_pairs_mut()
+                    .append_pair("db", bucket)
+                    .append_pair("precision", "ns");
+            }
+        }
+
+        Ok(Some(Self {
+            client: Client::new(),
+            url,
+            retry: RetryPolicy {
+                max_attempts: options.write_attempts.max(1),
+                ..Default::default()
+            },
+            batch: Vec::with_capacity(options.batch_size),
+            options,
+            written: 0,
+        }))
+    }
+
+    /// Queues a measurement, sending the batch once it is full.
+    pub async fn write(&mut self, measurement: &Measurement) -> Result<(), String> {
+        self.batch.push(measurement.to_string());
+        if self.batch.len() >= self.options.batch_size.max(1) {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Sends any queued measurements and returns the total number written.
+    pub async fn finish(mut self) -> Result<usize, String> {
+        self.flush().await?;
+        Ok(self.written)
+    }
+
+    fn request(&self, body: String) -> RequestBuilder {
+        let request = self
+            .client
+            .post(self.url.clone())
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body);
+
+        if let Some(ref token) = self.options.token {
+            request.header("Authorization", format!("Token {}", token))
+        } else if let Some(ref username) = self.options.influx_username {
+            request.basic_auth(username, self.options.influx_password.as_ref())
+        } else {
+            request
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), String> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let body = self.batch.join("\n");
+        let mut attempt = 1;
+        loop {
+            let error = match self.request(body.clone()).send().await {
+                Ok(response) if response.status().is_success() => break,
+                Ok(response) => {
+                    let status = response.status();
+                    let message = response.text().await.unwrap_or_default();
+                    let error = format!(
+                        "InfluxDB rejected the write with status {}: {}",
+                        status,
+                        message.trim()
+                    );
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) => format!("Failed to write to InfluxDB: {}", e),
+            };
+
+            if attempt >= self.retry.max_attempts {
+                return Err(error);
+            }
+
+            attempt += 1;
+            let delay = self.retry.backoff(attempt);
+            log::warn!("{}, retrying in {:.1}s", error, delay.as_secs_f64());
+            tokio::time::sleep(delay).await;
+        }
+
+        log::debug!("Wrote {} measurements to InfluxDB", self.batch.len());
+        self.written += self.batch.len();
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
@@ -0,0 +1,57 @@
+use glowmarkt::{classifier::Classifier, AggregationFunction, ReadingPeriod, Resource};
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LegacyQuery {
+    from: String,
+    to: String,
+    period: String,
+    function: String,
+}
+
+/// A readings document in the shape returned by the raw readings endpoint,
+/// which is what most of the Python and Node clients pass through.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegacyReadings {
+    status: String,
+    name: String,
+    resource_type_id: String,
+    resource_id: String,
+    query: LegacyQuery,
+    data: Vec<(i64, f64)>,
+    units: Option<String>,
+    classifier: Option<Classifier>,
+}
+
+impl LegacyReadings {
+    pub fn new(
+        resource: &Resource,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+        period: ReadingPeriod,
+        function: AggregationFunction,
+    ) -> Self {
+        Self {
+            status: "OK".to_string(),
+            name: resource.name.clone(),
+            resource_type_id: resource.type_id.clone(),
+            resource_id: resource.id.clone(),
+            query: LegacyQuery {
+                from: start.format(&Rfc3339).unwrap(),
+                to: end.format(&Rfc3339).unwrap(),
+                period: period.iso_duration().to_string(),
+                function: function.to_string(),
+            },
+            data: Vec::new(),
+            units: resource.base_unit.clone(),
+            classifier: resource.classifier.clone(),
+        }
+    }
+
+    pub fn push(&mut self, start: OffsetDateTime, value: f64) {
+        self.data.push((start.unix_timestamp(), value));
+    }
+}
@@ -1,21 +1,74 @@
 //! Access to the Glowmarkt API for meter readings.
 //!
 //! Developed based on <https://bitbucket.org/ijosh/brightglowmarkt/src/master/>
+//!
+//! Nothing here depends on a particular async runtime, timers come from
+//! `futures-timer`. The HTTP client does need a tokio reactor though so under
+//! async-std or smol wrap futures in `async_compat::Compat`.
 #![warn(missing_docs)]
 
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fmt::Display,
+    future::Future,
+    pin::pin,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
 
-use error::maybe;
-use reqwest::{Client, RequestBuilder};
+use classifier::Classifier;
+use coalesce::Coalescer;
+use error::{maybe, maybe_tariff};
+use futures::{stream, FutureExt, Stream, StreamExt, TryStreamExt};
+use futures_timer::Delay;
+use ratelimit::RateLimiter;
+use reqwest::{
+    header::{DATE, RETRY_AFTER},
+    Client, Proxy, RequestBuilder, Response,
+};
 use serde::{de::DeserializeOwned, Serialize};
-use time::format_description::well_known::Rfc3339;
-use time::{Duration, Month, OffsetDateTime, UtcOffset};
+use sink::ResourceContext;
+use time::format_description::{self, well_known::Rfc3339};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+use unit::Unit;
 
 pub mod api;
+pub mod cache;
+pub mod calendar;
+pub mod classifier;
+pub mod clock;
+mod coalesce;
+pub mod cost;
 pub mod error;
+pub mod event;
+pub mod format;
+pub mod gaps;
+pub mod gas;
+pub mod health;
+pub mod homeassistant;
+pub mod manifest;
+#[cfg(feature = "octopus")]
+pub mod octopus;
+mod ratelimit;
+pub mod resample;
+pub mod retry;
+pub mod settlement;
+pub mod simulate;
+pub mod sink;
+pub mod stats;
+pub mod tariff;
+pub mod transform;
+pub mod unit;
+pub mod warning;
 
-pub use api::{Device, DeviceType, Resource, ResourceType, VirtualEntity};
+pub use api::{Device, DeviceType, Resource, ResourceType, TariffData, VirtualEntity};
+pub use calendar::Calendar;
+pub use clock::Clock;
 pub use error::{Error, ErrorKind};
+pub use reqwest;
+pub use retry::RetryPolicy;
+pub use warning::Warning;
 
 /// The default API endpoint.
 pub const BASE_URL: &str = "https://api.glowmarkt.com/api/v0-1";
@@ -34,9 +87,62 @@ fn iso(dt: OffsetDateTime) -> String {
     )
 }
 
+/// Parses an ISO-8601 duration such as `PT30M`, `P1D` or `P2W`.
+///
+/// Years and months vary in length so cannot be represented as a fixed
+/// duration and are rejected.
+pub fn parse_iso_duration(duration: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid ISO-8601 duration '{}'", duration);
+
+    let mut rest = duration.strip_prefix('P').ok_or_else(invalid)?;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+
+    let mut result = Duration::ZERO;
+    let mut in_time = false;
+
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('T') {
+            if in_time || r.is_empty() {
+                return Err(invalid());
+            }
+            in_time = true;
+            rest = r;
+            continue;
+        }
+
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let value: i64 = rest[..split].parse().map_err(|_| invalid())?;
+        let unit = rest[split..].chars().next().ok_or_else(invalid)?;
+        result += match (in_time, unit) {
+            (false, 'Y') | (false, 'M') => {
+                return Err(format!(
+                    "The duration '{}' uses years or months which have no fixed length, use \
+                    days or weeks instead",
+                    duration
+                ))
+            }
+            (false, 'W') => Duration::weeks(value),
+            (false, 'D') => Duration::days(value),
+            (true, 'H') => Duration::hours(value),
+            (true, 'M') => Duration::minutes(value),
+            (true, 'S') => Duration::seconds(value),
+            _ => return Err(invalid()),
+        };
+        rest = &rest[split + 1..];
+    }
+
+    Ok(result)
+}
+
 #[derive(Debug, Clone, Copy)]
 /// The time window for each reading.
 pub enum ReadingPeriod {
+    /// 1 minute. Only available for resources that record real-time data.
+    Minute,
     /// 30 minutes.
     HalfHour,
     /// 1 hour.
@@ -51,7 +157,202 @@ pub enum ReadingPeriod {
     Year,
 }
 
-fn clear_seconds(date: OffsetDateTime) -> OffsetDateTime {
+impl ReadingPeriod {
+    /// The ISO-8601 duration used by the API to represent this period.
+    pub fn iso_duration(&self) -> &'static str {
+        match self {
+            ReadingPeriod::Minute => "PT1M",
+            ReadingPeriod::HalfHour => "PT30M",
+            ReadingPeriod::Hour => "PT1H",
+            ReadingPeriod::Day => "P1D",
+            ReadingPeriod::Week => "P1W",
+            ReadingPeriod::Month => "P1M",
+            ReadingPeriod::Year => "P1Y",
+        }
+    }
+
+    /// The shortest length of time this period can cover.
+    pub(crate) fn min_duration(&self) -> Duration {
+        match self {
+            ReadingPeriod::Minute => Duration::minutes(1),
+            ReadingPeriod::HalfHour => Duration::minutes(30),
+            ReadingPeriod::Hour => Duration::hours(1),
+            ReadingPeriod::Day => Duration::hours(23),
+            ReadingPeriod::Week => Duration::days(7),
+            ReadingPeriod::Month => Duration::days(28),
+            ReadingPeriod::Year => Duration::days(365),
+        }
+    }
+
+    /// The longest length of time this period can cover.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn max_duration(&self) -> Duration {
+        match self {
+            ReadingPeriod::Minute => Duration::minutes(1),
+            ReadingPeriod::HalfHour => Duration::minutes(30),
+            ReadingPeriod::Hour => Duration::hours(1),
+            ReadingPeriod::Day => Duration::hours(25),
+            ReadingPeriod::Week => Duration::days(7),
+            ReadingPeriod::Month => Duration::days(31),
+            ReadingPeriod::Year => Duration::days(366),
+        }
+    }
+
+    /// Suggests a period for reading a range of the given length from a
+    /// resource whose storage has the given sampling interval.
+    ///
+    /// This is the finest period the resource supports that keeps the number
+    /// of readings manageable, half-hourly for a few days or daily for a year.
+    /// Per-minute readings are never suggested.
+    pub fn suggested(range: Duration, sampling: Option<Duration>) -> ReadingPeriod {
+        [
+            ReadingPeriod::HalfHour,
+            ReadingPeriod::Hour,
+            ReadingPeriod::Day,
+        ]
+        .into_iter()
+        .find(|period| {
+            sampling.is_none_or(|sampling| sampling <= period.min_duration())
+                && range <= period.min_duration() * MAX_SUGGESTED_READINGS
+        })
+        .unwrap_or(ReadingPeriod::Week)
+    }
+}
+
+/// The most readings [`ReadingPeriod::suggested`] aims to return.
+const MAX_SUGGESTED_READINGS: i32 = 3000;
+
+impl FromStr for ReadingPeriod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1m" => Ok(ReadingPeriod::Minute),
+            "30m" => Ok(ReadingPeriod::HalfHour),
+            "1h" => Ok(ReadingPeriod::Hour),
+            "1d" => Ok(ReadingPeriod::Day),
+            "1w" => Ok(ReadingPeriod::Week),
+            "1mon" => Ok(ReadingPeriod::Month),
+            "1y" => Ok(ReadingPeriod::Year),
+            "P1M" => Ok(ReadingPeriod::Month),
+            "P1Y" => Ok(ReadingPeriod::Year),
+            _ => {
+                let unknown = || {
+                    format!(
+                        "Unknown period '{}', expected one of 1m, 30m, 1h, 1d, 1w, 1mon or 1y or \
+                        an ISO-8601 duration such as PT30M",
+                        s
+                    )
+                };
+
+                if !s.starts_with('P') {
+                    return Err(unknown());
+                }
+
+                let duration = parse_iso_duration(s)?;
+                if duration == Duration::minutes(1) {
+                    Ok(ReadingPeriod::Minute)
+                } else if duration == Duration::minutes(30) {
+                    Ok(ReadingPeriod::HalfHour)
+                } else if duration == Duration::hours(1) {
+                    Ok(ReadingPeriod::Hour)
+                } else if duration == Duration::days(1) {
+                    Ok(ReadingPeriod::Day)
+                } else if duration == Duration::weeks(1) {
+                    Ok(ReadingPeriod::Week)
+                } else {
+                    Err(unknown())
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ReadingPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            ReadingPeriod::Minute => "1m",
+            ReadingPeriod::HalfHour => "30m",
+            ReadingPeriod::Hour => "1h",
+            ReadingPeriod::Day => "1d",
+            ReadingPeriod::Week => "1w",
+            ReadingPeriod::Month => "1mon",
+            ReadingPeriod::Year => "1y",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How the values within each reading period are combined.
+pub enum AggregationFunction {
+    /// The total of the values, appropriate for consumption.
+    #[default]
+    Sum,
+    /// The mean of the values, appropriate for power.
+    Avg,
+    /// The smallest value.
+    Min,
+    /// The largest value.
+    Max,
+}
+
+impl AggregationFunction {
+    /// The name used by the API for this function.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AggregationFunction::Sum => "sum",
+            AggregationFunction::Avg => "avg",
+            AggregationFunction::Min => "min",
+            AggregationFunction::Max => "max",
+        }
+    }
+}
+
+impl FromStr for AggregationFunction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sum" => Ok(AggregationFunction::Sum),
+            "avg" => Ok(AggregationFunction::Avg),
+            "min" => Ok(AggregationFunction::Min),
+            "max" => Ok(AggregationFunction::Max),
+            _ => Err(format!(
+                "Unknown function '{}', expected one of sum, avg, min or max",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AggregationFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Converts a timestamp from the API, returning a warning for any that can't
+/// be represented.
+fn valid_timestamp(resource_id: &str, timestamp: i64) -> Result<OffsetDateTime, Warning> {
+    OffsetDateTime::from_unix_timestamp(timestamp).map_err(|_| Warning::InvalidTimestamp {
+        resource_id: resource_id.to_owned(),
+        timestamp,
+    })
+}
+
+/// Converts a timestamp from the API, logging and skipping any that can't be
+/// represented.
+fn logged_timestamp(resource_id: &str, timestamp: i64) -> Option<OffsetDateTime> {
+    match valid_timestamp(resource_id, timestamp) {
+        Ok(date) => Some(date),
+        Err(warning) => {
+            log::warn!("{}", warning);
+            None
+        }
+    }
+}
+
+pub(crate) fn clear_seconds(date: OffsetDateTime) -> OffsetDateTime {
     date.replace_second(0)
         .unwrap()
         .replace_millisecond(0)
@@ -62,25 +363,20 @@ fn clear_seconds(date: OffsetDateTime) -> OffsetDateTime {
         .unwrap()
 }
 
-/// Attempts to align the given date to the start of a reading period.
+/// Aligns the given date to the start of a reading period.
+///
+/// Days, weeks (starting on Monday), months and years are aligned in UTC, as
+/// that is how the API buckets readings, so for those periods the result is
+/// always returned in UTC. Minute, half-hour and hour alignment preserve the
+/// offset of the given date. Use [`Calendar::align`] for weeks or days that
+/// start at other times.
 pub fn align_to_period(date: OffsetDateTime, period: ReadingPeriod) -> OffsetDateTime {
-    match period {
-        ReadingPeriod::HalfHour => {
-            if date.minute() >= 30 {
-                clear_seconds(date).replace_minute(30).unwrap()
-            } else {
-                clear_seconds(date).replace_minute(0).unwrap()
-            }
-        }
-        ReadingPeriod::Hour => clear_seconds(date).replace_minute(0).unwrap(),
-        _ => panic!(
-            "Aligning to anything other than half-hour and hour periods is currently unsupported."
-        ),
-    }
+    Calendar::default().align(date, period)
 }
 
 fn max_days_for_period(period: ReadingPeriod) -> i64 {
     match period {
+        ReadingPeriod::Minute => 1,
         ReadingPeriod::HalfHour => 10,
         ReadingPeriod::Hour => 31,
         ReadingPeriod::Day => 31,
@@ -90,27 +386,39 @@ fn max_days_for_period(period: ReadingPeriod) -> i64 {
     }
 }
 
-fn increase_by_period(date: OffsetDateTime, period: ReadingPeriod) -> OffsetDateTime {
+/// Moves a date forward by one reading period.
+///
+/// Months and years follow calendar rules, keeping the time of day and
+/// offset. The day of the month is clamped to the length of the new month so
+/// the 31st of January becomes the 28th or 29th of February and the 29th of
+/// February becomes the 28th a year later.
+pub fn increase_by_period(date: OffsetDateTime, period: ReadingPeriod) -> OffsetDateTime {
     let duration = match period {
+        ReadingPeriod::Minute => Duration::minutes(1),
         ReadingPeriod::HalfHour => Duration::minutes(30),
         ReadingPeriod::Hour => Duration::hours(1),
         ReadingPeriod::Day => Duration::days(1),
         ReadingPeriod::Week => Duration::days(7),
         ReadingPeriod::Month => {
-            let month = date.month();
-            return if month == Month::December {
-                date.replace_month(Month::January).unwrap()
-            } else {
-                date.replace_month(Month::try_from(month as u8 + 1).unwrap())
-                    .unwrap()
+            let (year, month) = match date.month() {
+                Month::December => (date.year() + 1, Month::January),
+                month => (date.year(), month.next()),
             };
+            return with_year_month(date, year, month);
         }
-        ReadingPeriod::Year => return date.replace_year(date.year() + 1).unwrap(),
+        ReadingPeriod::Year => return with_year_month(date, date.year() + 1, date.month()),
     };
 
     date + duration
 }
 
+/// Moves a date to another month, clamping the day to the month's length.
+fn with_year_month(date: OffsetDateTime, year: i32, month: Month) -> OffsetDateTime {
+    let day = date.day().min(time::util::days_in_year_month(year, month));
+    let new_date = Date::from_calendar_date(year, month, day).unwrap();
+    date.replace_date(new_date)
+}
+
 /// Splits a range of readings into a set of ranges that the API will accept.
 pub fn split_periods(
     start: OffsetDateTime,
@@ -137,6 +445,49 @@ pub fn split_periods(
     ranges
 }
 
+/// The resolution readings are fetched at by [`split_tiers`], each applying to
+/// readings more recent than the given number of days. Older readings are
+/// fetched daily.
+const TIERS: [(ReadingPeriod, i64); 2] = [(ReadingPeriod::Hour, 90), (ReadingPeriod::HalfHour, 7)];
+
+/// Splits a range into progressively finer tiers, daily readings for anything
+/// more than 90 days before `now`, hourly readings for anything more than a
+/// week before and half-hourly readings after that.
+///
+/// Each tier is returned as its period and the start times of its first and
+/// last readings. Tier boundaries fall at midnight UTC so they align with
+/// every period.
+pub fn split_tiers(
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    now: OffsetDateTime,
+) -> Vec<(ReadingPeriod, OffsetDateTime, OffsetDateTime)> {
+    let mut tiers = Vec::new();
+    let mut period = ReadingPeriod::Day;
+    let mut current = align_to_period(start, period);
+
+    for (next_period, days) in TIERS {
+        let boundary = align_to_period(now - Duration::days(days), ReadingPeriod::Day);
+        if boundary > current {
+            let last = align_to_period(end, period)
+                .min(align_to_period(boundary - Duration::SECOND, period));
+            if last >= current {
+                tiers.push((period, current, last));
+            }
+            current = boundary.max(current);
+        }
+
+        if current > end {
+            return tiers;
+        }
+        period = next_period;
+        current = align_to_period(current.max(start), period);
+    }
+
+    tiers.push((period, current, align_to_period(end, period)));
+    tiers
+}
+
 trait Identified {
     fn id(&self) -> &str;
 }
@@ -177,8 +528,11 @@ impl Identified for api::Resource {
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone)]
 /// A meter reading
+///
+/// The start serializes as RFC 3339, use [`format::TimestampFormat::wrap`] for
+/// unix timestamps.
 pub struct Reading {
     #[serde(with = "time::serde::rfc3339")]
     /// The start time of the period.
@@ -186,8 +540,103 @@ pub struct Reading {
     /// The length of the period.
     #[serde(skip)]
     pub period: ReadingPeriod,
-    /// The total usage.
+    /// The usage, normally the total but see [`AggregationFunction`].
+    pub value: f32,
+    /// Any status or quality markers the API returned alongside the value.
+    /// A single marker is kept as is, several are kept as an array.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<serde_json::Value>,
+}
+
+impl Reading {
+    /// Attaches the unit the reading is measured in.
+    pub fn with_unit(self, unit: Option<Unit>) -> TypedReading {
+        TypedReading {
+            start: self.start,
+            period: self.period,
+            value: self.value,
+            quality: self.quality,
+            unit,
+        }
+    }
+}
+
+/// A reading from a consumption or export resource alongside the reading for
+/// the same period from the resource measuring its cost.
+///
+/// Either may be missing if the API only returned one of them.
+#[derive(Debug, Clone)]
+pub struct PairedReading {
+    /// The start time of the period.
+    pub start: OffsetDateTime,
+    /// The energy reading.
+    pub energy: Option<Reading>,
+    /// The cost reading, in pence.
+    pub cost: Option<Reading>,
+}
+
+/// Joins energy and cost readings by their start times.
+pub fn pair_readings(energy: Vec<Reading>, cost: Vec<Reading>) -> Vec<PairedReading> {
+    let mut pairs: BTreeMap<OffsetDateTime, PairedReading> = BTreeMap::new();
+    let pair = |start| PairedReading {
+        start,
+        energy: None,
+        cost: None,
+    };
+
+    for reading in energy {
+        let start = reading.start;
+        pairs.entry(start).or_insert_with(|| pair(start)).energy = Some(reading);
+    }
+    for reading in cost {
+        let start = reading.start;
+        pairs.entry(start).or_insert_with(|| pair(start)).cost = Some(reading);
+    }
+
+    pairs.into_values().collect()
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// A meter reading along with the unit it is measured in.
+pub struct TypedReading {
+    #[serde(with = "time::serde::rfc3339")]
+    /// The start time of the period.
+    pub start: OffsetDateTime,
+    /// The length of the period.
+    #[serde(skip)]
+    pub period: ReadingPeriod,
+    /// The usage, normally the total but see [`AggregationFunction`].
+    pub value: f32,
+    /// Any status or quality markers the API returned alongside the value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<serde_json::Value>,
+    /// The unit of the value, if the resource has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<Unit>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// The most recent value recorded by a resource.
+pub struct CurrentReading {
+    #[serde(with = "time::serde::rfc3339")]
+    /// When the value was recorded.
+    pub timestamp: OffsetDateTime,
+    /// The value, such as the instantaneous power.
     pub value: f32,
+    /// The unit of the value.
+    pub unit: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// The cumulative register value of a meter, as shown on its display.
+pub struct MeterRead {
+    #[serde(with = "time::serde::rfc3339")]
+    /// When the register was read.
+    pub timestamp: OffsetDateTime,
+    /// The register value.
+    pub value: f64,
+    /// The unit of the value.
+    pub unit: Option<String>,
 }
 
 /// The API endpoint.
@@ -199,6 +648,10 @@ pub struct GlowmarktEndpoint {
     pub base_url: String,
     /// The application ID to use when communicating with the endpoint.
     pub app_id: String,
+    /// How requests that fail for transient reasons are retried.
+    pub retry: RetryPolicy,
+    /// The longest to wait for each request to complete.
+    pub timeout: Option<std::time::Duration>,
 }
 
 impl Default for GlowmarktEndpoint {
@@ -206,6 +659,8 @@ impl Default for GlowmarktEndpoint {
         Self {
             base_url: BASE_URL.to_string(),
             app_id: APPLICATION_ID.to_string(),
+            retry: Default::default(),
+            timeout: None,
         }
     }
 }
@@ -215,150 +670,790 @@ impl GlowmarktEndpoint {
         format!("{}/{}", self.base_url, path)
     }
 
-    async fn api_call<T>(&self, client: &Client, request: RequestBuilder) -> Result<T, Error>
+    async fn send(
+        &self,
+        client: &Client,
+        request: RequestBuilder,
+        limiter: Option<&RateLimiter>,
+        clock: &dyn Clock,
+    ) -> Result<Response, Error> {
+        let request = request
+            .header("applicationId", &self.app_id)
+            .header("Content-Type", "application/json");
+        let request = match self.timeout {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        };
+        let mut request = Some(request.build()?);
+
+        let mut attempt = 1;
+        loop {
+            // Requests with streaming bodies cannot be cloned and so are only
+            // attempted once.
+            let template = request.take().unwrap();
+            let current = match template.try_clone() {
+                Some(current) => {
+                    request = Some(template);
+                    current
+                }
+                None => template,
+            };
+
+            if let Some(limiter) = limiter {
+                limiter.acquire().await;
+            }
+
+            log::debug!("Sending {} request to {}", current.method(), current.url());
+            let (error, retry_after) = match client.execute(current).await {
+                Ok(response) => {
+                    let status = response.status();
+                    if !status.is_client_error() && !status.is_server_error() {
+                        return Ok(response);
+                    }
+
+                    let retry_after = retry_after(&response, clock);
+                    let url = response.url().to_string();
+                    let body = response.text().await.unwrap_or_default();
+                    let error = Error::from_response(status, url, &body);
+                    log::warn!("Received API error: {}", error);
+                    (error, retry_after)
+                }
+                Err(e) => (Error::from(e), None),
+            };
+
+            if request.is_none() || !self.retry.should_retry(&error, attempt) {
+                return Err(error);
+            }
+
+            attempt += 1;
+            let delay = retry_after.unwrap_or_else(|| self.retry.backoff(attempt));
+            log::info!(
+                "Retrying request in {:.1} seconds (attempt {} of {})",
+                delay.as_secs_f64(),
+                attempt,
+                self.retry.max_attempts
+            );
+            Delay::new(delay).await;
+        }
+    }
+
+    async fn api_call<T>(
+        &self,
+        client: &Client,
+        request: RequestBuilder,
+        limiter: Option<&RateLimiter>,
+        clock: &dyn Clock,
+    ) -> Result<T, Error>
     where
         T: DeserializeOwned,
     {
-        let request = request
-            .header("applicationId", &self.app_id)
-            .header("Content-Type", "application/json")
-            .build()?;
+        decode(self.send(client, request, limiter, clock).await?).await
+    }
+}
 
-        log::debug!("Sending {} request to {}", request.method(), request.url());
-        let response = client
-            .execute(request)
-            .await?
-            .error_for_status()
-            .map_err(|e| {
-                log::warn!("Received API error: {}", e);
-                e
-            })?;
+async fn decode<T: DeserializeOwned>(response: Response) -> Result<T, Error> {
+    let url = response.url().to_string();
+    let result = response
+        .text()
+        .await
+        .map_err(|e| Error::from(e).with_url(&url))?;
+    log::trace!("Received: {}", result);
+
+    serde_json::from_str::<T>(&result).map_err(|e| Error::from(e).with_url(&url))
+}
+
+fn http_date(header: &str) -> Option<OffsetDateTime> {
+    let format = format_description::parse(
+        "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT",
+    )
+    .ok()?;
+
+    PrimitiveDateTime::parse(header, &format)
+        .ok()
+        .map(|date| date.assume_utc())
+}
 
-        let result = response.text().await?;
-        log::trace!("Received: {}", result);
+fn server_date(response: &Response) -> Option<OffsetDateTime> {
+    http_date(response.headers().get(DATE)?.to_str().ok()?)
+}
+
+/// Reads the delay requested by a `Retry-After` header, given either in
+/// seconds or as a date.
+fn retry_after(response: &Response, clock: &dyn Clock) -> Option<std::time::Duration> {
+    let header = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
 
-        Ok(serde_json::from_str::<T>(&result)?)
+    if let Ok(seconds) = header.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
     }
+
+    let delay = http_date(header)? - clock.now();
+    Some(delay.try_into().unwrap_or_default())
 }
 
 struct ApiRequest<'a> {
-    endpoint: &'a GlowmarktEndpoint,
-    client: &'a Client,
+    api: &'a GlowmarktApi,
     request: RequestBuilder,
 }
 
 impl<'a> ApiRequest<'a> {
     async fn request<T: DeserializeOwned>(self) -> Result<T, Error> {
-        self.endpoint.api_call(self.client, self.request).await
+        decode(self.response().await?).await
     }
-}
 
-#[derive(Debug, Clone)]
-/// Access to the Glowmarkt API.
-pub struct GlowmarktApi {
-    /// The current JWT token.
-    pub token: String,
-    endpoint: GlowmarktEndpoint,
-    client: Client,
-}
+    async fn response(self) -> Result<Response, Error> {
+        let api = self.api;
+        let can_refresh = api.credentials.is_some();
 
-impl GlowmarktApi {
-    /// Create with a provided JWT token.
-    pub fn new(token: &str) -> Self {
-        Self {
-            token: token.to_owned(),
-            endpoint: Default::default(),
-            client: Client::new(),
+        let mut token = api.token();
+        if can_refresh && api.token_expiring() {
+            log::debug!("Token has expired, authenticating again");
+            token = api.replace_token(&token).await?;
+        }
+
+        // Keep a copy of the request to retry with a fresh token.
+        let retry = if can_refresh {
+            self.request.try_clone()
+        } else {
+            None
+        };
+
+        match (api.send_with_token(self.request, &token).await, retry) {
+            (Err(e), Some(retry)) if e.kind() == ErrorKind::NotAuthenticated => {
+                log::info!("Token was rejected, authenticating again");
+                let token = api.replace_token(&token).await?;
+                api.send_with_token(retry, &token).await
+            }
+            (result, _) => result,
         }
     }
+}
 
-    /// Authenticates with the default Glowmarkt API endpoint.
-    ///
-    /// Generates a valid JWT token if successful.
-    pub async fn authenticate(username: &str, password: &str) -> Result<GlowmarktApi, Error> {
-        Self::auth(Default::default(), username, password).await
+/// A source of readings.
+///
+/// Implemented by [`GlowmarktApi`] and by wrappers around it such as
+/// [`cache::CachedGlowmarktApi`] so code can work with either.
+pub trait GlowmarktClient: Sync {
+    /// The maximum number of requests to make at once when retrieving ranges
+    /// of readings.
+    fn concurrency(&self) -> usize {
+        1
     }
 
-    fn get_request<S>(&self, path: S) -> ApiRequest
-    where
-        S: Display,
-    {
-        let request = self
-            .client
-            .get(self.endpoint.url(path))
-            .header("token", &self.token);
+    /// Retrieves the readings for a single resource.
+    ///
+    /// See [`GlowmarktApi::readings`].
+    fn readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Future<Output = Result<Vec<Reading>, Error>> + Send;
 
-        ApiRequest {
-            endpoint: &self.endpoint,
-            client: &self.client,
-            request,
+    /// Retrieves the readings for a single resource along with warnings for
+    /// any readings that had to be skipped.
+    ///
+    /// See [`GlowmarktApi::readings_with_warnings`].
+    fn readings_with_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Future<Output = Result<(Vec<Reading>, Vec<Warning>), Error>> + Send {
+        async move {
+            let readings = self.readings(resource_id, start, end, period).await?;
+            Ok((readings, Vec::new()))
         }
     }
 
-    fn query_request<S, T>(&self, path: S, query: &T) -> ApiRequest
-    where
-        S: Display,
-        T: Serialize + ?Sized,
-    {
-        let request = self
-            .client
-            .get(self.endpoint.url(path))
-            .header("token", &self.token)
-            .query(query);
-
-        ApiRequest {
-            endpoint: &self.endpoint,
-            client: &self.client,
-            request,
+    /// Retrieves the readings for a single resource over any length of time.
+    ///
+    /// See [`GlowmarktApi::readings_range`].
+    fn readings_range(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Future<Output = Result<Vec<Reading>, Error>> + Send {
+        async move {
+            let (readings, _) = self
+                .readings_range_with_warnings(resource_id, start, end, period)
+                .await?;
+            Ok(readings)
         }
     }
 
-    // fn post_request<S, T>(&self, path: S, data: &T) -> ApiRequest
-    // where
-    //     S: Display,
-    //     T: Serialize,
-    // {
-    //     let request = self
-    //         .client
-    //         .post(self.endpoint.url(path))
-    //         .header("Content-Type", "application/json")
-    //         .header("token", &self.token)
-    //         .json(data);
-
-    //     ApiRequest {
-    //         endpoint: &self.endpoint,
-    //         client: &self.client,
-    //         request,
-    //     }
-    // }
-}
+    /// Retrieves the readings for a single resource over any length of time
+    /// along with warnings for skipped readings and a warning if duplicate
+    /// readings were dropped.
+    ///
+    /// See [`GlowmarktApi::readings_range_with_warnings`].
+    fn readings_range_with_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Future<Output = Result<(Vec<Reading>, Vec<Warning>), Error>> + Send {
+        let ranges = split_periods(*start, *end, period);
 
-/// [User System](https://api.glowmarkt.com/api-docs/v0-1/usersys/usertypes/)
-impl GlowmarktApi {
-    /// Authenticate against a specific endpoint.
-    pub async fn auth(
-        endpoint: GlowmarktEndpoint,
-        username: &str,
-        password: &str,
-    ) -> Result<GlowmarktApi, Error> {
-        let client = Client::new();
-        let request = client.post(endpoint.url("auth")).json(&api::AuthRequest {
-            username: username.to_owned(),
-            password: password.to_owned(),
-        });
+        async move {
+            let mut chunks = stream::iter(ranges)
+                .map(|(start, end)| async move {
+                    self.readings_with_warnings(resource_id, &start, &end, period)
+                        .await
+                })
+                .buffered(self.concurrency().max(1));
 
-        let response = endpoint
-            .api_call::<api::AuthResponse>(&client, request)
-            .await?
-            .validate()?;
+            let mut readings = BTreeMap::new();
+            let mut warnings = Vec::new();
+            let mut duplicates = 0;
+            while let Some(chunk) = chunks.next().await {
+                let (chunk, skipped) = chunk?;
+                warnings.extend(skipped);
+                for reading in chunk {
+                    if readings.insert(reading.start, reading).is_some() {
+                        duplicates += 1;
+                    }
+                }
+            }
 
-        log::debug!("Authenticated with API until {}", iso(response.expiry));
+            if duplicates > 0 {
+                warnings.push(Warning::DuplicatesDropped {
+                    resource_id: resource_id.to_owned(),
+                    count: duplicates,
+                });
+            }
 
-        Ok(Self {
-            token: response.token,
-            endpoint,
-            client,
-        })
+            Ok((readings.into_values().collect(), warnings))
+        }
+    }
+}
+
+/// The HTTP versions used to talk to the API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// Uses HTTP/2 when it is negotiated for the connection and HTTP/1.1
+    /// otherwise.
+    #[default]
+    Auto,
+    /// Only uses HTTP/1.1.
+    Http1,
+    /// Uses HTTP/2 without negotiating it first.
+    Http2,
+}
+
+#[derive(Debug, Clone)]
+/// Configures a [`GlowmarktApi`] before connecting.
+pub struct GlowmarktApiBuilder {
+    endpoint: GlowmarktEndpoint,
+    clock: Arc<dyn Clock>,
+    calendar: Calendar,
+    concurrency: usize,
+    clamp_future: bool,
+    rate_limit: Option<f64>,
+    coalesce: bool,
+    credentials: Option<Arc<Credentials>>,
+    token_expiry: Option<OffsetDateTime>,
+    client: Option<Client>,
+    proxy: Option<Proxy>,
+    user_agent: Option<String>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Option<std::time::Duration>>,
+    http_version: HttpVersion,
+    tcp_keepalive: Option<std::time::Duration>,
+}
+
+impl Default for GlowmarktApiBuilder {
+    fn default() -> Self {
+        Self {
+            endpoint: Default::default(),
+            clock: Arc::new(clock::SystemClock),
+            calendar: Default::default(),
+            concurrency: 1,
+            clamp_future: true,
+            rate_limit: None,
+            coalesce: false,
+            credentials: None,
+            token_expiry: None,
+            client: None,
+            proxy: None,
+            user_agent: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+            http_version: Default::default(),
+            tcp_keepalive: None,
+        }
+    }
+}
+
+impl GlowmarktApiBuilder {
+    /// Sets the API endpoint to use.
+    pub fn endpoint(mut self, endpoint: GlowmarktEndpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Sets the application ID sent with every request.
+    pub fn app_id(mut self, app_id: &str) -> Self {
+        self.endpoint.app_id = app_id.to_owned();
+        self
+    }
+
+    /// Sets the longest to wait for each request to complete. By default
+    /// requests never time out.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.endpoint.timeout = Some(timeout);
+        self
+    }
+
+    /// Uses a pre-configured HTTP client for requests.
+    ///
+    /// This replaces any [`proxy`](Self::proxy),
+    /// [`user_agent`](Self::user_agent) and connection settings.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Sends requests through a proxy. Proxies configured through the usual
+    /// environment variables are used by default.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets the user agent sent with every request.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    /// Sets the most idle connections kept open to the API for reuse. By
+    /// default there is no limit.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Sets how long an idle connection is kept open for reuse, `None` keeps
+    /// idle connections open indefinitely. Defaults to 90 seconds.
+    ///
+    /// Routers that silently drop idle connections can leave requests made
+    /// on them hanging, a timeout shorter than the router's avoids this.
+    pub fn pool_idle_timeout<D: Into<Option<std::time::Duration>>>(mut self, timeout: D) -> Self {
+        self.pool_idle_timeout = Some(timeout.into());
+        self
+    }
+
+    /// Sets the HTTP versions used. Defaults to [`HttpVersion::Auto`].
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// Sends TCP keepalive probes on connections after they have been idle
+    /// for the given time, keeping them open through routers that drop
+    /// quiet connections. By default no probes are sent.
+    pub fn tcp_keepalive(mut self, interval: std::time::Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Sets how requests that fail for transient reasons are retried.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.endpoint.retry = policy;
+        self
+    }
+
+    /// Limits the number of requests made each second. All requests made
+    /// through the API and its clones share the limit. By default there is no
+    /// limit.
+    pub fn rate_limit(mut self, requests_per_second: f64) -> Self {
+        self.rate_limit = (requests_per_second > 0.0).then_some(requests_per_second);
+        self
+    }
+
+    /// Sets whether identical requests for readings made while one is already
+    /// in progress wait for its result instead of going to the API again.
+    /// This suits servers where several clients often ask for the same data
+    /// at once. All clones of the API share the requests in progress.
+    /// Defaults to false.
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    /// Sets whether requests for readings that extend into the future are
+    /// clamped to end at the current time. Defaults to true as the API returns
+    /// confusing results for future ranges.
+    pub fn clamp_future(mut self, clamp: bool) -> Self {
+        self.clamp_future = clamp;
+        self
+    }
+
+    /// Sets the clock used to determine the current time.
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets where weeks and days start when aligning dates. Defaults to the
+    /// API's own Monday weeks and midnight UTC days.
+    pub fn calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Sets the maximum number of requests made at once when retrieving long
+    /// ranges of readings. Defaults to 1, making requests one after another.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Sets the credentials used to authenticate again when the token
+    /// expires.
+    ///
+    /// [`authenticate`](Self::authenticate) sets these itself, this is only
+    /// needed when starting from a previously generated token.
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some(Arc::new(Credentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        }));
+        self
+    }
+
+    /// Sets when a token passed to [`token`](Self::token) expires, if known,
+    /// so that it can be replaced before the API rejects it.
+    pub fn token_expiry(mut self, expiry: OffsetDateTime) -> Self {
+        self.token_expiry = Some(expiry);
+        self
+    }
+
+    fn http_client(&mut self) -> Client {
+        if let Some(client) = self.client.take() {
+            return client;
+        }
+
+        let mut builder = Client::builder();
+        if let Some(proxy) = self.proxy.take() {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(ref user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        match self.http_version {
+            HttpVersion::Auto => (),
+            HttpVersion::Http1 => builder = builder.http1_only(),
+            HttpVersion::Http2 => builder = builder.http2_prior_knowledge(),
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+
+        // This only fails in the same situations that `Client::new` panics.
+        builder.build().expect("Unable to create the HTTP client")
+    }
+
+    fn build(mut self, token: String) -> GlowmarktApi {
+        let client = self.http_client();
+        GlowmarktApi {
+            token: Arc::new(RwLock::new(TokenState {
+                token,
+                expiry: self.token_expiry,
+            })),
+            refreshing: Default::default(),
+            credentials: self.credentials,
+            endpoint: self.endpoint,
+            client,
+            clock: self.clock,
+            calendar: self.calendar,
+            concurrency: self.concurrency,
+            clamp_future: self.clamp_future,
+            limiter: self.rate_limit.map(|rate| Arc::new(RateLimiter::new(rate))),
+            coalescer: self.coalesce.then(Default::default),
+        }
+    }
+
+    /// Creates the API with a provided JWT token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no custom client was given and the HTTP client cannot be
+    /// created, as [`Client::new`] does.
+    pub fn token(self, token: &str) -> GlowmarktApi {
+        self.build(token.to_owned())
+    }
+
+    /// Authenticates with the API.
+    ///
+    /// Generates a valid JWT token if successful. The credentials are kept so
+    /// that a new token can be generated when this one expires.
+    pub async fn authenticate(self, username: &str, password: &str) -> Result<GlowmarktApi, Error> {
+        let api = self.credentials(username, password).build(String::new());
+        api.refresh_token().await?;
+        Ok(api)
+    }
+}
+
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct TokenState {
+    token: String,
+    expiry: Option<OffsetDateTime>,
+}
+
+/// How long before a token's expiry it is replaced.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::minutes(1);
+
+#[derive(Debug, Clone)]
+/// Access to the Glowmarkt API.
+///
+/// When created with credentials an expired token is replaced automatically
+/// and any request rejected as unauthenticated is retried with the new token.
+///
+/// Clones share the token, so a single authenticated API can be cloned or
+/// placed in an `Arc` and used from many tasks at once. When several requests
+/// find the token has expired only one of them generates a new token.
+pub struct GlowmarktApi {
+    token: Arc<RwLock<TokenState>>,
+    /// Held while a new token is generated.
+    refreshing: Arc<futures::lock::Mutex<()>>,
+    credentials: Option<Arc<Credentials>>,
+    endpoint: GlowmarktEndpoint,
+    client: Client,
+    clock: Arc<dyn Clock>,
+    calendar: Calendar,
+    concurrency: usize,
+    clamp_future: bool,
+    limiter: Option<Arc<RateLimiter>>,
+    coalescer: Option<Arc<Coalescer<ReadingsKey, Arc<FetchedReadings>>>>,
+}
+
+/// The readings from a single request and warnings for any skipped.
+type FetchedReadings = (Vec<Reading>, Vec<Warning>);
+
+/// Identifies identical requests for readings.
+type ReadingsKey = (
+    String,
+    OffsetDateTime,
+    OffsetDateTime,
+    &'static str,
+    &'static str,
+);
+
+/// Notes that the end of a requested range was moved back to the current time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clamped {
+    /// The end that was requested.
+    pub requested: OffsetDateTime,
+    /// The end that was used.
+    pub end: OffsetDateTime,
+}
+
+impl GlowmarktApi {
+    /// Configures a new API.
+    pub fn builder() -> GlowmarktApiBuilder {
+        Default::default()
+    }
+
+    /// Create with a provided JWT token.
+    pub fn new(token: &str) -> Self {
+        Self::builder().token(token)
+    }
+
+    /// Replaces the clock used to determine the current time.
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// The clock used to determine the current time.
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Replaces where weeks and days start when aligning dates.
+    pub fn with_calendar(mut self, calendar: Calendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    /// Where weeks and days start when aligning dates.
+    pub fn calendar(&self) -> &Calendar {
+        &self.calendar
+    }
+
+    /// Authenticates with the default Glowmarkt API endpoint.
+    ///
+    /// Generates a valid JWT token if successful.
+    pub async fn authenticate(username: &str, password: &str) -> Result<GlowmarktApi, Error> {
+        Self::auth(Default::default(), username, password).await
+    }
+
+    /// The current JWT token.
+    pub fn token(&self) -> String {
+        self.token.read().unwrap().token.clone()
+    }
+
+    /// When the current token expires, if known.
+    ///
+    /// This is known after authenticating or validating the token.
+    pub fn token_expiry(&self) -> Option<OffsetDateTime> {
+        self.token.read().unwrap().expiry
+    }
+
+    fn token_expiring(&self) -> bool {
+        self.token_expiry()
+            .map(|expiry| expiry - TOKEN_EXPIRY_MARGIN <= self.clock.now())
+            .unwrap_or(false)
+    }
+
+    /// Generates a new token using the credentials the API was created with.
+    pub async fn refresh_token(&self) -> Result<(), Error> {
+        let _guard = self.refreshing.lock().await;
+        self.generate_token().await
+    }
+
+    /// Replaces a token that has expired or been rejected, unless another
+    /// task has already replaced it. Returns the token to use.
+    async fn replace_token(&self, stale: &str) -> Result<String, Error> {
+        let _guard = self.refreshing.lock().await;
+
+        let current = self.token();
+        if current != stale {
+            log::trace!("Token was already replaced");
+            return Ok(current);
+        }
+
+        self.generate_token().await?;
+        Ok(self.token())
+    }
+
+    async fn generate_token(&self) -> Result<(), Error> {
+        let credentials = self.credentials.as_ref().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotAuthenticated,
+                "No credentials are available to generate a new token",
+            )
+        })?;
+
+        let request = self
+            .client
+            .post(self.endpoint.url("auth"))
+            .json(&api::AuthRequest {
+                username: credentials.username.clone(),
+                password: credentials.password.clone(),
+            });
+
+        let response = self
+            .endpoint
+            .api_call::<api::AuthResponse>(
+                &self.client,
+                request,
+                self.limiter.as_deref(),
+                self.clock(),
+            )
+            .await?
+            .validate()?;
+
+        log::debug!("Authenticated with API until {}", iso(response.expiry));
+
+        *self.token.write().unwrap() = TokenState {
+            token: response.token,
+            expiry: Some(response.expiry),
+        };
+        Ok(())
+    }
+
+    async fn send_with_token(
+        &self,
+        request: RequestBuilder,
+        token: &str,
+    ) -> Result<Response, Error> {
+        self.endpoint
+            .send(
+                &self.client,
+                request.header("token", token),
+                self.limiter.as_deref(),
+                self.clock(),
+            )
+            .await
+    }
+
+    fn get_request<S>(&self, path: S) -> ApiRequest<'_>
+    where
+        S: Display,
+    {
+        ApiRequest {
+            api: self,
+            request: self.client.get(self.endpoint.url(path)),
+        }
+    }
+
+    fn query_request<S, T>(&self, path: S, query: &T) -> ApiRequest<'_>
+    where
+        S: Display,
+        T: Serialize + ?Sized,
+    {
+        ApiRequest {
+            api: self,
+            request: self.client.get(self.endpoint.url(path)).query(query),
+        }
+    }
+
+    // fn post_request<S, T>(&self, path: S, data: &T) -> ApiRequest<'_>
+    // where
+    //     S: Display,
+    //     T: Serialize,
+    // {
+    //     let request = self
+    //         .client
+    //         .post(self.endpoint.url(path))
+    //         .header("Content-Type", "application/json")
+    //         .json(data);
+
+    //     ApiRequest {
+    //         api: self,
+    //         request,
+    //     }
+    // }
+}
+
+/// [User System](https://api.glowmarkt.com/api-docs/v0-1/usersys/usertypes/)
+impl GlowmarktApi {
+    /// Authenticate against a specific endpoint.
+    pub async fn auth(
+        endpoint: GlowmarktEndpoint,
+        username: &str,
+        password: &str,
+    ) -> Result<GlowmarktApi, Error> {
+        Self::builder()
+            .endpoint(endpoint)
+            .authenticate(username, password)
+            .await
     }
 
     /// Validates the current token.
@@ -370,9 +1465,54 @@ impl GlowmarktApi {
             .and_then(|r| r.validate())?;
 
         log::debug!("Authenticated with API until {}", iso(response.expiry));
+        self.token.write().unwrap().expiry = Some(response.expiry);
 
         Ok(true)
     }
+
+    /// Retrieves the current time according to the API server.
+    ///
+    /// This is taken from the `Date` header of a token validation request.
+    pub async fn server_time(&self) -> Result<OffsetDateTime, Error> {
+        let response = self.get_request("auth").response().await?;
+
+        server_date(&response).ok_or_else(|| {
+            Error::new(
+                ErrorKind::Response,
+                "Server response did not include a valid date",
+            )
+        })
+    }
+
+    /// Retrieves any rate limiting headers the API server includes in its
+    /// responses, such as `X-RateLimit-Remaining` or `Retry-After`.
+    ///
+    /// These are taken from a token validation request. Header names are
+    /// lowercase.
+    pub async fn rate_limit_headers(&self) -> Result<BTreeMap<String, String>, Error> {
+        let response = self.get_request("auth").response().await?;
+
+        Ok(response
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name.contains("ratelimit") || name.contains("rate-limit") || name == "retry-after"
+            })
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_owned(), value.to_str().ok()?.to_owned()))
+            })
+            .collect())
+    }
+
+    /// Estimates how far the local clock is behind the API server's clock.
+    ///
+    /// A positive duration means the local clock is slow. The estimate is only
+    /// accurate to within a second or so.
+    pub async fn clock_skew(&self) -> Result<Duration, Error> {
+        let server = self.server_time().await?;
+        Ok(server - self.clock.now())
+    }
 }
 
 /// [Device Management System](https://api.glowmarkt.com/api-docs/v0-1/dmssys/#/)
@@ -395,7 +1535,7 @@ impl GlowmarktApi {
         match self.get_request(format!("device/{}", id)).request().await {
             Ok(device) => Ok(Some(device)),
             Err(error) => {
-                if error.kind == ErrorKind::NotFound {
+                if error.kind() == ErrorKind::NotFound {
                     Ok(None)
                 } else {
                     Err(error)
@@ -403,6 +1543,71 @@ impl GlowmarktApi {
             }
         }
     }
+
+    /// Retrieves the resources of the devices identified by a hardware ID,
+    /// such as the serial number printed on a meter or its MPAN.
+    pub async fn resources_for_hardware(&self, hardware_id: &str) -> Result<Vec<Resource>, Error> {
+        let resource_ids: Vec<String> = self
+            .devices()
+            .await?
+            .into_values()
+            .filter(|device| device.has_hardware_id(hardware_id))
+            .flat_map(|device| device.protocol.sensors)
+            .filter_map(|sensor| sensor.resource_id)
+            .collect();
+
+        if resource_ids.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No device has the hardware ID {}", hardware_id),
+            ));
+        }
+
+        let mut resources = self.resources().await?;
+        Ok(resource_ids
+            .iter()
+            .filter_map(|id| resources.remove(id))
+            .collect())
+    }
+
+    /// Retrieves resources along with the device recording to each and the
+    /// virtual entity it belongs to, ready to pass to an
+    /// [`ExportSink`](sink::ExportSink). If no IDs are given every resource is
+    /// returned.
+    pub async fn resource_contexts(&self, ids: &[String]) -> Result<Vec<ResourceContext>, Error> {
+        let mut resources = self.resources().await?;
+        let devices = self.devices().await?;
+        let mut entities = sink::entities_by_resource(self.virtual_entities().await?);
+
+        let mut owners: HashMap<String, Device> = HashMap::new();
+        for device in devices.into_values() {
+            for resource_id in device.protocol.resource_ids() {
+                owners
+                    .entry(resource_id.to_owned())
+                    .or_insert_with(|| device.clone());
+            }
+        }
+
+        let ids: Vec<String> = if ids.is_empty() {
+            resources.keys().cloned().collect()
+        } else {
+            ids.to_vec()
+        };
+
+        ids.into_iter()
+            .map(|id| match resources.remove(&id) {
+                Some(resource) => Ok(ResourceContext {
+                    device: owners.remove(&id),
+                    entity: entities.remove(&id),
+                    resource,
+                }),
+                None => Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("Unknown resource {}", id),
+                )),
+            })
+            .collect()
+    }
 }
 
 /// [Virtual Entity System](https://api.glowmarkt.com/api-docs/v0-1/vesys/#/)
@@ -426,6 +1631,18 @@ impl GlowmarktApi {
                 .await,
         )
     }
+
+    /// Retrieves a virtual entity with the full details of its resources.
+    pub async fn virtual_entity_resources(
+        &self,
+        entity_id: &str,
+    ) -> Result<Option<api::VirtualEntityResources>, Error> {
+        maybe(
+            self.get_request(format!("virtualentity/{}/resources", entity_id))
+                .request()
+                .await,
+        )
+    }
 }
 
 /// [Resource System](https://api.glowmarkt.com/api-docs/v0-1/resourcesys/#/)
@@ -452,6 +1669,216 @@ impl GlowmarktApi {
         )
     }
 
+    /// Finds the resource measuring the cost of an energy resource, such as
+    /// the `electricity.consumption.cost` resource for an
+    /// `electricity.consumption` resource.
+    ///
+    /// The cost resource is looked for in the virtual entities the resource
+    /// belongs to, or across the account if it belongs to none. Returns
+    /// `None` if there isn't exactly one match.
+    pub async fn cost_resource(&self, resource: &Resource) -> Result<Option<Resource>, Error> {
+        let Some(classifier) = resource.classifier.as_ref().and_then(Classifier::cost) else {
+            return Ok(None);
+        };
+
+        let resources = self.resources().await?;
+        let entities = self.virtual_entities().await?;
+        let siblings: Vec<&str> = entities
+            .values()
+            .filter(|entity| {
+                entity
+                    .resources
+                    .iter()
+                    .any(|info| info.resource_id == resource.id)
+            })
+            .flat_map(|entity| {
+                entity
+                    .resources
+                    .iter()
+                    .map(|info| info.resource_id.as_str())
+            })
+            .collect();
+
+        let matches: Vec<&Resource> = resources
+            .values()
+            .filter(|candidate| siblings.is_empty() || siblings.contains(&candidate.id.as_str()))
+            .filter(|candidate| candidate.classifier.as_ref() == Some(&classifier))
+            .collect();
+
+        match matches.as_slice() {
+            [cost] => Ok(Some((*cost).clone())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Retrieves the most recent value recorded by a resource, such as the
+    /// current power draw.
+    ///
+    /// Returns `None` if the resource has not recorded anything recently.
+    pub async fn current(&self, resource_id: &str) -> Result<Option<CurrentReading>, Error> {
+        let response = self
+            .get_request(format!("resource/{}/current", resource_id))
+            .request::<api::CurrentResponse>()
+            .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .filter_map(|reading| {
+                Some(CurrentReading {
+                    timestamp: logged_timestamp(resource_id, reading.timestamp)?,
+                    value: reading.value,
+                    unit: response.units.clone(),
+                })
+            })
+            .max_by_key(|reading| reading.timestamp))
+    }
+
+    /// Retrieves the most recent cumulative register value of a resource's
+    /// meter, the figure suppliers ask for when submitting a meter reading.
+    ///
+    /// Returns `None` if the meter has not reported a register value.
+    pub async fn meter_read(&self, resource_id: &str) -> Result<Option<MeterRead>, Error> {
+        let response = self
+            .get_request(format!("resource/{}/meterread", resource_id))
+            .request::<api::MeterReadResponse>()
+            .await?;
+
+        Ok(response
+            .data
+            .into_iter()
+            .filter_map(|(timestamp, value)| {
+                Some(MeterRead {
+                    timestamp: logged_timestamp(resource_id, timestamp)?,
+                    value,
+                    unit: response.units.clone(),
+                })
+            })
+            .max_by_key(|read| read.timestamp))
+    }
+
+    /// Retrieves the start of the earliest reading a resource holds.
+    ///
+    /// Returns `None` if the resource has no readings.
+    pub async fn first_time(&self, resource_id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        self.resource_time(resource_id, "first-time").await
+    }
+
+    /// Retrieves the start of the latest reading a resource holds.
+    ///
+    /// Returns `None` if the resource has no readings.
+    pub async fn last_time(&self, resource_id: &str) -> Result<Option<OffsetDateTime>, Error> {
+        self.resource_time(resource_id, "last-time").await
+    }
+
+    async fn resource_time(
+        &self,
+        resource_id: &str,
+        endpoint: &str,
+    ) -> Result<Option<OffsetDateTime>, Error> {
+        let response = self
+            .get_request(format!("resource/{}/{}", resource_id, endpoint))
+            .request::<api::ResourceTimeResponse>()
+            .await?;
+
+        Ok(response
+            .data
+            .timestamp
+            .and_then(|timestamp| logged_timestamp(resource_id, timestamp)))
+    }
+
+    /// Asks the API to fetch any outstanding data for a resource from the DCC.
+    ///
+    /// Smart meters only deliver data periodically so recent readings are
+    /// often missing until this is requested. Retrieval happens in the
+    /// background, it can take a little while before the readings appear.
+    pub async fn catchup(&self, resource_id: &str) -> Result<(), Error> {
+        self.get_request(format!("resource/{}/catchup", resource_id))
+            .response()
+            .await?;
+        Ok(())
+    }
+
+    /// Picks a period for reading a range from a resource based on its
+    /// storage sampling and the length of the range.
+    ///
+    /// See [`ReadingPeriod::suggested`].
+    pub async fn default_period(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<ReadingPeriod, Error> {
+        let resource = self.resource(resource_id).await?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Unknown resource {}", resource_id),
+            )
+        })?;
+
+        let sampling = self
+            .resource_types()
+            .await?
+            .get(&resource.type_id)
+            .and_then(|resource_type| resource_type.sampling());
+
+        Ok(ReadingPeriod::suggested(*end - *start, sampling))
+    }
+
+    /// Checks that readings for a resource can be requested at the given
+    /// period.
+    ///
+    /// Only per-minute readings need checking, they are available for real-time
+    /// resources such as instantaneous power but not for cumulative meter
+    /// readings. Fails with [`ErrorKind::UnsupportedPeriod`] if the resource
+    /// does not record data that often.
+    pub async fn check_period(
+        &self,
+        resource_id: &str,
+        period: ReadingPeriod,
+    ) -> Result<(), Error> {
+        if !matches!(period, ReadingPeriod::Minute) {
+            return Ok(());
+        }
+
+        let resource = self.resource(resource_id).await?.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Unknown resource {}", resource_id),
+            )
+        })?;
+
+        resource.check_period(&self.resource_types().await?, period)
+    }
+
+    /// Retrieves the current tariff for a resource.
+    ///
+    /// Returns `None` if the resource has no tariff configured.
+    pub async fn latest_tariff(&self, resource_id: &str) -> Result<Option<TariffData>, Error> {
+        let tariff = maybe_tariff(
+            self.get_request(format!("resource/{}/tariff", resource_id))
+                .request::<api::TariffResponse>()
+                .await
+                .and_then(|r| r.validate()),
+        )?;
+
+        Ok(tariff.and_then(|t| t.data.into_iter().next()))
+    }
+
+    /// Retrieves the history of tariffs for a resource.
+    ///
+    /// Returns an empty list if the resource has no tariff configured.
+    pub async fn tariff_list(&self, resource_id: &str) -> Result<Vec<TariffData>, Error> {
+        let tariff = maybe_tariff(
+            self.get_request(format!("resource/{}/tariff-list", resource_id))
+                .request::<api::TariffResponse>()
+                .await
+                .and_then(|r| r.validate()),
+        )?;
+
+        Ok(tariff.map(|t| t.data).unwrap_or_default())
+    }
+
     /// Retrieves the readings for a single resource.
     ///
     /// The API docs suggest that the start date should be set to the beginning
@@ -462,6 +1889,9 @@ impl GlowmarktApi {
     /// The Glowmarkt API behaves strangely in the presence of non-UTC
     /// timezones so `start` and `end` will first be converted to UTC and all
     /// returned readings will be in UTC.
+    ///
+    /// Unless disabled with [`GlowmarktApiBuilder::clamp_future`] an `end` in
+    /// the future is moved back to the current time.
     pub async fn readings(
         &self,
         resource_id: &str,
@@ -469,45 +1899,448 @@ impl GlowmarktApi {
         end: &OffsetDateTime,
         period: ReadingPeriod,
     ) -> Result<Vec<Reading>, Error> {
+        self.readings_with_function(resource_id, start, end, period, AggregationFunction::Sum)
+            .await
+    }
+
+    /// Retrieves the readings for a single resource along with a warning for
+    /// each reading skipped because the API gave it a timestamp that can't
+    /// be represented.
+    ///
+    /// See [`GlowmarktApi::readings`] for details of how dates are handled.
+    pub async fn readings_with_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
+        self.readings_and_warnings(resource_id, start, end, period, AggregationFunction::Sum)
+            .await
+    }
+
+    /// Retrieves the readings for a single resource, combining the values in
+    /// each period with the given function.
+    ///
+    /// See [`GlowmarktApi::readings`] for details of how dates are handled.
+    /// Readings with invalid timestamps are skipped and logged.
+    pub async fn readings_with_function(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+        function: AggregationFunction,
+    ) -> Result<Vec<Reading>, Error> {
+        let (readings, warnings) = self
+            .readings_and_warnings(resource_id, start, end, period, function)
+            .await?;
+        for warning in warnings {
+            log::warn!("{}", warning);
+        }
+        Ok(readings)
+    }
+
+    async fn readings_and_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+        function: AggregationFunction,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
+        let end = &if self.clamp_future {
+            self.clamp_to_now(end).0
+        } else {
+            *end
+        };
+        if start > end {
+            log::debug!("Skipping request for readings that are entirely in the future");
+            return Ok((Vec::new(), Vec::new()));
+        }
+
+        match self.coalescer {
+            Some(ref coalescer) => {
+                let key = (
+                    resource_id.to_owned(),
+                    *start,
+                    *end,
+                    period.iso_duration(),
+                    function.as_str(),
+                );
+                let api = self.clone();
+                let resource_id = resource_id.to_owned();
+                let (start, end) = (*start, *end);
+                let readings = coalescer
+                    .run(key, move || {
+                        async move {
+                            api.fetch_readings(&resource_id, &start, &end, period, function)
+                                .await
+                                .map(Arc::new)
+                        }
+                        .boxed()
+                    })
+                    .await?;
+                Ok(readings.as_ref().clone())
+            }
+            None => {
+                self.fetch_readings(resource_id, start, end, period, function)
+                    .await
+            }
+        }
+    }
+
+    async fn fetch_readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+        function: AggregationFunction,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
         log::trace!(
-            "Requesting readings for {} in range {} to {}, period {:?}",
+            "Requesting readings for {} in range {} to {}, period {:?}, function {}",
             resource_id,
             start.format(&Rfc3339).unwrap(),
             end.format(&Rfc3339).unwrap(),
-            period
+            period,
+            function
         );
 
-        let period_arg = match period {
-            ReadingPeriod::HalfHour => "PT30M".to_string(),
-            ReadingPeriod::Hour => "PT1H".to_string(),
-            ReadingPeriod::Day => "P1D".to_string(),
-            ReadingPeriod::Week => "P1W".to_string(),
-            ReadingPeriod::Month => "P1M".to_string(),
-            ReadingPeriod::Year => "P1Y".to_string(),
-        };
-
         let readings = self
             .query_request(
                 format!("resource/{}/readings", resource_id),
                 &[
                     ("from", iso(start.to_offset(UtcOffset::UTC))),
                     ("to", iso(end.to_offset(UtcOffset::UTC))),
-                    ("period", period_arg),
+                    ("period", period.iso_duration().to_string()),
                     ("offset", 0.to_string()),
-                    ("function", "sum".to_string()),
+                    ("function", function.as_str().to_string()),
                 ],
             )
             .request::<api::ReadingsResponse>()
             .await?;
 
-        Ok(readings
+        let mut warnings = Vec::new();
+        let readings = readings
             .data
             .into_iter()
-            .map(|(timestamp, value)| Reading {
-                start: OffsetDateTime::from_unix_timestamp(timestamp).unwrap(),
-                period,
-                value,
-            })
+            .filter_map(
+                |reading| match valid_timestamp(resource_id, reading.timestamp) {
+                    Ok(start) => Some(Reading {
+                        start,
+                        period,
+                        value: reading.value,
+                        quality: reading.quality,
+                    }),
+                    Err(warning) => {
+                        warnings.push(warning);
+                        None
+                    }
+                },
+            )
+            .collect();
+
+        Ok((readings, warnings))
+    }
+
+    /// Moves the end of a range back to the current time if it is in the
+    /// future, returning the new end and a notice if it was changed.
+    pub fn clamp_to_now(&self, end: &OffsetDateTime) -> (OffsetDateTime, Option<Clamped>) {
+        let now = self.clock.now();
+        if *end > now {
+            log::debug!(
+                "Clamping the end of the range from {} to {}",
+                end.format(&Rfc3339).unwrap(),
+                now.format(&Rfc3339).unwrap()
+            );
+            (
+                now,
+                Some(Clamped {
+                    requested: *end,
+                    end: now,
+                }),
+            )
+        } else {
+            (*end, None)
+        }
+    }
+
+    /// Retrieves the readings for a single resource at a resolution that
+    /// falls with their age, see [`split_tiers`].
+    ///
+    /// The period of each reading shows which tier it came from.
+    pub async fn readings_tiered(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<Vec<Reading>, Error> {
+        let mut readings = Vec::new();
+
+        for (period, tier_start, tier_end) in split_tiers(*start, *end, self.clock.now()) {
+            readings.extend(
+                self.readings_range(resource_id, &tier_start, &tier_end, period)
+                    .await?,
+            );
+        }
+
+        Ok(readings)
+    }
+
+    /// Retrieves the readings for a single resource over any length of time,
+    /// noting whether the range had to be clamped to the current time.
+    ///
+    /// Clamping happens regardless of [`GlowmarktApiBuilder::clamp_future`].
+    pub async fn readings_range_clamped(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<(Vec<Reading>, Option<Clamped>), Error> {
+        let (end, clamped) = self.clamp_to_now(end);
+        if *start > end {
+            return Ok((Vec::new(), clamped));
+        }
+
+        let readings = self
+            .readings_range(resource_id, start, &end, period)
+            .await?;
+        Ok((readings, clamped))
+    }
+
+    /// Retrieves the readings for a single resource over any length of time,
+    /// reporting anything that affected them as warnings rather than only
+    /// logging it.
+    ///
+    /// Warnings are produced when the end of the range is moved back to the
+    /// current time, when duplicate readings are dropped and when the last
+    /// reading's period hasn't finished yet. Clamping only happens as
+    /// configured by [`GlowmarktApiBuilder::clamp_future`].
+    pub async fn readings_range_with_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
+        let mut warnings = Vec::new();
+        let end = if self.clamp_future {
+            let (end, clamped) = self.clamp_to_now(end);
+            if let Some(clamped) = clamped {
+                warnings.push(Warning::Clamped {
+                    resource_id: resource_id.to_owned(),
+                    requested: clamped.requested,
+                    end: clamped.end,
+                });
+            }
+            end
+        } else {
+            *end
+        };
+        if *start > end {
+            return Ok((Vec::new(), warnings));
+        }
+
+        let (readings, merged) =
+            GlowmarktClient::readings_range_with_warnings(self, resource_id, start, &end, period)
+                .await?;
+        warnings.extend(merged);
+
+        if let Some(last) = readings.last() {
+            let period_end = increase_by_period(last.start, last.period);
+            if period_end > self.clock.now() {
+                warnings.push(Warning::PartialPeriod {
+                    resource_id: resource_id.to_owned(),
+                    start: last.start,
+                    end: period_end,
+                });
+            }
+        }
+
+        Ok((readings, warnings))
+    }
+
+    /// Retrieves the readings for a single resource over any length of time.
+    ///
+    /// The range is split into as many requests as the API requires and the
+    /// results merged into a single list ordered by start time. Where requests
+    /// overlap at their boundaries the reading from the later request is kept.
+    pub async fn readings_range(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<Reading>, Error> {
+        GlowmarktClient::readings_range(self, resource_id, start, end, period).await
+    }
+
+    /// Retrieves the readings for a resource over any length of time, each
+    /// labelled with the resource's unit.
+    ///
+    /// See [`GlowmarktApi::readings_range`].
+    pub async fn typed_readings(
+        &self,
+        resource: &Resource,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<TypedReading>, Error> {
+        let unit = resource.unit();
+        Ok(self
+            .readings_range(&resource.id, start, end, period)
+            .await?
+            .into_iter()
+            .map(|reading| reading.with_unit(unit.clone()))
             .collect())
     }
+
+    /// Streams the readings for a single resource over any length of time.
+    ///
+    /// Like [`GlowmarktApi::readings_range`] the range is split into as many
+    /// requests as the API requires but requests are only made as readings are
+    /// consumed, at most the configured concurrency ahead, so long histories
+    /// can be processed without holding them all in memory. A failed request
+    /// yields an error in place of its readings.
+    pub fn readings_stream<'a>(
+        &'a self,
+        resource_id: &'a str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Stream<Item = Result<Reading, Error>> + 'a {
+        stream::iter(split_periods(*start, *end, period))
+            .map(move |(start, end)| async move {
+                self.readings(resource_id, &start, &end, period).await
+            })
+            .buffered(self.concurrency)
+            .map_ok(|readings| stream::iter(readings.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Calls a function with each chunk of readings for a single resource as
+    /// it is fetched, for writing them somewhere or aggregating them without
+    /// holding the whole range in memory.
+    ///
+    /// The range is split into chunks as for
+    /// [`GlowmarktApi::readings_stream`]. Chunks are passed in order and each
+    /// call is awaited before the next, with at most the configured
+    /// concurrency of chunks fetched ahead. Stops at the first error from
+    /// either a request or the function.
+    pub async fn readings_for_each_chunk<F, Fut, E>(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+        mut f: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(Vec<Reading>) -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+        E: From<Error>,
+    {
+        let mut chunks = pin!(stream::iter(split_periods(*start, *end, period))
+            .map(
+                |(start, end)| async move { self.readings(resource_id, &start, &end, period).await }
+            )
+            .buffered(self.concurrency));
+
+        while let Some(chunk) = chunks.next().await {
+            f(chunk?).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Streams the readings for an energy resource and the resource measuring
+    /// its cost together, see [`GlowmarktApi::cost_resource`].
+    ///
+    /// The range is split into chunks as for [`GlowmarktApi::readings_stream`]
+    /// but both resources are requested for each chunk before moving to the
+    /// next, and each chunk's readings are yielded joined by start time. If
+    /// a request fails an error is yielded in place of the chunk, so every
+    /// chunk before it is complete for both resources and at most the
+    /// configured concurrency of chunks are held in memory.
+    pub fn paired_readings_stream<'a>(
+        &'a self,
+        energy_id: &'a str,
+        cost_id: &'a str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> impl Stream<Item = Result<Vec<PairedReading>, Error>> + 'a {
+        // Each chunk makes two requests at once.
+        let concurrency = self.concurrency.div_ceil(2).max(1);
+
+        stream::iter(split_periods(*start, *end, period))
+            .map(move |(start, end)| async move {
+                let (energy, cost) = futures::try_join!(
+                    self.readings(energy_id, &start, &end, period),
+                    self.readings(cost_id, &start, &end, period)
+                )?;
+                Ok(pair_readings(energy, cost))
+            })
+            .buffered(concurrency)
+    }
+
+    /// Retrieves the readings for a resource and costs them using its current
+    /// tariff.
+    ///
+    /// Fails with [`ErrorKind::NoTariff`] if the resource has no tariff or
+    /// the tariff has no unit rates.
+    pub async fn costed_readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<cost::Costs, Error> {
+        let rates = self
+            .latest_tariff(resource_id)
+            .await?
+            .as_ref()
+            .and_then(cost::Rates::from_tariff)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NoTariff,
+                    format!("Resource {} has no usable tariff", resource_id),
+                )
+            })?;
+
+        let readings = self.readings_range(resource_id, start, end, period).await?;
+
+        // The range runs to the end of the last reading, but days yet to come
+        // aren't charged.
+        let range_end = increase_by_period(*end, period).min(self.clock.now());
+        Ok(cost::cost(&readings, &rates, *start, range_end))
+    }
+}
+
+impl GlowmarktClient for GlowmarktApi {
+    fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    async fn readings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<Vec<Reading>, Error> {
+        GlowmarktApi::readings(self, resource_id, start, end, period).await
+    }
+
+    async fn readings_with_warnings(
+        &self,
+        resource_id: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        period: ReadingPeriod,
+    ) -> Result<(Vec<Reading>, Vec<Warning>), Error> {
+        GlowmarktApi::readings_with_warnings(self, resource_id, start, end, period).await
+    }
 }
@@ -0,0 +1,101 @@
+//! Finding a resource from something easier to remember than its ID.
+
+use glowmarkt::{classifier::Classifier, event::is_event_resource, GlowmarktApi, Resource};
+
+use crate::{dashboard::fuel, hint::CliError};
+
+/// Whether a string looks like a resource ID, a UUID, so can be used without
+/// listing the account's resources.
+fn is_id(selector: &str) -> bool {
+    selector.len() == 36
+        && selector.chars().enumerate().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+fn describe(resources: &[&Resource]) -> String {
+    let mut lines: Vec<String> = resources
+        .iter()
+        .map(|resource| match resource.classifier {
+            Some(ref classifier) => {
+                format!("  {}  {} ({})", resource.id, resource.name, classifier)
+            }
+            None => format!("  {}  {}", resource.id, resource.name),
+        })
+        .collect();
+    lines.sort();
+    lines.join("\n")
+}
+
+/// Finds the ID of the resource a selector refers to. The selector may be the
+/// resource's ID, its classifier such as `electricity.consumption`, or part of
+/// its name.
+pub async fn resolve_resource(api: &GlowmarktApi, selector: &str) -> Result<String, CliError> {
+    if is_id(selector) {
+        return Ok(selector.to_owned());
+    }
+
+    let resources = api.resources().await?;
+    if resources.contains_key(selector) {
+        return Ok(selector.to_owned());
+    }
+
+    let classifier = Classifier::from(selector);
+    let mut matches: Vec<&Resource> = resources
+        .values()
+        .filter(|resource| resource.classifier.as_ref() == Some(&classifier))
+        .collect();
+
+    if matches.is_empty() {
+        let name = selector.to_lowercase();
+        matches = resources
+            .values()
+            .filter(|resource| resource.name.to_lowercase().contains(&name))
+            .collect();
+    }
+
+    match matches.as_slice() {
+        [resource] => {
+            log::debug!("Using resource {} ({})", resource.id, resource.name);
+            Ok(resource.id.clone())
+        }
+        [] => Err(format!(
+            "No resource matches '{}', the available resources are:\n{}",
+            selector,
+            describe(&resources.values().collect::<Vec<_>>())
+        )
+        .into()),
+        _ => Err(format!(
+            "'{}' matches more than one resource, use one of their IDs instead:\n{}",
+            selector,
+            describe(&matches)
+        )
+        .into()),
+    }
+}
+
+/// Resolves a list of resource selectors, or returns every consumption
+/// resource if the list is empty.
+pub async fn select_resources(
+    api: &GlowmarktApi,
+    selectors: &[String],
+) -> Result<Vec<Resource>, CliError> {
+    let all = api.resources().await?;
+    if selectors.is_empty() {
+        return Ok(all
+            .into_values()
+            .filter(|resource| fuel(resource).is_some() && !is_event_resource(resource))
+            .collect());
+    }
+
+    let mut selected = Vec::new();
+    for selector in selectors {
+        let id = resolve_resource(api, selector).await?;
+        match all.get(&id) {
+            Some(resource) => selected.push(resource.clone()),
+            None => return Err(format!("Unknown resource {}", id).into()),
+        }
+    }
+    Ok(selected)
+}
@@ -1,21 +1,111 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    env,
     fmt::Display,
+    io::{stdout, BufWriter},
+    path::{Path, PathBuf},
+    process::exit,
 };
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use flexi_logger::Logger;
 use glowmarkt::{
-    align_to_period, split_periods, Device, Error, ErrorKind, GlowmarktApi, ReadingPeriod, Resource,
+    api::VirtualEntity,
+    calendar::parse_weekday,
+    classifier::Classifier,
+    clock::{Clock, FixedClock, SkewedClock, SystemClock},
+    cost::{self, Rates},
+    format::{self, CsvWriter, TimestampFormat},
+    increase_by_period,
+    manifest::{Manifest, Mismatch},
+    parse_iso_duration, reqwest, settlement,
+    sink::{
+        add_tags_for_device, add_tags_for_entity, add_tags_for_resource, entities_by_resource,
+        field_for_classifier, Measurement,
+    },
+    split_periods, split_tiers,
+    transform::{Pipeline, Transform},
+    unit::Unit,
+    AggregationFunction, Calendar, Device, Error, ErrorKind, GlowmarktApi, GlowmarktApiBuilder,
+    HttpVersion, ReadingPeriod, Resource, RetryPolicy,
 };
-use influx::Measurement;
 use serde::Serialize;
 use serde_json::to_string_pretty;
-use time::{format_description::well_known::Iso8601, Duration, OffsetDateTime};
+use time::{format_description::well_known::Iso8601, Duration, OffsetDateTime, Weekday};
 
-use crate::influx::{add_tags_for_device, add_tags_for_resource, field_for_classifier};
+#[cfg(feature = "octopus")]
+use crate::agile::{agile_cost, AgileCostArgs};
+use crate::audit::{audit, AuditArgs};
+use crate::budget::{budget, BudgetArgs};
+use crate::compare::{compare, CompareArgs};
+use crate::config::{CalendarConfig, Config};
+use crate::daemon::{daemon, DaemonArgs};
+use crate::dashboard::{dashboard, DashboardArgs};
+use crate::devicestatus::{device_statuses, DeviceStatusArgs};
+use crate::diagnose::diagnose;
+use crate::events::{events, EventsArgs};
+use crate::export::{export, ExportArgs};
+use crate::forecast::{forecast_accuracy, ForecastOptions};
+use crate::gapcheck::{gap_report, GapsArgs};
+use crate::generate::{generate, GenerateArgs};
+use crate::healthcheck::{health, HealthArgs};
+use crate::hint::CliError;
+use crate::influxdb::{InfluxDbOptions, InfluxDbWriter};
+use crate::legacy::LegacyReadings;
+use crate::lookup::resolve_resource;
+use crate::metrics::{serve_metrics, ServeMetricsArgs};
+use crate::mqtt::{mqtt, MqttArgs};
+use crate::output::{
+    transform_resource, CsvOptions, GasOptions, JsonWriter, LegacyWriter, NdjsonWriter,
+    OutputOptions, ReadingsWriter, TransformOptions,
+};
+use crate::overview::overview;
+use crate::payments::{export_payments, ExportPaymentsArgs};
+use crate::project::{project, ProjectArgs};
+use crate::resourcelist::resource_table;
+use crate::state::SyncState;
+use crate::summary::{summary, SummaryArgs};
+use crate::sync::{sync, SyncArgs};
+use crate::tariffsim::{simulate, SimulateArgs};
+use crate::version::{version, VersionArgs};
 
-mod influx;
+#[cfg(feature = "octopus")]
+mod agile;
+mod audit;
+mod budget;
+mod compare;
+mod config;
+mod daemon;
+mod dashboard;
+mod devicestatus;
+mod diagnose;
+mod events;
+mod export;
+mod forecast;
+mod gapcheck;
+mod generate;
+mod healthcheck;
+mod hint;
+mod influxdb;
+mod legacy;
+mod lookup;
+mod metrics;
+mod mqtt;
+mod notify;
+mod output;
+mod overview;
+mod payments;
+mod project;
+mod resourcelist;
+mod schedule;
+mod secrets;
+mod state;
+mod summary;
+mod sync;
+mod tariffsim;
+mod tokencache;
+mod version;
+mod wal;
 
 #[derive(Parser)]
 #[clap(author, version)]
@@ -24,16 +114,79 @@ mod influx;
 /// All commands require either a username and password or a valid JWT token to
 /// operate. If you provide both then the token will be checked for validity
 /// and if not valid a new token will be generated.
-/// Dates can be specified either is ISO-8601 (`2022-08-21T09:00:00Z`) or as a
-/// negative offset from the current time in minutes, so `-1440` would be
-/// interpreted as 24 hours ago.
+/// Dates can be specified either is ISO-8601 (`2022-08-21T09:00:00Z`), as unix
+/// epoch seconds or milliseconds, or as an offset back from the current time,
+/// either a negative number of minutes or an ISO-8601 duration, so `-1440` and
+/// `P1D` would both be interpreted as 24 hours ago.
 struct Args {
-    #[clap(short, long, env)]
+    /// The account's username. `USERNAME` is also read if this isn't set.
+    #[clap(short, long, env = "GLOWMARKT_USERNAME")]
     pub username: Option<String>,
-    #[clap(short, long, env)]
+    /// The account's password. `PASSWORD` is also read if this isn't set.
+    #[clap(short, long, env = "GLOWMARKT_PASSWORD", hide_env_values = true)]
     pub password: Option<String>,
-    #[clap(short, long, env)]
+    /// A JWT token. `TOKEN` is also read if this isn't set.
+    #[clap(short, long, env = "GLOWMARKT_TOKEN", hide_env_values = true)]
     pub token: Option<String>,
+    /// Warn if the local clock differs significantly from the API server's.
+    #[clap(long, env)]
+    pub check_clock: bool,
+    /// Adjust relative dates to account for any difference between the local
+    /// clock and the API server's.
+    #[clap(long, env)]
+    pub compensate_clock: bool,
+    /// Run as if the current time were this ISO-8601 time, for dry runs.
+    #[clap(long)]
+    pub now: Option<String>,
+    /// The number of decimal places to include in output values.
+    #[clap(long, env, value_parser = clap::value_parser!(u32).range(0..=15))]
+    pub precision: Option<u32>,
+    /// The maximum number of requests to make at once.
+    #[clap(long, env, default_value = "4")]
+    pub concurrency: usize,
+    /// The maximum number of requests to make each second.
+    #[clap(long, env)]
+    pub rate_limit: Option<f64>,
+    /// The maximum number of times to attempt each request when the API
+    /// fails for transient reasons.
+    #[clap(long, env, default_value = "3")]
+    pub max_attempts: u32,
+    /// The longest to wait for each request, in seconds.
+    #[clap(long, env)]
+    pub timeout: Option<u64>,
+    /// The proxy to send requests through.
+    #[clap(long, env = "GLOWMARKT_PROXY")]
+    pub proxy: Option<String>,
+    /// The HTTP versions to use.
+    #[clap(long, env, value_enum, default_value = "auto")]
+    pub http_version: HttpVersionArg,
+    /// The most idle connections to keep open for reuse.
+    #[clap(long, env)]
+    pub pool_max_idle: Option<usize>,
+    /// How long to keep idle connections open for reuse, in seconds. 0 keeps
+    /// them open indefinitely.
+    #[clap(long, env)]
+    pub pool_idle_timeout: Option<u64>,
+    /// Send TCP keepalive probes after connections have been idle for this
+    /// many seconds.
+    #[clap(long, env)]
+    pub tcp_keepalive: Option<u64>,
+    /// The configuration file to use, defaults to
+    /// `$XDG_CONFIG_HOME/glowmarkt/config.toml`.
+    #[clap(long, env = "GLOWMARKT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// The first day of the week when aligning weekly periods, defaults to
+    /// Monday as the API uses.
+    #[clap(long, env, value_parser = parse_weekday)]
+    pub week_start: Option<Weekday>,
+    /// The hour, in UTC, at which days start when aligning daily and longer
+    /// periods, defaults to midnight as the API uses.
+    #[clap(long, env, value_parser = clap::value_parser!(u8).range(0..24))]
+    pub day_start_hour: Option<u8>,
+    /// Don't reuse or store the token generated from the username and
+    /// password in `$XDG_CACHE_HOME/glowmarkt/token.json`.
+    #[clap(long, env)]
+    pub no_cache: bool,
 
     #[clap(subcommand)]
     command: Command,
@@ -50,115 +203,577 @@ fn parse_tag(val: &str) -> Result<(String, String), String> {
     }
 }
 
+fn parse_validity(val: &str) -> Result<Duration, String> {
+    let invalid = || format!("Invalid duration '{}', try 1h, 30m or PT1H", val);
+    if val.starts_with('P') {
+        return parse_iso_duration(val);
+    }
+
+    let split = val
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let count: i64 = val[..split].parse().map_err(|_| invalid())?;
+    match &val[split..] {
+        "s" => Ok(Duration::seconds(count)),
+        "m" => Ok(Duration::minutes(count)),
+        "h" => Ok(Duration::hours(count)),
+        "d" => Ok(Duration::days(count)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HttpVersionArg {
+    /// HTTP/2 where the connection negotiates it, otherwise HTTP/1.1.
+    Auto,
+    /// Only HTTP/1.1.
+    Http1,
+    /// HTTP/2 without negotiating it first.
+    Http2,
+}
+
+impl From<HttpVersionArg> for HttpVersion {
+    fn from(version: HttpVersionArg) -> Self {
+        match version {
+            HttpVersionArg::Auto => HttpVersion::Auto,
+            HttpVersionArg::Http1 => HttpVersion::Http1,
+            HttpVersionArg::Http2 => HttpVersion::Http2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// JSON arrays of readings.
+    Json,
+    /// A single JSON document matching the raw API response, as produced by
+    /// the Python and Node Glowmarkt clients.
+    Legacy,
+    /// Newline delimited JSON, one reading per line.
+    Ndjson,
+    /// Comma separated values.
+    Csv,
+}
+
+#[derive(clap::Args)]
+struct ReadingsArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "json")]
+    format: Format,
+    /// Label each reading with its UK settlement period.
+    #[clap(long)]
+    settlement_period: bool,
+    /// The length of each reading (1m, 30m, 1h, 1d, 1w, 1mon or 1y). Defaults
+    /// to the finest period the resource supports that suits the length of
+    /// the range.
+    #[clap(long)]
+    period: Option<ReadingPeriod>,
+    /// Read recent data half-hourly and older data at progressively coarser
+    /// periods, hourly from a week back and daily from 90 days back.
+    #[clap(long, conflicts_with = "period")]
+    auto_period: bool,
+    /// How values within each period are combined (sum, avg, min or max).
+    #[clap(long, default_value = "sum")]
+    function: AggregationFunction,
+    /// The format of timestamps in CSV output (rfc3339, unix or unix-ms).
+    #[clap(long, default_value = "rfc3339")]
+    time_format: TimestampFormat,
+    /// Don't include a header row in CSV output.
+    #[clap(long)]
+    no_header: bool,
+    #[clap(flatten)]
+    csv: CsvOptions,
+    #[clap(flatten)]
+    gas: GasOptions,
+    #[clap(flatten)]
+    transform: TransformOptions,
+    /// Ask the API to fetch outstanding data from the meter first.
+    #[clap(long)]
+    auto_catchup: bool,
+    /// The resource to read, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Breakdown {
+    /// By time of use rate band.
+    Tou,
+}
+
+#[derive(clap::Args)]
+struct CostArgs {
+    /// The length of each reading (30m, 1h, 1d, 1w, 1mon or 1y).
+    #[clap(long, default_value = "30m")]
+    period: ReadingPeriod,
+    /// The unit rate in pence per kWh to use if the resource has no tariff.
+    #[clap(long)]
+    unit_rate: Option<f64>,
+    /// The daily standing charge in pence to use if the resource has no tariff.
+    #[clap(long, requires = "unit-rate")]
+    standing_charge: Option<f64>,
+    /// Also split each day's consumption and cost between the tariff's rate
+    /// bands, such as the day and night rates of Economy 7.
+    #[clap(long, value_enum)]
+    breakdown: Option<Breakdown>,
+    /// The resource to cost, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct InfluxArgs {
+    /// The device to read. If absent all devices are read.
+    #[clap(short, long, env)]
+    device: Option<String>,
+    /// Only read devices with this tag.
+    #[clap(long = "device-tag")]
+    device_tags: Vec<String>,
+    /// Don't strip trailing zero readings.
+    #[clap(short, long, env)]
+    no_strip: bool,
+    /// Tag each reading with its UK settlement period.
+    #[clap(long)]
+    settlement_period: bool,
+    /// Add additional tags to the readings.
+    #[clap(short, long = "tag", value_parser=parse_tag)]
+    tags: Vec<(String, String)>,
+    /// Ask the API to fetch outstanding data from the meters first.
+    #[clap(long)]
+    auto_catchup: bool,
+    #[clap(flatten)]
+    influxdb: InfluxDbOptions,
+    /// Start each resource after the newest reading output by the last run
+    /// that used this flag. Resources not seen before start at the start time.
+    #[clap(long)]
+    since_last_run: bool,
+    /// The file recording the last run, defaults to
+    /// `$XDG_DATA_HOME/glowmarkt/influx-state.json`.
+    #[clap(long, env = "GLOWMARKT_INFLUX_STATE", requires = "since-last-run")]
+    state: Option<PathBuf>,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true, required_unless_present = "since-last-run")]
+    from: Option<String>,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TokenFormat {
+    /// Just the token.
+    Text,
+    /// The token with its expiry.
+    Json,
+}
+
+#[derive(clap::Args)]
+struct TokenArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: TokenFormat,
+    /// Generate a new token even if the current one is still valid.
+    #[clap(long)]
+    refresh: bool,
+    /// Generate a new token if the current one expires within this time,
+    /// e.g. `1h`, `30m` or an ISO-8601 duration.
+    #[clap(long, value_parser = parse_validity)]
+    min_validity: Option<Duration>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenInfo {
+    token: String,
+    #[serde(with = "time::serde::rfc3339::option")]
+    expiry: Option<OffsetDateTime>,
+    valid_for: Option<i64>,
+    refreshed: bool,
+}
+
+#[derive(Subcommand)]
+enum DeviceCommand {
+    /// Shows when each device last reported, listing devices that have gone
+    /// quiet first.
+    ///
+    /// The last-seen time comes from the device if the API reports one,
+    /// otherwise from the most recent value recorded by its resources.
+    Status(DeviceStatusArgs),
+}
+
 #[derive(Subcommand)]
 enum Command {
     /// Generates a valid authentication token.
-    Token,
+    ///
+    /// With --refresh or --min-validity a new token is generated from the
+    /// username and password when needed, so schedulers can keep a token
+    /// fresh for other tools that call the API directly.
+    Token(TokenArgs),
+    /// Runs a series of checks against the API and prints a report.
+    ///
+    /// The report covers authentication, the time taken to list metadata and
+    /// fetch a few readings, any rate limiting reported by the server and the
+    /// local clock's accuracy. Attach it to support requests and bug reports.
+    Diagnose,
+    /// Shows the account's virtual entities, devices, sensors and resources
+    /// as a tree, to help work out which ID is which.
+    #[clap(alias = "tree")]
+    Overview,
     /// Lists devices.
     Device {
+        /// Only list devices with this tag.
+        #[clap(long = "device-tag")]
+        device_tags: Vec<String>,
         /// The specific device to display.
         id: Option<String>,
+        #[clap(subcommand)]
+        action: Option<DeviceCommand>,
     },
     /// Lists device types.
     DeviceType {
         /// The specific device type to display.
         id: Option<String>,
     },
+    /// Lists virtual entities, the groupings of resources such as a home.
+    VirtualEntity {
+        /// Include the full details of each entity's resources.
+        #[clap(long)]
+        resources: bool,
+        /// The specific entity to display.
+        id: Option<String>,
+    },
     /// Lists resource types.
     ResourceType {
         /// The specific resource type to display.
         id: Option<String>,
     },
     /// Lists resources.
+    #[clap(alias = "resources")]
     Resource {
+        /// List resources in a table with their classifier, unit, owning
+        /// device and the times of the first and last readings they hold.
+        #[clap(long)]
+        wide: bool,
         /// The specific resource to display.
         id: Option<String>,
     },
+    /// Lists the resources of the devices with a hardware ID, such as a meter
+    /// serial number or MPAN.
+    Hardware {
+        /// The hardware ID to look for.
+        hardware_id: String,
+    },
+    /// Displays the most recent value recorded by a resource, such as the
+    /// current power draw.
+    Current {
+        /// The resource to read, either its ID, its classifier such as
+        /// `electricity.consumption` or part of its name.
+        resource: String,
+    },
+    /// Displays the cumulative register value of a resource's meter, for
+    /// submitting meter readings to a supplier.
+    #[clap(name = "meterread")]
+    MeterRead {
+        /// The resource to read, either its ID, its classifier such as
+        /// `electricity.consumption` or part of its name.
+        resource: String,
+    },
+    /// Asks the API to fetch outstanding data for a resource from its meter.
+    ///
+    /// Data arrives in the background and can take a few minutes to appear.
+    Catchup {
+        /// The resource to catch up, either its ID, its classifier such as
+        /// `electricity.consumption` or part of its name.
+        resource: String,
+    },
     /// Lists meter readings.
     ///
-    /// Times are expressed either in ISO-8601 format (e.g. 2023-11-01T00:00:00Z) or as a
-    /// negative offset from the current time in minutes, so `-1440` would be
+    /// Times are expressed either in ISO-8601 format (e.g. 2023-11-01T00:00:00Z), as unix epoch
+    /// seconds or milliseconds, or as an offset back from the current time, either a negative
+    /// number of minutes or an ISO-8601 duration, so `-1440` and `P1D` would both be
     /// interpreted as 24 hours ago.
-    Readings {
-        /// The resource to read.
-        resource_id: String,
-        /// Start time of first reading.
-        from: String,
-        /// Start time of last reading (defaults to now).
-        to: Option<String>,
-    },
+    Readings(ReadingsArgs),
+    /// Calculates the cost of a resource's consumption from its tariff.
+    ///
+    /// Times are expressed in the same way as for the readings command. Costs
+    /// are in pence. Without a tariff or --unit-rate only the consumption is
+    /// included, with a note explaining why.
+    Cost(CostArgs),
+    /// Summarises a resource's half-hourly usage over a range.
+    ///
+    /// Shows the total, the daily average, the lowest and highest days, the
+    /// peak half hour and a table of each day's total, smallest and largest
+    /// reading. Days and times are UK local time.
+    Summary(SummaryArgs),
+    /// Compares a resource's consumption and cost over two windows.
+    ///
+    /// With --period the most recent complete day, week or month is compared
+    /// with the one before it, otherwise the window from --from to --to is
+    /// compared with one of the same length starting at --baseline, or
+    /// immediately before it. The windows are aligned bucket by bucket.
+    Compare(CompareArgs),
+    /// Shows what a resource's half-hourly consumption would have cost on
+    /// other tariffs.
+    ///
+    /// Each --rates file gives a tariff's standing charge, unit rate and time
+    /// of use bands in TOML, or JSON if its name ends in `.json`. Totals are
+    /// compared with the resource's current tariff when it has one. Costs are
+    /// in pence.
+    Simulate(SimulateArgs),
+    /// Projects a resource's annual consumption and cost from its history
+    /// and current tariff.
+    ///
+    /// With less than a year of history the usage is scaled for the time of
+    /// year it covers. The figures match those on supplier quotes: estimated
+    /// annual consumption, unit rate, standing charge and annual cost.
+    Project(ProjectArgs),
+    /// Lists the periods in a range that a resource has no reading for.
+    ///
+    /// Consecutive missing periods are grouped together. Times are expressed
+    /// in the same way as for the readings command.
+    Gaps(GapsArgs),
+    /// Lists the export payment for every settlement period as CSV.
+    ///
+    /// Each UK settlement period's exported kWh is paid at the matching rate,
+    /// from --rate, --rates or the resource's tariff, followed by a total row.
+    /// Payments are in pence.
+    ExportPayments(ExportPaymentsArgs),
+    /// Costs a resource's half-hourly consumption at Octopus Agile prices.
+    ///
+    /// Prices for --region are fetched from Octopus Energy's public API and
+    /// each half hour is costed at its price including VAT. Costs are in
+    /// pence.
+    #[cfg(feature = "octopus")]
+    AgileCost(AgileCostArgs),
     /// Retrieves device data in InfluxDB line protocol.
     ///
-    /// Times are expressed either in ISO-8601 format (e.g. 2023-11-01T00:00:00Z) or as a
-    /// negative offset from the current time in minutes, so `-1440` would be
+    /// With --url the measurements are written to the InfluxDB v2 write API,
+    /// or the v1 API if no --org is given, instead of being printed.
+    ///
+    /// Times are expressed either in ISO-8601 format (e.g. 2023-11-01T00:00:00Z), as unix epoch
+    /// seconds or milliseconds, or as an offset back from the current time, either a negative
+    /// number of minutes or an ISO-8601 duration, so `-1440` and `P1D` would both be
+    /// interpreted as 24 hours ago.
+    Influx(InfluxArgs),
+    /// Exports readings from a source to a sink.
+    ///
+    /// Times are expressed either in ISO-8601 format (e.g. 2023-11-01T00:00:00Z), as unix epoch
+    /// seconds or milliseconds, or as an offset back from the current time, either a negative
+    /// number of minutes or an ISO-8601 duration, so `-1440` and `P1D` would both be
     /// interpreted as 24 hours ago.
-    Influx {
-        /// The device to read. If absent all devices are read.
-        #[clap(short, long, env)]
-        device: Option<String>,
-        /// Don't strip trailing zero readings.
-        #[clap(short, long, env)]
-        no_strip: bool,
-        /// Add additional tags to the readings.
-        #[clap(short, long = "tag", value_parser=parse_tag)]
-        tags: Vec<(String, String)>,
-        /// Start time of first reading.
-        from: String,
-        /// Start time of last reading (defaults to now).
-        to: Option<String>,
+    Export(ExportArgs),
+    /// Exports readings newer than those exported by previous syncs.
+    ///
+    /// The newest reading exported for each resource is recorded after each
+    /// chunk, so an interrupted sync resumes where it stopped. Resources that
+    /// haven't been synced before start from --from.
+    Sync(SyncArgs),
+    /// Produces a JSON summary of recent usage and cost for every fuel.
+    ///
+    /// The document includes daily usage and cost, the current tariff and the
+    /// latest reading for each consumption resource, suitable for driving a
+    /// static dashboard.
+    DashboardData(DashboardArgs),
+    /// Lists events such as power outages reported by the account's meters.
+    ///
+    /// Times are expressed in the same way as for the readings command.
+    Events(EventsArgs),
+    /// Shows spending so far this month against the configured budget.
+    ///
+    /// The budget is set in pence in the `[budget]` section of the config file
+    /// along with an optional list of the resources it covers.
+    Budget(BudgetArgs),
+    /// Compares the budget's past month end projections with what was
+    /// actually spent.
+    ///
+    /// Projections are recorded by the daemon and by `budget --record`, at
+    /// most one for each day. Once a month is over its actual spending is
+    /// fetched and each day's projection is reported with its error, along
+    /// with the mean absolute error and bias across every finished month.
+    ForecastAccuracy(ForecastOptions),
+    /// Scores how fresh and complete each resource's data has been over the
+    /// last 30 days.
+    ///
+    /// Output can be JSON, InfluxDB line protocol or Prometheus metrics so
+    /// degraded meters can be caught by existing alerting.
+    Health(HealthArgs),
+    /// Runs an HTTP server exposing the latest readings as Prometheus metrics
+    /// at `/metrics`.
+    ///
+    /// Gauges cover the latest half-hourly consumption, usage and cost so far
+    /// today for each resource. Readings are refreshed from the API on an
+    /// interval rather than on each scrape.
+    ServeMetrics(ServeMetricsArgs),
+    /// Publishes readings to an MQTT broker.
+    ///
+    /// Each resource's usage today and meter reading are published as JSON to
+    /// `<topic-prefix>/<resource>/state` along with Home Assistant discovery
+    /// messages so meters appear in its Energy dashboard. Only plain TCP
+    /// connections are supported.
+    Mqtt(MqttArgs),
+    /// Runs continuously, exporting new readings on a schedule.
+    ///
+    /// The last reading exported for each resource is recorded in a state
+    /// file so only new readings are fetched, even after a restart. Readings
+    /// are sent as line protocol to stdout, to InfluxDB or to an MQTT broker.
+    /// If a budget is configured an alert is sent through the notification
+    /// options the first time each month spending is projected to exceed it.
+    Daemon(DaemonArgs),
+    /// Checks every device and resource on the account and reports what needs
+    /// attention.
+    ///
+    /// Devices are checked for how recently they reported and resources for
+    /// fresh and complete data, a tariff and a sensible classifier. Each check
+    /// passes, warns or fails. Markdown output is suitable for support tickets.
+    Audit(AuditArgs),
+    /// Displays the current tariff for a resource.
+    Tariff {
+        /// The resource to display the tariff for, either its ID, its
+        /// classifier such as `electricity.consumption` or part of its name.
+        resource: String,
     },
+    /// Lists the tariff history for a resource.
+    TariffList {
+        /// The resource to list tariffs for, either its ID, its classifier
+        /// such as `electricity.consumption` or part of its name.
+        resource: String,
+    },
+    /// Writes a SHA-256 manifest of every file in a directory of exports.
+    Manifest {
+        /// The directory to generate a manifest for.
+        dir: PathBuf,
+    },
+    /// Verifies a directory of exports against its SHA-256 manifest.
+    #[clap(alias = "verify-install")]
+    Verify {
+        /// The directory to verify.
+        dir: PathBuf,
+    },
+    /// Generates synthetic half-hourly readings for testing dashboards.
+    ///
+    /// No credentials are needed.
+    Generate(GenerateArgs),
+    /// Displays the version, git commit, enabled features, API endpoint and
+    /// TLS backend of this build, for including in bug reports.
+    ///
+    /// No credentials are needed.
+    Version(VersionArgs),
 }
 
-fn parse_date(date: String, period: ReadingPeriod) -> Result<OffsetDateTime, String> {
-    if let Some(date) = date.strip_prefix('-') {
-        let offset = date.parse::<i64>().str_err()?;
-        Ok(align_to_period(
-            OffsetDateTime::now_utc() - Duration::minutes(offset),
-            period,
-        ))
+pub(crate) const MAX_CLOCK_SKEW: Duration = Duration::minutes(5);
+
+/// Parses an offset back from the current time, either a negative number of
+/// minutes (`-1440`) or an ISO-8601 duration with or without a leading minus
+/// (`P1D` or `-P1D`). Returns `None` if the string is not an offset.
+fn parse_offset(date: &str) -> Option<Result<Duration, String>> {
+    let offset = date.strip_prefix('-');
+    match offset.unwrap_or(date) {
+        duration if duration.starts_with('P') => Some(parse_iso_duration(duration)),
+        minutes => offset.map(|_| minutes.parse::<i64>().map(Duration::minutes).str_err()),
+    }
+}
+
+/// Epoch timestamps at least this large are taken to be in milliseconds. As
+/// seconds this would be thousands of years in the future, as milliseconds it
+/// is in 1973.
+const EPOCH_MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// Parses an absolute time, either ISO-8601 or unix epoch seconds or
+/// milliseconds.
+fn parse_time(date: &str) -> Result<OffsetDateTime, String> {
+    if !date.is_empty() && date.bytes().all(|b| b.is_ascii_digit()) {
+        let epoch = date.parse::<i64>().str_err()?;
+        let nanos = if epoch >= EPOCH_MILLIS_THRESHOLD {
+            epoch as i128 * 1_000_000
+        } else {
+            epoch as i128 * 1_000_000_000
+        };
+
+        return OffsetDateTime::from_unix_timestamp_nanos(nanos)
+            .map_err(|_| format!("The epoch timestamp '{date}' is out of range"));
+    }
+
+    OffsetDateTime::parse(date, &Iso8601::DEFAULT).map_err(|_| {
+        format!("Couldn't format the date '{date}' as ISO-8601, try '2023-01-01T00:00:00Z'")
+    })
+}
+
+fn parse_date(
+    date: String,
+    period: ReadingPeriod,
+    api: &GlowmarktApi,
+) -> Result<OffsetDateTime, String> {
+    let now = api.clock().now();
+    let calendar = api.calendar();
+    if let Some(offset) = parse_offset(&date) {
+        Ok(calendar.align(now - offset?, period))
     } else {
-        OffsetDateTime::parse(&date, &Iso8601::DEFAULT)
-            .map_err(|_| {
-                format!("Couldn't format the date '{date}' as ISO-8601, try '2023-01-01T00:00:00Z'")
-            })
-            .and_then(|date| {
-                let now = OffsetDateTime::now_utc();
+        parse_time(&date).and_then(|date| {
+            if date > now {
+                Err("Cannot use a date that is in the future.".to_string())
+            } else {
+                Ok(calendar.align(date, period))
+            }
+        })
+    }
+}
+
+fn parse_end_date(
+    date: Option<String>,
+    period: ReadingPeriod,
+    api: &GlowmarktApi,
+) -> Result<OffsetDateTime, String> {
+    let now = api.clock().now();
+    let calendar = api.calendar();
+    if let Some(date) = date {
+        if let Some(offset) = parse_offset(&date) {
+            Ok(calendar.align(now - offset?, period))
+        } else {
+            parse_time(&date).and_then(|date| {
                 if date > now {
                     Err("Cannot use a date that is in the future.".to_string())
                 } else {
-                    Ok(align_to_period(date, period))
+                    Ok(calendar.align(date, period))
                 }
             })
+        }
+    } else {
+        Ok(calendar.align(now, period))
     }
 }
 
-fn parse_end_date(date: Option<String>, period: ReadingPeriod) -> Result<OffsetDateTime, String> {
-    if let Some(date) = date {
-        if let Some(date) = date.strip_prefix('-') {
-            let offset = date.parse::<i64>().str_err()?;
-            Ok(align_to_period(
-                OffsetDateTime::now_utc() - Duration::minutes(offset),
-                period,
-            ))
-        } else {
-            OffsetDateTime::parse(&date, &Iso8601::DEFAULT)
-                .map_err(|_| {
-                    format!(
-                        "Couldn't format the date '{date}' as ISO-8601, try '2023-01-01T00:00:00Z'"
-                    )
-                })
-                .and_then(|date| {
-                    let now = OffsetDateTime::now_utc();
-                    if date > now {
-                        Err("Cannot use a date that is in the future.".to_string())
-                    } else {
-                        Ok(align_to_period(date, period))
-                    }
-                })
+/// Combines the calendar options with the config file, options taking
+/// precedence.
+fn calendar(args: &Args, config: &CalendarConfig) -> Result<Calendar, String> {
+    let mut calendar = Calendar::default();
+
+    if let Some(week_start) = args.week_start {
+        calendar.week_start = week_start;
+    } else if let Some(ref week_start) = config.week_start {
+        calendar.week_start = parse_weekday(week_start)?;
+    }
+
+    match args.day_start_hour.or(config.day_start_hour) {
+        Some(hour) if hour >= 24 => {
+            return Err(format!("The day start hour must be below 24, not {}", hour))
         }
-    } else {
-        Ok(align_to_period(OffsetDateTime::now_utc(), period))
+        Some(hour) => calendar.day_start_hour = hour,
+        None => {}
     }
+
+    Ok(calendar)
 }
 
 trait ErrorStr<V> {
@@ -175,11 +790,15 @@ fn values<T>(map: HashMap<String, T>) -> Vec<T> {
     map.into_values().collect()
 }
 
+fn has_tags(device: &Device, tags: &[String]) -> bool {
+    tags.iter().all(|tag| device.tags.contains(tag))
+}
+
 fn display_result<T: Serialize>(
     items: Result<HashMap<String, T>, Error>,
     id: Option<String>,
-) -> Result<(), String> {
-    let items = items.str_err()?;
+) -> Result<(), CliError> {
+    let items = items?;
 
     if let Some(id) = id {
         println!("{}", to_string_pretty(&items.get(&id)).str_err()?);
@@ -190,84 +809,409 @@ fn display_result<T: Serialize>(
     Ok(())
 }
 
+async fn virtual_entity(
+    api: &GlowmarktApi,
+    resources: bool,
+    id: Option<String>,
+) -> Result<(), CliError> {
+    if !resources {
+        return display_result(api.virtual_entities().await, id);
+    }
+
+    let ids = match id {
+        Some(id) => vec![id],
+        None => api.virtual_entities().await?.into_keys().collect(),
+    };
+
+    let mut entities = Vec::new();
+    for id in ids {
+        match api.virtual_entity_resources(&id).await? {
+            Some(entity) => entities.push(entity),
+            None => return Err(format!("Unknown virtual entity {}", id).into()),
+        }
+    }
+    entities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    println!("{}", to_string_pretty(&entities).str_err()?);
+    Ok(())
+}
+
+/// How long to wait after requesting a catchup for the data to arrive.
+const CATCHUP_WAIT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Requests a catchup for each resource then waits for the data to arrive.
+async fn catchup<'a, I>(api: &GlowmarktApi, resource_ids: I) -> Result<(), CliError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut requested = false;
+    for resource_id in resource_ids {
+        log::debug!("Requesting catchup for {}", resource_id);
+        api.catchup(resource_id).await?;
+        requested = true;
+    }
+
+    if requested {
+        log::info!(
+            "Waiting {} seconds for data to arrive",
+            CATCHUP_WAIT.as_secs()
+        );
+        tokio::time::sleep(CATCHUP_WAIT).await;
+    }
+
+    Ok(())
+}
+
+async fn lookup_resource(api: &GlowmarktApi, id: &str) -> Result<Resource, CliError> {
+    match api.resource(id).await? {
+        Some(resource) => Ok(resource),
+        None => Err(format!("Unknown resource {}", id).into()),
+    }
+}
+
 async fn readings(
     api: GlowmarktApi,
-    resource: String,
-    start: String,
-    end: Option<String>,
-) -> Result<(), String> {
-    let period = ReadingPeriod::HalfHour;
-    let start = parse_date(start, period)?;
-    let end = parse_end_date(end, period)?;
-    let ranges = split_periods(start, end, period);
+    mut options: OutputOptions,
+    args: ReadingsArgs,
+    transforms: &[String],
+) -> Result<(), CliError> {
+    let configured = args.transform.pipeline(transforms)?;
+    let resource = resolve_resource(&api, &args.resource).await?;
+    options.settlement_period = args.settlement_period;
+    let (period, start, end) = match args.period {
+        _ if args.auto_period => {
+            let start = parse_date(args.from, ReadingPeriod::HalfHour, &api)?;
+            let end = parse_end_date(args.to, ReadingPeriod::HalfHour, &api)?;
+            (ReadingPeriod::HalfHour, start, end)
+        }
+        Some(period) => (
+            period,
+            parse_date(args.from, period, &api)?,
+            parse_end_date(args.to, period, &api)?,
+        ),
+        None => {
+            let start = parse_date(args.from, ReadingPeriod::Minute, &api)?;
+            let end = parse_end_date(args.to, ReadingPeriod::Minute, &api)?;
+            let period = api.default_period(&resource, &start, &end).await?;
+            log::debug!("Using a period of {}", period);
+            (
+                period,
+                api.calendar().align(start, period),
+                api.calendar().align(end, period),
+            )
+        }
+    };
+    if args.auto_catchup {
+        catchup(&api, [resource.as_str()]).await?;
+    }
+
+    let ranges: Vec<_> = if args.auto_period {
+        split_tiers(start, end, api.clock().now())
+            .into_iter()
+            .flat_map(|(period, start, end)| {
+                split_periods(start, end, period)
+                    .into_iter()
+                    .map(move |(start, end)| (period, start, end))
+            })
+            .collect()
+    } else {
+        api.check_period(&resource, period).await?;
+        split_periods(start, end, period)
+            .into_iter()
+            .map(|(start, end)| (period, start, end))
+            .collect()
+    };
+
+    let mut resource = lookup_resource(&api, &resource).await?;
+    let mut pipeline = Pipeline::new();
+    if let Some(conversion) = args.gas.conversion(&resource) {
+        pipeline.push(conversion);
+    }
+    pipeline.extend(configured);
+    transform_resource(&pipeline, &mut resource);
+    let out = BufWriter::new(stdout().lock());
+    let mut writer: Box<dyn ReadingsWriter> = match args.format {
+        Format::Json => Box::new(JsonWriter::new(out, options).unit(resource.unit())),
+        Format::Ndjson => Box::new(NdjsonWriter::new(out, options).unit(resource.unit())),
+        Format::Legacy if args.auto_period => {
+            return Err(
+                "The legacy format needs a single period and cannot be used with \
+                --auto-period"
+                    .to_string()
+                    .into(),
+            )
+        }
+        Format::Legacy => Box::new(LegacyWriter::new(
+            out,
+            LegacyReadings::new(&resource, start, end, period, args.function),
+            options,
+        )),
+        Format::Csv => {
+            let csv = CsvWriter::new(
+                out,
+                resource.unit().as_ref().map(Unit::as_str),
+                resource.classifier.as_ref().map(Classifier::as_str),
+            );
+            let mut csv = args
+                .csv
+                .apply(csv)
+                .timestamp_format(args.time_format)
+                .settlement_period(args.settlement_period)
+                .precision(options.precision);
+            if !args.no_header {
+                csv.write_header().str_err()?;
+            }
+            Box::new(csv)
+        }
+    };
+
+    let mut latest = None;
+    for (period, start, end) in ranges {
+        let readings = pipeline.apply(
+            api.readings_with_function(&resource.id, &start, &end, period, args.function)
+                .await?,
+        );
+
+        if let Some(reading) = readings.last() {
+            latest = Some(reading.start);
+        }
+
+        writer.write_chunk(&readings).str_err()?;
+    }
 
-    for (start, end) in ranges {
-        let readings = api
-            .readings(&resource, &start, &end, period)
-            .await
-            .str_err()?;
+    writer.finish().str_err()?;
 
-        println!("{}", to_string_pretty(&readings).str_err()?);
+    if let Some(latest) = latest {
+        if latest > api.clock().now() {
+            log::warn!(
+                "The latest reading is in the future according to the local clock, check that \
+                the system time is correct."
+            );
+        }
+    } else {
+        log::warn!(
+            "No readings were returned. Newly installed meters can take a few days before data \
+            arrives from the DCC, a catch-up may be requested with `glowmarkt catchup`."
+        );
     }
 
     Ok(())
 }
 
+/// The output of the cost command. Without any rates only the total
+/// consumption is included.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CostReport {
+    #[serde(flatten)]
+    costs: Option<cost::Costs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    consumption: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakdown: Option<Vec<cost::DayBreakdown>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+async fn cost(api: GlowmarktApi, options: OutputOptions, args: CostArgs) -> Result<(), CliError> {
+    let start = parse_date(args.from, args.period, &api)?;
+    let end = parse_end_date(args.to, args.period, &api)?;
+
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+    let mut notes = Vec::new();
+    let tariff = match api.latest_tariff(&resource_id).await {
+        Ok(tariff) => tariff,
+        Err(e) if e.is_unavailable() => {
+            notes.push(format!("The tariff could not be retrieved: {}", e));
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let rates = match (tariff.as_ref().and_then(Rates::from_tariff), args.unit_rate) {
+        (Some(rates), _) => Some(rates),
+        (None, Some(unit_rate)) => {
+            notes.push(
+                "Costed with the supplied rates as the resource has no usable tariff.".to_string(),
+            );
+            Some(Rates::flat(
+                unit_rate,
+                args.standing_charge.unwrap_or_default(),
+            ))
+        }
+        (None, None) => {
+            notes.push(format!(
+                "Resource {} has no usable tariff so only consumption is included, pass \
+                --unit-rate to cost it.",
+                resource_id
+            ));
+            None
+        }
+    };
+    if args.breakdown.is_some() && !matches!(args.period, ReadingPeriod::HalfHour) {
+        notes.push(
+            "Readings longer than half an hour are placed in the band in force at their start."
+                .to_string(),
+        );
+    }
+
+    let readings = api
+        .readings_range(&resource_id, &start, &end, args.period)
+        .await?;
+
+    let round = |value: f64| format::round(value, options.precision);
+    let report = match rates {
+        Some(rates) => {
+            // The range runs to the end of the last reading, but days yet to
+            // come aren't charged.
+            let range_end = increase_by_period(end, args.period).min(api.clock().now());
+            let mut costs = cost::cost(&readings, &rates, start, range_end);
+            for reading in costs.readings.iter_mut() {
+                reading.consumption = round(reading.consumption);
+                reading.cost = round(reading.cost);
+            }
+            costs.standing_charge = round(costs.standing_charge);
+            costs.consumption_cost = round(costs.consumption_cost);
+            costs.total = round(costs.total);
+
+            let breakdown = args.breakdown.map(|Breakdown::Tou| {
+                let mut days = cost::breakdown(&readings, &rates);
+                for band in days.iter_mut().flat_map(|day| day.bands.iter_mut()) {
+                    band.consumption = round(band.consumption);
+                    band.cost = round(band.cost);
+                }
+                days
+            });
+
+            CostReport {
+                costs: Some(costs),
+                consumption: None,
+                breakdown,
+                notes,
+            }
+        }
+        None => CostReport {
+            costs: None,
+            consumption: Some(round(readings.iter().map(|r| r.value as f64).sum())),
+            breakdown: None,
+            notes,
+        },
+    };
+
+    println!("{}", to_string_pretty(&report).str_err()?);
+    Ok(())
+}
+
 async fn influx(
     api: GlowmarktApi,
-    device: Option<String>,
-    no_strip: bool,
-    tags: BTreeMap<String, String>,
-    start: String,
-    end: Option<String>,
-) -> Result<(), String> {
+    mut options: OutputOptions,
+    args: InfluxArgs,
+) -> Result<(), CliError> {
+    let InfluxArgs {
+        device,
+        device_tags,
+        no_strip,
+        settlement_period,
+        tags,
+        auto_catchup,
+        influxdb,
+        since_last_run,
+        state,
+        from,
+        to,
+    } = args;
+    let writer = InfluxDbWriter::new(influxdb)?;
+    let tags: BTreeMap<String, String> = tags.into_iter().collect();
+    options.settlement_period = settlement_period;
+
     let period = ReadingPeriod::HalfHour;
-    let start = parse_date(start, period)?;
-    let end = parse_end_date(end, period)?;
-    let ranges = split_periods(start, end, period);
+    let start = from
+        .map(|from| parse_date(from, period, &api))
+        .transpose()?;
+    let end = parse_end_date(to, period, &api)?;
+
+    let state_path = match (since_last_run, state) {
+        (false, _) => None,
+        (true, Some(path)) => Some(path),
+        (true, None) => {
+            match state::xdg_path("XDG_DATA_HOME", &[".local", "share"], "influx-state.json") {
+                Some(path) => Some(path),
+                None => return Err("No state file location, pass --state".to_string().into()),
+            }
+        }
+    };
+    let mut last_run: SyncState = match state_path {
+        Some(ref path) => state::load(path)?,
+        None => Default::default(),
+    };
 
     let mut measurements = BTreeMap::new();
 
     let resources = api.resources().await?;
+    let entities = entities_by_resource(api.virtual_entities().await?);
 
     async fn process_device(
         api: &GlowmarktApi,
+        options: OutputOptions,
         tags: &BTreeMap<String, String>,
-        resources: &HashMap<String, Resource>,
+        (resources, entities): (&HashMap<String, Resource>, &HashMap<String, VirtualEntity>),
         device: Device,
-        ranges: &Vec<(OffsetDateTime, OffsetDateTime)>,
+        (last_run, start, end): (&SyncState, Option<OffsetDateTime>, OffsetDateTime),
         measurements: &mut BTreeMap<OffsetDateTime, Vec<Measurement>>,
-    ) -> Result<(), Error> {
+    ) -> Result<(), CliError> {
         let mut tags = tags.clone();
         add_tags_for_device(&mut tags, &device);
 
-        for sensor in device.protocol.sensors {
-            if let Some(resource) = resources.get(&sensor.resource_id) {
+        for resource_id in device.protocol.resource_ids() {
+            if let Some(resource) = resources.get(resource_id) {
                 let mut tags = tags.clone();
+                if let Some(entity) = entities.get(&resource.id) {
+                    add_tags_for_entity(&mut tags, entity);
+                }
                 add_tags_for_resource(&mut tags, resource);
 
-                for (start, end) in ranges {
-                    let readings = match api
-                        .readings(&resource.id, start, end, ReadingPeriod::HalfHour)
-                        .await
-                    {
-                        Ok(r) => r,
-                        Err(_) => return Ok(()),
-                    };
-
-                    for reading in readings {
-                        let mut measurement =
-                            Measurement::new("glowmarkt", reading.start, tags.clone());
-                        measurement.add_field(
-                            field_for_classifier(&resource.classifier),
-                            reading.value as f64,
-                        );
+                let watermark = last_run.watermark(&resource.id, ReadingPeriod::HalfHour);
+                let start = match (watermark, start) {
+                    (Some(watermark), _) => watermark.latest + Duration::minutes(30),
+                    (None, Some(start)) => start,
+                    (None, None) => {
+                        return Err(format!(
+                            "Resource {} ({}) has not been output before, pass a start time",
+                            resource.name, resource.id
+                        )
+                        .into())
+                    }
+                };
+                if start > end {
+                    continue;
+                }
+
+                let readings = match api
+                    .readings_range(&resource.id, &start, &end, ReadingPeriod::HalfHour)
+                    .await
+                {
+                    Ok(r) => r,
+                    Err(_) => return Ok(()),
+                };
 
-                        measurements
-                            .entry(reading.start)
-                            .or_default()
-                            .push(measurement);
+                for reading in readings {
+                    let mut tags = tags.clone();
+                    if options.settlement_period {
+                        tags.insert(
+                            "settlement-period".to_string(),
+                            settlement::settlement_period(reading.start).to_string(),
+                        );
                     }
+
+                    let mut measurement = Measurement::new("glowmarkt", reading.start, tags);
+                    measurement.add_field(
+                        field_for_classifier(&resource.classifier),
+                        options.value(reading.value),
+                    );
+
+                    measurements
+                        .entry(reading.start)
+                        .or_default()
+                        .push(measurement);
                 }
             }
         }
@@ -275,17 +1219,45 @@ async fn influx(
         Ok(())
     }
 
-    if let Some(device) = device {
-        if let Some(device) = api.device(&device).await? {
-            process_device(&api, &tags, &resources, device, &ranges, &mut measurements).await?;
-        } else {
-            eprintln!("Error: Unknown device {}", device);
+    let devices: Vec<Device> = if let Some(device) = device {
+        match api
+            .device(&device)
+            .await?
+            .filter(|device| has_tags(device, &device_tags))
+        {
+            Some(device) => vec![device],
+            None => {
+                eprintln!("Error: Unknown device {}", device);
+                Vec::new()
+            }
         }
     } else {
-        let devices = api.devices().await?.into_values();
-        for device in devices {
-            process_device(&api, &tags, &resources, device, &ranges, &mut measurements).await?;
-        }
+        api.devices()
+            .await?
+            .into_values()
+            .filter(|device| has_tags(device, &device_tags))
+            .collect()
+    };
+
+    if auto_catchup {
+        let resource_ids = devices
+            .iter()
+            .flat_map(|device| device.protocol.resource_ids())
+            .filter(|id| resources.contains_key(*id));
+        catchup(&api, resource_ids).await?;
+    }
+
+    for device in devices {
+        process_device(
+            &api,
+            options,
+            &tags,
+            (&resources, &entities),
+            device,
+            (&last_run, start, end),
+            &mut measurements,
+        )
+        .await?;
     }
 
     if !no_strip {
@@ -302,70 +1274,424 @@ async fn influx(
         }
     }
 
-    for (_, measurements) in measurements {
-        for measurement in measurements {
-            println!("{}", measurement);
+    // Trailing zeros may be filled in by the DCC later, so each resource
+    // resumes after its newest non-zero reading.
+    if since_last_run {
+        let now = api.clock().now();
+        for (timestamp, measurements) in &measurements {
+            for measurement in measurements {
+                let resource_id = match measurement.tags.get("resource-id") {
+                    Some(id) => id,
+                    None => continue,
+                };
+                let advances = last_run
+                    .watermark(resource_id, period)
+                    .map(|watermark| watermark.latest < *timestamp)
+                    .unwrap_or(true);
+                if advances && measurement.fields.values().any(|v| *v != 0.0) {
+                    last_run.set_watermark(resource_id, period, *timestamp, now);
+                }
+            }
+        }
+    }
+
+    match writer {
+        Some(mut writer) => {
+            for measurement in measurements.values().flatten() {
+                writer.write(measurement).await?;
+            }
+            let written = writer.finish().await?;
+            log::info!("Wrote {} measurements to InfluxDB", written);
+        }
+        None => {
+            for (_, measurements) in measurements {
+                for measurement in measurements {
+                    println!("{}", measurement);
+                }
+            }
+        }
+    }
+
+    if let Some(path) = state_path {
+        state::save(&path, &last_run)?;
+    }
+
+    Ok(())
+}
+
+async fn print_token(api: &GlowmarktApi, args: TokenArgs) -> Result<(), CliError> {
+    let now = api.clock().now();
+    let expiring = match (args.min_validity, api.token_expiry()) {
+        (Some(min_validity), Some(expiry)) => expiry - min_validity <= now,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+
+    let refreshed = args.refresh || expiring;
+    if refreshed {
+        log::debug!("Generating a new token");
+        api.refresh_token().await?;
+    }
+
+    match args.format {
+        TokenFormat::Text => println!("{}", api.token()),
+        TokenFormat::Json => {
+            let expiry = api.token_expiry();
+            let info = TokenInfo {
+                token: api.token(),
+                expiry,
+                valid_for: expiry.map(|expiry| (expiry - now).whole_seconds()),
+                refreshed,
+            };
+            println!("{}", to_string_pretty(&info).str_err()?);
         }
     }
 
     Ok(())
 }
 
-async fn login(args: &Args) -> Result<GlowmarktApi, String> {
+fn builder(args: &Args) -> Result<GlowmarktApiBuilder, CliError> {
+    let mut builder = GlowmarktApi::builder()
+        .user_agent(concat!("glowmarkt/", env!("CARGO_PKG_VERSION")))
+        .concurrency(args.concurrency)
+        .http_version(args.http_version.into())
+        .retry(RetryPolicy {
+            max_attempts: args.max_attempts.max(1),
+            ..Default::default()
+        });
+
+    if let Some(rate) = args.rate_limit {
+        builder = builder.rate_limit(rate);
+    }
+
+    // Set before logging in so token expiry is judged at the same time.
+    if let Some(clock) = fixed_clock(args)? {
+        builder = builder.clock(clock);
+    }
+
+    if let Some(timeout) = args.timeout {
+        builder = builder.timeout(std::time::Duration::from_secs(timeout));
+    }
+
+    if let Some(max) = args.pool_max_idle {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+
+    if let Some(timeout) = args.pool_idle_timeout {
+        builder = builder
+            .pool_idle_timeout((timeout > 0).then(|| std::time::Duration::from_secs(timeout)));
+    }
+
+    if let Some(interval) = args.tcp_keepalive {
+        builder = builder.tcp_keepalive(std::time::Duration::from_secs(interval));
+    }
+
+    if let Some(ref proxy) = args.proxy {
+        let proxy =
+            reqwest::Proxy::all(proxy).map_err(|e| format!("Invalid proxy '{}': {}", proxy, e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    Ok(builder)
+}
+
+/// The clock set by `--now`, if any.
+fn fixed_clock(args: &Args) -> Result<Option<FixedClock>, CliError> {
+    let Some(ref now) = args.now else {
+        return Ok(None);
+    };
+
+    let now = OffsetDateTime::parse(now, &Iso8601::DEFAULT).map_err(|_| {
+        format!("Couldn't format the date '{now}' as ISO-8601, try '2023-01-01T00:00:00Z'")
+    })?;
+    Ok(Some(FixedClock(now)))
+}
+
+async fn login(args: &Args) -> Result<GlowmarktApi, CliError> {
     if let Some(ref token) = args.token {
-        let api = GlowmarktApi::new(token);
+        let builder = match (&args.username, &args.password) {
+            (Some(username), Some(password)) => builder(args)?.credentials(username, password),
+            _ => builder(args)?,
+        };
+        let api = builder.token(token);
 
         match api.validate().await {
             Ok(_) => {
                 return Ok(api);
             }
             Err(e) => {
-                if e.kind != ErrorKind::NotAuthenticated {
-                    return Err(e.to_string());
+                if e.kind() != ErrorKind::NotAuthenticated {
+                    return Err(e.into());
                 }
             }
         }
     }
 
     if let (Some(username), Some(password)) = (&args.username, &args.password) {
-        GlowmarktApi::authenticate(username, password)
-            .await
-            .str_err()
+        if !args.no_cache {
+            let fixed = fixed_clock(args)?;
+            let clock: &dyn Clock = match fixed {
+                Some(ref clock) => clock,
+                None => &SystemClock,
+            };
+            if let Some(cached) = tokencache::load(username, clock) {
+                log::debug!("Using cached token");
+                return Ok(builder(args)?
+                    .credentials(username, password)
+                    .token_expiry(cached.expiry)
+                    .token(&cached.token));
+            }
+        }
+
+        let api = builder(args)?.authenticate(username, password).await?;
+        if !args.no_cache {
+            tokencache::save(username, &api);
+        }
+        Ok(api)
     } else {
-        Err("Must pass username and password.".to_string())
+        Err("Must pass username and password.".to_string().into())
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), String> {
-    if let Err(e) = Logger::try_with_env_or_str("info").and_then(|logger| logger.start()) {
-        eprintln!("Warning, failed to start logging: {}", e);
+async fn configure_clock(api: GlowmarktApi, args: &Args) -> Result<GlowmarktApi, CliError> {
+    // A fixed clock is set when building the API.
+    if args.now.is_some() {
+        return Ok(api);
     }
 
-    let args = Args::parse();
+    if !args.check_clock && !args.compensate_clock {
+        return Ok(api);
+    }
 
-    let api = login(&args).await?;
+    let skew = api.clock_skew().await?;
+    if skew.abs() > MAX_CLOCK_SKEW {
+        log::warn!(
+            "The local clock differs from the API server's by {} seconds, recent readings may \
+            appear to be missing.",
+            skew.whole_seconds()
+        );
+    }
 
-    match args.command {
-        Command::Token => {
-            println!("{}", api.token);
-            Ok(())
+    if args.compensate_clock {
+        Ok(api.with_clock(SkewedClock(skew)))
+    } else {
+        Ok(api)
+    }
+}
+
+fn write_manifest(dir: &Path) -> Result<(), CliError> {
+    let manifest = Manifest::create(dir).str_err()?;
+    manifest.write(dir).str_err()?;
+    println!(
+        "Wrote {} entries to {}",
+        manifest.entries.len(),
+        Manifest::path(dir).display()
+    );
+
+    Ok(())
+}
+
+fn verify_manifest(dir: &Path) -> Result<(), CliError> {
+    let manifest = Manifest::read(dir).str_err()?;
+    let mismatches = manifest.verify(dir).str_err()?;
+
+    for mismatch in &mismatches {
+        match mismatch {
+            Mismatch::Missing(path) => println!("MISSING   {}", path),
+            Mismatch::Modified(path) => println!("MODIFIED  {}", path),
+            Mismatch::Unlisted(path) => println!("UNLISTED  {}", path),
         }
-        Command::Device { id } => display_result(api.devices().await, id),
+    }
+
+    if mismatches.is_empty() {
+        println!("All {} files verified", manifest.entries.len());
+        Ok(())
+    } else {
+        Err(format!("{} files failed verification", mismatches.len()).into())
+    }
+}
+
+async fn run(args: Args) -> Result<(), CliError> {
+    match &args.command {
+        Command::Manifest { dir } => return write_manifest(dir),
+        Command::Verify { dir } => return verify_manifest(dir),
+        Command::Generate(generate_args) => {
+            let options = OutputOptions {
+                precision: args.precision,
+                ..Default::default()
+            };
+            let fixed = fixed_clock(&args)?;
+            let clock: &dyn Clock = match fixed {
+                Some(ref clock) => clock,
+                None => &SystemClock,
+            };
+            return generate(options, generate_args, clock);
+        }
+        Command::Version(version_args) => return version(version_args),
+        _ => {}
+    }
+
+    let config = Config::load(args.config.as_deref())?;
+    let api = login(&args).await?;
+    let api = configure_clock(api, &args)
+        .await?
+        .with_calendar(calendar(&args, &config.calendar)?);
+
+    // Clones share the token so this sees any refreshed during the command.
+    let session = api.clone();
+    let token = session.token();
+    let cache_user = match (&args.username, &args.password) {
+        (Some(username), Some(_)) if !args.no_cache => Some(username.clone()),
+        _ => None,
+    };
+    let options = OutputOptions {
+        precision: args.precision,
+        ..Default::default()
+    };
+
+    let result = match args.command {
+        Command::Token(args) => print_token(&api, args).await,
+        Command::Device {
+            device_tags,
+            id,
+            action: Some(DeviceCommand::Status(args)),
+        } => device_statuses(api, device_tags, id, args).await,
+        Command::Device {
+            device_tags,
+            id,
+            action: None,
+        } => display_result(
+            api.devices().await.map(|devices| {
+                devices
+                    .into_iter()
+                    .filter(|(_, device)| has_tags(device, &device_tags))
+                    .collect()
+            }),
+            id,
+        ),
         Command::DeviceType { id } => display_result(api.device_types().await, id),
+        Command::VirtualEntity { resources, id } => virtual_entity(&api, resources, id).await,
         Command::ResourceType { id } => display_result(api.resource_types().await, id),
-        Command::Resource { id } => display_result(api.resources().await, id),
-        Command::Readings {
-            resource_id,
-            from,
-            to,
-        } => readings(api, resource_id, from, to).await,
-        Command::Influx {
-            device,
-            no_strip,
-            tags,
-            from,
-            to,
-        } => influx(api, device, no_strip, tags.into_iter().collect(), from, to).await,
+        Command::Resource { wide: true, id } => resource_table(&api, id).await,
+        Command::Resource { wide: false, id } => display_result(api.resources().await, id),
+        Command::Current { resource } => {
+            let resource_id = resolve_resource(&api, &resource).await?;
+            let mut current = api.current(&resource_id).await?;
+            if let Some(ref mut current) = current {
+                current.value = options.value(current.value) as f32;
+            }
+            println!("{}", to_string_pretty(&current).str_err()?);
+            Ok(())
+        }
+        Command::MeterRead { resource } => {
+            let resource_id = resolve_resource(&api, &resource).await?;
+            let mut read = api.meter_read(&resource_id).await?;
+            if let Some(ref mut read) = read {
+                read.value = format::round(read.value, options.precision);
+            }
+            println!("{}", to_string_pretty(&read).str_err()?);
+            Ok(())
+        }
+        Command::Catchup { resource } => {
+            let resource_id = resolve_resource(&api, &resource).await?;
+            api.catchup(&resource_id).await?;
+            Ok(())
+        }
+        Command::Hardware { hardware_id } => {
+            let resources = api.resources_for_hardware(&hardware_id).await?;
+            println!("{}", to_string_pretty(&resources).str_err()?);
+            Ok(())
+        }
+        Command::Readings(args) => readings(api, options, args, &config.transforms).await,
+        Command::Cost(args) => cost(api, options, args).await,
+        Command::Summary(args) => summary(api, options, args).await,
+        Command::Compare(args) => compare(api, options, args).await,
+        Command::Simulate(args) => simulate(api, options, args).await,
+        Command::Project(args) => project(api, options, args).await,
+        Command::Gaps(args) => gap_report(api, args).await,
+        Command::ExportPayments(args) => export_payments(api, options, args).await,
+        #[cfg(feature = "octopus")]
+        Command::AgileCost(args) => agile_cost(api, options, args).await,
+        Command::Influx(args) => influx(api, options, args).await,
+        Command::Export(args) => export(api, options, args, &config.transforms).await,
+        Command::Sync(args) => sync(api, options, args, &config.transforms).await,
+        Command::DashboardData(args) => dashboard(api, options, args).await,
+        Command::Events(args) => events(api, args).await,
+        Command::Diagnose => diagnose(api).await,
+        Command::Overview => overview(api).await,
+        Command::Budget(args) => budget(api, options, args, config.budget).await,
+        Command::ForecastAccuracy(forecasts) => {
+            forecast_accuracy(api, options, forecasts, config.budget).await
+        }
+        Command::Health(args) => health(api, args).await,
+        Command::ServeMetrics(args) => serve_metrics(api, args).await,
+        Command::Mqtt(args) => mqtt(api, args).await,
+        Command::Daemon(args) => daemon(api, options, args, config.budget).await,
+        Command::Audit(args) => audit(api, args).await,
+        Command::Tariff { resource } => {
+            let resource_id = resolve_resource(&api, &resource).await?;
+            let tariff = api.latest_tariff(&resource_id).await?;
+            println!("{}", to_string_pretty(&tariff).str_err()?);
+            Ok(())
+        }
+        Command::TariffList { resource } => {
+            let resource_id = resolve_resource(&api, &resource).await?;
+            let tariffs = api.tariff_list(&resource_id).await?;
+            println!("{}", to_string_pretty(&tariffs).str_err()?);
+            Ok(())
+        }
+        Command::Manifest { .. }
+        | Command::Verify { .. }
+        | Command::Generate(_)
+        | Command::Version(_) => {
+            unreachable!()
+        }
+    };
+
+    if let Some(username) = cache_user {
+        if session.token() != token {
+            tokencache::save(&username, &session);
+        }
+    }
+
+    result
+}
+
+/// Falls back to the variable credentials were read from before they had a
+/// `GLOWMARKT_` prefix.
+fn unprefixed_env(value: &mut Option<String>, name: &str) {
+    if value.is_none() {
+        *value = env::var(name).ok();
+    }
+}
+
+fn main() {
+    // The environment can only be safely changed before the runtime starts
+    // its threads.
+    if let Err(e) = secrets::load_secret_files() {
+        eprintln!("Error: {}", e);
+        exit(1);
+    }
+
+    start();
+}
+
+#[tokio::main]
+async fn start() {
+    if let Err(e) = Logger::try_with_env_or_str("info").and_then(|logger| logger.start()) {
+        eprintln!("Warning, failed to start logging: {}", e);
+    }
+
+    let mut args = Args::parse();
+    unprefixed_env(&mut args.username, "USERNAME");
+    unprefixed_env(&mut args.password, "PASSWORD");
+    unprefixed_env(&mut args.token, "TOKEN");
+
+    if let Err(e) = run(args).await {
+        eprintln!("Error: {}", e);
+        if let Some(hint) = e.hint() {
+            eprintln!("Hint: {}", hint);
+        }
+        exit(1);
     }
 }
@@ -0,0 +1,206 @@
+//! SHA-256 manifests for verifying exported data.
+//!
+//! Manifests use the same format as `sha256sum` so they can also be checked
+//! with `sha256sum -c SHA256SUMS` from within the exported directory.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+/// The name of the manifest file within a directory.
+pub const MANIFEST_FILE: &str = "SHA256SUMS";
+
+/// A problem found when verifying a directory against its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// A file listed in the manifest no longer exists.
+    Missing(String),
+    /// A file's contents no longer match the manifest.
+    Modified(String),
+    /// A file exists that isn't listed in the manifest.
+    Unlisted(String),
+}
+
+/// A list of files and their SHA-256 digests.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// Hex encoded digests keyed by the path relative to the directory.
+    pub entries: BTreeMap<String, String>,
+}
+
+/// Computes the hex encoded SHA-256 digest of a file.
+pub fn file_digest(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[0..count]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+        // Symlinks could point outside the directory or loop back into it.
+        if file_type.is_symlink() {
+            log::debug!("Skipping symlink {}", path.display());
+        } else if file_type.is_dir() {
+            list_files(root, &path, files)?;
+        } else {
+            let relative = path.strip_prefix(root).unwrap();
+            if relative != Path::new(MANIFEST_FILE) {
+                files.push(relative.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a path within a directory is a regular file reached without
+/// following symlinks, as [`list_files`] would find it.
+fn is_listed_file(dir: &Path, path: &Path) -> bool {
+    let mut current = dir.to_path_buf();
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        current.push(component);
+        let file_type = match fs::symlink_metadata(&current) {
+            Ok(metadata) => metadata.file_type(),
+            Err(_) => return false,
+        };
+
+        let valid = if components.peek().is_some() {
+            file_type.is_dir()
+        } else {
+            file_type.is_file()
+        };
+        if !valid {
+            return false;
+        }
+    }
+
+    true
+}
+
+impl Manifest {
+    /// Generates a manifest for every file within a directory.
+    pub fn create(dir: &Path) -> io::Result<Manifest> {
+        let mut files = Vec::new();
+        list_files(dir, dir, &mut files)?;
+
+        let mut entries = BTreeMap::new();
+        for file in files {
+            let digest = file_digest(&dir.join(&file))?;
+            entries.insert(file, digest);
+        }
+
+        Ok(Manifest { entries })
+    }
+
+    /// The path of the manifest file for a directory.
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(MANIFEST_FILE)
+    }
+
+    /// Reads the manifest stored in a directory.
+    pub fn read(dir: &Path) -> io::Result<Manifest> {
+        let reader = BufReader::new(File::open(Self::path(dir))?);
+        let mut entries = BTreeMap::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // sha256sum separates the file with a space and either another
+            // space or a `*` marking binary mode.
+            let entry = line.split_once(' ').and_then(|(digest, rest)| {
+                rest.strip_prefix(' ')
+                    .or_else(|| rest.strip_prefix('*'))
+                    .map(|file| (digest, file))
+            });
+            match entry {
+                // Entries must stay within the directory.
+                Some((_, file))
+                    if !Path::new(file)
+                        .components()
+                        .all(|component| matches!(component, Component::Normal(_))) =>
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Manifest entry '{}' must be a relative path without '..'",
+                            file
+                        ),
+                    ))
+                }
+                Some((digest, file)) => {
+                    entries.insert(file.to_string(), digest.to_lowercase());
+                }
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid manifest line '{}'", line),
+                    ))
+                }
+            }
+        }
+
+        Ok(Manifest { entries })
+    }
+
+    /// Writes this manifest into a directory.
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        let mut file = File::create(Self::path(dir))?;
+        for (path, digest) in &self.entries {
+            writeln!(file, "{}  {}", digest, path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the files in a directory against this manifest.
+    ///
+    /// Returns the list of problems found, which is empty if the directory is
+    /// intact.
+    pub fn verify(&self, dir: &Path) -> io::Result<Vec<Mismatch>> {
+        let mut files = Vec::new();
+        list_files(dir, dir, &mut files)?;
+
+        let mut mismatches = Vec::new();
+        for (path, digest) in &self.entries {
+            if !is_listed_file(dir, Path::new(path)) {
+                mismatches.push(Mismatch::Missing(path.clone()));
+            } else if &file_digest(&dir.join(path))? != digest {
+                mismatches.push(Mismatch::Modified(path.clone()));
+            }
+        }
+
+        for file in files {
+            if !self.entries.contains_key(&file) {
+                mismatches.push(Mismatch::Unlisted(file));
+            }
+        }
+
+        Ok(mismatches)
+    }
+}
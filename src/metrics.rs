@@ -0,0 +1,318 @@
+//! Serves the latest readings as Prometheus metrics.
+//!
+//! Readings are fetched from the API on an interval and the rendered metrics
+//! cached, so scrapes never wait on the API and don't count towards its rate
+//! limits.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration as StdDuration,
+};
+
+use glowmarkt::{
+    cost::{cost, Rates},
+    settlement::uk_day_start,
+    Error, GlowmarktApi, ReadingPeriod, Resource,
+};
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{healthcheck::escape_label, hint::CliError, lookup::select_resources, ErrorStr};
+
+/// The longest request head that will be read from a scraper.
+const MAX_REQUEST: usize = 8192;
+
+/// How long a scraper has to send its request head.
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+
+#[derive(clap::Args)]
+pub struct ServeMetricsArgs {
+    /// The address to listen on.
+    #[clap(long, env = "GLOWMARKT_METRICS_LISTEN", default_value = "0.0.0.0:9184")]
+    listen: SocketAddr,
+    /// The number of seconds between refreshes from the API. New half-hourly
+    /// readings arrive at most every 30 minutes.
+    #[clap(long, env = "GLOWMARKT_METRICS_INTERVAL", default_value = "300")]
+    interval: u64,
+    /// The resources to expose. If absent all consumption resources are
+    /// exposed.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+}
+
+/// The latest rendered metrics and whether the last refresh succeeded.
+#[derive(Default)]
+struct State {
+    metrics: String,
+    up: bool,
+    refreshed: Option<OffsetDateTime>,
+}
+
+impl State {
+    fn render(&self) -> String {
+        let mut out = self.metrics.clone();
+        gauge(
+            &mut out,
+            "glowmarkt_up",
+            "Whether the last refresh from the API succeeded.",
+        );
+        out.push_str(&format!("glowmarkt_up {}\n", u8::from(self.up)));
+
+        if let Some(refreshed) = self.refreshed {
+            gauge(
+                &mut out,
+                "glowmarkt_last_refresh_timestamp_seconds",
+                "When metrics were last refreshed from the API.",
+            );
+            out.push_str(&format!(
+                "glowmarkt_last_refresh_timestamp_seconds {}\n",
+                refreshed.unix_timestamp()
+            ));
+        }
+
+        out
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} gauge\n",
+        name, help, name
+    ));
+}
+
+fn labels(resource: &Resource) -> String {
+    let mut labels = vec![
+        format!("resource=\"{}\"", escape_label(&resource.id)),
+        format!("name=\"{}\"", escape_label(&resource.name)),
+    ];
+    if let Some(ref classifier) = resource.classifier {
+        labels.push(format!(
+            "classifier=\"{}\"",
+            escape_label(classifier.as_str())
+        ));
+    }
+    if let Some(unit) = resource.unit() {
+        labels.push(format!("unit=\"{}\"", escape_label(unit.as_str())));
+    }
+    labels.join(",")
+}
+
+/// A resource's values for the current UK day.
+struct ResourceMetrics {
+    labels: String,
+    latest: Option<(OffsetDateTime, f64)>,
+    usage: f64,
+    cost: Option<f64>,
+}
+
+async fn resource_metrics(
+    api: &GlowmarktApi,
+    resource: &Resource,
+    start: OffsetDateTime,
+    now: OffsetDateTime,
+) -> Result<ResourceMetrics, Error> {
+    let readings = api
+        .readings_range(&resource.id, &start, &now, ReadingPeriod::HalfHour)
+        .await?;
+    let rates = api
+        .latest_tariff(&resource.id)
+        .await?
+        .as_ref()
+        .and_then(Rates::from_tariff);
+
+    Ok(ResourceMetrics {
+        labels: labels(resource),
+        // Recent readings are zero until the DCC delivers them.
+        latest: readings
+            .iter()
+            .rev()
+            .find(|reading| reading.value != 0.0)
+            .map(|reading| (reading.start, reading.value as f64)),
+        usage: readings.iter().map(|reading| reading.value as f64).sum(),
+        cost: rates.map(|rates| cost(&readings, &rates, start, now).total),
+    })
+}
+
+async fn collect(api: &GlowmarktApi, resources: &[Resource]) -> Result<String, Error> {
+    let now = api.clock().now();
+    let start = uk_day_start(now);
+
+    let mut all = Vec::new();
+    for resource in resources {
+        all.push(resource_metrics(api, resource, start, now).await?);
+    }
+
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        "glowmarkt_latest_consumption",
+        "The most recent non-zero half-hourly reading.",
+    );
+    for metrics in &all {
+        if let Some((_, value)) = metrics.latest {
+            out.push_str(&format!(
+                "glowmarkt_latest_consumption{{{}}} {}\n",
+                metrics.labels, value
+            ));
+        }
+    }
+
+    gauge(
+        &mut out,
+        "glowmarkt_latest_reading_timestamp_seconds",
+        "The start of the most recent non-zero half-hourly reading.",
+    );
+    for metrics in &all {
+        if let Some((start, _)) = metrics.latest {
+            out.push_str(&format!(
+                "glowmarkt_latest_reading_timestamp_seconds{{{}}} {}\n",
+                metrics.labels,
+                start.unix_timestamp()
+            ));
+        }
+    }
+
+    gauge(
+        &mut out,
+        "glowmarkt_daily_usage",
+        "Usage so far today in the UK.",
+    );
+    for metrics in &all {
+        out.push_str(&format!(
+            "glowmarkt_daily_usage{{{}}} {}\n",
+            metrics.labels, metrics.usage
+        ));
+    }
+
+    gauge(
+        &mut out,
+        "glowmarkt_daily_cost_pence",
+        "Cost so far today in the UK, including the standing charge.",
+    );
+    for metrics in &all {
+        if let Some(cost) = metrics.cost {
+            out.push_str(&format!(
+                "glowmarkt_daily_cost_pence{{{}}} {}\n",
+                metrics.labels, cost
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Reads the request head, up to the blank line ending it.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(request)
+}
+
+async fn respond(mut stream: TcpStream, state: &RwLock<State>) -> std::io::Result<()> {
+    // Clients that never finish sending a request would otherwise hold the
+    // task open forever.
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "Timed out waiting for the request",
+            )
+        })??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            state.read().unwrap().render(),
+        ),
+        (Some("GET"), Some("/")) => (
+            "200 OK",
+            "text/plain",
+            "Metrics are served at /metrics\n".to_string(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn serve(listener: TcpListener, state: Arc<RwLock<State>>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &state).await {
+                log::debug!("Failed to respond to a scrape: {}", e);
+            }
+        });
+    }
+}
+
+pub async fn serve_metrics(api: GlowmarktApi, args: ServeMetricsArgs) -> Result<(), CliError> {
+    let resources = select_resources(&api, &args.resources).await?;
+
+    let listener = TcpListener::bind(args.listen).await.str_err()?;
+    log::info!(
+        "Serving metrics for {} resources at http://{}/metrics",
+        resources.len(),
+        args.listen
+    );
+
+    let state: Arc<RwLock<State>> = Default::default();
+    tokio::spawn(serve(listener, state.clone()));
+
+    let interval = StdDuration::from_secs(args.interval.max(1));
+    loop {
+        match collect(&api, &resources).await {
+            Ok(metrics) => {
+                let mut state = state.write().unwrap();
+                state.metrics = metrics;
+                state.up = true;
+                state.refreshed = Some(api.clock().now());
+            }
+            Err(e) => {
+                // Keep serving the last values so a brief outage doesn't
+                // leave gaps in graphs.
+                log::warn!("Failed to refresh metrics: {}", e);
+                state.write().unwrap().up = false;
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,128 @@
+//! Publishes readings to an MQTT broker with Home Assistant discovery.
+//!
+//! Messages are sent with [`MqttSink`] over plain TCP as retained QoS 0
+//! messages, see [`glowmarkt::homeassistant`].
+
+use std::{io, time::Duration as StdDuration};
+
+use glowmarkt::{
+    homeassistant::{ResourceState, Topics},
+    sink::{ExportSink, MqttSink},
+    GlowmarktApi, Resource,
+};
+
+use crate::{hint::CliError, lookup::select_resources, ErrorStr};
+
+/// How to connect to the broker and where to publish.
+#[derive(clap::Args)]
+pub struct MqttOptions {
+    /// The MQTT broker's host name.
+    #[clap(long, env = "MQTT_HOST", default_value = "localhost")]
+    host: String,
+    /// The MQTT broker's port.
+    #[clap(long, env = "MQTT_PORT", default_value = "1883")]
+    port: u16,
+    /// The username to connect to the broker with.
+    #[clap(long, env = "MQTT_USERNAME")]
+    mqtt_username: Option<String>,
+    /// The password to connect to the broker with.
+    #[clap(long, env = "MQTT_PASSWORD", requires = "mqtt-username")]
+    mqtt_password: Option<String>,
+    /// The client ID to connect to the broker with.
+    #[clap(long, default_value = "glowmarkt")]
+    client_id: String,
+    /// The prefix of the topics readings are published to.
+    #[clap(long, default_value = "glowmarkt")]
+    topic_prefix: String,
+    /// The prefix Home Assistant listens for discovery messages on.
+    #[clap(long, default_value = "homeassistant")]
+    discovery_prefix: String,
+    /// Don't publish Home Assistant discovery messages.
+    #[clap(long)]
+    no_discovery: bool,
+}
+
+#[derive(clap::Args)]
+pub struct MqttArgs {
+    #[clap(flatten)]
+    options: MqttOptions,
+    /// Publish again every this many seconds rather than exiting after
+    /// publishing once.
+    #[clap(long)]
+    interval: Option<u64>,
+    /// The resources to publish. If absent all consumption resources are
+    /// published.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+}
+
+impl MqttOptions {
+    fn topics(&self) -> Topics {
+        Topics {
+            prefix: self.topic_prefix.clone(),
+            discovery_prefix: self.discovery_prefix.clone(),
+        }
+    }
+
+    /// A sink publishing each reading to the broker.
+    pub fn sink(&self, precision: Option<u32>) -> MqttSink {
+        MqttSink::new(&format!("{}:{}", self.host, self.port))
+            .client_id(&self.client_id)
+            .credentials(self.mqtt_username.clone(), self.mqtt_password.clone())
+            .topics(self.topics())
+            .precision(precision)
+    }
+}
+
+/// Publishes the current state of each resource.
+async fn publish(
+    api: &GlowmarktApi,
+    args: &MqttOptions,
+    resources: &[Resource],
+) -> Result<(), CliError> {
+    let mut states = Vec::new();
+    for resource in resources {
+        states.push((resource.clone(), ResourceState::fetch(api, resource).await?));
+    }
+
+    // The sink blocks while talking to the broker.
+    let mut sink = args.sink(None);
+    let discovery = !args.no_discovery;
+    let count = states.len();
+    tokio::task::spawn_blocking(move || -> io::Result<()> {
+        for (resource, state) in &states {
+            if discovery {
+                sink.publish_discovery(resource, state.meter_read.is_some())?;
+            }
+            sink.publish_state(resource, state)?;
+        }
+        sink.flush()
+    })
+    .await
+    .str_err()?
+    .str_err()?;
+
+    log::info!(
+        "Published {} resources to {}:{}",
+        count,
+        args.host,
+        args.port
+    );
+    Ok(())
+}
+
+pub async fn mqtt(api: GlowmarktApi, args: MqttArgs) -> Result<(), CliError> {
+    let resources = select_resources(&api, &args.resources).await?;
+
+    let Some(interval) = args.interval else {
+        return publish(&api, &args.options, &resources).await;
+    };
+
+    let interval = StdDuration::from_secs(interval.max(1));
+    loop {
+        if let Err(e) = publish(&api, &args.options, &resources).await {
+            log::warn!("Failed to publish readings: {}", e);
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,137 @@
+//! Notifications sent when long running commands complete.
+
+use std::{process::Stdio, time::Instant};
+
+use glowmarkt::{clock::Clock, reqwest::Client, Warning};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+#[derive(clap::Args, Clone, Default)]
+pub struct NotifyOptions {
+    /// A shell command to run on completion. The summary is passed as JSON on
+    /// stdin.
+    #[clap(long, env = "GLOWMARKT_NOTIFY_COMMAND")]
+    pub notify_command: Option<String>,
+    /// A URL to POST the JSON summary to on completion.
+    #[clap(long, env = "GLOWMARKT_NOTIFY_URL")]
+    pub notify_url: Option<String>,
+}
+
+/// A summary of a completed run.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub command: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started: OffsetDateTime,
+    pub duration: f64,
+    pub points: usize,
+    pub failures: usize,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Problems that didn't fail the run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+}
+
+impl Summary {
+    /// Adds the warnings collected during the run.
+    pub fn warnings(mut self, warnings: Vec<Warning>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+}
+
+/// Tracks a run so a summary can be produced at the end.
+pub struct Run {
+    command: &'static str,
+    started: OffsetDateTime,
+    timer: Instant,
+}
+
+impl Run {
+    pub fn start(command: &'static str, clock: &dyn Clock) -> Self {
+        Self {
+            command,
+            started: clock.now(),
+            timer: Instant::now(),
+        }
+    }
+
+    pub fn finish<E: ToString>(self, points: usize, failures: usize, error: Option<&E>) -> Summary {
+        Summary {
+            command: self.command.to_owned(),
+            started: self.started,
+            duration: self.timer.elapsed().as_secs_f64(),
+            points,
+            failures: failures + usize::from(error.is_some()),
+            success: error.is_none() && failures == 0,
+            error: error.map(|e| e.to_string()),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+async fn run_command(command: &str, payload: &str) -> std::io::Result<()> {
+    let mut child = if cfg!(windows) {
+        Command::new("cmd")
+            .arg("/C")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()?
+    } else {
+        Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .spawn()?
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(payload.as_bytes()).await?;
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        log::warn!("Notification command exited with {}", status);
+    }
+
+    Ok(())
+}
+
+/// Sends the summary, or another JSON payload such as an alert, to the
+/// configured destinations. Failures are logged rather than failing the run.
+pub async fn notify<T: Serialize>(options: &NotifyOptions, summary: &T) {
+    if options.notify_command.is_none() && options.notify_url.is_none() {
+        return;
+    }
+
+    let payload = match serde_json::to_string(summary) {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::warn!("Unable to encode notification: {}", e);
+            return;
+        }
+    };
+
+    if let Some(ref command) = options.notify_command {
+        if let Err(e) = run_command(command, &payload).await {
+            log::warn!("Unable to run notification command: {}", e);
+        }
+    }
+
+    if let Some(ref url) = options.notify_url {
+        let result = Client::new()
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(e) = result {
+            log::warn!("Unable to send notification to {}: {}", url, e);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Half-hourly Agile prices from Octopus Energy's public API.
+//!
+//! Agile tariffs set a different unit rate for every half hour, published a
+//! day ahead. These can be turned into [`Rates`] to cost half-hourly
+//! consumption at the prices actually charged.
+//!
+//! Only available with the `octopus` feature.
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{
+    cost::Rates,
+    decode,
+    error::{Error, ErrorKind},
+};
+
+/// The Octopus Energy API endpoint.
+pub const OCTOPUS_URL: &str = "https://api.octopus.energy/v1";
+
+/// The Agile product used when none is given.
+pub const DEFAULT_AGILE_PRODUCT: &str = "AGILE-24-10-01";
+
+/// The letters identifying the regions, the grid supply point groups, that
+/// Agile prices are published for.
+pub const REGIONS: &str = "ABCDEFGHJKLMNP";
+
+/// The unit rate for one half hour.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgilePrice {
+    /// When the price starts to apply.
+    #[serde(with = "time::serde::rfc3339")]
+    pub valid_from: OffsetDateTime,
+    /// When the price stops applying.
+    #[serde(with = "time::serde::rfc3339")]
+    pub valid_to: OffsetDateTime,
+    /// The price in pence per kWh excluding VAT.
+    pub value_exc_vat: f64,
+    /// The price in pence per kWh including VAT.
+    pub value_inc_vat: f64,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    next: Option<String>,
+    results: Vec<AgilePrice>,
+}
+
+/// A client for Octopus Energy's public pricing API. No account is needed.
+#[derive(Debug, Clone)]
+pub struct OctopusClient {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for OctopusClient {
+    fn default() -> Self {
+        Self::new(Client::new())
+    }
+}
+
+impl OctopusClient {
+    /// Creates a client using the given HTTP client, so proxy and timeout
+    /// settings can be shared with the Glowmarkt API.
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            base_url: OCTOPUS_URL.to_string(),
+        }
+    }
+
+    /// Uses a different API endpoint, normally only useful for testing.
+    pub fn with_base_url<S: Into<String>>(self, base_url: S) -> Self {
+        Self {
+            base_url: base_url.into(),
+            ..self
+        }
+    }
+
+    /// Fetches the half-hourly prices of an Agile product for a region
+    /// between two instants, in order of time.
+    ///
+    /// The region is the letter of the grid supply point group, e.g. `C` for
+    /// London.
+    pub async fn agile_prices(
+        &self,
+        product: &str,
+        region: char,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<AgilePrice>, Error> {
+        let region = region.to_ascii_uppercase();
+        if !REGIONS.contains(region) {
+            return Err(Error::new(
+                ErrorKind::Client,
+                format!("Unknown region '{}', expected one of {}", region, REGIONS),
+            ));
+        }
+
+        let format = |date: OffsetDateTime| {
+            date.format(&Rfc3339)
+                .map_err(|e| Error::new(ErrorKind::Client, e.to_string()))
+        };
+        let mut url = Some(format!(
+            "{}/products/{}/electricity-tariffs/E-1R-{}-{}/standard-unit-rates/?period_from={}&period_to={}&page_size=1500",
+            self.base_url,
+            product,
+            product,
+            region,
+            format(start)?,
+            format(end)?,
+        ));
+
+        let mut prices = Vec::new();
+        while let Some(next) = url {
+            log::debug!("Fetching Agile prices from {}", next);
+            let response = self.client.get(&next).send().await?;
+            let status = response.status();
+            if status.is_client_error() || status.is_server_error() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(Error::from_response(status, next, &body));
+            }
+
+            let page: Page = decode(response).await?;
+            prices.extend(page.results);
+            url = page.next;
+        }
+
+        // The API lists the newest prices first.
+        prices.sort_by_key(|price| price.valid_from);
+        Ok(prices)
+    }
+}
+
+/// Creates rates charging each half hour at its Agile price including VAT.
+pub fn agile_rates(prices: &[AgilePrice], standing_charge: f64) -> Rates {
+    Rates {
+        standing_charge,
+        ..Rates::half_hourly(
+            prices
+                .iter()
+                .map(|price| (price.valid_from, price.value_inc_vat)),
+        )
+    }
+}
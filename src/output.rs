@@ -0,0 +1,310 @@
+use std::io::{self, Write};
+
+use glowmarkt::{
+    format::{round, CsvWriter},
+    gas::{GasConversion, DEFAULT_CALORIFIC_VALUE, DEFAULT_VOLUME_CORRECTION},
+    settlement::settlement_period,
+    sink::CsvSink,
+    transform::{Pipeline, Transform},
+    unit::Unit,
+    Reading, Resource,
+};
+use serde::Serialize;
+use serde_json::{to_writer, to_writer_pretty, Value};
+use time::OffsetDateTime;
+
+use crate::legacy::LegacyReadings;
+
+/// Writes readings out as they are fetched from the API.
+///
+/// Writers are given each chunk of readings as it is received and flush their
+/// output before returning. Writes block while the output is full so the next
+/// chunk isn't requested until the previous one has been consumed.
+pub trait ReadingsWriter {
+    fn write_chunk(&mut self, readings: &[Reading]) -> io::Result<()>;
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Options controlling the content of each output reading.
+#[derive(Clone, Copy, Default)]
+pub struct OutputOptions {
+    /// Include the UK settlement period of each reading.
+    pub settlement_period: bool,
+    /// The number of decimal places to round values to.
+    pub precision: Option<u32>,
+}
+
+/// The formats of commands that produce a report rather than readings.
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A readable report.
+    Text,
+    /// A JSON document.
+    Json,
+}
+
+/// Options controlling the layout of CSV output.
+#[derive(clap::Args, Clone, Copy)]
+pub struct CsvOptions {
+    /// The character separating fields in CSV output, `tab` for a tab.
+    /// Defaults to a comma, or a semicolon with --decimal-comma.
+    #[clap(long, value_parser = parse_delimiter)]
+    pub delimiter: Option<char>,
+    /// Use a comma as the decimal separator in CSV output, as expected by
+    /// spreadsheets in many European locales.
+    #[clap(long)]
+    pub decimal_comma: bool,
+}
+
+/// Options for converting gas readings from cubic metres to kWh.
+#[derive(clap::Args, Clone, Copy)]
+pub struct GasOptions {
+    /// Convert gas readings in cubic metres to kWh as suppliers do for
+    /// billing.
+    #[clap(long)]
+    pub convert_kwh: bool,
+    /// The calorific value of the gas in MJ/m³ used by --convert-kwh. Bills
+    /// show the value used for each period.
+    #[clap(long, default_value_t = DEFAULT_CALORIFIC_VALUE, requires = "convert-kwh")]
+    pub calorific_value: f64,
+    /// The volume correction factor used by --convert-kwh.
+    #[clap(long, default_value_t = DEFAULT_VOLUME_CORRECTION, requires = "convert-kwh")]
+    pub volume_correction: f64,
+}
+
+impl GasOptions {
+    /// Returns the conversion to apply to a resource's readings, if any.
+    pub fn conversion(&self, resource: &Resource) -> Option<GasConversion> {
+        if !self.convert_kwh {
+            return None;
+        }
+
+        if resource.unit() != Some(Unit::CubicMetres) {
+            log::warn!(
+                "Resource {} is not measured in cubic metres so will not be converted.",
+                resource.id
+            );
+            return None;
+        }
+
+        Some(GasConversion {
+            calorific_value: self.calorific_value,
+            volume_correction: self.volume_correction,
+        })
+    }
+}
+
+/// Options for transforming readings before they are written out.
+#[derive(clap::Args, Clone)]
+pub struct TransformOptions {
+    /// A comma separated list of transforms to apply to readings in order:
+    /// scale=<factor>, shift=<duration>, pence-to-pounds, wh-to-kwh,
+    /// gas-to-kwh, fill-gaps and strip-zeros. Replaces any transforms in the
+    /// config file.
+    #[clap(long = "transform", value_parser = parse_transforms)]
+    pub transforms: Option<String>,
+}
+
+impl TransformOptions {
+    /// Builds the pipeline to apply, falling back to the configured transforms.
+    pub fn pipeline(&self, configured: &[String]) -> Result<Pipeline, String> {
+        match &self.transforms {
+            Some(transforms) => transforms.parse(),
+            None => configured.join(",").parse(),
+        }
+    }
+}
+
+/// Applies a pipeline to a resource, updating its unit to match the
+/// transformed readings.
+pub fn transform_resource(pipeline: &Pipeline, resource: &mut Resource) {
+    if let Some(unit) = pipeline.unit(resource.unit()) {
+        resource.base_unit = Some(unit.to_string());
+    }
+}
+
+fn parse_transforms(val: &str) -> Result<String, String> {
+    val.parse::<Pipeline>().map(|_| val.to_owned())
+}
+
+fn parse_delimiter(val: &str) -> Result<char, String> {
+    let mut chars = val.chars();
+    match (val, chars.next(), chars.next()) {
+        ("tab" | "\\t", _, _) => Ok('\t'),
+        (_, Some(c), None) if c != '"' && c != '\n' && c != '\r' => Ok(c),
+        _ => Err(format!(
+            "Invalid delimiter '{}', expected a single character",
+            val
+        )),
+    }
+}
+
+impl CsvOptions {
+    /// Applies these options to a CSV writer.
+    pub fn apply<W: Write>(&self, writer: CsvWriter<W>) -> CsvWriter<W> {
+        writer
+            .delimiter(self.delimiter())
+            .decimal_comma(self.decimal_comma)
+    }
+
+    /// Applies the options to a CSV sink.
+    pub fn apply_sink<W: Write>(&self, sink: CsvSink<W>) -> CsvSink<W> {
+        sink.delimiter(self.delimiter())
+            .decimal_comma(self.decimal_comma)
+    }
+
+    /// The character separating fields.
+    pub fn delimiter(&self) -> char {
+        let default = if self.decimal_comma { ';' } else { ',' };
+        self.delimiter.unwrap_or(default)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputReading<'a> {
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'a Unit>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    settlement_period: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<&'a Value>,
+}
+
+impl OutputOptions {
+    /// Converts a value for output.
+    pub fn value(&self, value: f32) -> f64 {
+        round(value as f64, self.precision)
+    }
+
+    fn reading<'a>(&self, reading: &'a Reading, unit: Option<&'a Unit>) -> OutputReading<'a> {
+        OutputReading {
+            start: reading.start,
+            value: self.value(reading.value),
+            unit,
+            settlement_period: self
+                .settlement_period
+                .then(|| settlement_period(reading.start)),
+            quality: reading.quality.as_ref(),
+        }
+    }
+}
+
+/// Writes each chunk as a pretty-printed JSON array.
+pub struct JsonWriter<W: Write> {
+    out: W,
+    options: OutputOptions,
+    unit: Option<Unit>,
+}
+
+impl<W: Write> JsonWriter<W> {
+    pub fn new(out: W, options: OutputOptions) -> Self {
+        Self {
+            out,
+            options,
+            unit: None,
+        }
+    }
+
+    /// Includes the unit with each reading.
+    pub fn unit(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+}
+
+impl<W: Write> ReadingsWriter for JsonWriter<W> {
+    fn write_chunk(&mut self, readings: &[Reading]) -> io::Result<()> {
+        let readings: Vec<OutputReading> = readings
+            .iter()
+            .map(|r| self.options.reading(r, self.unit.as_ref()))
+            .collect();
+        to_writer_pretty(&mut self.out, &readings)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+/// Writes one JSON object per line.
+pub struct NdjsonWriter<W: Write> {
+    out: W,
+    options: OutputOptions,
+    unit: Option<Unit>,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(out: W, options: OutputOptions) -> Self {
+        Self {
+            out,
+            options,
+            unit: None,
+        }
+    }
+
+    /// Includes the unit with each reading.
+    pub fn unit(mut self, unit: Option<Unit>) -> Self {
+        self.unit = unit;
+        self
+    }
+}
+
+impl<W: Write> ReadingsWriter for NdjsonWriter<W> {
+    fn write_chunk(&mut self, readings: &[Reading]) -> io::Result<()> {
+        for reading in readings {
+            to_writer(
+                &mut self.out,
+                &self.options.reading(reading, self.unit.as_ref()),
+            )?;
+            writeln!(self.out)?;
+        }
+        self.out.flush()
+    }
+}
+
+impl<W: Write> ReadingsWriter for CsvWriter<W> {
+    fn write_chunk(&mut self, readings: &[Reading]) -> io::Result<()> {
+        for reading in readings {
+            self.write(reading)?;
+        }
+        self.flush()
+    }
+}
+
+/// Collects all readings into a single legacy document written at the end.
+pub struct LegacyWriter<W: Write> {
+    out: W,
+    document: LegacyReadings,
+    options: OutputOptions,
+}
+
+impl<W: Write> LegacyWriter<W> {
+    pub fn new(out: W, document: LegacyReadings, options: OutputOptions) -> Self {
+        Self {
+            out,
+            document,
+            options,
+        }
+    }
+}
+
+impl<W: Write> ReadingsWriter for LegacyWriter<W> {
+    fn write_chunk(&mut self, readings: &[Reading]) -> io::Result<()> {
+        for reading in readings {
+            self.document
+                .push(reading.start, self.options.value(reading.value));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        to_writer_pretty(&mut self.out, &self.document)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
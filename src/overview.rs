@@ -0,0 +1,181 @@
+//! A tree of everything on an account, to help work out which ID is which.
+
+use std::collections::{HashMap, HashSet};
+
+use glowmarkt::{api::DeviceType, Device, GlowmarktApi, Resource};
+
+use crate::hint::CliError;
+
+struct Node {
+    label: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(label: String) -> Self {
+        Self {
+            label,
+            children: Vec::new(),
+        }
+    }
+
+    fn render(&self, out: &mut String, prefix: &str, last: bool, root: bool) {
+        if root {
+            out.push_str(&self.label);
+        } else {
+            out.push_str(prefix);
+            out.push_str(if last { "└── " } else { "├── " });
+            out.push_str(&self.label);
+        }
+        out.push('\n');
+
+        let prefix = if root {
+            String::new()
+        } else if last {
+            format!("{}    ", prefix)
+        } else {
+            format!("{}│   ", prefix)
+        };
+
+        for (i, child) in self.children.iter().enumerate() {
+            child.render(out, &prefix, i + 1 == self.children.len(), false);
+        }
+    }
+}
+
+fn resource_node(resource: &Resource) -> Node {
+    let mut details = Vec::new();
+    if let Some(ref classifier) = resource.classifier {
+        details.push(classifier.to_string());
+    }
+    if let Some(ref unit) = resource.base_unit {
+        details.push(unit.clone());
+    }
+    if !resource.active {
+        details.push("inactive".to_string());
+    }
+
+    let label = if details.is_empty() {
+        format!("{} {}", resource.name, resource.id)
+    } else {
+        format!("{} ({}) {}", resource.name, details.join(", "), resource.id)
+    };
+    Node::new(label)
+}
+
+fn device_node(
+    device: &Device,
+    device_types: &HashMap<String, DeviceType>,
+    resources: &HashMap<String, Resource>,
+) -> Node {
+    let name = device
+        .description
+        .clone()
+        .or_else(|| {
+            device_types
+                .get(&device.device_type_id)
+                .and_then(|device_type| device_type.description.clone())
+        })
+        .unwrap_or_else(|| "Device".to_string());
+
+    let mut node = Node::new(format!("{} [{}] {}", name, device.hardware_id, device.id));
+    for sensor in &device.protocol.sensors {
+        let mut sensor_node = Node::new(format!("Sensor {}", sensor.protocol_id));
+        match sensor.resource_id.as_ref() {
+            Some(id) => match resources.get(id) {
+                Some(resource) => sensor_node.children.push(resource_node(resource)),
+                None => sensor_node
+                    .children
+                    .push(Node::new(format!("Unknown resource {}", id))),
+            },
+            None => sensor_node
+                .children
+                .push(Node::new("No resource".to_string())),
+        }
+        node.children.push(sensor_node);
+    }
+
+    node
+}
+
+/// Prints virtual entities, their devices, the devices' sensors and the
+/// resources each sensor records to.
+pub async fn overview(api: GlowmarktApi) -> Result<(), CliError> {
+    let (entities, devices, device_types, resources) = futures::try_join!(
+        api.virtual_entities(),
+        api.devices(),
+        api.device_types(),
+        api.resources(),
+    )?;
+
+    let mut entities: Vec<_> = entities.into_values().collect();
+    entities.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut devices: Vec<_> = devices.into_values().collect();
+    devices.sort_by(|a, b| a.hardware_id.cmp(&b.hardware_id));
+
+    let mut shown_devices = HashSet::new();
+    let mut shown_resources = HashSet::new();
+    let mut root = Node::new("Account".to_string());
+
+    for entity in &entities {
+        let entity_resources: HashSet<&str> = entity
+            .resources
+            .iter()
+            .map(|info| info.resource_id.as_str())
+            .collect();
+        let mut node = Node::new(format!("{} {}", entity.name, entity.id));
+        let mut device_resources = HashSet::new();
+
+        for device in &devices {
+            let in_entity = device
+                .protocol
+                .resource_ids()
+                .any(|id| entity_resources.contains(id));
+            if in_entity {
+                shown_devices.insert(device.id.as_str());
+                device_resources.extend(device.protocol.resource_ids());
+                node.children
+                    .push(device_node(device, &device_types, &resources));
+            }
+        }
+
+        // Resources such as costs that no device records to.
+        let mut loose: Vec<&Resource> = entity_resources
+            .iter()
+            .filter(|id| !device_resources.contains(*id))
+            .filter_map(|id| resources.get(*id))
+            .collect();
+        loose.sort_by(|a, b| a.name.cmp(&b.name));
+        node.children.extend(loose.into_iter().map(resource_node));
+
+        shown_resources.extend(device_resources);
+        shown_resources.extend(entity_resources);
+
+        root.children.push(node);
+    }
+
+    let mut other = Node::new("Not in any virtual entity".to_string());
+    for device in devices
+        .iter()
+        .filter(|device| !shown_devices.contains(device.id.as_str()))
+    {
+        shown_resources.extend(device.protocol.resource_ids());
+        other
+            .children
+            .push(device_node(device, &device_types, &resources));
+    }
+    let mut loose: Vec<&Resource> = resources
+        .values()
+        .filter(|resource| !shown_resources.contains(resource.id.as_str()))
+        .collect();
+    loose.sort_by(|a, b| a.name.cmp(&b.name));
+    other.children.extend(loose.into_iter().map(resource_node));
+    if !other.children.is_empty() {
+        root.children.push(other);
+    }
+
+    let mut out = String::new();
+    root.render(&mut out, "", true, true);
+    print!("{}", out);
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Per settlement period export payments, for reconciling statements from
+//! suppliers paying for exported energy such as on Agile Outgoing or SEG
+//! tariffs.
+
+use std::{
+    fs,
+    io::{stdout, Write},
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::{
+    cost::{cost, Rates},
+    format::{delimited_field, round},
+    increase_by_period,
+    settlement::{settlement_period, uk_offset},
+    GlowmarktApi, ReadingPeriod,
+};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{
+    hint::CliError, lookup::resolve_resource, output::CsvOptions, output::OutputOptions,
+    parse_date, parse_end_date, parse_time, ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct ExportPaymentsArgs {
+    /// The export resource, either its ID, its classifier or part of its name.
+    #[clap(long, default_value = "electricity.export")]
+    resource: String,
+    /// A flat export rate in pence per kWh, as paid on most SEG tariffs.
+    #[clap(long, conflicts_with = "rates")]
+    rate: Option<f64>,
+    /// A CSV file of half-hourly rates in pence per kWh, such as Agile
+    /// Outgoing prices. The first column of each row is the start of the half
+    /// hour and the last is the rate, rows that don't start with a time are
+    /// skipped.
+    #[clap(long)]
+    rates: Option<PathBuf>,
+    #[clap(flatten)]
+    csv: CsvOptions,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+/// Reads half-hourly rates from a CSV file.
+fn read_rates(path: &Path) -> Result<Rates, String> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let mut rates = Vec::new();
+    for (index, line) in data.lines().enumerate() {
+        let fields: Vec<&str> = line
+            .split(',')
+            .map(|f| f.trim().trim_matches('"'))
+            .collect();
+        let Ok(start) = parse_time(fields[0]) else {
+            continue;
+        };
+
+        let rate = fields
+            .last()
+            .filter(|_| fields.len() > 1)
+            .and_then(|rate| rate.parse::<f64>().ok())
+            .ok_or_else(|| {
+                format!(
+                    "{} line {}: expected a rate in the last column",
+                    path.display(),
+                    index + 1
+                )
+            })?;
+        rates.push((start, rate));
+    }
+
+    if rates.is_empty() {
+        return Err(format!("{} contains no rates", path.display()));
+    }
+    Ok(Rates::half_hourly(rates))
+}
+
+pub async fn export_payments(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: ExportPaymentsArgs,
+) -> Result<(), CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let start = parse_date(args.from, period, &api)?;
+    let end = parse_end_date(args.to, period, &api)?;
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+
+    let rates = match (args.rate, args.rates) {
+        (Some(rate), _) => Rates::flat(rate, 0.0),
+        (None, Some(path)) => read_rates(&path)?,
+        (None, None) => {
+            let tariff = api.latest_tariff(&resource_id).await?;
+            match tariff.as_ref().and_then(Rates::from_tariff) {
+                Some(rates) => Rates {
+                    standing_charge: 0.0,
+                    ..rates
+                },
+                None => {
+                    return Err(format!(
+                        "Resource {} has no usable tariff, pass --rate or --rates",
+                        resource_id
+                    )
+                    .into())
+                }
+            }
+        }
+    };
+
+    let readings = api
+        .readings_range(&resource_id, &start, &end, period)
+        .await?;
+    let range_end = increase_by_period(end, period).min(api.clock().now());
+    let payments = cost(&readings, &rates, start, range_end);
+
+    let delimiter = args.csv.delimiter();
+    let number = |value: f64| {
+        let value = round(value, options.precision).to_string();
+        if args.csv.decimal_comma {
+            value.replace('.', ",")
+        } else {
+            value
+        }
+    };
+    let row = |fields: &[String]| {
+        fields
+            .iter()
+            .map(|field| delimited_field(field, delimiter))
+            .collect::<Vec<_>>()
+            .join(&delimiter.to_string())
+    };
+
+    let mut out = stdout().lock();
+    let header = [
+        "date",
+        "settlement_period",
+        "start",
+        "export_kwh",
+        "rate_p_per_kwh",
+        "payment_p",
+    ];
+    writeln!(out, "{}", row(&header.map(String::from))).str_err()?;
+
+    let mut missing = 0;
+    for payment in &payments.readings {
+        if rates.rate_at(payment.start).is_none() {
+            missing += 1;
+        }
+
+        let local = payment.start.to_offset(uk_offset(payment.start));
+        writeln!(
+            out,
+            "{}",
+            row(&[
+                local.date().to_string(),
+                settlement_period(payment.start).to_string(),
+                payment.start.format(&Rfc3339).str_err()?,
+                number(payment.consumption),
+                number(payment.rate),
+                number(payment.cost),
+            ])
+        )
+        .str_err()?;
+    }
+
+    let exported: f64 = payments.readings.iter().map(|p| p.consumption).sum();
+    writeln!(
+        out,
+        "{}",
+        row(&[
+            "total".to_string(),
+            String::new(),
+            String::new(),
+            number(exported),
+            String::new(),
+            number(payments.consumption_cost),
+        ])
+    )
+    .str_err()?;
+
+    if missing > 0 {
+        log::warn!(
+            "{} half hours had no rate and were paid at zero, check the rates cover the range",
+            missing
+        );
+    }
+
+    Ok(())
+}
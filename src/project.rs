@@ -0,0 +1,197 @@
+//! Projects a resource's annual consumption and cost, as suppliers quote
+//! them.
+
+use std::io::{stdout, Write};
+
+use glowmarkt::{
+    cost::{cost, Rates},
+    format::round,
+    stats::{daily, project_annual, ProjectionMethod, Seasonality, MIN_SEASONAL_DAYS},
+    GlowmarktApi, ReadingPeriod,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::Duration;
+
+use crate::{
+    hint::CliError,
+    lookup::select_resources,
+    output::{OutputOptions, ReportFormat},
+    ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct ProjectArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// How many days of history to base the projection on.
+    #[clap(long, default_value = "365")]
+    days: u32,
+    /// The unit rate in pence per kWh to use if the resource has no tariff.
+    #[clap(long)]
+    unit_rate: Option<f64>,
+    /// The daily standing charge in pence to use if the resource has no tariff.
+    #[clap(long, requires = "unit-rate")]
+    standing_charge: Option<f64>,
+    /// The resource to project, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Projection {
+    resource_id: String,
+    resource: String,
+    /// The estimated annual consumption in kWh.
+    annual_consumption: f64,
+    method: ProjectionMethod,
+    /// The complete days of history the projection is based on.
+    days: usize,
+    /// The average unit rate paid over the history in pence per kWh.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_rate: Option<f64>,
+    /// The daily standing charge in pence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    standing_charge: Option<f64>,
+    /// The estimated annual cost in pence.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annual_cost: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+fn print_text(projection: &Projection) -> Result<(), CliError> {
+    let mut out = stdout().lock();
+    writeln!(out, "{} ({})", projection.resource, projection.resource_id).str_err()?;
+    writeln!(
+        out,
+        "Estimated annual consumption: {} kWh",
+        projection.annual_consumption
+    )
+    .str_err()?;
+    if let Some(rate) = projection.unit_rate {
+        writeln!(out, "Unit rate: {}p per kWh", rate).str_err()?;
+    }
+    if let Some(standing_charge) = projection.standing_charge {
+        writeln!(out, "Standing charge: {}p per day", standing_charge).str_err()?;
+    }
+    if let Some(cost) = projection.annual_cost {
+        writeln!(out, "Estimated annual cost: £{:.2}", cost / 100.0).str_err()?;
+    }
+
+    for note in &projection.notes {
+        writeln!(out).str_err()?;
+        writeln!(out, "{}", note).str_err()?;
+    }
+
+    Ok(())
+}
+
+pub async fn project(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: ProjectArgs,
+) -> Result<(), CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let resource = select_resources(&api, &[args.resource]).await?.remove(0);
+
+    let mut notes = Vec::new();
+    let tariff = match api.latest_tariff(&resource.id).await {
+        Ok(tariff) => tariff,
+        Err(e) if e.is_unavailable() => {
+            notes.push(format!("The tariff could not be retrieved: {}", e));
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let rates = tariff.as_ref().and_then(Rates::from_tariff).or_else(|| {
+        args.unit_rate.map(|unit_rate| {
+            notes.push(
+                "Costed with the supplied rates as the resource has no usable tariff.".to_string(),
+            );
+            Rates::flat(unit_rate, args.standing_charge.unwrap_or_default())
+        })
+    });
+
+    let end = api.clock().now();
+    let start = end - Duration::days(args.days.into());
+    let readings = api
+        .readings_range(&resource.id, &start, &end, period)
+        .await?;
+
+    let seasonality = Seasonality::for_fuel(
+        resource
+            .classifier
+            .as_ref()
+            .map(|classifier| classifier.fuel())
+            .unwrap_or_default(),
+    );
+    let projection = project_annual(&daily(&readings), seasonality).ok_or_else(|| {
+        format!(
+            "Resource {} has no complete days of readings in the last {} days",
+            resource.id, args.days
+        )
+    })?;
+    match projection.method {
+        ProjectionMethod::Actual => {}
+        ProjectionMethod::Seasonal => notes.push(format!(
+            "Only {} days of history were available so usage was scaled for the time of year \
+            using a typical {} profile.",
+            projection.days,
+            match seasonality {
+                Seasonality::Electricity => "electricity",
+                Seasonality::Gas => "gas",
+            }
+        )),
+        ProjectionMethod::Linear => notes.push(format!(
+            "Only {} days of history were available, fewer than the {} needed to allow for the \
+            time of year, so the projection may be far out.",
+            projection.days, MIN_SEASONAL_DAYS
+        )),
+    }
+
+    // The rate actually paid over the history accounts for time of use bands.
+    let unit_rate = rates.as_ref().and_then(|rates| {
+        let costs = cost(&readings, rates, start, end);
+        let consumption: f64 = costs.readings.iter().map(|r| r.consumption).sum();
+        (consumption > 0.0).then(|| costs.consumption_cost / consumption)
+    });
+    let standing_charge = rates.as_ref().map(|rates| rates.standing_charge);
+    let annual_cost = standing_charge.map(|standing_charge| {
+        projection.total * unit_rate.unwrap_or_default() + standing_charge * 365.0
+    });
+    if rates.is_none() {
+        notes.push(format!(
+            "Resource {} has no usable tariff so only consumption is projected, pass \
+            --unit-rate to cost it.",
+            resource.id
+        ));
+    }
+
+    let precision = match args.format {
+        ReportFormat::Text => options.precision.or(Some(3)),
+        ReportFormat::Json => options.precision,
+    };
+    let round = |value: f64| round(value, precision);
+    let projection = Projection {
+        resource_id: resource.id,
+        resource: resource.name,
+        annual_consumption: round(projection.total),
+        method: projection.method,
+        days: projection.days,
+        unit_rate: unit_rate.map(round),
+        standing_charge: standing_charge.map(round),
+        annual_cost: annual_cost.map(round),
+        notes,
+    };
+
+    match args.format {
+        ReportFormat::Text => print_text(&projection),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&projection).str_err()?);
+            Ok(())
+        }
+    }
+}
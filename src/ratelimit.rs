@@ -0,0 +1,42 @@
+//! Client-side limiting of the rate of requests.
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use futures_timer::Delay;
+
+/// Spaces requests out so that no more than a given number are made each
+/// second. Shared by every clone of an API so all request paths are limited
+/// together.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / requests_per_second),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the next request may be made.
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+            slot - now
+        };
+
+        if !wait.is_zero() {
+            log::trace!("Rate limiting, waiting {}ms", wait.as_millis());
+            Delay::new(wait).await;
+        }
+    }
+}
@@ -0,0 +1,367 @@
+//! Combining readings into buckets of any length.
+//!
+//! The API only returns readings for the periods in [`ReadingPeriod`], and a
+//! day always runs from midnight. [`resample`] combines readings into buckets
+//! of other lengths such as 2 or 4 hours, or days that run from a different
+//! time such as 7am to 7am.
+//!
+//! Buckets are laid out in UK local time. Each day starts at the configured
+//! time and buckets shorter than a day start at the same local times every
+//! day and never cross into the next day. On the days the clocks change the
+//! bucket containing the change is an hour shorter or longer than the rest,
+//! and the last bucket of a day is cut short if the length doesn't divide the
+//! day evenly.
+//!
+//! [`ReadingPeriod`]: crate::ReadingPeriod
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use time::{macros::date, Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+
+use crate::{
+    settlement::{uk_local, uk_offset},
+    AggregationFunction, Reading,
+};
+
+// A Monday, so buckets of whole weeks start on Mondays.
+const WEEK_ORIGIN: Date = date!(1970 - 01 - 05);
+
+/// How [`resample`] divides time into buckets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buckets {
+    length: Duration,
+    day_start: Time,
+}
+
+impl Buckets {
+    /// Buckets of the given length with days starting at midnight.
+    ///
+    /// Lengths must be at least a minute and a whole number of seconds.
+    /// Lengths shorter than a day divide each day from its start. Longer
+    /// lengths must be a whole number of days and are counted from a Monday.
+    pub fn new(length: Duration) -> Result<Self, String> {
+        if length < Duration::MINUTE {
+            return Err(format!(
+                "Buckets must be at least a minute long, not {}",
+                length
+            ));
+        }
+
+        if length.subsec_nanoseconds() != 0 {
+            return Err(format!(
+                "Buckets must be a whole number of seconds long, not {}",
+                length
+            ));
+        }
+
+        if length >= Duration::DAY && length.whole_seconds() % Duration::DAY.whole_seconds() != 0 {
+            return Err(format!(
+                "Buckets longer than a day must be a whole number of days, not {}",
+                length
+            ));
+        }
+
+        Ok(Self {
+            length,
+            day_start: Time::MIDNIGHT,
+        })
+    }
+
+    /// Buckets of a day with days starting at the given UK local time.
+    pub fn days_from(day_start: Time) -> Self {
+        Self {
+            length: Duration::DAY,
+            day_start,
+        }
+    }
+
+    /// Starts days at the given UK local time rather than midnight.
+    pub fn with_day_start(self, day_start: Time) -> Self {
+        Self { day_start, ..self }
+    }
+
+    /// The length of each bucket.
+    pub fn length(&self) -> Duration {
+        self.length
+    }
+
+    /// The UK local time each day starts.
+    pub fn day_start(&self) -> Time {
+        self.day_start
+    }
+
+    /// The start of the day, as set by the day start time, containing an
+    /// instant.
+    fn day(&self, instant: OffsetDateTime) -> Date {
+        let local = instant.to_offset(uk_offset(instant));
+        let date = local.date();
+        if local.time() < self.day_start {
+            date.previous_day().unwrap_or(date)
+        } else {
+            date
+        }
+    }
+
+    /// The start and end of the bucket containing an instant.
+    pub fn bucket(&self, instant: OffsetDateTime) -> (OffsetDateTime, OffsetDateTime) {
+        let day = self.day(instant);
+
+        if self.length < Duration::DAY {
+            let first = PrimitiveDateTime::new(day, self.day_start);
+            let last = PrimitiveDateTime::new(day + Duration::DAY, self.day_start);
+            let boundary = |index: i64| first + self.length * index as i32;
+
+            // Start from the bucket the local time falls in, which may be off
+            // by a few where the clocks change.
+            let local = instant.to_offset(uk_offset(instant));
+            let local = PrimitiveDateTime::new(local.date(), local.time());
+            let mut index = ((local - first).whole_seconds() / self.length.whole_seconds()).max(0);
+            let mut start = loop {
+                match local_instant(boundary(index)) {
+                    Some(start) if start <= instant => break start,
+                    _ if index <= 0 => break uk_local(day, self.day_start),
+                    _ => index -= 1,
+                }
+            };
+
+            // Local times repeat when the clocks go back so later buckets may
+            // still have started before the instant.
+            let end = loop {
+                index += 1;
+                let local = boundary(index);
+                if local >= last {
+                    break uk_local(last.date(), last.time());
+                }
+                match local_instant(local) {
+                    Some(boundary) if boundary <= instant => start = boundary,
+                    Some(boundary) => break boundary,
+                    None => {}
+                }
+            };
+
+            (start, end)
+        } else {
+            let days = self.length.whole_days();
+            let index = (day - WEEK_ORIGIN).whole_days().div_euclid(days);
+            let first = WEEK_ORIGIN + Duration::days(index * days);
+            (
+                uk_local(first, self.day_start),
+                uk_local(first + self.length, self.day_start),
+            )
+        }
+    }
+}
+
+/// The instant of a UK local time, or `None` if it is skipped when the clocks
+/// go forward.
+fn local_instant(local: PrimitiveDateTime) -> Option<OffsetDateTime> {
+    let instant = uk_local(local.date(), local.time());
+    let actual = instant.to_offset(uk_offset(instant));
+    (PrimitiveDateTime::new(actual.date(), actual.time()) == local).then_some(instant)
+}
+
+/// Readings combined into a single bucket.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    /// The start of the bucket.
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The end of the bucket.
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+    /// The combined value.
+    pub value: f64,
+    /// The number of readings combined, fewer than expected if some were
+    /// missing.
+    pub readings: usize,
+}
+
+/// Combines readings into buckets using the aggregation function, in order of
+/// time.
+///
+/// Readings are placed in the bucket containing their start so readings
+/// should be no longer than the buckets. Readings with no value (NaN) are
+/// ignored and buckets without any readings are left out.
+pub fn resample(
+    readings: &[Reading],
+    buckets: Buckets,
+    function: AggregationFunction,
+) -> Vec<Bucket> {
+    let mut values: BTreeMap<OffsetDateTime, (OffsetDateTime, Vec<f64>)> = BTreeMap::new();
+
+    for reading in readings.iter().filter(|reading| !reading.value.is_nan()) {
+        let (start, end) = buckets.bucket(reading.start);
+        values
+            .entry(start)
+            .or_insert_with(|| (end, Vec::new()))
+            .1
+            .push(reading.value as f64);
+    }
+
+    values
+        .into_iter()
+        .map(|(start, (end, values))| {
+            let value = match function {
+                AggregationFunction::Sum => values.iter().sum(),
+                AggregationFunction::Avg => values.iter().sum::<f64>() / values.len() as f64,
+                AggregationFunction::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+                AggregationFunction::Max => {
+                    values.iter().copied().fold(f64::NEG_INFINITY, f64::max)
+                }
+            };
+
+            Bucket {
+                start,
+                end,
+                value,
+                readings: values.len(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration, Time};
+
+    use super::{resample, Buckets};
+    use crate::{AggregationFunction, Reading, ReadingPeriod};
+
+    fn hours(hours: i64) -> Buckets {
+        Buckets::new(Duration::hours(hours)).unwrap()
+    }
+
+    #[test]
+    fn buckets_follow_local_time() {
+        let buckets = hours(2);
+
+        assert_eq!(
+            buckets.bucket(datetime!(2025-07-01 14:30 +01:00)),
+            (
+                datetime!(2025-07-01 14:00 +01:00),
+                datetime!(2025-07-01 16:00 +01:00)
+            )
+        );
+        assert_eq!(
+            buckets.bucket(datetime!(2025-07-01 23:30 UTC)),
+            (
+                datetime!(2025-07-02 00:00 +01:00),
+                datetime!(2025-07-02 02:00 +01:00)
+            )
+        );
+    }
+
+    #[test]
+    fn clocks_go_forward() {
+        // 2025-03-30 has no 01:00 to 02:00 local time.
+        let buckets = hours(2);
+
+        assert_eq!(
+            buckets.bucket(datetime!(2025-03-30 00:30 UTC)),
+            (
+                datetime!(2025-03-30 00:00 UTC),
+                datetime!(2025-03-30 02:00 +01:00)
+            )
+        );
+        assert_eq!(
+            buckets.bucket(datetime!(2025-03-30 03:30 +01:00)),
+            (
+                datetime!(2025-03-30 02:00 +01:00),
+                datetime!(2025-03-30 04:00 +01:00)
+            )
+        );
+        assert_eq!(
+            buckets.bucket(datetime!(2025-03-30 22:30 +01:00)),
+            (
+                datetime!(2025-03-30 22:00 +01:00),
+                datetime!(2025-03-31 00:00 +01:00)
+            )
+        );
+
+        let half_hours = Buckets::new(Duration::minutes(30)).unwrap();
+        assert_eq!(
+            half_hours.bucket(datetime!(2025-03-30 00:45 UTC)),
+            (
+                datetime!(2025-03-30 00:30 UTC),
+                datetime!(2025-03-30 02:00 +01:00)
+            )
+        );
+        assert_eq!(
+            half_hours.bucket(datetime!(2025-03-30 02:10 +01:00)),
+            (
+                datetime!(2025-03-30 02:00 +01:00),
+                datetime!(2025-03-30 02:30 +01:00)
+            )
+        );
+    }
+
+    #[test]
+    fn clocks_go_back() {
+        // 2025-10-26 has 01:00 to 02:00 local time twice.
+        let buckets = hours(2);
+
+        assert_eq!(
+            buckets.bucket(datetime!(2025-10-26 01:30 UTC)),
+            (
+                datetime!(2025-10-26 00:00 +01:00),
+                datetime!(2025-10-26 02:00 UTC)
+            )
+        );
+        assert_eq!(
+            buckets.bucket(datetime!(2025-10-26 03:00 UTC)),
+            (
+                datetime!(2025-10-26 02:00 UTC),
+                datetime!(2025-10-26 04:00 UTC)
+            )
+        );
+
+        let half_hours = Buckets::new(Duration::minutes(30)).unwrap();
+        assert_eq!(
+            half_hours.bucket(datetime!(2025-10-26 01:15 UTC)),
+            (
+                datetime!(2025-10-26 01:30 +01:00),
+                datetime!(2025-10-26 02:00 UTC)
+            )
+        );
+    }
+
+    #[test]
+    fn day_start() {
+        let buckets = Buckets::days_from(Time::from_hms(7, 0, 0).unwrap());
+
+        assert_eq!(
+            buckets.bucket(datetime!(2025-10-26 06:00 UTC)),
+            (
+                datetime!(2025-10-25 07:00 +01:00),
+                datetime!(2025-10-26 07:00 UTC)
+            )
+        );
+    }
+
+    #[test]
+    fn resample_clock_change_day() {
+        let start = datetime!(2025-10-25 23:00 UTC);
+        let readings: Vec<Reading> = (0..50)
+            .map(|index| Reading {
+                start: start + Duration::minutes(30 * index),
+                period: ReadingPeriod::HalfHour,
+                value: 1.0,
+                quality: None,
+            })
+            .collect();
+
+        let buckets = resample(&readings, hours(4), AggregationFunction::Sum);
+        let counts: Vec<usize> = buckets.iter().map(|bucket| bucket.readings).collect();
+        assert_eq!(counts, vec![10, 8, 8, 8, 8, 8]);
+        assert_eq!(buckets[1].start, datetime!(2025-10-26 04:00 UTC));
+    }
+
+    #[test]
+    fn short_lengths_are_rejected() {
+        assert!(Buckets::new(Duration::seconds(30)).is_err());
+        assert!(Buckets::new(Duration::milliseconds(90_500)).is_err());
+        assert!(Buckets::new(Duration::minutes(1)).is_ok());
+    }
+}
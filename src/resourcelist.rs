@@ -0,0 +1,116 @@
+//! A table of resources with when each holds data from, for `resource --wide`.
+
+use std::io::{stdout, Write};
+
+use glowmarkt::{settlement::uk_offset, sink::ResourceContext, GlowmarktApi};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{hint::CliError, ErrorStr};
+
+struct Row {
+    id: String,
+    name: String,
+    classifier: String,
+    unit: String,
+    first: String,
+    last: String,
+    device: String,
+}
+
+fn format_time(time: Option<OffsetDateTime>) -> Result<String, CliError> {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute]");
+    match time {
+        Some(time) => Ok(time.to_offset(uk_offset(time)).format(&format).str_err()?),
+        None => Ok("-".to_string()),
+    }
+}
+
+async fn row(api: &GlowmarktApi, context: ResourceContext) -> Result<Row, CliError> {
+    let resource = context.resource;
+
+    // Some resources, such as tariffs, hold no readings and the API reports
+    // that as an error.
+    let (first, last) =
+        match futures::try_join!(api.first_time(&resource.id), api.last_time(&resource.id)) {
+            Ok(times) => times,
+            Err(e) if e.is_unavailable() => {
+                log::debug!("Unable to read the data range of {}: {}", resource.id, e);
+                (None, None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+    Ok(Row {
+        classifier: resource
+            .classifier
+            .map(|classifier| classifier.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+        unit: resource.base_unit.unwrap_or_else(|| "-".to_string()),
+        first: format_time(first)?,
+        last: format_time(last)?,
+        device: context
+            .device
+            .map(|device| device.description.unwrap_or(device.id))
+            .unwrap_or_else(|| "-".to_string()),
+        id: resource.id,
+        name: resource.name,
+    })
+}
+
+pub async fn resource_table(api: &GlowmarktApi, id: Option<String>) -> Result<(), CliError> {
+    let ids: Vec<String> = id.into_iter().collect();
+    let mut contexts = api.resource_contexts(&ids).await?;
+    contexts.sort_by(|a, b| a.resource.name.cmp(&b.resource.name));
+
+    let mut rows = vec![Row {
+        id: "ID".to_string(),
+        name: "Name".to_string(),
+        classifier: "Classifier".to_string(),
+        unit: "Unit".to_string(),
+        first: "First".to_string(),
+        last: "Last".to_string(),
+        device: "Device".to_string(),
+    }];
+    for context in contexts {
+        rows.push(row(api, context).await?);
+    }
+
+    let width = |column: fn(&Row) -> &str| {
+        rows.iter()
+            .map(|row| column(row).chars().count())
+            .max()
+            .unwrap_or_default()
+    };
+    let widths = [
+        width(|row| &row.id),
+        width(|row| &row.name),
+        width(|row| &row.classifier),
+        width(|row| &row.unit),
+        width(|row| &row.first),
+        width(|row| &row.last),
+    ];
+
+    let mut out = stdout().lock();
+    for row in &rows {
+        writeln!(
+            out,
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {:<w4$}  {:<w5$}  {}",
+            row.id,
+            row.name,
+            row.classifier,
+            row.unit,
+            row.first,
+            row.last,
+            row.device,
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+            w5 = widths[5],
+        )
+        .str_err()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+//! Retrying requests that fail for transient reasons.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::Error;
+
+/// Controls how requests that fail with network errors, server errors or rate
+/// limiting are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of times to attempt a request, including the first.
+    pub max_attempts: u32,
+    /// The delay before the first retry. Each further retry doubles it.
+    pub initial_backoff: Duration,
+    /// The longest delay between attempts.
+    pub max_backoff: Duration,
+    /// Randomise delays so that many clients don't retry in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Whether a request that failed with the given error on the given
+    /// attempt (starting at 1) should be tried again.
+    pub(crate) fn should_retry(&self, error: &Error, attempt: u32) -> bool {
+        attempt < self.max_attempts && error.is_retryable()
+    }
+
+    /// The delay before making the given attempt (starting at 2).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(2).min(16);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+
+        if self.jitter {
+            // Scale to between 50% and 100% of the delay. This only needs to
+            // spread retries out so the clock is a good enough source.
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos())
+                .unwrap_or_default();
+            delay.mul_f64(0.5 + (nanos % 1000) as f64 / 2000.0)
+        } else {
+            delay
+        }
+    }
+}
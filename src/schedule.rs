@@ -0,0 +1,156 @@
+//! Cron-like schedules for the daemon.
+
+use std::str::FromStr;
+
+use time::{Duration, OffsetDateTime, UtcOffset};
+
+/// The furthest ahead to look for the next matching minute.
+const MAX_SEARCH: Duration = Duration::days(366 * 4);
+
+/// A five field cron schedule, `minute hour day-of-month month day-of-week`,
+/// evaluated in UTC.
+///
+/// Each field accepts `*`, single values, ranges such as `1-5`, steps such as
+/// `*/15` or `0-30/10` and comma separated lists of those. Days of the week
+/// run from 0 (Sunday) to 6, 7 is also accepted for Sunday. As with cron, when
+/// both the day of the month and day of the week are restricted a day
+/// matching either is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("Invalid step '{}'", step))?,
+            ),
+            None => (part, 1),
+        };
+
+        let value = |value: &str| {
+            value
+                .parse::<u32>()
+                .ok()
+                .filter(|value| (min..=max).contains(value))
+                .ok_or_else(|| format!("'{}' is not between {} and {}", value, min, max))
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+
+        if start > end {
+            return Err(format!("Invalid range '{}'", range));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "Expected five fields in the schedule '{}': minute hour day-of-month month \
+                day-of-week",
+                s
+            ));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7)?;
+        // Sunday can be either 0 or 7.
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days: parse_field(day, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+impl Schedule {
+    fn matches_day(&self, date: OffsetDateTime) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().number_days_from_sunday()) != 0;
+
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    fn months_match(&self, date: OffsetDateTime) -> bool {
+        self.months & (1 << date.month() as u8) != 0
+    }
+
+    fn matches(&self, date: OffsetDateTime) -> bool {
+        self.minutes & (1 << date.minute()) != 0
+            && self.hours & (1 << date.hour()) != 0
+            && self.months_match(date)
+            && self.matches_day(date)
+    }
+
+    /// The first time strictly after `after` that matches the schedule, or
+    /// `None` if nothing matches within the next four years.
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let after = after.to_offset(UtcOffset::UTC);
+        let mut next = after
+            .replace_second(0)
+            .unwrap()
+            .replace_nanosecond(0)
+            .unwrap()
+            + Duration::MINUTE;
+        let limit = after + MAX_SEARCH;
+
+        while next < limit {
+            if !self.months_match(next) || !self.matches_day(next) {
+                next = (next + Duration::DAY)
+                    .replace_hour(0)
+                    .unwrap()
+                    .replace_minute(0)
+                    .unwrap();
+            } else if self.hours & (1 << next.hour()) == 0 {
+                next = (next + Duration::HOUR).replace_minute(0).unwrap();
+            } else if self.matches(next) {
+                return Some(next);
+            } else {
+                next += Duration::MINUTE;
+            }
+        }
+
+        None
+    }
+}
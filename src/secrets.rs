@@ -0,0 +1,52 @@
+//! Reads secrets from files, as Docker and Kubernetes secrets provide them.
+//!
+//! Each of the variables holding a credential can instead be given as the
+//! same name with a `_FILE` suffix naming a file that contains the value, for
+//! example `GLOWMARKT_PASSWORD_FILE=/run/secrets/glowmarkt_password`.
+
+use std::{env, fs};
+
+/// The variables that can be read from files.
+const SECRETS: [&str; 11] = [
+    "GLOWMARKT_USERNAME",
+    "GLOWMARKT_PASSWORD",
+    "GLOWMARKT_TOKEN",
+    "INFLUX_TOKEN",
+    "INFLUX_PASSWORD",
+    "MQTT_PASSWORD",
+    "GLOWMARKT_POSTGRES_DSN",
+    "GLOWMARKT_NOTIFY_URL",
+    "USERNAME",
+    "PASSWORD",
+    "TOKEN",
+];
+
+/// Sets every secret variable that isn't already set from the file named by
+/// its `_FILE` variable.
+///
+/// This changes the process's environment so must be called before any other
+/// threads are started.
+pub fn load_secret_files() -> Result<(), String> {
+    for name in SECRETS {
+        if env::var_os(name).is_some() {
+            continue;
+        }
+
+        let Some(path) = env::var_os(format!("{}_FILE", name)) else {
+            continue;
+        };
+        let value = fs::read_to_string(&path).map_err(|e| {
+            format!(
+                "Failed to read {} from {}: {}",
+                name,
+                path.to_string_lossy(),
+                e
+            )
+        })?;
+
+        // Editors and `echo` usually leave a trailing newline.
+        env::set_var(name, value.trim_end_matches(['\r', '\n']));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+//! UK electricity settlement periods.
+//!
+//! The GB electricity market divides each local day into half-hour settlement
+//! periods numbered from 1 starting at midnight UK time. Most days have 48
+//! periods, the day the clocks go forward has 46 and the day they go back has
+//! 50.
+
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+
+fn last_sunday(year: i32, month: Month) -> Date {
+    let last =
+        Date::from_calendar_date(year, month, time::util::days_in_year_month(year, month)).unwrap();
+    last - Duration::days(last.weekday().number_days_from_sunday() as i64)
+}
+
+/// Returns the offset of UK local time from UTC at the given instant.
+///
+/// British Summer Time runs from 01:00 UTC on the last Sunday in March until
+/// 01:00 UTC on the last Sunday in October.
+pub fn uk_offset(date: OffsetDateTime) -> UtcOffset {
+    let date = date.to_offset(UtcOffset::UTC);
+    let one_am = Time::from_hms(1, 0, 0).unwrap();
+
+    let bst_start =
+        PrimitiveDateTime::new(last_sunday(date.year(), Month::March), one_am).assume_utc();
+    let bst_end =
+        PrimitiveDateTime::new(last_sunday(date.year(), Month::October), one_am).assume_utc();
+
+    if date >= bst_start && date < bst_end {
+        UtcOffset::from_hms(1, 0, 0).unwrap()
+    } else {
+        UtcOffset::UTC
+    }
+}
+
+/// Returns the instant of a UK local date and time.
+///
+/// Times skipped when the clocks go forward are read as GMT and times repeated
+/// when they go back are the first occurrence.
+pub(crate) fn uk_local(date: Date, time: Time) -> OffsetDateTime {
+    let local = PrimitiveDateTime::new(date, time);
+
+    // Clocks change at 01:00 UTC so the offset an hour before the local time
+    // read as UTC is the offset in force at that local time.
+    let offset = uk_offset(local.assume_utc() - Duration::hours(1));
+    local.assume_offset(offset)
+}
+
+/// Returns the start of the UK local day containing the given instant.
+pub fn uk_day_start(date: OffsetDateTime) -> OffsetDateTime {
+    let local = date.to_offset(uk_offset(date));
+    uk_local(local.date(), Time::MIDNIGHT)
+}
+
+/// Returns the settlement period (starting at 1) containing the given instant.
+pub fn settlement_period(date: OffsetDateTime) -> u8 {
+    ((date - uk_day_start(date)).whole_minutes() / 30 + 1) as u8
+}
+
+/// Returns the number of settlement periods in the UK local day containing the
+/// given instant.
+pub fn settlement_periods_in_day(date: OffsetDateTime) -> u8 {
+    let start = uk_day_start(date);
+    let next = uk_day_start(start + Duration::hours(26));
+    ((next - start).whole_minutes() / 30) as u8
+}
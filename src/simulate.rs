@@ -0,0 +1,155 @@
+//! Simulating what past consumption would have cost on a different tariff.
+//!
+//! A [`TariffDefinition`] describes hypothetical rates, usually read from a
+//! TOML or JSON file:
+//!
+//! ```toml
+//! name = "Economy 7"
+//! standing-charge = 53.35
+//! unit-rate = 31.2
+//!
+//! [[time-of-use]]
+//! start = "00:30"
+//! end = "07:30"
+//! rate = 14.8
+//! ```
+//!
+//! Its [`Rates`] can then be applied to historic readings with [`simulate`].
+
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use time::{OffsetDateTime, Time};
+
+use crate::{
+    cost::{self, BandUsage, RateBand, Rates},
+    tariff::{parse_time, TimeOfUseRate},
+    Reading,
+};
+
+/// A unit rate applying during part of the day in a [`TariffDefinition`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct BandDefinition {
+    /// The UK local time the rate starts to apply, as `HH:MM`.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: Time,
+    /// The UK local time the rate stops applying, as `HH:MM`.
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: Time,
+    /// The rate in pence per kWh.
+    pub rate: f64,
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Time, D::Error> {
+    let string = String::deserialize(deserializer)?;
+    parse_time(&string)
+        .ok_or_else(|| de::Error::custom(format!("invalid time '{}', expected HH:MM", string)))
+}
+
+/// Hypothetical tariff rates.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct TariffDefinition {
+    /// A name to identify the tariff in reports.
+    pub name: Option<String>,
+    /// The daily standing charge in pence.
+    #[serde(default)]
+    pub standing_charge: f64,
+    /// The flat unit rate in pence per kWh, used outside of any time of use
+    /// bands.
+    pub unit_rate: Option<f64>,
+    /// Unit rates that apply during part of the day.
+    #[serde(default)]
+    pub time_of_use: Vec<BandDefinition>,
+}
+
+impl TariffDefinition {
+    /// The rates the tariff charges.
+    ///
+    /// Returns `None` if the tariff includes no unit rates at all.
+    pub fn rates(&self) -> Option<Rates> {
+        if self.unit_rate.is_none() && self.time_of_use.is_empty() {
+            return None;
+        }
+
+        Some(Rates {
+            standing_charge: self.standing_charge,
+            unit_rate: self.unit_rate,
+            time_of_use: self
+                .time_of_use
+                .iter()
+                .map(|band| TimeOfUseRate {
+                    rate: band.rate,
+                    start: band.start,
+                    end: band.end,
+                })
+                .collect(),
+            half_hourly: BTreeMap::new(),
+        })
+    }
+}
+
+/// What a set of readings would have cost under some rates.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Simulation {
+    /// The name of the tariff.
+    pub name: String,
+    /// The number of UK local days the range covers.
+    pub days: u32,
+    /// The total consumption in kWh.
+    pub consumption: f64,
+    /// Consumption in kWh that fell outside every rate and so was costed at
+    /// zero.
+    pub unrated: f64,
+    /// The total standing charge in pence.
+    pub standing_charge: f64,
+    /// The total cost of consumption in pence.
+    pub consumption_cost: f64,
+    /// The total cost in pence.
+    pub total: f64,
+    /// The consumption and cost in each rate band over the whole range.
+    pub bands: Vec<BandUsage>,
+}
+
+/// Applies rates to historic readings from the range starting at `start` and
+/// ending before `end`, giving what the bill would have been.
+///
+/// Readings are costed as by [`cost::cost`] so half-hourly readings give the
+/// most accurate result for time of use tariffs.
+pub fn simulate(
+    name: &str,
+    readings: &[Reading],
+    rates: &Rates,
+    (start, end): (OffsetDateTime, OffsetDateTime),
+) -> Simulation {
+    let costs = cost::cost(readings, rates, start, end);
+
+    let mut bands: BTreeMap<RateBand, BandUsage> = BTreeMap::new();
+    for usage in cost::breakdown(readings, rates)
+        .into_iter()
+        .flat_map(|day| day.bands)
+    {
+        let total = bands.entry(usage.band).or_insert(BandUsage {
+            band: usage.band,
+            consumption: 0.0,
+            cost: 0.0,
+        });
+        total.consumption += usage.consumption;
+        total.cost += usage.cost;
+    }
+
+    Simulation {
+        name: name.to_owned(),
+        days: costs.days,
+        consumption: costs.readings.iter().map(|r| r.consumption).sum(),
+        unrated: bands
+            .get(&RateBand::Unrated)
+            .map(|usage| usage.consumption)
+            .unwrap_or_default(),
+        standing_charge: costs.standing_charge,
+        consumption_cost: costs.consumption_cost,
+        total: costs.total,
+        bands: bands.into_values().collect(),
+    }
+}
@@ -0,0 +1,566 @@
+//! Writing readings to other systems.
+//!
+//! An [`ExportSink`] receives readings along with the [`ResourceContext`] they
+//! were recorded in, so every sink can label readings with the same device,
+//! resource and virtual entity details. Sinks for InfluxDB line protocol,
+//! Graphite's plaintext protocol, QuestDB, MQTT, CSV and newline delimited
+//! JSON are included,
+//! along with PostgreSQL and Parquet sinks when the `postgres` and `parquet`
+//! features are enabled.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{self, Write},
+};
+
+use serde::Serialize;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::{
+    api::VirtualEntity, classifier::Classifier, format::round, format::CsvWriter, unit::Unit,
+    Device, Reading, Resource,
+};
+
+mod mqtt;
+mod questdb;
+
+#[cfg(feature = "postgres")]
+mod postgres;
+
+pub use mqtt::MqttSink;
+pub use questdb::QuestDbSink;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresSink;
+
+#[cfg(feature = "parquet")]
+mod parquet;
+
+#[cfg(feature = "parquet")]
+pub use self::parquet::{ParquetSink, ParquetSplit};
+
+/// A resource being exported along with the device and virtual entity it
+/// belongs to.
+#[derive(Debug, Clone)]
+pub struct ResourceContext {
+    /// The resource the readings are from.
+    pub resource: Resource,
+    /// The device recording to the resource, if known.
+    pub device: Option<Device>,
+    /// The virtual entity the resource belongs to, if known.
+    pub entity: Option<VirtualEntity>,
+}
+
+impl ResourceContext {
+    /// A context for a resource with no known device or virtual entity.
+    pub fn new(resource: Resource) -> Self {
+        Self {
+            resource,
+            device: None,
+            entity: None,
+        }
+    }
+
+    /// The tags describing the entity, device and resource, in that order so
+    /// that resource tags take precedence.
+    pub fn tags(&self) -> BTreeMap<String, String> {
+        let mut tags = BTreeMap::new();
+        if let Some(ref entity) = self.entity {
+            add_tags_for_entity(&mut tags, entity);
+        }
+        if let Some(ref device) = self.device {
+            add_tags_for_device(&mut tags, device);
+        }
+        add_tags_for_resource(&mut tags, &self.resource);
+        tags
+    }
+}
+
+/// A destination for exported readings.
+pub trait ExportSink {
+    /// Writes a single reading.
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()>;
+
+    /// Writes a set of readings from the same resource.
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        for reading in readings {
+            self.write_reading(context, reading)?;
+        }
+
+        self.flush()
+    }
+
+    /// Flushes any buffered output.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: ExportSink + ?Sized> ExportSink for Box<S> {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        (**self).write_reading(context, reading)
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        (**self).write_readings(context, readings)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+}
+
+/// A point in InfluxDB line protocol.
+pub struct Measurement {
+    /// The name of the measurement.
+    pub id: String,
+    /// The time of the point in nanoseconds since the unix epoch.
+    pub timestamp: i128,
+    /// The tags identifying the series.
+    pub tags: BTreeMap<String, String>,
+    /// The values.
+    pub fields: BTreeMap<String, f64>,
+}
+
+impl Measurement {
+    /// Creates a point with no fields.
+    pub fn new(id: &str, timestamp: OffsetDateTime, tags: BTreeMap<String, String>) -> Self {
+        Measurement {
+            id: id.to_owned(),
+            timestamp: timestamp.to_offset(UtcOffset::UTC).unix_timestamp_nanos(),
+            tags,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not finite, line protocol can't represent it.
+    pub fn add_field(&mut self, key: &str, value: f64) {
+        assert!(value.is_finite());
+
+        self.fields.insert(key.to_owned(), value);
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        assert!(!self.fields.is_empty());
+
+        let tags = self
+            .tags
+            .iter()
+            .map(|(k, v)| format!("{}={}", escape(k), escape(v)))
+            .collect::<Vec<String>>();
+
+        let fields = self
+            .fields
+            .iter()
+            .map(|(k, v)| format!("{}={}", escape(k), v))
+            .collect::<Vec<String>>();
+
+        if !tags.is_empty() {
+            f.pad(&format!(
+                "{},{} {} {}",
+                self.id,
+                tags.join(","),
+                fields.join(","),
+                self.timestamp
+            ))
+        } else {
+            f.pad(&format!(
+                "{} {} {}",
+                self.id,
+                fields.join(","),
+                self.timestamp
+            ))
+        }
+    }
+}
+
+/// Adds tags identifying a device.
+pub fn add_tags_for_device(tags: &mut BTreeMap<String, String>, device: &Device) {
+    tags.insert("device-id".to_string(), device.id.clone());
+    if let Some(ref description) = device.description {
+        tags.insert("device".to_string(), description.clone());
+    }
+    tags.insert("device-active".to_string(), device.active.to_string());
+    tags.insert("hardware-id".to_string(), device.hardware_id.to_string());
+    if !device.tags.is_empty() {
+        tags.insert("device-tags".to_string(), device.tags.join(","));
+    }
+    for (k, v) in device.hardware_ids.iter() {
+        tags.insert(k.clone(), v.clone());
+    }
+}
+
+/// Adds tags identifying a virtual entity.
+pub fn add_tags_for_entity(tags: &mut BTreeMap<String, String>, entity: &VirtualEntity) {
+    tags.insert("ve-id".to_string(), entity.id.clone());
+    tags.insert("ve-name".to_string(), entity.name.clone());
+}
+
+/// Maps each resource ID to the virtual entity it belongs to. A resource in
+/// more than one entity is mapped to the first by name.
+pub fn entities_by_resource(
+    entities: HashMap<String, VirtualEntity>,
+) -> HashMap<String, VirtualEntity> {
+    let mut entities: Vec<VirtualEntity> = entities.into_values().collect();
+    entities.sort_by(|a, b| a.name.cmp(&b.name).then(a.id.cmp(&b.id)));
+
+    let mut owners = HashMap::new();
+    for entity in entities {
+        for info in &entity.resources {
+            owners
+                .entry(info.resource_id.clone())
+                .or_insert_with(|| entity.clone());
+        }
+    }
+    owners
+}
+
+/// Adds tags identifying a resource and what it measures.
+pub fn add_tags_for_resource(tags: &mut BTreeMap<String, String>, resource: &Resource) {
+    tags.insert("resource-id".to_string(), resource.id.clone());
+    tags.insert("resource".to_string(), resource.name.clone());
+    tags.insert("resource-active".to_string(), resource.active.to_string());
+
+    if let Some(ref classifier) = resource.classifier {
+        tags.insert("classifier".to_string(), classifier.to_string());
+    }
+
+    if let Some(unit) = resource.unit() {
+        tags.insert("unit".to_string(), unit.to_string());
+    }
+
+    if let Some(ref classifier) = resource.classifier {
+        tags.insert("class".to_string(), classifier.fuel().to_string());
+    }
+}
+
+/// The name of the field holding a resource's values, the last part of its
+/// classifier such as `consumption` or `cost`.
+pub fn field_for_classifier(classifier: &Option<Classifier>) -> &str {
+    if let Some(classifier) = classifier {
+        classifier.parts().last().unwrap()
+    } else {
+        "value"
+    }
+}
+
+fn escape(tag: &str) -> String {
+    tag.replace(' ', "\\ ").replace(',', "\\,")
+}
+
+/// Writes readings as InfluxDB line protocol, one point per reading.
+pub struct LineProtocolSink<W: Write> {
+    out: W,
+    measurement: String,
+    precision: Option<u32>,
+}
+
+impl<W: Write> LineProtocolSink<W> {
+    /// Creates a sink writing points for the `glowmarkt` measurement.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            measurement: "glowmarkt".to_string(),
+            precision: None,
+        }
+    }
+
+    /// Sets the name of the measurement.
+    pub fn measurement(mut self, measurement: &str) -> Self {
+        self.measurement = measurement.to_owned();
+        self
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+}
+
+impl<W: Write> ExportSink for LineProtocolSink<W> {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let tags = context.tags();
+
+        // Line protocol has no way to write a missing value.
+        for reading in readings.iter().filter(|reading| !reading.value.is_nan()) {
+            let mut measurement = Measurement::new(&self.measurement, reading.start, tags.clone());
+            measurement.add_field(
+                field_for_classifier(&context.resource.classifier),
+                round(reading.value as f64, self.precision),
+            );
+            writeln!(self.out, "{}", measurement)?;
+        }
+
+        self.out.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Replaces characters Graphite treats specially in a path component.
+fn graphite_component(component: &str) -> String {
+    component
+        .chars()
+        .map(|c| match c {
+            c if c.is_whitespace() => '_',
+            '.' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Writes readings in Graphite's plaintext protocol, one
+/// `<prefix>.<classifier>.<resource id> <value> <timestamp>` line per reading.
+///
+/// Classifiers like `electricity.consumption` become two levels of the path.
+/// Writing to a [`TcpStream`](std::net::TcpStream) connected to a carbon-cache
+/// sends the readings straight to Graphite.
+pub struct GraphiteSink<W: Write> {
+    out: W,
+    prefix: String,
+    precision: Option<u32>,
+}
+
+impl<W: Write> GraphiteSink<W> {
+    /// Creates a sink writing paths under `glowmarkt`.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            prefix: "glowmarkt".to_string(),
+            precision: None,
+        }
+    }
+
+    /// Sets the prefix of every path, which may contain several levels.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    fn path(&self, resource: &Resource) -> String {
+        let classifier = match resource.classifier {
+            Some(ref classifier) => classifier
+                .as_str()
+                .split('.')
+                .map(graphite_component)
+                .collect::<Vec<_>>()
+                .join("."),
+            None => "unclassified".to_string(),
+        };
+
+        format!(
+            "{}.{}.{}",
+            self.prefix,
+            classifier,
+            graphite_component(&resource.id)
+        )
+    }
+}
+
+impl<W: Write> ExportSink for GraphiteSink<W> {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let path = self.path(&context.resource);
+
+        // Graphite has no way to write a missing value.
+        for reading in readings.iter().filter(|reading| !reading.value.is_nan()) {
+            writeln!(
+                self.out,
+                "{} {} {}",
+                path,
+                round(reading.value as f64, self.precision),
+                reading.start.unix_timestamp()
+            )?;
+        }
+
+        self.out.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes readings as CSV rows of `resource,timestamp,value,unit,classifier`,
+/// with a header before the first row. The resource column holds the
+/// resource's ID.
+pub struct CsvSink<W: Write> {
+    out: W,
+    header: bool,
+    delimiter: char,
+    decimal_comma: bool,
+    precision: Option<u32>,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Creates a sink using a comma as the delimiter.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            header: false,
+            delimiter: ',',
+            decimal_comma: false,
+            precision: None,
+        }
+    }
+
+    /// Sets the character separating fields. Defaults to a comma.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Writes values with a comma as the decimal separator.
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> Self {
+        self.decimal_comma = decimal_comma;
+        self
+    }
+
+    /// Sets the number of decimal places written for values.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+}
+
+impl<W: Write> ExportSink for CsvSink<W> {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let mut writer = CsvWriter::new(
+            &mut self.out,
+            context.resource.unit().as_ref().map(Unit::as_str),
+            context.resource.classifier.as_ref().map(Classifier::as_str),
+        )
+        .resource(Some(&context.resource.id))
+        .delimiter(self.delimiter)
+        .decimal_comma(self.decimal_comma)
+        .precision(self.precision);
+
+        if !self.header {
+            writer.write_header()?;
+            self.header = true;
+        }
+
+        for reading in readings {
+            writer.write(reading)?;
+        }
+
+        writer.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonRow<'a> {
+    resource_id: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'a Unit>,
+}
+
+/// Writes readings as newline delimited JSON objects with the resource ID,
+/// start, value and unit. Missing values are written as `null`.
+pub struct JsonSink<W: Write> {
+    out: W,
+    precision: Option<u32>,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Creates a sink.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            precision: None,
+        }
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+}
+
+impl<W: Write> ExportSink for JsonSink<W> {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let unit = context.resource.unit();
+        for reading in readings {
+            serde_json::to_writer(
+                &mut self.out,
+                &JsonRow {
+                    resource_id: &context.resource.id,
+                    start: reading.start,
+                    value: round(reading.value as f64, self.precision),
+                    unit: unit.as_ref(),
+                },
+            )?;
+            writeln!(self.out)?;
+        }
+
+        self.out.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
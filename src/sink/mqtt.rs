@@ -0,0 +1,307 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use super::{ExportSink, ResourceContext};
+use crate::{
+    format::round,
+    homeassistant::{
+        acknowledged_publish_packet, check_connack, check_puback, connect_packet,
+        disconnect_packet, publish_packet, ResourceState, Topics,
+    },
+    unit::Unit,
+    Reading, Resource,
+};
+
+/// The size the buffer can grow to before it is sent without waiting for a
+/// flush.
+const BATCH_BYTES: usize = 64 * 1024;
+
+/// How long to wait on the broker by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReadingMessage<'a> {
+    resource_id: &'a str,
+    #[serde(with = "time::serde::rfc3339")]
+    start: OffsetDateTime,
+    value: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quality: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'a Unit>,
+}
+
+/// Publishes each reading as a retained JSON message to an MQTT broker over
+/// TCP, normally port 1883.
+///
+/// Readings are published in order to the resource's
+/// [`Topics::reading`] topic so the retained message is always the newest
+/// reading. Missing values are published as `null`.
+///
+/// It can also publish a resource's [`ResourceState`] along with the Home
+/// Assistant discovery messages for it.
+///
+/// Messages are buffered and sent when the buffer fills or the sink is
+/// flushed. The connection is made when messages are first sent. Messages are
+/// published at QoS 0 unless [`acknowledged`](MqttSink::acknowledged) is set.
+///
+/// The sink blocks while sending, for at most the
+/// [`timeout`](MqttSink::timeout) on each step of connecting, writing and
+/// waiting for the broker to answer.
+pub struct MqttSink {
+    address: String,
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    topics: Topics,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    precision: Option<u32>,
+    acknowledged: bool,
+    timeout: Duration,
+    next_packet_id: u16,
+    unacknowledged: VecDeque<u16>,
+}
+
+impl MqttSink {
+    /// Creates a sink publishing to the broker at the given `host:port`.
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_owned(),
+            client_id: "glowmarkt".to_string(),
+            username: None,
+            password: None,
+            topics: Topics::default(),
+            stream: None,
+            buffer: Vec::new(),
+            precision: None,
+            acknowledged: false,
+            timeout: DEFAULT_TIMEOUT,
+            next_packet_id: 1,
+            unacknowledged: VecDeque::new(),
+        }
+    }
+
+    /// Sets the client ID to connect to the broker with.
+    pub fn client_id(mut self, client_id: &str) -> Self {
+        self.client_id = client_id.to_owned();
+        self
+    }
+
+    /// Sets the username and password to connect to the broker with.
+    pub fn credentials(mut self, username: Option<String>, password: Option<String>) -> Self {
+        self.username = username;
+        self.password = password;
+        self
+    }
+
+    /// Sets the topics readings are published to.
+    pub fn topics(mut self, topics: Topics) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Publishes at QoS 1 and waits for the broker to acknowledge every
+    /// message when sending, so a successful flush means the broker has the
+    /// readings. Slower, as each send waits on the broker.
+    pub fn acknowledged(mut self, acknowledged: bool) -> Self {
+        self.acknowledged = acknowledged;
+        self
+    }
+
+    /// Sets how long to wait when connecting to, writing to or reading from
+    /// the broker before failing. Defaults to 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Publishes the Home Assistant discovery messages for a resource, see
+    /// [`Topics::discovery`].
+    pub fn publish_discovery(&mut self, resource: &Resource, meter_read: bool) -> io::Result<()> {
+        for (topic, config) in self.topics.discovery(resource, meter_read) {
+            self.publish(&topic, &config)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes a resource's state to its [`Topics::state`] topic.
+    pub fn publish_state(&mut self, resource: &Resource, state: &ResourceState) -> io::Result<()> {
+        let topic = self.topics.state(resource);
+        self.publish(&topic, &serde_json::to_string(state)?)
+    }
+
+    /// Buffers a message, sending the buffer if it has filled.
+    fn publish(&mut self, topic: &str, payload: &str) -> io::Result<()> {
+        let packet = self.packet(topic, payload);
+        self.buffer.extend_from_slice(&packet);
+
+        if self.buffer.len() >= BATCH_BYTES {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn packet(&mut self, topic: &str, payload: &str) -> Vec<u8> {
+        if !self.acknowledged {
+            return publish_packet(topic, payload);
+        }
+
+        let packet_id = self.next_packet_id;
+        // Packet IDs must not be zero.
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        self.unacknowledged.push_back(packet_id);
+        acknowledged_publish_packet(topic, payload, packet_id)
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_error = None;
+        let mut stream = None;
+        for address in self.address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => {
+                return Err(last_error.unwrap_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} did not resolve to an address", self.address),
+                    )
+                }))
+            }
+        };
+
+        // A broker that stops answering fails the send rather than blocking
+        // forever.
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        stream.set_nodelay(true)?;
+        stream.write_all(&connect_packet(
+            &self.client_id,
+            self.username.as_deref(),
+            self.password.as_deref(),
+        ))?;
+
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack)?;
+        check_connack(&connack)?;
+        Ok(stream)
+    }
+
+    /// Describes a timeout, which is reported as `WouldBlock` on some
+    /// platforms, as such.
+    fn timed_out(&self, e: io::Error) -> io::Error {
+        match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "{} did not answer within {} seconds",
+                    self.address,
+                    self.timeout.as_secs()
+                ),
+            ),
+            _ => e,
+        }
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => {
+                let stream = self.connect().map_err(|e| self.timed_out(e))?;
+                self.stream.insert(stream)
+            }
+        };
+
+        let mut sent = stream.write_all(&self.buffer).and_then(|_| stream.flush());
+        // Brokers acknowledge QoS 1 messages in the order they were sent.
+        for packet_id in &self.unacknowledged {
+            if sent.is_err() {
+                break;
+            }
+            let mut puback = [0; 4];
+            sent = stream
+                .read_exact(&mut puback)
+                .and_then(|_| check_puback(&puback, *packet_id));
+        }
+
+        // Everything is sent again on a new connection after a failure.
+        if let Err(e) = sent {
+            self.stream = None;
+            return Err(self.timed_out(e));
+        }
+
+        self.buffer.clear();
+        self.unacknowledged.clear();
+        Ok(())
+    }
+}
+
+impl ExportSink for MqttSink {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let topic = self.topics.reading(&context.resource);
+        let unit = context.resource.unit();
+
+        for reading in readings {
+            let payload = serde_json::to_string(&ReadingMessage {
+                resource_id: &context.resource.id,
+                start: reading.start,
+                value: round(reading.value as f64, self.precision),
+                quality: reading.quality.as_ref(),
+                unit: unit.as_ref(),
+            })?;
+            self.publish(&topic, &payload)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+impl Drop for MqttSink {
+    fn drop(&mut self) {
+        if let Err(e) = self.send() {
+            log::warn!("Failed to publish readings to {}: {}", self.address, e);
+        }
+
+        if let Some(ref mut stream) = self.stream {
+            let _ = stream.write_all(&disconnect_packet());
+        }
+    }
+}
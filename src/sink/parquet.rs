@@ -0,0 +1,205 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fs::{self, File},
+    io,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampSecondArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{
+    arrow::ArrowWriter, basic::Compression, errors::ParquetError,
+    file::properties::WriterProperties,
+};
+use time::UtcOffset;
+
+use super::{ExportSink, ResourceContext};
+use crate::{classifier::Classifier, format::round, unit::Unit, Reading};
+
+fn parquet_error(e: ParquetError) -> io::Error {
+    io::Error::other(e)
+}
+
+/// How readings are divided between Parquet files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParquetSplit {
+    /// One file for each resource, named `<resource id>.parquet`.
+    #[default]
+    Resource,
+    /// One file for each resource and calendar month (in UTC), named
+    /// `<resource id>-<yyyy>-<mm>.parquet`.
+    Month,
+}
+
+/// Writes readings to Parquet files in a directory.
+///
+/// Every row holds the reading's timestamp and value along with the unit,
+/// classifier and the resource and device it was recorded by. Files are
+/// written with a footer only when the sink is flushed, writing to the same
+/// file after a flush replaces it.
+pub struct ParquetSink {
+    dir: PathBuf,
+    split: ParquetSplit,
+    precision: Option<u32>,
+    schema: SchemaRef,
+    writers: BTreeMap<PathBuf, ArrowWriter<File>>,
+}
+
+impl ParquetSink {
+    /// Creates a sink writing files to a directory, created if necessary.
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        let utc = Some(Arc::from("UTC"));
+        let schema = Schema::new(vec![
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Second, utc),
+                false,
+            ),
+            Field::new("value", DataType::Float64, false),
+            Field::new("unit", DataType::Utf8, true),
+            Field::new("classifier", DataType::Utf8, true),
+            Field::new("resource_id", DataType::Utf8, false),
+            Field::new("resource", DataType::Utf8, false),
+            Field::new("device_id", DataType::Utf8, true),
+            Field::new("device", DataType::Utf8, true),
+            Field::new("device_type_id", DataType::Utf8, true),
+            Field::new("hardware_id", DataType::Utf8, true),
+        ]);
+
+        Self {
+            dir: dir.into(),
+            split: Default::default(),
+            precision: None,
+            schema: Arc::new(schema),
+            writers: BTreeMap::new(),
+        }
+    }
+
+    /// Sets how readings are divided between files.
+    pub fn split(mut self, split: ParquetSplit) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    fn path(&self, resource_id: &str, reading: &Reading) -> PathBuf {
+        let start = reading.start.to_offset(UtcOffset::UTC);
+        let name = match self.split {
+            ParquetSplit::Resource => format!("{}.parquet", resource_id),
+            ParquetSplit::Month => format!(
+                "{}-{:04}-{:02}.parquet",
+                resource_id,
+                start.year(),
+                start.month() as u8
+            ),
+        };
+
+        self.dir.join(name)
+    }
+
+    fn batch(&self, context: &ResourceContext, readings: &[Reading]) -> io::Result<RecordBatch> {
+        let resource = &context.resource;
+        let device = context.device.as_ref();
+        let unit = resource.unit();
+        let repeat = |value: Option<&str>| -> ArrayRef {
+            Arc::new(StringArray::from(vec![value; readings.len()]))
+        };
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(
+                TimestampSecondArray::from(
+                    readings
+                        .iter()
+                        .map(|reading| reading.start.unix_timestamp())
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(Float64Array::from(
+                readings
+                    .iter()
+                    .map(|reading| round(reading.value as f64, self.precision))
+                    .collect::<Vec<_>>(),
+            )),
+            repeat(unit.as_ref().map(Unit::as_str)),
+            repeat(resource.classifier.as_ref().map(Classifier::as_str)),
+            repeat(Some(&resource.id)),
+            repeat(Some(&resource.name)),
+            repeat(device.map(|device| device.id.as_str())),
+            repeat(device.and_then(|device| device.description.as_deref())),
+            repeat(device.map(|device| device.device_type_id.as_str())),
+            repeat(device.map(|device| device.hardware_id.as_str())),
+        ];
+
+        RecordBatch::try_new(self.schema.clone(), columns).map_err(io::Error::other)
+    }
+
+    fn write_file(
+        &mut self,
+        path: PathBuf,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let batch = self.batch(context, readings)?;
+
+        let writer = match self.writers.entry(path) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                fs::create_dir_all(&self.dir)?;
+                let properties = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                let file = File::create(entry.key())?;
+                entry.insert(
+                    ArrowWriter::try_new(file, self.schema.clone(), Some(properties))
+                        .map_err(parquet_error)?,
+                )
+            }
+        };
+
+        writer.write(&batch).map_err(parquet_error)
+    }
+}
+
+impl ExportSink for ParquetSink {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        // Readings are in order so each file's readings are contiguous.
+        let mut rest = readings;
+        while let Some(first) = rest.first() {
+            let path = self.path(&context.resource.id, first);
+            let count = rest
+                .iter()
+                .take_while(|reading| self.path(&context.resource.id, reading) == path)
+                .count();
+
+            let (readings, remaining) = rest.split_at(count);
+            self.write_file(path, context, readings)?;
+            rest = remaining;
+        }
+
+        Ok(())
+    }
+
+    /// Completes every file written so far.
+    fn flush(&mut self) -> io::Result<()> {
+        for (_, writer) in std::mem::take(&mut self.writers) {
+            writer.close().map_err(parquet_error)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use native_tls::TlsConnector;
+use postgres_native_tls::MakeTlsConnector;
+use time::OffsetDateTime;
+use tokio_postgres::Client;
+
+use super::ResourceContext;
+use crate::{classifier::Classifier, format::round, unit::Unit, Error, ErrorKind, Reading};
+
+fn storage_error(e: tokio_postgres::Error) -> Error {
+    Error::new(ErrorKind::Storage, e.to_string())
+}
+
+/// Quotes a possibly schema qualified table name for use in SQL.
+fn quote_table(table: &str) -> String {
+    table
+        .split('.')
+        .map(|part| format!("\"{}\"", part.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Writes readings to a PostgreSQL table, one row per reading.
+///
+/// The table has `timestamp`, `resource_id`, `classifier`, `value` and `unit`
+/// columns and is created if it doesn't already exist. When the TimescaleDB
+/// extension is installed a newly created table is turned into a hypertable
+/// partitioned on `timestamp`. Rows are keyed by resource and timestamp so
+/// writing readings that are already present replaces them, making it safe to
+/// export overlapping ranges.
+///
+/// Unlike the other sinks this writes asynchronously, each batch of readings
+/// is upserted as a single statement.
+pub struct PostgresSink {
+    client: Client,
+    table: String,
+    precision: Option<u32>,
+}
+
+impl PostgresSink {
+    /// Connects to the database described by a connection string, either a
+    /// `postgresql://` URL or `key=value` pairs, and creates the table.
+    ///
+    /// TLS is used as the connection string's `sslmode` requests.
+    pub async fn connect(dsn: &str, table: &str) -> Result<Self, Error> {
+        let connector =
+            TlsConnector::new().map_err(|e| Error::new(ErrorKind::Storage, e.to_string()))?;
+        let (client, connection) = tokio_postgres::connect(dsn, MakeTlsConnector::new(connector))
+            .await
+            .map_err(storage_error)?;
+
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                log::error!("PostgreSQL connection failed: {}", e);
+            }
+        });
+
+        let sink = Self {
+            client,
+            table: quote_table(table),
+            precision: None,
+        };
+        sink.create_table().await.map_err(storage_error)?;
+
+        Ok(sink)
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    async fn create_table(&self) -> Result<(), tokio_postgres::Error> {
+        self.client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    timestamp TIMESTAMPTZ NOT NULL,
+                    resource_id TEXT NOT NULL,
+                    classifier TEXT,
+                    value DOUBLE PRECISION NOT NULL,
+                    unit TEXT,
+                    PRIMARY KEY (resource_id, timestamp)
+                )",
+                self.table
+            ))
+            .await?;
+
+        let timescale = self
+            .client
+            .query_opt(
+                "SELECT 1 FROM pg_extension WHERE extname = 'timescaledb'",
+                &[],
+            )
+            .await?
+            .is_some();
+        if timescale {
+            self.client
+                .execute(
+                    "SELECT create_hypertable($1::text::regclass, 'timestamp', \
+                    if_not_exists => TRUE, migrate_data => TRUE)",
+                    &[&self.table],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Inserts readings for a resource, replacing any already stored for the
+    /// same timestamps.
+    pub async fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> Result<(), Error> {
+        if readings.is_empty() {
+            return Ok(());
+        }
+
+        let resource = &context.resource;
+        let classifier = resource.classifier.as_ref().map(Classifier::as_str);
+        let unit = resource.unit();
+        let unit = unit.as_ref().map(Unit::as_str);
+
+        let timestamps: Vec<OffsetDateTime> =
+            readings.iter().map(|reading| reading.start).collect();
+        let values: Vec<f64> = readings
+            .iter()
+            .map(|reading| round(reading.value as f64, self.precision))
+            .collect();
+
+        self.client
+            .execute(
+                &format!(
+                    "INSERT INTO {} (timestamp, resource_id, classifier, value, unit)
+                    SELECT timestamp, $1, $2, value, $3
+                    FROM UNNEST($4::timestamptz[], $5::float8[]) AS r (timestamp, value)
+                    ON CONFLICT (resource_id, timestamp) DO UPDATE SET
+                        classifier = EXCLUDED.classifier,
+                        value = EXCLUDED.value,
+                        unit = EXCLUDED.unit",
+                    self.table
+                ),
+                &[&resource.id, &classifier, &unit, &timestamps, &values],
+            )
+            .await
+            .map_err(storage_error)?;
+
+        Ok(())
+    }
+}
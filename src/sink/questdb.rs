@@ -0,0 +1,144 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    net::TcpStream,
+};
+
+use super::{field_for_classifier, ExportSink, Measurement, ResourceContext};
+use crate::{format::round, Reading};
+
+/// The size the buffer can grow to before it is sent without waiting for a
+/// flush.
+const BATCH_BYTES: usize = 64 * 1024;
+
+/// Streams readings to QuestDB's InfluxDB line protocol endpoint over TCP,
+/// normally port 9009.
+///
+/// Points are buffered and sent when the buffer fills or the sink is flushed.
+/// If the connection has dropped it is reopened and the buffer sent again, so
+/// when a connection fails part way through a send some points may arrive
+/// twice. Enabling deduplication on the table with the timestamp and
+/// `resource_id` as keys discards the repeats.
+///
+/// QuestDB doesn't allow `-` in column names so the tags other sinks use are
+/// written with underscores instead, `resource-id` becomes `resource_id`.
+pub struct QuestDbSink {
+    address: String,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    table: String,
+    precision: Option<u32>,
+    attempts: u32,
+}
+
+impl QuestDbSink {
+    /// Creates a sink sending to the given `host:port`, writing to the
+    /// `glowmarkt` table. The connection is made when points are first sent.
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_owned(),
+            stream: None,
+            buffer: Vec::new(),
+            table: "glowmarkt".to_string(),
+            precision: None,
+            attempts: 3,
+        }
+    }
+
+    /// Sets the table points are written to.
+    pub fn table(mut self, table: &str) -> Self {
+        self.table = table.to_owned();
+        self
+    }
+
+    /// Sets the number of decimal places values are rounded to.
+    pub fn precision(mut self, precision: Option<u32>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets the number of connections tried before giving up on a send.
+    /// Defaults to 3.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    fn try_send(&mut self) -> io::Result<()> {
+        let stream = match self.stream {
+            Some(ref mut stream) => stream,
+            None => {
+                let stream = TcpStream::connect(&self.address)?;
+                stream.set_nodelay(true)?;
+                self.stream.insert(stream)
+            }
+        };
+
+        stream.write_all(&self.buffer)?;
+        stream.flush()
+    }
+
+    fn send(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.try_send() {
+                Ok(()) => {
+                    self.buffer.clear();
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.stream = None;
+                    if attempt >= self.attempts {
+                        return Err(e);
+                    }
+
+                    log::warn!(
+                        "Failed to send to QuestDB at {}, reconnecting: {}",
+                        self.address,
+                        e
+                    );
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+impl ExportSink for QuestDbSink {
+    fn write_reading(&mut self, context: &ResourceContext, reading: &Reading) -> io::Result<()> {
+        self.write_readings(context, std::slice::from_ref(reading))
+    }
+
+    fn write_readings(
+        &mut self,
+        context: &ResourceContext,
+        readings: &[Reading],
+    ) -> io::Result<()> {
+        let tags: BTreeMap<String, String> = context
+            .tags()
+            .into_iter()
+            .map(|(key, value)| (key.replace('-', "_"), value))
+            .collect();
+        let field = field_for_classifier(&context.resource.classifier);
+
+        // Line protocol has no way to write a missing value.
+        for reading in readings.iter().filter(|reading| !reading.value.is_nan()) {
+            let mut measurement = Measurement::new(&self.table, reading.start, tags.clone());
+            measurement.add_field(field, round(reading.value as f64, self.precision));
+            writeln!(self.buffer, "{}", measurement)?;
+        }
+
+        if self.buffer.len() >= BATCH_BYTES {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
@@ -0,0 +1,119 @@
+//! Progress kept between runs so that long running exports can resume rather
+//! than starting again.
+//!
+//! State is stored as JSON, written to a temporary file and renamed into place
+//! so that a crash part way through never leaves a truncated file behind.
+
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::ReadingPeriod;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::ErrorStr;
+
+/// Resolves a file within the `glowmarkt` directory of an XDG base directory,
+/// falling back to the given path under `$HOME` if the variable is unset.
+pub fn xdg_path(var: &str, fallback: &[&str], file: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => fallback
+            .iter()
+            .fold(PathBuf::from(env::var_os("HOME")?), |path, part| {
+                path.join(part)
+            }),
+    };
+
+    Some(base.join("glowmarkt").join(file))
+}
+
+/// Loads state from a JSON file, a missing file gives the default state.
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Default::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// Saves state to a JSON file, creating its directory if necessary.
+pub fn save<T: Serialize>(path: &Path, state: &T) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(OsStr::new(".tmp"));
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, serde_json::to_string_pretty(state).str_err()?)
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// How far a resource has been synced at one reading period.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub struct Watermark {
+    /// The start of the newest reading written.
+    #[serde(with = "time::serde::rfc3339")]
+    pub latest: OffsetDateTime,
+    /// When the watermark was last moved.
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated: OffsetDateTime,
+}
+
+/// The high-water marks for every resource, keyed by resource ID and then by
+/// reading period.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    #[serde(default)]
+    resources: HashMap<String, HashMap<String, Watermark>>,
+}
+
+impl SyncState {
+    /// The default location of the sync state, `$XDG_DATA_HOME/glowmarkt/state.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        xdg_path("XDG_DATA_HOME", &[".local", "share"], "state.json")
+    }
+
+    pub fn watermark(&self, resource: &str, period: ReadingPeriod) -> Option<Watermark> {
+        self.resources
+            .get(resource)
+            .and_then(|periods| periods.get(&period.to_string()))
+            .copied()
+    }
+
+    pub fn set_watermark(
+        &mut self,
+        resource: &str,
+        period: ReadingPeriod,
+        latest: OffsetDateTime,
+        updated: OffsetDateTime,
+    ) {
+        self.resources
+            .entry(resource.to_owned())
+            .or_default()
+            .insert(period.to_string(), Watermark { latest, updated });
+    }
+
+    /// Forgets how far a resource has been synced at a reading period.
+    pub fn reset(&mut self, resource: &str, period: ReadingPeriod) {
+        if let Some(periods) = self.resources.get_mut(resource) {
+            periods.remove(&period.to_string());
+            if periods.is_empty() {
+                self.resources.remove(resource);
+            }
+        }
+    }
+}
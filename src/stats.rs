@@ -0,0 +1,246 @@
+//! Summary statistics for a range of readings.
+//!
+//! Readings are grouped into UK local days, so a day with a clock change has
+//! 46 or 50 half-hourly readings rather than 48.
+
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+use time::{util::days_in_year_month, Date, OffsetDateTime};
+
+use crate::{settlement::uk_offset, Reading};
+
+fn serialize_date<S: Serializer>(date: &Date, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(date)
+}
+
+/// The usage for a single UK local day.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DayStats {
+    /// The day.
+    #[serde(serialize_with = "serialize_date")]
+    pub date: Date,
+    /// The total usage for the day.
+    pub total: f64,
+    /// The smallest single reading.
+    pub min: f64,
+    /// The largest single reading.
+    pub max: f64,
+    /// The number of readings the day had.
+    pub readings: usize,
+}
+
+/// The largest single reading in a range.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Peak {
+    /// The start of the reading.
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The reading's value.
+    pub value: f64,
+}
+
+/// Totals and daily statistics for a range of readings.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    /// The total usage across every reading.
+    pub total: f64,
+    /// The number of readings.
+    pub readings: usize,
+    /// The day with the lowest total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_day: Option<DayStats>,
+    /// The day with the highest total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_day: Option<DayStats>,
+    /// The average daily total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_average: Option<f64>,
+    /// The largest single reading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak: Option<Peak>,
+    /// Each day in order.
+    pub days: Vec<DayStats>,
+}
+
+/// Groups readings by the UK local day they start in and summarises each day.
+pub fn daily(readings: &[Reading]) -> Vec<DayStats> {
+    let mut days: BTreeMap<Date, DayStats> = BTreeMap::new();
+
+    for reading in readings {
+        let date = reading.start.to_offset(uk_offset(reading.start)).date();
+        let value = reading.value as f64;
+        let day = days.entry(date).or_insert(DayStats {
+            date,
+            total: 0.0,
+            min: value,
+            max: value,
+            readings: 0,
+        });
+
+        day.total += value;
+        day.min = day.min.min(value);
+        day.max = day.max.max(value);
+        day.readings += 1;
+    }
+
+    days.into_values().collect()
+}
+
+/// Summarises a range of readings.
+pub fn summarise(readings: &[Reading]) -> Summary {
+    let days = daily(readings);
+    let total = readings.iter().map(|reading| reading.value as f64).sum();
+
+    let min_day = days
+        .iter()
+        .min_by(|a, b| a.total.total_cmp(&b.total))
+        .cloned();
+    let max_day = days
+        .iter()
+        .max_by(|a, b| a.total.total_cmp(&b.total))
+        .cloned();
+    let daily_average = (!days.is_empty())
+        .then(|| days.iter().map(|day| day.total).sum::<f64>() / days.len() as f64);
+
+    // The earliest of equal readings is the peak.
+    let peak = readings
+        .iter()
+        .rev()
+        .max_by(|a, b| a.value.total_cmp(&b.value))
+        .map(|reading| Peak {
+            start: reading.start,
+            value: reading.value as f64,
+        });
+
+    Summary {
+        total,
+        readings: readings.len(),
+        min_day,
+        max_day,
+        daily_average,
+        peak,
+        days,
+    }
+}
+
+/// Days with fewer half-hourly readings than this are assumed to be
+/// incomplete. Days with a clock change have 46.
+const COMPLETE_DAY_READINGS: usize = 46;
+
+/// The fewest complete days needed to scale usage for the time of year.
+/// Fewer days are extrapolated linearly.
+pub const MIN_SEASONAL_DAYS: usize = 28;
+
+/// Typical percentages of a UK home's annual usage in each month, from
+/// January.
+const ELECTRICITY_PROFILE: [f64; 12] =
+    [10.0, 8.9, 9.0, 7.9, 7.4, 6.8, 7.0, 7.2, 7.4, 8.5, 9.4, 10.5];
+const GAS_PROFILE: [f64; 12] = [
+    15.5, 13.5, 12.0, 8.5, 5.5, 3.5, 3.0, 3.0, 4.0, 7.5, 11.0, 13.0,
+];
+
+/// How usage varies through the year.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Seasonality {
+    /// Electricity use, which rises moderately in winter.
+    Electricity,
+    /// Gas use, dominated by heating in winter.
+    Gas,
+}
+
+impl Seasonality {
+    /// The seasonality of a fuel, as given by [`Classifier::fuel`].
+    ///
+    /// [`Classifier::fuel`]: crate::classifier::Classifier::fuel
+    pub fn for_fuel(fuel: &str) -> Seasonality {
+        match fuel {
+            "gas" => Seasonality::Gas,
+            _ => Seasonality::Electricity,
+        }
+    }
+
+    /// The fraction of a year's usage expected on a day.
+    fn weight(&self, date: Date) -> f64 {
+        let profile = match self {
+            Seasonality::Electricity => &ELECTRICITY_PROFILE,
+            Seasonality::Gas => &GAS_PROFILE,
+        };
+        let month = date.month();
+        profile[month as usize - 1] / 100.0 / days_in_year_month(date.year(), month) as f64
+    }
+}
+
+/// How an annual projection was made.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProjectionMethod {
+    /// The usage of the most recent full year.
+    Actual,
+    /// Usage scaled by how much of a typical year's usage falls on the days
+    /// covered.
+    Seasonal,
+    /// The average daily usage over a year.
+    Linear,
+}
+
+/// A projection of a year's usage.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnualProjection {
+    /// The projected usage over a year.
+    pub total: f64,
+    /// The number of complete days the projection is based on.
+    pub days: usize,
+    /// How the projection was made.
+    pub method: ProjectionMethod,
+}
+
+/// Projects a year's usage from the complete days of half-hourly usage
+/// available.
+///
+/// With a full year of days the most recent year is used as is. Otherwise
+/// the usage is scaled for the time of year the days fall in if there are at
+/// least [`MIN_SEASONAL_DAYS`] of them, or extrapolated linearly if not.
+/// Returns `None` if there are no complete days.
+pub fn project_annual(days: &[DayStats], seasonality: Seasonality) -> Option<AnnualProjection> {
+    let complete: Vec<&DayStats> = days
+        .iter()
+        .filter(|day| day.readings >= COMPLETE_DAY_READINGS)
+        .collect();
+
+    if complete.len() >= 365 {
+        return Some(AnnualProjection {
+            total: complete.iter().rev().take(365).map(|day| day.total).sum(),
+            days: 365,
+            method: ProjectionMethod::Actual,
+        });
+    }
+
+    if complete.is_empty() {
+        return None;
+    }
+
+    let total: f64 = complete.iter().map(|day| day.total).sum();
+    if complete.len() >= MIN_SEASONAL_DAYS {
+        let share: f64 = complete
+            .iter()
+            .map(|day| seasonality.weight(day.date))
+            .sum();
+        return Some(AnnualProjection {
+            total: total / share,
+            days: complete.len(),
+            method: ProjectionMethod::Seasonal,
+        });
+    }
+
+    Some(AnnualProjection {
+        total: total / complete.len() as f64 * 365.0,
+        days: complete.len(),
+        method: ProjectionMethod::Linear,
+    })
+}
@@ -0,0 +1,170 @@
+//! A quick summary of a resource's usage over a range.
+
+use std::io::{stdout, Write};
+
+use glowmarkt::{
+    format::round,
+    settlement::uk_offset,
+    stats::{summarise, DayStats, Summary},
+    unit::Unit,
+    GlowmarktApi, ReadingPeriod,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+use time::macros::format_description;
+
+use crate::{
+    hint::CliError,
+    lookup::select_resources,
+    output::{OutputOptions, ReportFormat},
+    parse_date, parse_end_date, ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct SummaryArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// The resource to summarise, either its ID, its classifier such as
+    /// `electricity.consumption` or part of its name.
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    resource_id: String,
+    resource: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<Unit>,
+    #[serde(flatten)]
+    summary: Summary,
+}
+
+fn round_day(day: &mut DayStats, precision: Option<u32>) {
+    day.total = round(day.total, precision);
+    day.min = round(day.min, precision);
+    day.max = round(day.max, precision);
+}
+
+fn print_text(report: &Report) -> Result<(), CliError> {
+    let summary = &report.summary;
+    let unit = report
+        .unit
+        .as_ref()
+        .map(|unit| format!(" {}", unit.as_str()))
+        .unwrap_or_default();
+    let hour = format_description!("[year]-[month]-[day] [hour]:[minute]");
+
+    let mut out = stdout().lock();
+    writeln!(out, "{} ({})", report.resource, report.resource_id).str_err()?;
+    writeln!(
+        out,
+        "Total: {}{} over {} days",
+        summary.total,
+        unit,
+        summary.days.len()
+    )
+    .str_err()?;
+    if let Some(average) = summary.daily_average {
+        writeln!(out, "Daily average: {}{}", average, unit).str_err()?;
+    }
+    if let Some(ref day) = summary.min_day {
+        writeln!(out, "Lowest day: {} with {}{}", day.date, day.total, unit).str_err()?;
+    }
+    if let Some(ref day) = summary.max_day {
+        writeln!(out, "Highest day: {} with {}{}", day.date, day.total, unit).str_err()?;
+    }
+    if let Some(ref peak) = summary.peak {
+        let start = peak.start.to_offset(uk_offset(peak.start));
+        writeln!(
+            out,
+            "Peak half hour: {} with {}{}",
+            start.format(&hour).str_err()?,
+            peak.value,
+            unit
+        )
+        .str_err()?;
+    }
+
+    if !summary.days.is_empty() {
+        writeln!(out).str_err()?;
+        writeln!(
+            out,
+            "{:<10}  {:>10}  {:>10}  {:>10}",
+            "Date", "Total", "Min", "Max"
+        )
+        .str_err()?;
+        for day in &summary.days {
+            writeln!(
+                out,
+                "{:<10}  {:>10}  {:>10}  {:>10}",
+                day.date.to_string(),
+                day.total,
+                day.min,
+                day.max
+            )
+            .str_err()?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn summary(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: SummaryArgs,
+) -> Result<(), CliError> {
+    let period = ReadingPeriod::HalfHour;
+    let start = parse_date(args.from, period, &api)?;
+    let end = parse_end_date(args.to, period, &api)?;
+    let resource = select_resources(&api, &[args.resource]).await?.remove(0);
+
+    let readings = api
+        .readings_range(&resource.id, &start, &end, period)
+        .await?;
+
+    // Long fractions make the text report hard to read.
+    let precision = match args.format {
+        ReportFormat::Text => options.precision.or(Some(3)),
+        ReportFormat::Json => options.precision,
+    };
+    let mut summary = summarise(&readings);
+    summary.total = round(summary.total, precision);
+    summary.daily_average = summary
+        .daily_average
+        .map(|average| round(average, precision));
+    for day in summary
+        .min_day
+        .iter_mut()
+        .chain(summary.max_day.iter_mut())
+        .chain(summary.days.iter_mut())
+    {
+        round_day(day, precision);
+    }
+    if let Some(ref mut peak) = summary.peak {
+        peak.value = round(peak.value, precision);
+    }
+
+    let report = Report {
+        unit: resource.unit(),
+        resource_id: resource.id,
+        resource: resource.name,
+        summary,
+    };
+
+    match args.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&report).str_err()?);
+            Ok(())
+        }
+    }
+}
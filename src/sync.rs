@@ -0,0 +1,155 @@
+//! Exports only the readings newer than those exported by previous runs.
+//!
+//! The newest reading written for each resource is recorded in the state store
+//! after every chunk so a long backfill that fails part way through resumes
+//! from the last chunk written rather than starting again.
+
+use std::path::PathBuf;
+
+use glowmarkt::{split_periods, transform::Transform, GlowmarktApi, ReadingPeriod};
+
+use crate::{
+    export::{sink, SinkKind, SinkOptions},
+    hint::CliError,
+    notify::{notify, NotifyOptions, Run},
+    output::{transform_resource, CsvOptions, OutputOptions, TransformOptions},
+    parse_date, parse_end_date, state,
+    state::SyncState,
+};
+
+#[derive(clap::Args)]
+pub struct SyncArgs {
+    /// Where to write readings to.
+    #[clap(long, value_enum, default_value = "influx")]
+    sink: SinkKind,
+    /// The resources to sync. If absent all resources are synced.
+    #[clap(long, use_value_delimiter = true)]
+    resources: Vec<String>,
+    /// The length of each reading (1m, 30m, 1h, 1d, 1w, 1mon or 1y).
+    #[clap(long, default_value = "30m")]
+    period: ReadingPeriod,
+    /// Start time of the first reading for resources that haven't been synced
+    /// before.
+    #[clap(long, allow_hyphen_values = true)]
+    from: Option<String>,
+    /// Ignore the stored watermarks and sync from --from again.
+    #[clap(long, requires = "from")]
+    reset: bool,
+    /// The file recording how far each resource has been synced, defaults to
+    /// `$XDG_DATA_HOME/glowmarkt/state.json`.
+    #[clap(long, env = "GLOWMARKT_SYNC_STATE")]
+    state: Option<PathBuf>,
+    #[clap(flatten)]
+    csv: CsvOptions,
+    #[clap(flatten)]
+    sinks: SinkOptions,
+    #[clap(flatten)]
+    transform: TransformOptions,
+    #[clap(flatten)]
+    notify: NotifyOptions,
+}
+
+pub async fn sync(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: SyncArgs,
+    transforms: &[String],
+) -> Result<(), CliError> {
+    let run = Run::start("sync", api.clock());
+    let mut points = 0;
+    let result = sync_readings(&api, options, &args, transforms, &mut points).await;
+
+    let summary = run.finish(points, 0, result.as_ref().err());
+    notify(&args.notify, &summary).await;
+
+    result
+}
+
+async fn sync_readings(
+    api: &GlowmarktApi,
+    options: OutputOptions,
+    args: &SyncArgs,
+    transforms: &[String],
+    points: &mut usize,
+) -> Result<(), CliError> {
+    let path = match args.state.clone().or_else(SyncState::default_path) {
+        Some(path) => path,
+        None => return Err("No state file location, pass --state".to_string().into()),
+    };
+    let mut state: SyncState = state::load(&path)?;
+
+    let pipeline = args.transform.pipeline(transforms)?;
+    let from = args
+        .from
+        .clone()
+        .map(|from| parse_date(from, args.period, api))
+        .transpose()?;
+    let end = parse_end_date(None, args.period, api)?;
+
+    let mut contexts = api.resource_contexts(&args.resources).await?;
+    for context in contexts.iter_mut() {
+        transform_resource(&pipeline, &mut context.resource);
+    }
+    if matches!(args.sink, SinkKind::Parquet) {
+        return Err("Parquet files can't be appended to, use export instead"
+            .to_string()
+            .into());
+    }
+    let mut sink = sink(args.sink, options, args.csv, &args.sinks).await?;
+
+    for context in &contexts {
+        let id = &context.resource.id;
+        if args.reset {
+            state.reset(id, args.period);
+        }
+
+        let watermark = state.watermark(id, args.period).map(|w| w.latest);
+        let start = match (watermark, from) {
+            // The newest reading is fetched again but filtered out below.
+            (Some(watermark), _) => watermark,
+            (None, Some(from)) => from,
+            (None, None) => {
+                return Err(format!(
+                    "Resource {} ({}) has not been synced at {} before, pass --from",
+                    context.resource.name, id, args.period
+                )
+                .into())
+            }
+        };
+        if start > end {
+            continue;
+        }
+
+        let ranges = split_periods(start, end, args.period);
+        let last_range = ranges.len() - 1;
+        for (index, (start, end)) in ranges.into_iter().enumerate() {
+            let mut readings = api.readings(id, &start, &end, args.period).await?;
+            readings.retain(|reading| watermark.map(|w| reading.start > w).unwrap_or(true));
+
+            // The most recent readings are zero until the DCC delivers them,
+            // leave them to be fetched again next time.
+            if index == last_range {
+                while readings.last().map(|r| r.value == 0.0).unwrap_or(false) {
+                    readings.pop();
+                }
+            }
+
+            let Some(latest) = readings.last().map(|reading| reading.start) else {
+                continue;
+            };
+
+            *points += readings.len();
+            sink.write_readings(context, &pipeline.apply(readings))
+                .await?;
+
+            state.set_watermark(id, args.period, latest, api.clock().now());
+            state::save(&path, &state)?;
+        }
+    }
+
+    sink.flush()?;
+    state::save(&path, &state)?;
+    log::info!("Synced {} new readings", points);
+
+    Ok(())
+}
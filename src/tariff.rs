@@ -0,0 +1,197 @@
+//! Typed access to tariff plans.
+//!
+//! The API describes each tariff plan as a list of loosely structured
+//! objects. These are parsed into [`PlanDetail`] values where the shape is
+//! recognised, anything else is preserved as [`PlanDetail::Other`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use time::{format_description::FormatItem, macros::format_description, Time};
+
+use crate::api::TariffData;
+
+const TIME_FORMAT: &[FormatItem<'static>] = format_description!("[hour]:[minute]");
+const TIME_FORMAT_SECONDS: &[FormatItem<'static>] = format_description!("[hour]:[minute]:[second]");
+
+/// A unit rate that only applies during part of the day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeOfUseRate {
+    /// The rate in pence per kWh.
+    pub rate: f64,
+    /// The time of day the rate starts to apply (UK local time).
+    pub start: Time,
+    /// The time of day the rate stops applying (UK local time).
+    pub end: Time,
+}
+
+impl TimeOfUseRate {
+    /// Whether this rate applies at the given time of day.
+    ///
+    /// Bands that wrap past midnight (e.g. 23:30 to 06:30) are supported.
+    pub fn applies_at(&self, time: Time) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// A unit rate that applies to a tier of consumption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierRate {
+    /// The rate in pence per kWh.
+    pub rate: f64,
+    /// The tier number.
+    pub tier: Option<u32>,
+    /// The consumption in kWh above which this tier applies.
+    pub threshold: Option<f64>,
+}
+
+/// A single component of a tariff plan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Map<String, Value>", into = "Map<String, Value>")]
+pub enum PlanDetail {
+    /// The daily standing charge in pence.
+    StandingCharge(f64),
+    /// A flat unit rate in pence per kWh.
+    UnitRate(f64),
+    /// A unit rate that applies during part of the day.
+    TimeOfUse(TimeOfUseRate),
+    /// A unit rate for a tier of consumption.
+    Tier(TierRate),
+    /// A component that wasn't recognised.
+    Other(Map<String, Value>),
+}
+
+fn number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses a time of day given as `HH:MM` or `HH:MM:SS`.
+pub(crate) fn parse_time(string: &str) -> Option<Time> {
+    Time::parse(string, TIME_FORMAT)
+        .or_else(|_| Time::parse(string, TIME_FORMAT_SECONDS))
+        .ok()
+}
+
+fn time(value: Option<&Value>) -> Option<Time> {
+    parse_time(value?.as_str()?)
+}
+
+fn first<'a>(map: &'a Map<String, Value>, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().find_map(|key| map.get(*key))
+}
+
+impl From<Map<String, Value>> for PlanDetail {
+    fn from(map: Map<String, Value>) -> PlanDetail {
+        if let Some(standing) = number(first(&map, &["standing", "standingCharge"])) {
+            return PlanDetail::StandingCharge(standing);
+        }
+
+        let rate = match number(first(&map, &["rate", "unitRate"])) {
+            Some(rate) => rate,
+            None => return PlanDetail::Other(map),
+        };
+
+        let start = time(first(&map, &["startTime", "start", "from"]));
+        let end = time(first(&map, &["endTime", "end", "to"]));
+        if let (Some(start), Some(end)) = (start, end) {
+            return PlanDetail::TimeOfUse(TimeOfUseRate { rate, start, end });
+        }
+
+        let tier = number(map.get("tier")).map(|t| t as u32);
+        let threshold = number(first(&map, &["threshold", "tierThreshold"]));
+        if tier.is_some() || threshold.is_some() {
+            return PlanDetail::Tier(TierRate {
+                rate,
+                tier,
+                threshold,
+            });
+        }
+
+        PlanDetail::UnitRate(rate)
+    }
+}
+
+impl From<PlanDetail> for Map<String, Value> {
+    fn from(detail: PlanDetail) -> Map<String, Value> {
+        let mut map = Map::new();
+        match detail {
+            PlanDetail::StandingCharge(standing) => {
+                map.insert("standing".to_string(), standing.into());
+            }
+            PlanDetail::UnitRate(rate) => {
+                map.insert("rate".to_string(), rate.into());
+            }
+            PlanDetail::TimeOfUse(band) => {
+                map.insert("rate".to_string(), band.rate.into());
+                map.insert(
+                    "startTime".to_string(),
+                    band.start.format(TIME_FORMAT).unwrap().into(),
+                );
+                map.insert(
+                    "endTime".to_string(),
+                    band.end.format(TIME_FORMAT).unwrap().into(),
+                );
+            }
+            PlanDetail::Tier(tier) => {
+                map.insert("rate".to_string(), tier.rate.into());
+                if let Some(number) = tier.tier {
+                    map.insert("tier".to_string(), number.into());
+                }
+                if let Some(threshold) = tier.threshold {
+                    map.insert("threshold".to_string(), threshold.into());
+                }
+            }
+            PlanDetail::Other(other) => return other,
+        }
+        map
+    }
+}
+
+impl TariffData {
+    fn details(&self) -> impl Iterator<Item = &PlanDetail> {
+        self.plan.iter().flat_map(|plan| plan.plan_detail.iter())
+    }
+
+    /// The daily standing charge in pence, if known.
+    pub fn standing_charge(&self) -> Option<f64> {
+        self.details().find_map(|detail| match detail {
+            PlanDetail::StandingCharge(standing) => Some(*standing),
+            _ => None,
+        })
+    }
+
+    /// The flat unit rate in pence per kWh, if the plan has one.
+    pub fn unit_rate(&self) -> Option<f64> {
+        self.details().find_map(|detail| match detail {
+            PlanDetail::UnitRate(rate) => Some(*rate),
+            _ => None,
+        })
+    }
+
+    /// The time of use rates in the plan.
+    pub fn time_of_use_rates(&self) -> Vec<TimeOfUseRate> {
+        self.details()
+            .filter_map(|detail| match detail {
+                PlanDetail::TimeOfUse(band) => Some(band.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The tiered rates in the plan.
+    pub fn tier_rates(&self) -> Vec<TierRate> {
+        self.details()
+            .filter_map(|detail| match detail {
+                PlanDetail::Tier(tier) => Some(tier.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
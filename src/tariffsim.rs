@@ -0,0 +1,239 @@
+//! Shows what past consumption would have cost on other tariffs.
+
+use std::{
+    fs,
+    io::{stdout, Write},
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::{
+    cost::Rates,
+    format::round,
+    increase_by_period,
+    simulate::{simulate as simulate_rates, Simulation, TariffDefinition},
+    GlowmarktApi, ReadingPeriod,
+};
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+use crate::{
+    hint::CliError,
+    lookup::resolve_resource,
+    output::{OutputOptions, ReportFormat},
+    parse_date, parse_end_date, ErrorStr,
+};
+
+#[derive(clap::Args)]
+pub struct SimulateArgs {
+    /// The output format.
+    #[clap(short, long, value_enum, default_value = "text")]
+    format: ReportFormat,
+    /// A TOML or JSON file defining the tariff to simulate. May be given more
+    /// than once to compare several tariffs.
+    #[clap(long = "rates", required = true)]
+    rates: Vec<PathBuf>,
+    /// The resource whose consumption is costed, either its ID, its
+    /// classifier such as `electricity.consumption` or part of its name.
+    resource: String,
+    /// Start time of first reading.
+    #[clap(allow_hyphen_values = true)]
+    from: String,
+    /// Start time of last reading (defaults to now).
+    #[clap(allow_hyphen_values = true)]
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TariffResult {
+    #[serde(flatten)]
+    simulation: Simulation,
+    /// The total less the current tariff's total.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    difference: Option<f64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    resource_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Simulation>,
+    tariffs: Vec<TariffResult>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    notes: Vec<String>,
+}
+
+fn load_definition(path: &Path) -> Result<TariffDefinition, CliError> {
+    let data = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    let definition = if path.extension() == Some("json".as_ref()) {
+        serde_json::from_str(&data).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(&data).map_err(|e| e.to_string())
+    };
+    definition.map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+}
+
+fn round_simulation(simulation: &mut Simulation, precision: Option<u32>) {
+    simulation.consumption = round(simulation.consumption, precision);
+    simulation.unrated = round(simulation.unrated, precision);
+    simulation.standing_charge = round(simulation.standing_charge, precision);
+    simulation.consumption_cost = round(simulation.consumption_cost, precision);
+    simulation.total = round(simulation.total, precision);
+    for band in simulation.bands.iter_mut() {
+        band.consumption = round(band.consumption, precision);
+        band.cost = round(band.cost, precision);
+    }
+}
+
+fn print_text(report: &Report) -> Result<(), CliError> {
+    let mut out = stdout().lock();
+    writeln!(
+        out,
+        "{:<20}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+        "Tariff", "Standing", "Units", "Total", "Difference", "Unrated"
+    )
+    .str_err()?;
+
+    let rows = report
+        .current
+        .iter()
+        .map(|simulation| (simulation, None))
+        .chain(
+            report
+                .tariffs
+                .iter()
+                .map(|result| (&result.simulation, result.difference)),
+        );
+    for (simulation, difference) in rows {
+        writeln!(
+            out,
+            "{:<20}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+            simulation.name,
+            simulation.standing_charge,
+            simulation.consumption_cost,
+            simulation.total,
+            difference
+                .map(|difference| format!("{:+}", difference))
+                .unwrap_or_default(),
+            simulation.unrated,
+        )
+        .str_err()?;
+    }
+
+    for note in &report.notes {
+        writeln!(out).str_err()?;
+        writeln!(out, "{}", note).str_err()?;
+    }
+
+    Ok(())
+}
+
+pub async fn simulate(
+    api: GlowmarktApi,
+    options: OutputOptions,
+    args: SimulateArgs,
+) -> Result<(), CliError> {
+    // Reject bad tariff files before fetching anything.
+    let mut tariffs = Vec::new();
+    for path in &args.rates {
+        let definition = load_definition(path)?;
+        let rates = definition.rates().ok_or_else(|| {
+            format!(
+                "{} has no unit rates, add unit-rate or time-of-use",
+                path.display()
+            )
+        })?;
+        let name = definition.name.unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into()
+        });
+        tariffs.push((name, rates));
+    }
+
+    let period = ReadingPeriod::HalfHour;
+    let start = parse_date(args.from, period, &api)?;
+    let end = parse_end_date(args.to, period, &api)?;
+    let resource_id = resolve_resource(&api, &args.resource).await?;
+
+    let mut notes = Vec::new();
+    let current = match api.latest_tariff(&resource_id).await {
+        Ok(tariff) => tariff.as_ref().and_then(Rates::from_tariff),
+        Err(e) if e.is_unavailable() => {
+            notes.push(format!("The current tariff could not be retrieved: {}", e));
+            None
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if current.is_none() && notes.is_empty() {
+        notes.push(format!(
+            "Resource {} has no usable tariff to compare against.",
+            resource_id
+        ));
+    }
+
+    let readings = api
+        .readings_range(&resource_id, &start, &end, period)
+        .await?;
+
+    let precision = match args.format {
+        ReportFormat::Text => options.precision.or(Some(3)),
+        ReportFormat::Json => options.precision,
+    };
+    // The range runs to the end of the last reading, but days yet to come
+    // aren't charged.
+    let range = (
+        start,
+        increase_by_period(end, period).min(api.clock().now()),
+    );
+    let current = current.map(|rates| simulate_rates("current", &readings, &rates, range));
+    let mut report = Report {
+        resource_id,
+        tariffs: tariffs
+            .iter()
+            .map(|(name, rates)| {
+                let simulation = simulate_rates(name, &readings, rates, range);
+                TariffResult {
+                    difference: current
+                        .as_ref()
+                        .map(|current| round(simulation.total - current.total, precision)),
+                    simulation,
+                }
+            })
+            .collect(),
+        current,
+        notes,
+    };
+
+    if report
+        .tariffs
+        .iter()
+        .any(|result| result.simulation.unrated > 0.0)
+    {
+        report.notes.push(
+            "Some consumption fell outside every time of use band of a tariff with no unit \
+            rate and was costed at zero."
+                .to_string(),
+        );
+    }
+    for simulation in report.current.iter_mut().chain(
+        report
+            .tariffs
+            .iter_mut()
+            .map(|result| &mut result.simulation),
+    ) {
+        round_simulation(simulation, precision);
+    }
+
+    match args.format {
+        ReportFormat::Text => print_text(&report),
+        ReportFormat::Json => {
+            println!("{}", to_string_pretty(&report).str_err()?);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Keeps the token generated from a username and password between runs so
+//! that every run doesn't need to authenticate again.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::{clock::Clock, GlowmarktApi};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::state;
+
+/// Cached tokens are only used if they remain valid for at least this long.
+const MIN_VALIDITY: Duration = Duration::minutes(5);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedToken {
+    pub username: String,
+    pub token: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expiry: OffsetDateTime,
+}
+
+/// The location of the token cache, `$XDG_CACHE_HOME/glowmarkt/token.json`.
+fn cache_path() -> Option<PathBuf> {
+    state::xdg_path("XDG_CACHE_HOME", &[".cache"], "token.json")
+}
+
+/// Loads the cached token for a user if it is still valid.
+pub fn load(username: &str, clock: &dyn Clock) -> Option<CachedToken> {
+    let path = cache_path()?;
+    let data = fs::read_to_string(&path).ok()?;
+
+    let cached = match serde_json::from_str::<CachedToken>(&data) {
+        Ok(cached) => cached,
+        Err(e) => {
+            log::debug!("Ignoring invalid token cache {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    if cached.username != username {
+        log::debug!("Cached token is for a different user");
+        None
+    } else if cached.expiry - MIN_VALIDITY <= clock.now() {
+        log::debug!("Cached token has expired");
+        None
+    } else {
+        Some(cached)
+    }
+}
+
+/// Stores the API's current token for a user. Failures are only logged as
+/// the cache is just an optimisation.
+pub fn save(username: &str, api: &GlowmarktApi) {
+    let Some(path) = cache_path() else {
+        return;
+    };
+
+    let Some(expiry) = api.token_expiry() else {
+        return;
+    };
+
+    let cached = CachedToken {
+        username: username.to_owned(),
+        token: api.token(),
+        expiry,
+    };
+
+    if let Err(e) = write(&path, &cached) {
+        log::warn!("Unable to write token cache {}: {}", path.display(), e);
+    }
+}
+
+fn write(path: &Path, cached: &CachedToken) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    // The token grants access to the account so keep it private.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(serde_json::to_string(cached)?.as_bytes())
+}
@@ -0,0 +1,258 @@
+//! Transformations applied to readings between fetching and writing them.
+//!
+//! Each [`Transform`] works on a chunk of readings ordered by start time and
+//! they can be chained into a [`Pipeline`]. Pipelines can be parsed from a
+//! comma separated list such as `fill-gaps,pence-to-pounds`, the names are
+//! listed on [`Pipeline`]'s `FromStr` implementation.
+
+use std::{fmt, str::FromStr};
+
+use time::Duration;
+
+use crate::{gas::GasConversion, increase_by_period, parse_iso_duration, unit::Unit, Reading};
+
+/// A change made to readings.
+pub trait Transform: fmt::Debug + Send + Sync {
+    /// Transforms a chunk of readings ordered by start time.
+    fn apply(&self, readings: Vec<Reading>) -> Vec<Reading>;
+
+    /// The unit of readings after the transform, given their unit before.
+    fn unit(&self, unit: Option<Unit>) -> Option<Unit> {
+        unit
+    }
+}
+
+/// Multiplies every value by a factor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale(pub f64);
+
+impl Transform for Scale {
+    fn apply(&self, mut readings: Vec<Reading>) -> Vec<Reading> {
+        for reading in readings.iter_mut() {
+            reading.value = (reading.value as f64 * self.0) as f32;
+        }
+        readings
+    }
+}
+
+/// Converts values to a different unit by multiplying by a factor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertUnit {
+    /// The factor to multiply values by.
+    pub factor: f64,
+    /// The unit of the converted values.
+    pub unit: Unit,
+}
+
+impl ConvertUnit {
+    /// Converts pence to pounds.
+    pub fn pence_to_pounds() -> Self {
+        Self {
+            factor: 0.01,
+            unit: Unit::Pounds,
+        }
+    }
+
+    /// Converts watt hours to kWh.
+    pub fn wh_to_kwh() -> Self {
+        Self {
+            factor: 0.001,
+            unit: Unit::KilowattHours,
+        }
+    }
+}
+
+impl Transform for ConvertUnit {
+    fn apply(&self, readings: Vec<Reading>) -> Vec<Reading> {
+        Scale(self.factor).apply(readings)
+    }
+
+    fn unit(&self, _: Option<Unit>) -> Option<Unit> {
+        Some(self.unit.clone())
+    }
+}
+
+impl Transform for GasConversion {
+    fn apply(&self, mut readings: Vec<Reading>) -> Vec<Reading> {
+        self.convert(&mut readings);
+        readings
+    }
+
+    fn unit(&self, _: Option<Unit>) -> Option<Unit> {
+        Some(Unit::KilowattHours)
+    }
+}
+
+/// Moves every reading later, or earlier for a negative duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift(pub Duration);
+
+impl Transform for Shift {
+    fn apply(&self, mut readings: Vec<Reading>) -> Vec<Reading> {
+        for reading in readings.iter_mut() {
+            reading.start += self.0;
+        }
+        readings
+    }
+}
+
+/// Inserts zero readings for any periods missing between the first and last
+/// reading of a chunk.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FillGaps;
+
+impl Transform for FillGaps {
+    fn apply(&self, readings: Vec<Reading>) -> Vec<Reading> {
+        let mut filled: Vec<Reading> = Vec::with_capacity(readings.len());
+
+        for reading in readings {
+            if let Some(previous) = filled.last() {
+                let period = previous.period;
+                let mut expected = increase_by_period(previous.start, period);
+                while expected < reading.start {
+                    filled.push(Reading {
+                        start: expected,
+                        period,
+                        value: 0.0,
+                        quality: None,
+                    });
+                    expected = increase_by_period(expected, period);
+                }
+            }
+            filled.push(reading);
+        }
+
+        filled
+    }
+}
+
+/// Removes readings with a value of zero, which are normally data that has
+/// not yet arrived from the meter.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StripZeros;
+
+impl Transform for StripZeros {
+    fn apply(&self, mut readings: Vec<Reading>) -> Vec<Reading> {
+        readings.retain(|reading| reading.value != 0.0);
+        readings
+    }
+}
+
+/// A series of transforms applied in order.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    transforms: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline that leaves readings unchanged.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a transform to the end of the pipeline.
+    pub fn push<T: Transform + 'static>(&mut self, transform: T) {
+        self.transforms.push(Box::new(transform));
+    }
+
+    /// Adds a transform to the end of the pipeline.
+    pub fn then<T: Transform + 'static>(mut self, transform: T) -> Self {
+        self.push(transform);
+        self
+    }
+
+    /// Adds the transforms of another pipeline to the end of this one.
+    pub fn extend(&mut self, other: Pipeline) {
+        self.transforms.extend(other.transforms);
+    }
+
+    /// Whether the pipeline has no transforms.
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+}
+
+impl Transform for Pipeline {
+    fn apply(&self, readings: Vec<Reading>) -> Vec<Reading> {
+        self.transforms
+            .iter()
+            .fold(readings, |readings, transform| transform.apply(readings))
+    }
+
+    fn unit(&self, unit: Option<Unit>) -> Option<Unit> {
+        self.transforms
+            .iter()
+            .fold(unit, |unit, transform| transform.unit(unit))
+    }
+}
+
+fn parse_shift(value: &str) -> Result<Duration, String> {
+    match value.strip_prefix('-') {
+        Some(value) => parse_iso_duration(value).map(|duration| -duration),
+        None => parse_iso_duration(value),
+    }
+}
+
+/// Parses a scale factor, which must be finite when applied to values.
+fn parse_factor(factor: &str) -> Result<f64, String> {
+    match factor.parse::<f64>() {
+        Ok(value) if value.is_finite() && (value as f32).is_finite() => Ok(value),
+        _ => Err(format!("Invalid scale factor '{}'", factor)),
+    }
+}
+
+/// Parses a comma separated list of transforms:
+///
+/// * `scale=<factor>` multiplies values by a factor.
+/// * `pence-to-pounds` and `wh-to-kwh` convert units.
+/// * `gas-to-kwh` converts cubic metres of gas to kWh with the default
+///   [`GasConversion`].
+/// * `shift=<duration>` moves readings by an ISO-8601 duration, e.g. `PT1H`
+///   or `-PT30M`.
+/// * `fill-gaps` inserts zero readings for missing periods.
+/// * `strip-zeros` removes zero readings.
+impl FromStr for Pipeline {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pipeline = Pipeline::new();
+
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let (name, argument) = match name.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim())),
+                None => (name, None),
+            };
+
+            match (name, argument) {
+                ("scale", Some(factor)) => pipeline.push(Scale(parse_factor(factor)?)),
+                ("shift", Some(duration)) => pipeline.push(Shift(parse_shift(duration)?)),
+                ("pence-to-pounds", None) => pipeline.push(ConvertUnit::pence_to_pounds()),
+                ("wh-to-kwh", None) => pipeline.push(ConvertUnit::wh_to_kwh()),
+                ("gas-to-kwh", None) => pipeline.push(GasConversion::default()),
+                ("fill-gaps", None) => pipeline.push(FillGaps),
+                ("strip-zeros", None) => pipeline.push(StripZeros),
+                ("scale" | "shift", None) => {
+                    return Err(format!(
+                        "The {} transform needs a value, e.g. {}=...",
+                        name, name
+                    ))
+                }
+                (_, Some(_))
+                    if matches!(
+                        name,
+                        "pence-to-pounds"
+                            | "wh-to-kwh"
+                            | "gas-to-kwh"
+                            | "fill-gaps"
+                            | "strip-zeros"
+                    ) =>
+                {
+                    return Err(format!("The {} transform does not take a value", name))
+                }
+                _ => return Err(format!("Unknown transform '{}'", name)),
+            }
+        }
+
+        Ok(pipeline)
+    }
+}
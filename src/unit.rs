@@ -0,0 +1,103 @@
+//! The units readings are measured in.
+//!
+//! Resources give their unit as a free-form `baseUnit` string, [`Unit`]
+//! recognises the common spellings so consumers don't have to.
+
+use std::{convert::Infallible, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// The unit of a reading.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Unit {
+    /// Kilowatt hours of energy.
+    KilowattHours,
+    /// Watt hours of energy.
+    WattHours,
+    /// Watts of power.
+    Watts,
+    /// Kilowatts of power.
+    Kilowatts,
+    /// Pence.
+    Pence,
+    /// Pounds sterling.
+    Pounds,
+    /// Cubic metres of gas.
+    CubicMetres,
+    /// Any other unit.
+    Other(String),
+}
+
+impl Unit {
+    /// A short name for the unit, e.g. `kWh`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Unit::KilowattHours => "kWh",
+            Unit::WattHours => "Wh",
+            Unit::Watts => "W",
+            Unit::Kilowatts => "kW",
+            Unit::Pence => "pence",
+            Unit::Pounds => "pounds",
+            Unit::CubicMetres => "m3",
+            Unit::Other(unit) => unit,
+        }
+    }
+
+    /// Whether this is a unit of energy.
+    pub fn is_energy(&self) -> bool {
+        matches!(self, Unit::KilowattHours | Unit::WattHours)
+    }
+
+    /// Whether this is a unit of money.
+    pub fn is_currency(&self) -> bool {
+        matches!(self, Unit::Pence | Unit::Pounds)
+    }
+}
+
+impl From<&str> for Unit {
+    fn from(unit: &str) -> Self {
+        match unit.trim().to_lowercase().as_str() {
+            "kwh" => Unit::KilowattHours,
+            "wh" => Unit::WattHours,
+            "w" | "watt" | "watts" => Unit::Watts,
+            "kw" => Unit::Kilowatts,
+            "p" | "pence" => Unit::Pence,
+            "£" | "gbp" | "pounds" => Unit::Pounds,
+            "m3" | "m³" | "m^3" => Unit::CubicMetres,
+            _ => Unit::Other(unit.to_owned()),
+        }
+    }
+}
+
+impl From<String> for Unit {
+    fn from(unit: String) -> Self {
+        match Unit::from(unit.as_str()) {
+            Unit::Other(_) => Unit::Other(unit),
+            known => known,
+        }
+    }
+}
+
+impl From<Unit> for String {
+    fn from(unit: Unit) -> Self {
+        match unit {
+            Unit::Other(unit) => unit,
+            known => known.as_str().to_owned(),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
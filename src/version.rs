@@ -0,0 +1,81 @@
+//! Identifies the build and its configuration for bug reports.
+
+use std::env::consts::{ARCH, OS};
+
+use glowmarkt::BASE_URL;
+use serde::Serialize;
+use serde_json::to_string_pretty;
+
+use crate::{hint::CliError, ErrorStr};
+
+/// reqwest's default TLS backend, which this crate uses.
+const TLS_BACKEND: &str = "native-tls";
+
+#[derive(clap::Args)]
+pub struct VersionArgs {
+    /// Output JSON instead of text.
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Version {
+    version: &'static str,
+    /// The git commit built, when built from a checkout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_hash: Option<&'static str>,
+    features: Vec<&'static str>,
+    endpoint: &'static str,
+    tls_backend: &'static str,
+    target: String,
+}
+
+fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "sqlite") {
+        features.push("sqlite");
+    }
+    if cfg!(feature = "postgres") {
+        features.push("postgres");
+    }
+    if cfg!(feature = "parquet") {
+        features.push("parquet");
+    }
+    if cfg!(feature = "octopus") {
+        features.push("octopus");
+    }
+    features
+}
+
+pub fn version(args: &VersionArgs) -> Result<(), CliError> {
+    let version = Version {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: option_env!("GLOWMARKT_GIT_HASH"),
+        features: features(),
+        endpoint: BASE_URL,
+        tls_backend: TLS_BACKEND,
+        target: format!("{}-{}", ARCH, OS),
+    };
+
+    if args.json {
+        println!("{}", to_string_pretty(&version).str_err()?);
+    } else {
+        println!(
+            "glowmarkt {} ({})",
+            version.version,
+            version.git_hash.unwrap_or("unknown commit")
+        );
+        let features = if version.features.is_empty() {
+            "none".to_string()
+        } else {
+            version.features.join(", ")
+        };
+        println!("Features: {}", features);
+        println!("Endpoint: {}", version.endpoint);
+        println!("TLS backend: {}", version.tls_backend);
+        println!("Target: {}", version.target);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! A write-ahead log of readings waiting to be delivered to a sink.
+//!
+//! Readings are appended and synced to disk before the daemon tries to deliver
+//! them and the log is only cleared once delivery succeeds and the daemon's
+//! state has been saved. Anything left in the log after a crash or reboot is
+//! delivered again on the next start, so readings may be delivered more than
+//! once but are never lost.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use glowmarkt::{Reading, ReadingPeriod};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::ErrorStr;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WalReading {
+    start: i64,
+    value: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quality: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WalEntry {
+    resource_id: String,
+    period: String,
+    readings: Vec<WalReading>,
+}
+
+/// Readings for a resource recovered from the log.
+pub struct Pending {
+    pub resource_id: String,
+    pub readings: Vec<Reading>,
+}
+
+pub struct Wal {
+    path: PathBuf,
+}
+
+impl Wal {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends readings for a resource, returning once they are on disk.
+    pub fn append(&self, resource_id: &str, readings: &[Reading]) -> Result<(), String> {
+        let Some(period) = readings.first().map(|reading| reading.period) else {
+            return Ok(());
+        };
+
+        let entry = WalEntry {
+            resource_id: resource_id.to_owned(),
+            period: period.to_string(),
+            readings: readings
+                .iter()
+                .map(|reading| WalReading {
+                    start: reading.start.unix_timestamp(),
+                    value: reading.value,
+                    quality: reading.quality.clone(),
+                })
+                .collect(),
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+
+        let mut line = serde_json::to_string(&entry).str_err()?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(line.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+
+    /// Reads everything in the log. A partly written final entry, left by a
+    /// crash while appending, is skipped as it was never delivered.
+    pub fn pending(&self) -> Result<Vec<Pending>, String> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+
+        let mut pending = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line =
+                line.map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+
+            let entry = match serde_json::from_str::<WalEntry>(&line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::warn!("Skipping damaged entry in {}: {}", self.path.display(), e);
+                    continue;
+                }
+            };
+            let period = entry.period.parse::<ReadingPeriod>()?;
+
+            pending.push(Pending {
+                resource_id: entry.resource_id,
+                readings: entry
+                    .readings
+                    .into_iter()
+                    .filter_map(|reading| {
+                        Some(Reading {
+                            start: OffsetDateTime::from_unix_timestamp(reading.start).ok()?,
+                            period,
+                            value: reading.value,
+                            quality: reading.quality,
+                        })
+                    })
+                    .collect(),
+            });
+        }
+
+        Ok(pending)
+    }
+
+    /// Empties the log once its readings have been delivered.
+    pub fn clear(&self) -> Result<(), String> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to remove {}: {}", self.path.display(), e)),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Problems that don't stop an operation but that callers may want to know
+//! about.
+//!
+//! Higher level operations return these alongside their results rather than
+//! logging them or failing, so automation can record them without treating
+//! the run as failed.
+
+use std::fmt;
+
+use serde::Serialize;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Something that affected the results of an operation without failing it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Warning {
+    /// The end of the requested range was in the future and was moved back
+    /// to the current time.
+    Clamped {
+        /// The resource the readings were for.
+        resource_id: String,
+        /// The end that was requested.
+        #[serde(with = "time::serde::rfc3339")]
+        requested: OffsetDateTime,
+        /// The end that was used.
+        #[serde(with = "time::serde::rfc3339")]
+        end: OffsetDateTime,
+    },
+    /// A resource was left out of the results.
+    SkippedResource {
+        /// The resource that was skipped.
+        resource_id: String,
+        /// Why it was skipped.
+        reason: String,
+    },
+    /// The API returned more than one reading with the same start time, only
+    /// the last of each was kept.
+    DuplicatesDropped {
+        /// The resource the readings were for.
+        resource_id: String,
+        /// The number of readings dropped.
+        count: usize,
+    },
+    /// The last reading's period hasn't finished yet so its value only covers
+    /// part of it.
+    PartialPeriod {
+        /// The resource the reading was for.
+        resource_id: String,
+        /// The start of the reading.
+        #[serde(with = "time::serde::rfc3339")]
+        start: OffsetDateTime,
+        /// When the reading's period ends.
+        #[serde(with = "time::serde::rfc3339")]
+        end: OffsetDateTime,
+    },
+    /// The API returned a reading with a timestamp that couldn't be
+    /// represented, the reading was skipped.
+    InvalidTimestamp {
+        /// The resource the reading was for.
+        resource_id: String,
+        /// The timestamp, in seconds since the unix epoch.
+        timestamp: i64,
+    },
+    /// Periods in the requested range had no reading.
+    MissingReadings {
+        /// The resource the readings were for.
+        resource_id: String,
+        /// The number of periods with no reading.
+        missing: usize,
+        /// The start of the first period with no reading.
+        #[serde(with = "time::serde::rfc3339")]
+        first: OffsetDateTime,
+    },
+    /// Part of the requested range isn't held in the local store so it has
+    /// no readings.
+    NotStored {
+        /// The resource the readings were for.
+        resource_id: String,
+        /// The start of the part not held.
+        #[serde(with = "time::serde::rfc3339")]
+        start: OffsetDateTime,
+        /// The end of the part not held.
+        #[serde(with = "time::serde::rfc3339")]
+        end: OffsetDateTime,
+    },
+}
+
+impl Warning {
+    /// The resource the warning concerns.
+    pub fn resource_id(&self) -> &str {
+        match self {
+            Warning::Clamped { resource_id, .. }
+            | Warning::SkippedResource { resource_id, .. }
+            | Warning::DuplicatesDropped { resource_id, .. }
+            | Warning::PartialPeriod { resource_id, .. }
+            | Warning::InvalidTimestamp { resource_id, .. }
+            | Warning::MissingReadings { resource_id, .. }
+            | Warning::NotStored { resource_id, .. } => resource_id,
+        }
+    }
+}
+
+fn time(time: &OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_else(|_| time.to_string())
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::Clamped {
+                resource_id,
+                requested,
+                end,
+            } => write!(
+                f,
+                "{}: the range ending {} is in the future, readings stop at {}",
+                resource_id,
+                time(requested),
+                time(end)
+            ),
+            Warning::SkippedResource {
+                resource_id,
+                reason,
+            } => write!(f, "{}: skipped, {}", resource_id, reason),
+            Warning::DuplicatesDropped { resource_id, count } => write!(
+                f,
+                "{}: dropped {} readings with duplicate start times",
+                resource_id, count
+            ),
+            Warning::PartialPeriod {
+                resource_id,
+                start,
+                end,
+            } => write!(
+                f,
+                "{}: the reading starting {} is incomplete until {}",
+                resource_id,
+                time(start),
+                time(end)
+            ),
+            Warning::InvalidTimestamp {
+                resource_id,
+                timestamp,
+            } => write!(
+                f,
+                "{}: skipped a reading with the invalid timestamp {}",
+                resource_id, timestamp
+            ),
+            Warning::MissingReadings {
+                resource_id,
+                missing,
+                first,
+            } => write!(
+                f,
+                "{}: {} readings are missing, the first starting {}",
+                resource_id,
+                missing,
+                time(first)
+            ),
+            Warning::NotStored {
+                resource_id,
+                start,
+                end,
+            } => write!(
+                f,
+                "{}: readings from {} to {} aren't stored locally",
+                resource_id,
+                time(start),
+                time(end)
+            ),
+        }
+    }
+}